@@ -36,8 +36,12 @@ install -p -m 0644 %{S:101} %{buildroot}%{_cross_tmpfilesdir}/nvidia-migmanager.
 install -d %{buildroot}%{_cross_unitdir}/reboot-if-required.service.d
 install -p -m 0644 %{S:102} %{buildroot}%{_cross_unitdir}/reboot-if-required.service.d/mig-gpu-reset.conf
 
+install -d %{buildroot}%{_cross_mandir}/man8
+install -p -m 0644 %{_builddir}/sources/nvidia-migmanager/nvidia-migmanager.8 %{buildroot}%{_cross_mandir}/man8
+
 %files
 %{_cross_bindir}/nvidia-migmanager
 %{_cross_unitdir}/nvidia-migmanager.service
 %{_cross_tmpfilesdir}/nvidia-migmanager.conf
 %{_cross_unitdir}/reboot-if-required.service.d/mig-gpu-reset.conf
+%{_cross_mandir}/man8/nvidia-migmanager.8
@@ -0,0 +1,13 @@
+# sources
+
+The Rust crates of the kernel kit. Every crate's README is generated from its docs, as is this
+index, which lists the crates with the first paragraph of their docs:
+
+```shell
+cargo run -p generate-readme -- .
+```
+
+{{crates}}
+
+This directory is also a workaround for twoliter, which currently expects a `sources` directory
+to exist in order for the docker volume mounts to work in buildkit.
@@ -0,0 +1,806 @@
+//! Renders a command-line reference from the `argh::FromArgs` definitions in a Rust source file.
+//!
+//! The command tree is read from the source, not from the compiled binary: the top-level command is
+//! the `FromArgs` struct that isn't a subcommand, and its subcommands are the structs wrapped by
+//! the variants of its `#[argh(subcommand)]` enum. Descriptions come from the doc comments, like
+//! `argh` does for `--help`.
+//!
+//! Defaults are shown as the value of the `default` expression when it is a literal, a constant
+//! defined in the same file or imported from a sibling crate in the workspace, or a unit enum
+//! variant that the type's `FromStr` implementation parses from a string literal. The enums and
+//! constants of the modules declared with `mod name;` next to the file are read too. Other
+//! expressions are left out, since the source expression isn't what the user types.
+
+use crate::error;
+use crate::Result;
+use snafu::{OptionExt, ResultExt};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use syn::{Attribute, Expr, Fields, Item, Lit, Type, UseTree};
+
+/// A command or subcommand.
+#[derive(Debug, PartialEq)]
+struct Command {
+    name: String,
+    description: String,
+    options: Vec<Opt>,
+    subcommands: Vec<Command>,
+}
+
+/// An option, switch or positional argument of a command.
+#[derive(Debug, PartialEq)]
+struct Opt {
+    kind: OptKind,
+    name: String,
+    short: Option<char>,
+    arg_name: String,
+    description: String,
+    default: Option<String>,
+    required: bool,
+    repeating: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OptKind {
+    Option,
+    Switch,
+    Positional,
+}
+
+// The parts of a `FromArgs` type that the reference needs.
+#[derive(Default)]
+struct ArghType {
+    description: String,
+    subcommand_name: Option<String>,
+    is_subcommand: bool,
+    fields: Vec<syn::Field>,
+    // For `#[argh(subcommand)]` enums, the types wrapped by the variants.
+    variants: Vec<String>,
+}
+
+/// Returns the Markdown reference for the `argh` command defined in `rust_file`, named `name`.
+pub(crate) fn reference(rust_file: &Path, source: &str, name: &str) -> Result<String> {
+    let file = syn::parse_file(source).context(error::CliParseSnafu { file: rust_file })?;
+    let types = argh_types(&file.items);
+    // The first `FromArgs` type that isn't a subcommand is the top-level command.
+    let root = types
+        .iter()
+        .find(|(_, argh_type)| !argh_type.is_subcommand)
+        .map(|(ident, _)| ident.clone())
+        .context(error::CliMissingSnafu { file: rust_file })?;
+    let types: HashMap<_, _> = types.into_iter().collect();
+
+    let mut consts = HashMap::new();
+    collect_consts(&file.items, &mut consts);
+    collect_from_str(&file.items, &mut consts);
+    collect_module_consts(rust_file, &file.items, &mut consts);
+    collect_imported_consts(&file.items, &mut consts);
+
+    let command = command(&types, &consts, &root, name.to_string());
+    let mut output = String::new();
+    render(&command, name, &mut output);
+    Ok(output)
+}
+
+// Collects the `FromArgs` structs and enums in source order, recursing into inline modules.
+fn argh_types(items: &[Item]) -> Vec<(String, ArghType)> {
+    let mut types = Vec::new();
+    for item in items {
+        match item {
+            Item::Struct(item) if derives_from_args(&item.attrs) => {
+                let mut argh_type = ArghType {
+                    description: doc_text(&item.attrs),
+                    fields: item.fields.iter().cloned().collect(),
+                    ..Default::default()
+                };
+                read_type_attrs(&item.attrs, &mut argh_type);
+                types.push((item.ident.to_string(), argh_type));
+            }
+            Item::Enum(item) if derives_from_args(&item.attrs) => {
+                let mut argh_type = ArghType {
+                    variants: item
+                        .variants
+                        .iter()
+                        .filter_map(|variant| match &variant.fields {
+                            Fields::Unnamed(fields) => fields
+                                .unnamed
+                                .first()
+                                .and_then(|field| type_name(&field.ty)),
+                            _ => None,
+                        })
+                        .collect(),
+                    ..Default::default()
+                };
+                read_type_attrs(&item.attrs, &mut argh_type);
+                types.push((item.ident.to_string(), argh_type));
+            }
+            Item::Mod(item) => {
+                if let Some((_, items)) = &item.content {
+                    types.extend(argh_types(items));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    types
+}
+
+fn derives_from_args(attrs: &[Attribute]) -> bool {
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("derive"))
+        .any(|attr| {
+            let mut found = false;
+            let _ = attr.parse_nested_meta(|meta| {
+                if meta.path.segments.last().map(|s| s.ident == "FromArgs") == Some(true) {
+                    found = true;
+                }
+                Ok(())
+            });
+            found
+        })
+}
+
+// Reads `#[argh(subcommand, name = "...", description = "...")]` from a struct or enum.
+fn read_type_attrs(attrs: &[Attribute], argh_type: &mut ArghType) {
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("argh")) {
+        let _ = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("subcommand") {
+                argh_type.is_subcommand = true;
+            } else if meta.path.is_ident("name") {
+                argh_type.subcommand_name = Some(meta.value()?.parse::<syn::LitStr>()?.value());
+            } else if meta.path.is_ident("description") {
+                argh_type.description = meta.value()?.parse::<syn::LitStr>()?.value();
+            } else {
+                skip_meta(&meta)?;
+            }
+            Ok(())
+        });
+    }
+}
+
+// Consumes the value or arguments of an `argh` attribute the reference doesn't use, such as
+// `example = "..."` or `error_code(1, "...")`.
+fn skip_meta(meta: &syn::meta::ParseNestedMeta) -> syn::Result<()> {
+    if meta.input.peek(syn::Token![=]) {
+        meta.value()?.parse::<Expr>()?;
+    } else if meta.input.peek(syn::token::Paren) {
+        meta.input.step(|cursor| {
+            cursor
+                .token_tree()
+                .map(|(_, rest)| ((), rest))
+                .ok_or_else(|| cursor.error("expected arguments"))
+        })?;
+    }
+    Ok(())
+}
+
+// Builds the command tree from the type named `ident`.
+fn command(
+    types: &HashMap<String, ArghType>,
+    consts: &HashMap<String, String>,
+    ident: &str,
+    name: String,
+) -> Command {
+    let mut command = Command {
+        name,
+        description: String::new(),
+        options: Vec::new(),
+        subcommands: Vec::new(),
+    };
+    let argh_type = match types.get(ident) {
+        Some(argh_type) => argh_type,
+        None => return command,
+    };
+    command.description = argh_type.description.clone();
+
+    for field in &argh_type.fields {
+        let field_name = field
+            .ident
+            .as_ref()
+            .map(|ident| ident.to_string())
+            .unwrap_or_default();
+        let mut kind = None;
+        let mut opt = Opt {
+            kind: OptKind::Option,
+            name: field_name.replace('_', "-"),
+            short: None,
+            arg_name: field_name.replace('_', "-"),
+            description: doc_text(&field.attrs),
+            default: None,
+            required: false,
+            repeating: false,
+        };
+        let mut subcommand = false;
+        let mut has_default = false;
+
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("argh"))
+        {
+            let _ = attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("option") {
+                    kind = Some(OptKind::Option);
+                } else if meta.path.is_ident("switch") {
+                    kind = Some(OptKind::Switch);
+                } else if meta.path.is_ident("positional") {
+                    kind = Some(OptKind::Positional);
+                } else if meta.path.is_ident("subcommand") {
+                    subcommand = true;
+                } else if meta.path.is_ident("default") {
+                    let expr = meta.value()?.parse::<syn::LitStr>()?.value();
+                    has_default = true;
+                    opt.default = resolve_default(&expr, consts);
+                } else if meta.path.is_ident("short") {
+                    opt.short = Some(meta.value()?.parse::<syn::LitChar>()?.value());
+                } else if meta.path.is_ident("long") {
+                    let long = meta.value()?.parse::<syn::LitStr>()?.value();
+                    opt.name = long.trim_start_matches("--").to_string();
+                } else if meta.path.is_ident("arg_name") {
+                    opt.arg_name = meta.value()?.parse::<syn::LitStr>()?.value();
+                } else if meta.path.is_ident("description") {
+                    opt.description = meta.value()?.parse::<syn::LitStr>()?.value();
+                } else {
+                    skip_meta(&meta)?;
+                }
+                Ok(())
+            });
+        }
+
+        if subcommand {
+            let subcommand_types = type_name(&field.ty)
+                .and_then(|name| types.get(&name))
+                .map(|argh_type| argh_type.variants.as_slice())
+                .unwrap_or_default();
+            for variant in subcommand_types {
+                let name = types
+                    .get(variant)
+                    .and_then(|argh_type| argh_type.subcommand_name.clone())
+                    .unwrap_or_else(|| variant.clone());
+                command
+                    .subcommands
+                    .push(self::command(types, consts, variant, name));
+            }
+            continue;
+        }
+
+        let Some(kind) = kind else { continue };
+        let wrapper = wrapper_name(&field.ty);
+        opt.kind = kind;
+        opt.repeating = wrapper.as_deref() == Some("Vec");
+        opt.required = kind != OptKind::Switch
+            && !has_default
+            && !matches!(wrapper.as_deref(), Some("Option") | Some("Vec"));
+        command.options.push(opt);
+    }
+
+    command
+}
+
+// The name of the type, or the name of the type in `Option<T>` and `Vec<T>`.
+fn type_name(ty: &Type) -> Option<String> {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .map(|segment| segment.ident.to_string()),
+        _ => None,
+    }
+}
+
+// Returns "Option" or "Vec" when the type is one of them.
+fn wrapper_name(ty: &Type) -> Option<String> {
+    type_name(ty).filter(|name| name == "Option" || name == "Vec")
+}
+
+// Joins the lines of the doc comments into one paragraph, as `argh` does.
+pub(crate) fn doc_text(attrs: &[Attribute]) -> String {
+    attrs
+        .iter()
+        .filter_map(|attr| match &attr.meta {
+            syn::Meta::NameValue(meta) if meta.path.is_ident("doc") => match &meta.value {
+                Expr::Lit(expr) => match &expr.lit {
+                    Lit::Str(lit) => Some(lit.value().trim().to_string()),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        })
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Returns the value of a `default` expression if it can be read from the source.
+fn resolve_default(expr: &str, consts: &HashMap<String, String>) -> Option<String> {
+    syn::parse_str::<Expr>(expr)
+        .ok()
+        .and_then(|parsed| literal_value(&parsed, consts))
+}
+
+fn literal_value(expr: &Expr, consts: &HashMap<String, String>) -> Option<String> {
+    match expr {
+        Expr::Lit(expr) => match &expr.lit {
+            Lit::Str(lit) => Some(lit.value()),
+            Lit::Int(lit) => Some(lit.base10_digits().to_string()),
+            Lit::Float(lit) => Some(lit.base10_digits().to_string()),
+            Lit::Bool(lit) => Some(lit.value.to_string()),
+            Lit::Char(lit) => Some(lit.value().to_string()),
+            _ => None,
+        },
+        // A constant, or an enum variant as `Type::Variant`.
+        Expr::Path(expr) => {
+            let segments: Vec<_> = expr
+                .path
+                .segments
+                .iter()
+                .map(|segment| segment.ident.to_string())
+                .collect();
+            let start = segments.len().saturating_sub(2);
+            consts.get(&segments[start..].join("::")).cloned()
+        }
+        // Conversions such as `DEFAULT_PATH.to_string()` don't change the value.
+        Expr::MethodCall(call)
+            if call.args.is_empty()
+                && ["to_string", "to_owned", "into"]
+                    .contains(&call.method.to_string().as_str()) =>
+        {
+            literal_value(&call.receiver, consts)
+        }
+        Expr::Call(call) if call.args.len() == 1 => match &*call.func {
+            Expr::Path(func)
+                if func.path.segments.last().map(|s| s.ident == "from") == Some(true) =>
+            {
+                call.args.first().and_then(|arg| literal_value(arg, consts))
+            }
+            _ => None,
+        },
+        Expr::Reference(expr) => literal_value(&expr.expr, consts),
+        Expr::Paren(expr) => literal_value(&expr.expr, consts),
+        _ => None,
+    }
+}
+
+// Collects the constants with a literal value, recursing into inline modules.
+fn collect_consts(items: &[Item], consts: &mut HashMap<String, String>) {
+    let mut pending = Vec::new();
+    for item in items {
+        match item {
+            Item::Const(item) => pending.push((item.ident.to_string(), (*item.expr).clone())),
+            Item::Mod(item) => {
+                if let Some((_, items)) = &item.content {
+                    collect_consts(items, consts);
+                }
+            }
+            _ => {}
+        }
+    }
+    // Constants can be defined in terms of other constants, in any order.
+    loop {
+        let before = pending.len();
+        pending.retain(|(name, expr)| match literal_value(expr, consts) {
+            Some(value) => {
+                consts.insert(name.clone(), value);
+                false
+            }
+            None => true,
+        });
+        if pending.is_empty() || pending.len() == before {
+            break;
+        }
+    }
+}
+
+// Collects the unit variants that `FromStr` implementations parse from a string literal, such as
+// `"text" => Ok(LogFormat::Text)`, as `LogFormat::Text`, recursing into inline modules.
+fn collect_from_str(items: &[Item], consts: &mut HashMap<String, String>) {
+    for item in items {
+        match item {
+            Item::Impl(item) => {
+                let is_from_str = item.trait_.as_ref().and_then(|(_, path, _)| {
+                    path.segments
+                        .last()
+                        .map(|segment| segment.ident == "FromStr")
+                });
+                let Some(type_name) =
+                    type_name(&item.self_ty).filter(|_| is_from_str == Some(true))
+                else {
+                    continue;
+                };
+                for impl_item in &item.items {
+                    if let syn::ImplItem::Fn(function) = impl_item {
+                        for stmt in &function.block.stmts {
+                            if let syn::Stmt::Expr(Expr::Match(expr), _) = stmt {
+                                collect_arms(&type_name, &expr.arms, consts);
+                            }
+                        }
+                    }
+                }
+            }
+            Item::Mod(item) => {
+                if let Some((_, items)) = &item.content {
+                    collect_from_str(items, consts);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Collects the arms of a `from_str` match that map a string literal to `Ok(Type::Variant)` or
+// `Ok(Self::Variant)`.
+fn collect_arms(type_name: &str, arms: &[syn::Arm], consts: &mut HashMap<String, String>) {
+    for arm in arms {
+        let syn::Pat::Lit(pat) = &arm.pat else {
+            continue;
+        };
+        let Lit::Str(value) = &pat.lit else {
+            continue;
+        };
+        let Expr::Call(call) = &*arm.body else {
+            continue;
+        };
+        let is_ok = matches!(&*call.func, Expr::Path(func) if func.path.is_ident("Ok"));
+        let Some(Expr::Path(variant)) = call.args.first().filter(|_| is_ok) else {
+            continue;
+        };
+        let Some(variant) = variant.path.segments.last() else {
+            continue;
+        };
+        consts
+            .entry(format!("{}::{}", type_name, variant.ident))
+            .or_insert_with(|| value.value());
+    }
+}
+
+// Collects the constants and `FromStr` variants of the modules that `rust_file` declares with
+// `mod name;`, from `name.rs` or `name/mod.rs` next to it.
+fn collect_module_consts(rust_file: &Path, items: &[Item], consts: &mut HashMap<String, String>) {
+    let dir = rust_file.parent().unwrap_or(Path::new(""));
+    for item in items {
+        let Item::Mod(item) = item else { continue };
+        if item.content.is_some() {
+            continue;
+        }
+        let name = item.ident.to_string();
+        let file = [
+            dir.join(format!("{}.rs", name)),
+            dir.join(&name).join("mod.rs"),
+        ]
+        .into_iter()
+        .find(|path| path.is_file())
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|source| syn::parse_file(&source).ok());
+        if let Some(file) = file {
+            // The file's own items win over the module's.
+            let mut module_consts = HashMap::new();
+            collect_consts(&file.items, &mut module_consts);
+            collect_from_str(&file.items, &mut module_consts);
+            for (name, value) in module_consts {
+                consts.entry(name).or_insert(value);
+            }
+        }
+    }
+}
+
+// Collects the constants imported with `use` from a sibling crate in the workspace, like
+// `use other_crate::module::DEFAULT_PATH;`. Imports that can't be read are ignored, since the
+// default is then left out.
+fn collect_imported_consts(items: &[Item], consts: &mut HashMap<String, String>) {
+    let mut imports = Vec::new();
+    for item in items {
+        if let Item::Use(item) = item {
+            use_paths(&item.tree, Vec::new(), &mut imports);
+        }
+    }
+
+    let mut modules: HashMap<PathBuf, HashMap<String, String>> = HashMap::new();
+    for (path, name) in imports {
+        let Some((krate, module)) = path.split_first() else {
+            continue;
+        };
+        let Some(module_file) = module_file(krate, module) else {
+            continue;
+        };
+        let module_consts = modules.entry(module_file.clone()).or_insert_with(|| {
+            let mut module_consts = HashMap::new();
+            if let Some(file) = fs::read_to_string(&module_file)
+                .ok()
+                .and_then(|source| syn::parse_file(&source).ok())
+            {
+                collect_consts(&file.items, &mut module_consts);
+            }
+            module_consts
+        });
+        if let Some(value) = module_consts.get(&name) {
+            consts.entry(name).or_insert_with(|| value.clone());
+        }
+    }
+}
+
+// Flattens a `use` tree into the module path and name of every imported item.
+fn use_paths(tree: &UseTree, prefix: Vec<String>, imports: &mut Vec<(Vec<String>, String)>) {
+    match tree {
+        UseTree::Path(path) => {
+            let mut prefix = prefix;
+            prefix.push(path.ident.to_string());
+            use_paths(&path.tree, prefix, imports);
+        }
+        UseTree::Name(name) => imports.push((prefix, name.ident.to_string())),
+        UseTree::Group(group) => {
+            for tree in &group.items {
+                use_paths(tree, prefix.clone(), imports);
+            }
+        }
+        UseTree::Rename(_) | UseTree::Glob(_) => {}
+    }
+}
+
+// Finds the file of `krate::module` in a sibling crate of the workspace, relative to the directory
+// of the crate being documented.
+fn module_file(krate: &str, module: &[String]) -> Option<PathBuf> {
+    let src = Path::new("..").join(krate.replace('_', "-")).join("src");
+    let Some((last, parents)) = module.split_last() else {
+        return Some(src.join("lib.rs")).filter(|path| path.is_file());
+    };
+    let dir = parents.iter().fold(src, |dir, module| dir.join(module));
+    [
+        dir.join(format!("{}.rs", last)),
+        dir.join(last).join("mod.rs"),
+    ]
+    .into_iter()
+    .find(|path| path.is_file())
+}
+
+// Renders the section of a command, then the sections of its subcommands.
+fn render(command: &Command, path: &str, output: &mut String) {
+    let _ = writeln!(output, "### `{}`\n", path);
+    if !command.description.is_empty() {
+        let _ = writeln!(output, "{}\n", command.description);
+    }
+    let _ = writeln!(output, "```\nUsage: {}\n```\n", usage(command, path));
+
+    if !command.options.is_empty() {
+        output.push_str("| Option | Description | Default |\n| --- | --- | --- |\n");
+        for opt in &command.options {
+            let default = match (&opt.default, opt.required) {
+                (Some(default), _) => format!("`{}`", default),
+                (None, true) => "required".to_string(),
+                (None, false) => String::new(),
+            };
+            let _ = writeln!(
+                output,
+                "| `{}` | {} | {} |",
+                flag(opt),
+                escape_cell(&opt.description),
+                default
+            );
+        }
+        output.push('\n');
+    }
+
+    if !command.subcommands.is_empty() {
+        output.push_str("| Subcommand | Description |\n| --- | --- |\n");
+        for subcommand in &command.subcommands {
+            let _ = writeln!(
+                output,
+                "| [`{}`](#{}) | {} |",
+                subcommand.name,
+                anchor(&format!("{} {}", path, subcommand.name)),
+                escape_cell(&subcommand.description)
+            );
+        }
+        output.push('\n');
+    }
+
+    for subcommand in &command.subcommands {
+        render(subcommand, &format!("{} {}", path, subcommand.name), output);
+    }
+}
+
+// The usage line, in the format of `argh`'s `--help`.
+fn usage(command: &Command, path: &str) -> String {
+    let mut usage = path.to_string();
+    for opt in &command.options {
+        let mut arg = match (opt.kind, opt.short) {
+            (OptKind::Positional, _) => format!("<{}>", opt.arg_name),
+            (OptKind::Switch, Some(short)) => format!("-{}", short),
+            (OptKind::Switch, None) => format!("--{}", opt.name),
+            (OptKind::Option, Some(short)) => format!("-{} <{}>", short, opt.arg_name),
+            (OptKind::Option, None) => format!("--{} <{}>", opt.name, opt.arg_name),
+        };
+        if opt.repeating {
+            arg.push_str("...");
+        }
+        if !opt.required {
+            arg = format!("[{}]", arg);
+        }
+        usage.push(' ');
+        usage.push_str(&arg);
+    }
+    if !command.subcommands.is_empty() {
+        usage.push_str(" <command> [<args>]");
+    }
+    usage
+}
+
+// The flag as listed in the options table, with its short form and value.
+fn flag(opt: &Opt) -> String {
+    let long = match opt.kind {
+        OptKind::Positional => return format!("<{}>", opt.arg_name),
+        OptKind::Switch => format!("--{}", opt.name),
+        OptKind::Option => format!("--{} <{}>", opt.name, opt.arg_name),
+    };
+    match opt.short {
+        Some(short) => format!("-{}, {}", short, long),
+        None => long,
+    }
+}
+
+// GitHub's anchor for a heading: lower-case, without punctuation, with dashes for spaces.
+pub(crate) fn anchor(heading: &str) -> String {
+    heading
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+pub(crate) fn escape_cell(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SOURCE: &str = r#"
+use argh::FromArgs;
+
+const DEFAULT_TIMEOUT: u64 = 60;
+const DEFAULT_PATH: &str = "/etc/tool.toml";
+
+/// Configures the widgets
+#[derive(FromArgs, PartialEq, Debug)]
+struct Args {
+    /// log-level trace|debug|info
+    #[argh(option)]
+    log_level: Option<String>,
+    /// configuration file
+    #[argh(option, default = "DEFAULT_PATH.to_string()", short = 'c')]
+    config_path: String,
+    /// seconds to wait
+    #[argh(option, default = "DEFAULT_TIMEOUT")]
+    timeout: u64,
+    /// output format
+    #[argh(option, default = "Format::Json")]
+    format: Format,
+    /// attempts
+    #[argh(option, default = "default_attempts()")]
+    attempts: u32,
+    #[argh(subcommand)]
+    subcommand: Subcommand,
+}
+
+enum Format {
+    Text,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("unknown format '{}'", s)),
+        }
+    }
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand)]
+enum Subcommand {
+    Apply(ApplyArgs),
+    Show(ShowArgs),
+}
+
+/// Applies the widgets
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand, name = "apply")]
+struct ApplyArgs {
+    /// do nothing
+    #[argh(switch)]
+    dry_run: bool,
+    /// the widget
+    #[argh(positional)]
+    widget: String,
+}
+
+/// Shows the widgets
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand, name = "show", example = "tool show")]
+struct ShowArgs {}
+"#;
+
+    #[test]
+    fn test_command_tree() {
+        let file = syn::parse_file(SOURCE).unwrap();
+        let types = argh_types(&file.items).into_iter().collect();
+        let mut consts = HashMap::new();
+        collect_consts(&file.items, &mut consts);
+        collect_from_str(&file.items, &mut consts);
+        let command = command(&types, &consts, "Args", "tool".to_string());
+
+        assert_eq!(command.description, "Configures the widgets");
+        let options: Vec<_> = command
+            .options
+            .iter()
+            .map(|opt| (flag(opt), opt.default.clone(), opt.required))
+            .collect();
+        assert_eq!(
+            options,
+            vec![
+                ("--log-level <log-level>".to_string(), None, false),
+                (
+                    "-c, --config-path <config-path>".to_string(),
+                    Some("/etc/tool.toml".to_string()),
+                    false
+                ),
+                (
+                    "--timeout <timeout>".to_string(),
+                    Some("60".to_string()),
+                    false
+                ),
+                // The variant is shown as the string that parses to it.
+                (
+                    "--format <format>".to_string(),
+                    Some("json".to_string()),
+                    false
+                ),
+                // Other expressions are left out, but still make the option optional.
+                ("--attempts <attempts>".to_string(), None, false),
+            ]
+        );
+
+        let names: Vec<_> = command
+            .subcommands
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["apply", "show"]);
+        assert_eq!(
+            usage(&command.subcommands[0], "tool apply"),
+            "tool apply [--dry-run] <widget>"
+        );
+        assert_eq!(
+            usage(&command, "tool"),
+            "tool [--log-level <log-level>] [-c <config-path>] [--timeout <timeout>] \
+             [--format <format>] [--attempts <attempts>] <command> [<args>]"
+        );
+    }
+
+    #[test]
+    fn test_reference() {
+        let output = reference(Path::new("src/main.rs"), SOURCE, "tool").unwrap();
+        assert!(output.starts_with("### `tool`\n\nConfigures the widgets\n"));
+        assert!(
+            output.contains("| `--log-level <log-level>` | log-level trace\\|debug\\|info |  |\n")
+        );
+        assert!(output.contains("| [`apply`](#tool-apply) | Applies the widgets |\n"));
+        assert!(output.contains("### `tool show`\n\nShows the widgets\n"));
+
+        assert!(reference(Path::new("src/lib.rs"), "fn main() {}", "tool").is_err());
+    }
+}
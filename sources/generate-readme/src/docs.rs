@@ -0,0 +1,397 @@
+//! Extracts the crate-level docs of a Rust source file, the way rustdoc sees them.
+//!
+//! The file is parsed with `syn`, so the docs are the crate's inner `doc` attributes in order:
+//! `//!` and `/*! */` comments, `#![doc = "..."]`, `#![doc = include_str!("...")]`, and any of
+//! these behind `#![cfg_attr(doc, ...)]`. Every line remembers the file and line it came from, so
+//! that later steps can report errors against the source.
+
+use crate::error;
+use crate::Result;
+use proc_macro2::Span;
+use snafu::ResultExt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use syn::spanned::Spanned;
+use syn::{Attribute, Expr, Lit, Meta};
+
+/// A line of the docs, and where it came from.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DocLine {
+    pub(crate) text: String,
+    pub(crate) file: PathBuf,
+    pub(crate) line: usize,
+}
+
+// A `doc` attribute's text. Text from comments and string literals is unindented together, as
+// rustdoc does, while included files are kept as they are.
+struct Fragment {
+    lines: Vec<DocLine>,
+    included: bool,
+}
+
+/// Returns the crate-level docs of `source`, which was read from `rust_file`.
+pub(crate) fn crate_docs(rust_file: &Path, source: &str) -> Result<Vec<DocLine>> {
+    let file = syn::parse_file(source).map_err(|e| {
+        let (line, column) = location(e.span());
+        error::DocParseSnafu {
+            file: rust_file,
+            line,
+            column,
+            message: e.to_string(),
+        }
+        .build()
+    })?;
+
+    let mut fragments = Vec::new();
+    for attr in &file.attrs {
+        for meta in doc_metas(attr) {
+            if let Some(fragment) = fragment(rust_file, &meta)? {
+                fragments.push(fragment);
+            }
+        }
+    }
+
+    Ok(unindent(fragments))
+}
+
+/// Returns the line and column, counted from 1, where `span` starts.
+pub(crate) fn location(span: Span) -> (usize, usize) {
+    let start = span.start();
+    (start.line, start.column + 1)
+}
+
+// The `doc` metas of an attribute, including the ones of `cfg_attr` whose condition holds when
+// building docs.
+fn doc_metas(attr: &Attribute) -> Vec<Meta> {
+    if attr.path().is_ident("doc") {
+        return vec![attr.meta.clone()];
+    }
+    if !attr.path().is_ident("cfg_attr") {
+        return Vec::new();
+    }
+
+    let Ok(nested) =
+        attr.parse_args_with(syn::punctuated::Punctuated::<Meta, syn::Token![,]>::parse_terminated)
+    else {
+        return Vec::new();
+    };
+    let mut nested = nested.into_iter();
+    match nested.next() {
+        Some(predicate) if cfg_holds(&predicate) => {
+            nested.filter(|meta| meta.path().is_ident("doc")).collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+// Evaluates a `cfg` predicate as rustdoc would see it for this crate: `doc` is set, and features
+// and other options are not.
+fn cfg_holds(predicate: &Meta) -> bool {
+    match predicate {
+        Meta::Path(path) => path.is_ident("doc"),
+        Meta::NameValue(_) => false,
+        Meta::List(list) => {
+            let Ok(nested) = list.parse_args_with(
+                syn::punctuated::Punctuated::<Meta, syn::Token![,]>::parse_terminated,
+            ) else {
+                return false;
+            };
+            if list.path.is_ident("all") {
+                nested.iter().all(cfg_holds)
+            } else if list.path.is_ident("any") {
+                nested.iter().any(cfg_holds)
+            } else if list.path.is_ident("not") {
+                !nested.iter().any(cfg_holds)
+            } else {
+                false
+            }
+        }
+    }
+}
+
+// Reads the text of a `doc = ...` meta. Other forms, such as `doc(hidden)`, have no text.
+fn fragment(rust_file: &Path, meta: &Meta) -> Result<Option<Fragment>> {
+    let Meta::NameValue(meta) = meta else {
+        return Ok(None);
+    };
+    let (line, _) = location(meta.value.span());
+
+    match &meta.value {
+        Expr::Lit(expr) => match &expr.lit {
+            Lit::Str(lit) => Ok(Some(Fragment {
+                lines: lines(&lit.value(), rust_file, line),
+                included: false,
+            })),
+            _ => error::DocUnsupportedSnafu {
+                file: rust_file,
+                line,
+            }
+            .fail(),
+        },
+        Expr::Macro(expr) if expr.mac.path.is_ident("include_str") => {
+            let argument: Expr = expr.mac.parse_body().map_err(|e| {
+                let (line, column) = location(e.span());
+                error::DocParseSnafu {
+                    file: rust_file,
+                    line,
+                    column,
+                    message: e.to_string(),
+                }
+                .build()
+            })?;
+            let path = string_value(&argument).ok_or_else(|| {
+                error::DocUnsupportedSnafu {
+                    file: rust_file,
+                    line,
+                }
+                .build()
+            })?;
+            // Like `include_str!`, relative paths are relative to the including file.
+            let path = rust_file
+                .parent()
+                .unwrap_or_else(|| Path::new("."))
+                .join(path);
+            let content = fs::read_to_string(&path).context(error::DocIncludeSnafu {
+                file: rust_file,
+                line,
+                path: &path,
+            })?;
+            Ok(Some(Fragment {
+                lines: lines(content.strip_suffix('\n').unwrap_or(&content), &path, 1),
+                included: true,
+            }))
+        }
+        _ => error::DocUnsupportedSnafu {
+            file: rust_file,
+            line,
+        }
+        .fail(),
+    }
+}
+
+// The value of a string expression: a literal, `concat!` of string expressions, or
+// `env!("CARGO_MANIFEST_DIR")`.
+fn string_value(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Lit(expr) => match &expr.lit {
+            Lit::Str(lit) => Some(lit.value()),
+            _ => None,
+        },
+        Expr::Macro(expr) if expr.mac.path.is_ident("concat") => {
+            let parts = expr
+                .mac
+                .parse_body_with(
+                    syn::punctuated::Punctuated::<Expr, syn::Token![,]>::parse_terminated,
+                )
+                .ok()?;
+            parts.iter().map(string_value).collect()
+        }
+        Expr::Macro(expr) if expr.mac.path.is_ident("env") => {
+            let name: syn::LitStr = expr.mac.parse_body().ok()?;
+            // Only the crate's own directory is known outside of the compiler.
+            (name.value() == "CARGO_MANIFEST_DIR")
+                .then(|| std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_string()))
+        }
+        _ => None,
+    }
+}
+
+// Splits `text` into lines. An empty `//!` comment is an empty line, so unlike `str::lines`, an
+// empty text has one line.
+fn lines(text: &str, file: &Path, first_line: usize) -> Vec<DocLine> {
+    text.split('\n')
+        .enumerate()
+        .map(|(index, text)| DocLine {
+            text: text.trim_end().to_string(),
+            file: file.to_path_buf(),
+            line: first_line + index,
+        })
+        .collect()
+}
+
+// Removes the indentation that all the comment lines share, such as the space after `//!`, and
+// the blank lines around the docs.
+fn unindent(fragments: Vec<Fragment>) -> Vec<DocLine> {
+    let indent = fragments
+        .iter()
+        .filter(|fragment| !fragment.included)
+        .flat_map(|fragment| &fragment.lines)
+        .filter(|line| !line.text.trim().is_empty())
+        .map(|line| line.text.len() - line.text.trim_start().len())
+        .min()
+        .unwrap_or_default();
+
+    let mut lines: Vec<DocLine> = fragments
+        .into_iter()
+        .flat_map(|fragment| {
+            let included = fragment.included;
+            fragment.lines.into_iter().map(move |mut line| {
+                if !included {
+                    line.text = line.text.get(indent..).unwrap_or_default().to_string();
+                }
+                line
+            })
+        })
+        .collect();
+
+    while lines.first().is_some_and(|line| line.text.is_empty()) {
+        lines.remove(0);
+    }
+    while lines.last().is_some_and(|line| line.text.is_empty()) {
+        lines.pop();
+    }
+    lines
+}
+
+/// Prepares the docs for a README: headings are indented by one level if `indent_headings` is set,
+/// Rust code blocks are marked as such and lose their hidden `# ` lines, and `text` code blocks
+/// lose their language, which GitHub doesn't know.
+pub(crate) fn process(lines: Vec<DocLine>, indent_headings: bool) -> Vec<DocLine> {
+    let mut fence: Option<(String, bool)> = None;
+    let mut processed = Vec::new();
+    for mut line in lines {
+        match &fence {
+            Some((delimiter, rust)) => {
+                if line.text == *delimiter {
+                    fence = None;
+                } else if *rust && (line.text.starts_with("# ") || line.text == "#") {
+                    continue;
+                }
+            }
+            None => {
+                if let Some((delimiter, info)) = fence_start(&line.text) {
+                    let (delimiter, info) = (delimiter.to_string(), info.to_string());
+                    let rust = is_rust(&info);
+                    if rust {
+                        line.text = format!("{}rust", delimiter);
+                    } else if info == "text" {
+                        line.text = delimiter.clone();
+                    }
+                    fence = Some((delimiter, rust));
+                } else if indent_headings && line.text.starts_with('#') {
+                    line.text.insert(0, '#');
+                }
+            }
+        }
+        processed.push(line);
+    }
+    processed
+}
+
+/// Returns the delimiter and info string of a line that opens a fenced code block.
+pub(crate) fn fence_start(line: &str) -> Option<(&str, &str)> {
+    let delimiter_len = line.chars().take_while(|c| *c == '`' || *c == '~').count();
+    if delimiter_len < 3
+        || !line[..delimiter_len]
+            .chars()
+            .all(|c| c == line.as_bytes()[0] as char)
+    {
+        return None;
+    }
+    Some((&line[..delimiter_len], line[delimiter_len..].trim()))
+}
+
+// Code blocks without a language are Rust, as are the ones with rustdoc's attributes.
+fn is_rust(info: &str) -> bool {
+    info.split(',').map(str::trim).all(|attribute| {
+        matches!(
+            attribute,
+            "" | "rust"
+                | "no_run"
+                | "ignore"
+                | "should_panic"
+                | "compile_fail"
+                | "edition2018"
+                | "edition2021"
+        )
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn texts(lines: &[DocLine]) -> Vec<&str> {
+        lines.iter().map(|line| line.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_crate_docs() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let rust_file = temp_dir.path().join("lib.rs");
+        fs::write(temp_dir.path().join("extra.md"), "  Included, as is.\n").unwrap();
+        let source = r#"
+//! # Title
+//!
+//!     indented code
+#![doc = include_str!("extra.md")]
+#![cfg_attr(doc, doc = " From cfg_attr.")]
+#![cfg_attr(feature = "x", doc = " Not built.")]
+#![cfg_attr(not(any(doc, test)), doc = " Not built either.")]
+#![doc(html_root_url = "https://example.com")]
+
+/// Not a crate doc.
+pub fn f() {}
+"#;
+        let lines = crate_docs(&rust_file, source).unwrap();
+        assert_eq!(
+            texts(&lines),
+            vec![
+                "# Title",
+                "",
+                "    indented code",
+                "  Included, as is.",
+                "From cfg_attr."
+            ]
+        );
+        assert_eq!(lines[2].line, 4);
+        assert_eq!(lines[3].file, temp_dir.path().join("extra.md"));
+        assert_eq!(lines[3].line, 1);
+    }
+
+    #[test]
+    fn test_crate_docs_block_comment() {
+        let source = "/*!\n# Title\nText\n*/\n\nfn main() {}\n";
+        let lines = crate_docs(Path::new("src/main.rs"), source).unwrap();
+        assert_eq!(texts(&lines), vec!["# Title", "Text"]);
+        assert_eq!(lines[0].line, 2);
+    }
+
+    #[test]
+    fn test_crate_docs_errors() {
+        let error = crate_docs(Path::new("src/lib.rs"), "//! Docs\nfn main() {").unwrap_err();
+        assert!(error.to_string().starts_with("src/lib.rs:2:"), "{}", error);
+
+        let error = crate_docs(
+            Path::new("src/lib.rs"),
+            "//! Docs\n#![doc = include_str!(\"missing.md\")]\n",
+        )
+        .unwrap_err();
+        assert!(error.to_string().starts_with("src/lib.rs:2:"), "{}", error);
+    }
+
+    #[test]
+    fn test_process() {
+        let lines = lines(
+            "# Title\n```\nlet x = 1;\n# let hidden = 2;\n```\n```text\n# shown\n```\n```toml\n# shown\n```",
+            Path::new("src/lib.rs"),
+            1,
+        );
+        let processed = process(lines, true);
+        assert_eq!(
+            texts(&processed),
+            vec![
+                "## Title",
+                "```rust",
+                "let x = 1;",
+                "```",
+                "```",
+                "# shown",
+                "```",
+                "```toml",
+                "# shown",
+                "```"
+            ]
+        );
+    }
+}
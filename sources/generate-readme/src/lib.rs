@@ -1,13 +1,85 @@
 /*!
 This small lib is used to generate README files for the crates in the `sources` workspace. These
 functions are called in a crate's build.rs file to generate a README from Rust doc comments.
+
+The crate-level docs are read the way rustdoc reads them, by parsing the source file: `//!` and
+`/*! */` comments, `#![doc = "..."]` and `#![doc = include_str!("...")]` attributes, also behind
+`#![cfg_attr(doc, ...)]`. They replace the `{{readme}}` placeholder of `README.tpl`, alongside
+`{{crate}}`, `{{version}}` and `{{license}}` from `Cargo.toml`. Intra-doc links such as
+`[module::Type]` are rewritten to links that work in the README: to docs.rs for published crates
+and dependencies, and to the source file of the item for crates that aren't published. Links that
+resolve to nothing fail the build, with the file and line of the link.
+
+In check mode, the README is rendered in memory and compared with `README.md` instead of being
+written, and the build fails with a unified diff if they differ. This lets CI enforce that the
+READMEs in the source tree are current. Check mode is selected by setting the `CHECK_README`
+environment variable, or by calling one of the `*_checked` functions.
+
+If `README.tpl` contains a `{{cli}}` placeholder, it is replaced with a command-line reference
+rendered from the `argh::FromArgs` definitions in the source file: the usage, options, defaults
+and descriptions of the command and each of its subcommands. The command is named after the
+crate.
+
+A crate can also document its config type. It names the type, and the JSON Schema file to write,
+in its `Cargo.toml`:
+
+```toml
+[package.metadata.generate-readme]
+schema-type = "other_crate::config::Config"
+schema-file = "my-crate.schema.json"
+```
+
+The type is read from the `serde::Deserialize` definitions in the crate's source, or in the
+source of a sibling crate in the workspace. A `{{schema}}` placeholder in `README.tpl` is replaced
+with a table of its keys, types, defaults and docs, and the JSON Schema is written next to
+`README.md`, so that editors and tools can validate config files. Check mode checks the JSON
+Schema too.
+
+With `man-section` in the same table, the README is also rendered as a roff man page named after
+the crate, such as `my-crate.8`, next to `README.md`, so that the docs are available on hosts
+without network access. The NAME line uses the `description` of the package, or the first sentence
+of the docs.
+
+In workspace mode, the READMEs of all the crates of a workspace are generated at once, and so is
+the workspace's own `README.md`: an index of its crates, with a one-line summary of each from the
+first paragraph of its docs. The docs are read from `src/lib.rs`, or `src/main.rs` for a binary.
+A crate that has both and documents the binary names the file in its `Cargo.toml`, like its
+`build.rs` does:
+
+```toml
+[package.metadata.generate-readme]
+docs-file = "src/main.rs"
+```
+
+The `generate-readme` binary runs it, from the `sources` directory:
+
+```shell
+cargo run -p generate-readme -- .
+cargo run -p generate-readme -- --check .
+```
 !*/
 
-use snafu::ResultExt;
-use std::fs::File;
-use std::io::Write;
+use serde::Deserialize;
+use snafu::{ensure, OptionExt, ResultExt};
+use std::fs::{self, File};
+use std::io::{ErrorKind, Read, Write};
 use std::path::{Path, PathBuf};
 
+mod cli;
+mod diff;
+mod docs;
+mod links;
+mod man;
+mod schema;
+mod workspace;
+
+// Replaced in `README.tpl` by the command-line reference.
+const CLI_PLACEHOLDER: &str = "{{cli}}";
+// Replaced in `README.tpl` by the table of config keys.
+const SCHEMA_PLACEHOLDER: &str = "{{schema}}";
+// Replaced in `README.tpl` by the crate-level docs.
+const README_PLACEHOLDER: &str = "{{readme}}";
+
 pub type Result<T> = std::result::Result<T, error::Error>;
 
 pub mod error {
@@ -17,11 +89,66 @@ pub mod error {
     #[derive(Debug, Snafu)]
     #[snafu(visibility(pub(super)))]
     pub enum Error {
+        #[snafu(display(
+            "No argh command found in '{}' for the {{{{cli}}}} placeholder",
+            file.display()
+        ))]
+        CliMissing { file: PathBuf },
+
+        #[snafu(display("Unable to parse '{}': {}", file.display(), source))]
+        CliParse { file: PathBuf, source: syn::Error },
+
+        #[snafu(display("{}:{}: unable to read '{}': {}", file.display(), line, path.display(), source))]
+        DocInclude {
+            file: PathBuf,
+            line: usize,
+            path: PathBuf,
+            source: std::io::Error,
+        },
+
+        #[snafu(display("{}:{}: unresolved intra-doc link to '{}'", file.display(), line, link))]
+        DocLink {
+            file: PathBuf,
+            line: usize,
+            link: String,
+        },
+
+        #[snafu(display("{}:{}:{}: unable to parse: {}", file.display(), line, column, message))]
+        DocParse {
+            file: PathBuf,
+            line: usize,
+            column: usize,
+            message: String,
+        },
+
+        #[snafu(display(
+            "{}:{}: unsupported doc attribute, only string literals and include_str! are read",
+            file.display(),
+            line
+        ))]
+        DocUnsupported { file: PathBuf, line: usize },
+
         #[snafu(display("Unable to create the 'README.md' file: {}", source))]
         ReadmeCreate { source: std::io::Error },
 
-        #[snafu(display("Unable to generate the 'README.md' file contents: {}", error))]
-        ReadmeGenerate { error: String },
+        #[snafu(display("Unable to read 'Cargo.toml': {}", source))]
+        ManifestRead { source: std::io::Error },
+
+        #[snafu(display("Unable to parse 'Cargo.toml': {}", source))]
+        ManifestParse { source: toml::de::Error },
+
+        #[snafu(display(
+            "'{}' is out of date, rebuild without CHECK_README to update it:\n{}",
+            file.display(),
+            diff
+        ))]
+        ReadmeOutdated { file: PathBuf, diff: String },
+
+        #[snafu(display("Unable to read '{}': {}", file.display(), source))]
+        ReadmeRead {
+            file: PathBuf,
+            source: std::io::Error,
+        },
 
         #[snafu(display("Unable to open '{}': {}", file.display(), source))]
         ReadmeSourceOpen {
@@ -32,66 +159,482 @@ pub mod error {
         #[snafu(display("Unable to open 'README.tpl': {}", source))]
         ReadmeTemplateOpen { source: std::io::Error },
 
+        #[snafu(display("Unable to read '{}': {}", file.display(), source))]
+        ReadmeSourceRead {
+            file: PathBuf,
+            source: std::io::Error,
+        },
+
+        #[snafu(display("Unable to write '{}': {}", file.display(), source))]
+        ReadmeOutputWrite {
+            file: PathBuf,
+            source: std::io::Error,
+        },
+
         #[snafu(display("Unable to write to the 'README.md' file: {}", source))]
         ReadmeWrite { source: std::io::Error },
+
+        #[snafu(display(
+            "A {{{{schema}}}} placeholder needs 'schema-type' in [package.metadata.generate-readme]"
+        ))]
+        SchemaMissing,
+
+        #[snafu(display("Unable to parse '{}': {}", file.display(), source))]
+        SchemaParse { file: PathBuf, source: syn::Error },
+
+        #[snafu(display("Unable to read '{}': {}", file.display(), source))]
+        SchemaSourceRead {
+            file: PathBuf,
+            source: std::io::Error,
+        },
+
+        #[snafu(display(
+            "Unable to find the Deserialize type '{}' in '{}'",
+            type_path,
+            crate_dir.display()
+        ))]
+        SchemaType {
+            type_path: String,
+            crate_dir: PathBuf,
+        },
+
+        #[snafu(display("'README.tpl' uses {{{{license}}}}, but 'Cargo.toml' has no license"))]
+        TemplateLicense,
+
+        #[snafu(display("'README.tpl' has no {{{{readme}}}} placeholder"))]
+        TemplateReadme,
+
+        #[snafu(display("Unable to change to the directory '{}': {}", dir.display(), source))]
+        WorkspaceDir {
+            dir: PathBuf,
+            source: std::io::Error,
+        },
+
+        #[snafu(display("In '{}': {}", member.display(), source))]
+        WorkspaceMember {
+            member: PathBuf,
+            #[snafu(source(from(Error, Box::new)))]
+            source: Box<Error>,
+        },
+
+        #[snafu(display("Unable to parse '{}': {}", file.display(), source))]
+        WorkspaceParse {
+            file: PathBuf,
+            source: toml::de::Error,
+        },
+
+        #[snafu(display("Unable to read '{}': {}", file.display(), source))]
+        WorkspaceRead {
+            file: PathBuf,
+            source: std::io::Error,
+        },
+
+        #[snafu(display("No 'src/lib.rs' or 'src/main.rs' in '{}'", member.display()))]
+        WorkspaceSource { member: PathBuf },
+
+        #[snafu(display("'{}' has no {{{{crates}}}} placeholder", file.display()))]
+        WorkspaceTemplate { file: PathBuf },
     }
 }
 
 /// When this function is called in a `build.rs` file, it will generate a `README.md` (as a sibling
-/// to `build.rs`). It uses the doc comments found in `src/main.rs` and the `cargo-readme` crate to
-/// do so. The template for `cargo-readme` is expected to be `README.tpl` as a sibling file to
-/// `build.rs`.
+/// to `build.rs`). It uses the doc comments found in `src/main.rs` to do so. The template is
+/// expected to be `README.tpl` as a sibling file to `build.rs`.
 pub fn from_main() -> Result<()> {
     from_file("src/main.rs")
 }
 
 /// When this function is called in a `build.rs` file, it will generate a `README.md` (as a sibling
-/// to `build.rs`). It uses the doc comments found in `src/lib.rs` and the `cargo-readme` crate to
-/// do so. The template for `cargo-readme` is expected to be `README.tpl` as a sibling file to
-/// `build.rs`.
+/// to `build.rs`). It uses the doc comments found in `src/lib.rs` to do so. The template is
+/// expected to be `README.tpl` as a sibling file to `build.rs`.
 pub fn from_lib() -> Result<()> {
     from_file("src/lib.rs")
 }
 
 /// When this function is called in a `build.rs` file, it will generate a `README.md` (as a sibling
-/// to `build.rs`). It uses the doc comments found in `rust_file` and the `cargo-readme` crate to do
-/// so. The template for `cargo-readme` is expected to be `README.tpl` as a sibling file to
-/// `build.rs`.
+/// to `build.rs`). It uses the doc comments found in `rust_file` to do so. The template is expected
+/// to be `README.tpl` as a sibling file to `build.rs`.
+///
+/// If the `CHECK_README` environment variable is set, the `README.md` is checked instead of
+/// written, like [`from_file_checked`] does.
 pub fn from_file<P>(rust_file: P) -> Result<()>
 where
     P: AsRef<Path>,
 {
+    if std::env::var_os("OUT_DIR").is_some() {
+        print_rerun_directives(&mut std::io::stdout());
+    }
     // Check for environment variable "SKIP_README". If it is set,
     // skip README generation
     if std::env::var_os("SKIP_README").is_some() {
         return Ok(());
     }
+    if std::env::var_os("CHECK_README").is_some() {
+        return from_file_checked(rust_file);
+    }
 
-    let mut source = File::open(rust_file.as_ref()).context(error::ReadmeSourceOpenSnafu {
-        file: rust_file.as_ref(),
+    write_generated(generate(rust_file.as_ref())?)
+}
+
+/// Like [`from_main`], but fails if `README.md` differs from the generated README, instead of
+/// writing it.
+pub fn from_main_checked() -> Result<()> {
+    from_file_checked("src/main.rs")
+}
+
+/// Like [`from_lib`], but fails if `README.md` differs from the generated README, instead of
+/// writing it.
+pub fn from_lib_checked() -> Result<()> {
+    from_file_checked("src/lib.rs")
+}
+
+/// Like [`from_file`], but fails if `README.md`, the JSON Schema or the man page differ from the
+/// generated ones, instead of writing them. The error holds a unified diff from the file to the
+/// generated one.
+pub fn from_file_checked<P>(rust_file: P) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    check_generated(generate(rust_file.as_ref())?)
+}
+
+/// Generates the README of every crate in the Cargo workspace in `dir` that has a `README.tpl`,
+/// like a `build.rs` calling [`from_file`] would, and the `README.md` of the workspace itself.
+///
+/// The workspace's `README.md` is rendered from its `README.tpl`, whose `{{crates}}` placeholder
+/// is replaced with a table of the member crates: a link to each crate's README, and the first
+/// paragraph of its docs as a summary. The docs are read from the `docs-file` in the crate's
+/// `[package.metadata.generate-readme]`, which has to match the file that its `build.rs` passes to
+/// generate-readme, or else from `src/lib.rs`, or from `src/main.rs` if the crate has no library.
+///
+/// Every crate is generated from its own directory, as in its build script, so this changes the
+/// working directory of the process while it runs.
+pub fn from_workspace<P>(dir: P) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    workspace(dir.as_ref(), false)
+}
+
+/// Like [`from_workspace`], but fails if a README differs from the generated one, instead of
+/// writing it.
+pub fn from_workspace_checked<P>(dir: P) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    workspace(dir.as_ref(), true)
+}
+
+fn workspace(dir: &Path, check: bool) -> Result<()> {
+    let mut crates = Vec::new();
+    for member in workspace::members(dir)? {
+        let krate = in_dir(&member, || member_readme(check))
+            .context(error::WorkspaceMemberSnafu { member: &member })?;
+        crates.push(krate);
+    }
+
+    let template_file = dir.join("README.tpl");
+    let template = fs::read_to_string(&template_file).context(error::ReadmeReadSnafu {
+        file: &template_file,
     })?;
-    let mut template = File::open("README.tpl").context(error::ReadmeTemplateOpenSnafu)?;
-
-    let mut content = cargo_readme::generate_readme(
-        &PathBuf::from("."), // root
-        &mut source,         // source
-        Some(&mut template), // template
-        // The "add x" arguments don't apply when using a template.
-        true,  // add title
-        false, // add badges
-        false, // add license
-        true,  // indent headings
-    )
-    .map_err(|e| error::ReadmeGenerateSnafu { error: e }.build())?;
+    let readme = workspace::index(&template_file, &template, &crates)?;
+    let readme_file = dir.join("README.md");
+    if check {
+        check_file(&readme_file, &readme)
+    } else {
+        fs::write(&readme_file, readme)
+            .context(error::ReadmeOutputWriteSnafu { file: &readme_file })
+    }
+}
 
-    // Make sure the end of the file has a newline
-    if content.chars().last().unwrap_or_default() != '\n' {
-        content += "\n";
+// Generates or checks the README of the crate in the current directory, and returns its row in the
+// workspace index. The link is relative to the workspace, which is the parent directory.
+fn member_readme(check: bool) -> Result<workspace::Crate> {
+    let current_dir = std::env::current_dir().context(error::WorkspaceDirSnafu { dir: "." })?;
+    let manifest = fs::read_to_string("Cargo.toml").context(error::ManifestReadSnafu)?;
+    let manifest = toml::from_str::<Manifest>(&manifest).context(error::ManifestParseSnafu)?;
+    let docs_file = manifest
+        .package
+        .metadata
+        .generate_readme
+        .docs_file
+        .as_deref();
+    let rust_file =
+        workspace::crate_root(Path::new(""), docs_file).context(error::WorkspaceSourceSnafu {
+            member: &current_dir,
+        })?;
+    if Path::new("README.tpl").is_file() {
+        let generated = generate(&rust_file)?;
+        if check {
+            check_generated(generated)?;
+        } else {
+            write_generated(generated)?;
+        }
     }
 
+    let source = fs::read_to_string(&rust_file)
+        .context(error::ReadmeSourceReadSnafu { file: &rust_file })?;
+    let dir_name = current_dir
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let link = if Path::new("README.md").is_file() {
+        format!("{}/README.md", dir_name)
+    } else {
+        dir_name
+    };
+    Ok(workspace::Crate {
+        name: manifest.package.name,
+        link,
+        summary: workspace::summary(&docs::crate_docs(&rust_file, &source)?),
+    })
+}
+
+// Runs `f` with `dir` as the working directory.
+fn in_dir<T, F>(dir: &Path, f: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T>,
+{
+    let previous = std::env::current_dir().context(error::WorkspaceDirSnafu { dir: "." })?;
+    std::env::set_current_dir(dir).context(error::WorkspaceDirSnafu { dir })?;
+    let result = f();
+    std::env::set_current_dir(&previous).context(error::WorkspaceDirSnafu { dir: &previous })?;
+    result
+}
+
+fn write_generated(generated: Generated) -> Result<()> {
     let mut readme = File::create("README.md").context(error::ReadmeCreateSnafu)?;
     readme
-        .write_all(content.as_bytes())
+        .write_all(generated.readme.as_bytes())
         .context(error::ReadmeWriteSnafu)?;
+    for (file, content) in generated.files {
+        fs::write(&file, content).context(error::ReadmeOutputWriteSnafu { file: &file })?;
+    }
+    Ok(())
+}
+
+fn check_generated(generated: Generated) -> Result<()> {
+    check_file(Path::new("README.md"), &generated.readme)?;
+    for (file, content) in generated.files {
+        check_file(&file, &content)?;
+    }
     Ok(())
 }
+
+// Fails with a diff if `file` doesn't hold `content`.
+fn check_file(file: &Path, content: &str) -> Result<()> {
+    let current = match fs::read_to_string(file) {
+        Ok(current) => current,
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).context(error::ReadmeReadSnafu { file }),
+    };
+
+    let name = file.display().to_string();
+    let diff = diff::unified_diff(&current, content, &name, &format!("{} (generated)", name));
+    ensure!(diff.is_empty(), error::ReadmeOutdatedSnafu { file, diff });
+    Ok(())
+}
+
+// The parts of `Cargo.toml` that generate-readme reads.
+#[derive(Deserialize)]
+struct Manifest {
+    package: Package,
+    #[serde(default)]
+    dependencies: toml::Table,
+}
+
+#[derive(Deserialize)]
+struct Package {
+    name: String,
+    // A string, or a table when inherited from the workspace.
+    version: Option<toml::Value>,
+    description: Option<String>,
+    license: Option<String>,
+    // `false`, or the registries the crate is published to.
+    publish: Option<toml::Value>,
+    #[serde(default)]
+    metadata: PackageMetadata,
+}
+
+#[derive(Default, Deserialize)]
+struct PackageMetadata {
+    #[serde(default, rename = "generate-readme")]
+    generate_readme: Metadata,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Metadata {
+    docs_file: Option<PathBuf>,
+    schema_type: Option<String>,
+    schema_file: Option<PathBuf>,
+    man_section: Option<u8>,
+}
+
+// The generated README, and the path and content of the other generated files, such as the JSON
+// Schema and the man page.
+struct Generated {
+    readme: String,
+    files: Vec<(PathBuf, String)>,
+}
+
+// Renders the README from the doc comments in `rust_file` and `README.tpl`, and the files that go
+// with it.
+fn generate(rust_file: &Path) -> Result<Generated> {
+    let mut source = String::new();
+    File::open(rust_file)
+        .context(error::ReadmeSourceOpenSnafu { file: rust_file })?
+        .read_to_string(&mut source)
+        .context(error::ReadmeSourceReadSnafu { file: rust_file })?;
+    let mut template = fs::read_to_string("README.tpl").context(error::ReadmeTemplateOpenSnafu)?;
+    let manifest = fs::read_to_string("Cargo.toml").context(error::ManifestReadSnafu)?;
+    let manifest = toml::from_str::<Manifest>(&manifest).context(error::ManifestParseSnafu)?;
+    let package = manifest.package;
+    let version = package
+        .version
+        .as_ref()
+        .and_then(toml::Value::as_str)
+        .unwrap_or_default();
+    let metadata = &package.metadata.generate_readme;
+
+    if template.contains(CLI_PLACEHOLDER) {
+        let reference = cli::reference(rust_file, &source, &package.name)?;
+        template = template.replace(CLI_PLACEHOLDER, reference.trim_end());
+    }
+
+    let mut files = Vec::new();
+    if template.contains(SCHEMA_PLACEHOLDER) || metadata.schema_type.is_some() {
+        let type_path = metadata
+            .schema_type
+            .as_deref()
+            .context(error::SchemaMissingSnafu)?;
+        let crate_dir = schema_crate_dir(type_path, &package.name);
+        let schema = schema::render(&crate_dir, type_path)?;
+        template = template.replace(SCHEMA_PLACEHOLDER, schema.markdown.trim_end());
+        if let Some(file) = &metadata.schema_file {
+            files.push((file.clone(), schema.json));
+        }
+    }
+
+    let dependencies: Vec<String> = manifest.dependencies.keys().cloned().collect();
+    let context = links::Context {
+        name: &package.name,
+        version,
+        published: !matches!(package.publish, Some(toml::Value::Boolean(false))),
+        dependencies: &dependencies,
+    };
+    let lines = docs::crate_docs(rust_file, &source)?;
+    let index = links::ItemIndex::read(rust_file, &source)?;
+    let lines = links::rewrite(lines, &context, &index)?;
+    let readme = docs::process(lines, true)
+        .into_iter()
+        .map(|line| line.text)
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut content = render_template(&template, &package, version, &readme)?;
+
+    // Make sure the end of the file has a newline
+    if content.chars().last().unwrap_or_default() != '\n' {
+        content += "\n";
+    }
+
+    if let Some(section) = metadata.man_section {
+        let page = man::Page {
+            name: &package.name,
+            section,
+            version,
+            summary: package.description.as_deref().unwrap_or_default(),
+        };
+        let file = PathBuf::from(format!("{}.{}", package.name, section));
+        files.push((file, man::render(&page, &content)));
+    }
+
+    Ok(Generated {
+        readme: content,
+        files,
+    })
+}
+
+// Fills in the placeholders of `README.tpl`.
+fn render_template(
+    template: &str,
+    package: &Package,
+    version: &str,
+    readme: &str,
+) -> Result<String> {
+    let template = template.trim_end_matches('\n');
+    ensure!(
+        template.contains(README_PLACEHOLDER),
+        error::TemplateReadmeSnafu
+    );
+
+    let mut content = template.replace("{{crate}}", &package.name);
+    if content.contains("{{license}}") {
+        let license = package
+            .license
+            .as_deref()
+            .context(error::TemplateLicenseSnafu)?;
+        content = content.replace("{{license}}", license);
+    }
+    content = content.replace("{{version}}", version);
+    // The docs go in last, so that placeholders in them are left alone.
+    Ok(content.replace(README_PLACEHOLDER, readme))
+}
+
+// The package's own files, which Cargo has to be told about once any directive is given.
+const PACKAGE_FILES: [&str; 4] = ["src", "build.rs", "Cargo.toml", "README.tpl"];
+// The environment variables that select what `from_file` does.
+const MODE_VARS: [&str; 2] = ["CHECK_README", "SKIP_README"];
+
+// Tells Cargo to rerun the build script when the mode changes, such as when CI sets CHECK_README
+// on a tree that was already built. Otherwise the build script is only rerun for changed files.
+fn print_rerun_directives<W>(out: &mut W)
+where
+    W: Write,
+{
+    for var in MODE_VARS {
+        let _ = writeln!(out, "cargo:rerun-if-env-changed={}", var);
+    }
+    for path in PACKAGE_FILES {
+        let _ = writeln!(out, "cargo:rerun-if-changed={}", path);
+    }
+}
+
+// Returns the directory of the crate that defines the type at `type_path`. Types of other crates
+// are read from the sibling directory of the crate in the workspace.
+fn schema_crate_dir(type_path: &str, package_name: &str) -> PathBuf {
+    let krate = type_path.split("::").next().unwrap_or_default();
+    if krate == "crate" || krate == package_name.replace('-', "_") {
+        return PathBuf::from(".");
+    }
+
+    let crate_dir = Path::new("..").join(krate.replace('_', "-"));
+    // Cargo only reruns a build script for changes in its own package, unless told otherwise.
+    // Once any path is given, the package's own files have to be listed as well.
+    if std::env::var_os("OUT_DIR").is_some() {
+        for path in PACKAGE_FILES {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+        println!("cargo:rerun-if-changed={}", crate_dir.join("src").display());
+    }
+    crate_dir
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rerun_directives() {
+        let mut out = Vec::new();
+        print_rerun_directives(&mut out);
+        let directives = String::from_utf8(out).unwrap();
+        let directives: Vec<_> = directives.lines().collect();
+
+        assert!(directives.contains(&"cargo:rerun-if-env-changed=CHECK_README"));
+        assert!(directives.contains(&"cargo:rerun-if-env-changed=SKIP_README"));
+        // Without these, Cargo would stop rerunning the build script for the package's files.
+        assert!(directives.contains(&"cargo:rerun-if-changed=src"));
+        assert!(directives.contains(&"cargo:rerun-if-changed=build.rs"));
+    }
+}
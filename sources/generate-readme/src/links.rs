@@ -0,0 +1,642 @@
+//! Resolves rustdoc's intra-doc links, such as `[module::Type]`, for a README.
+//!
+//! Links to the standard library point to doc.rust-lang.org, and links to dependencies to docs.rs.
+//! Links to the crate's own items point to docs.rs if the crate is published, and otherwise to the
+//! source file that defines the item, relative to the README. A link that matches a heading of the
+//! docs, like `[Example]`, points to its anchor. Links that look like a Rust path but resolve to
+//! nothing are errors, as with `rustdoc -D rustdoc::broken_intra_doc_links`.
+
+use crate::cli::anchor;
+use crate::docs::{self, DocLine};
+use crate::error;
+use crate::Result;
+use snafu::{ensure, ResultExt};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use syn::{ImplItem, Item, TraitItem, Type};
+
+/// What links are resolved against.
+pub(crate) struct Context<'a> {
+    /// The package name, such as `my-crate`.
+    pub(crate) name: &'a str,
+    pub(crate) version: &'a str,
+    /// Whether the crate is on crates.io, and so has docs on docs.rs.
+    pub(crate) published: bool,
+    /// The package names of the dependencies.
+    pub(crate) dependencies: &'a [String],
+}
+
+// An item of the crate, and the file of the module that defines it.
+#[derive(Debug, Clone)]
+struct ItemInfo {
+    kind: &'static str,
+    file: PathBuf,
+    // Methods, variants, fields and associated items, by name, with the kind used in rustdoc's
+    // anchors.
+    members: HashMap<String, &'static str>,
+}
+
+/// The items of a crate, by module path.
+#[derive(Debug, Default)]
+pub(crate) struct ItemIndex {
+    modules: HashMap<Vec<String>, HashMap<String, ItemInfo>>,
+}
+
+impl ItemIndex {
+    /// Reads the modules of the crate whose root is `root_file`, following `mod` declarations.
+    pub(crate) fn read(root_file: &Path, source: &str) -> Result<Self> {
+        let mut index = ItemIndex::default();
+        let file = parse(root_file, source)?;
+        let dir = root_file.parent().unwrap_or_else(|| Path::new("."));
+        index.add_items(&file.items, Vec::new(), root_file, dir)?;
+        Ok(index)
+    }
+
+    fn add_items(
+        &mut self,
+        items: &[Item],
+        module: Vec<String>,
+        file: &Path,
+        dir: &Path,
+    ) -> Result<()> {
+        let mut entries: HashMap<String, ItemInfo> = HashMap::new();
+        let mut impls: Vec<(String, HashMap<String, &'static str>)> = Vec::new();
+        let info = |kind, members| ItemInfo {
+            kind,
+            file: file.to_path_buf(),
+            members,
+        };
+
+        for item in items {
+            match item {
+                Item::Struct(item) => {
+                    let fields = item
+                        .fields
+                        .iter()
+                        .filter_map(|field| field.ident.as_ref())
+                        .map(|ident| (ident.to_string(), "structfield"))
+                        .collect();
+                    entries.insert(item.ident.to_string(), info("struct", fields));
+                }
+                Item::Enum(item) => {
+                    let variants = item
+                        .variants
+                        .iter()
+                        .map(|variant| (variant.ident.to_string(), "variant"))
+                        .collect();
+                    entries.insert(item.ident.to_string(), info("enum", variants));
+                }
+                Item::Union(item) => {
+                    entries.insert(item.ident.to_string(), info("union", HashMap::new()));
+                }
+                Item::Trait(item) => {
+                    let members = item
+                        .items
+                        .iter()
+                        .filter_map(|member| match member {
+                            TraitItem::Fn(f) if f.default.is_some() => {
+                                Some((f.sig.ident.to_string(), "method"))
+                            }
+                            TraitItem::Fn(f) => Some((f.sig.ident.to_string(), "tymethod")),
+                            TraitItem::Const(c) => {
+                                Some((c.ident.to_string(), "associatedconstant"))
+                            }
+                            TraitItem::Type(t) => Some((t.ident.to_string(), "associatedtype")),
+                            _ => None,
+                        })
+                        .collect();
+                    entries.insert(item.ident.to_string(), info("trait", members));
+                }
+                Item::Fn(item) => {
+                    entries.insert(item.sig.ident.to_string(), info("fn", HashMap::new()));
+                }
+                Item::Type(item) => {
+                    entries.insert(item.ident.to_string(), info("type", HashMap::new()));
+                }
+                Item::Const(item) => {
+                    entries.insert(item.ident.to_string(), info("constant", HashMap::new()));
+                }
+                Item::Static(item) => {
+                    entries.insert(item.ident.to_string(), info("static", HashMap::new()));
+                }
+                Item::Macro(item) => {
+                    if let Some(ident) = &item.ident {
+                        entries.insert(ident.to_string(), info("macro", HashMap::new()));
+                    }
+                }
+                Item::Impl(item) => {
+                    let Type::Path(self_type) = &*item.self_ty else {
+                        continue;
+                    };
+                    let Some(segment) = self_type.path.segments.last() else {
+                        continue;
+                    };
+                    let members = item
+                        .items
+                        .iter()
+                        .filter_map(|member| match member {
+                            ImplItem::Fn(f) => Some((f.sig.ident.to_string(), "method")),
+                            ImplItem::Const(c) => Some((c.ident.to_string(), "associatedconstant")),
+                            _ => None,
+                        })
+                        .collect();
+                    impls.push((segment.ident.to_string(), members));
+                }
+                Item::Mod(item) => {
+                    let mut child = module.clone();
+                    child.push(item.ident.to_string());
+                    let name = item.ident.to_string();
+                    if let Some((_, items)) = &item.content {
+                        entries.insert(name.clone(), info("mod", HashMap::new()));
+                        self.add_items(items, child, file, &dir.join(&name))?;
+                    } else if let Some(child_file) = module_file(dir, &name) {
+                        let source =
+                            fs::read_to_string(&child_file).context(error::DocIncludeSnafu {
+                                file,
+                                line: docs::location(item.ident.span()).0,
+                                path: &child_file,
+                            })?;
+                        let parsed = parse(&child_file, &source)?;
+                        entries.insert(
+                            name.clone(),
+                            ItemInfo {
+                                kind: "mod",
+                                file: child_file.clone(),
+                                members: HashMap::new(),
+                            },
+                        );
+                        self.add_items(&parsed.items, child, &child_file, &dir.join(&name))?;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for (name, members) in impls {
+            if let Some(entry) = entries.get_mut(&name) {
+                entry.members.extend(members);
+            }
+        }
+        self.modules.entry(module).or_default().extend(entries);
+        Ok(())
+    }
+
+    fn get(&self, module: &[String], name: &str) -> Option<&ItemInfo> {
+        self.modules.get(module).and_then(|items| items.get(name))
+    }
+}
+
+fn parse(file: &Path, source: &str) -> Result<syn::File> {
+    syn::parse_file(source).map_err(|e| {
+        let (line, column) = docs::location(e.span());
+        error::DocParseSnafu {
+            file,
+            line,
+            column,
+            message: e.to_string(),
+        }
+        .build()
+    })
+}
+
+// Finds the file of `mod name;` declared in a module whose children live in `dir`.
+fn module_file(dir: &Path, name: &str) -> Option<PathBuf> {
+    [
+        dir.join(format!("{}.rs", name)),
+        dir.join(name).join("mod.rs"),
+    ]
+    .into_iter()
+    .find(|path| path.is_file())
+}
+
+/// Replaces the intra-doc links in `lines` with Markdown links.
+pub(crate) fn rewrite(
+    lines: Vec<DocLine>,
+    context: &Context,
+    index: &ItemIndex,
+) -> Result<Vec<DocLine>> {
+    let mut headings = HashSet::new();
+    let mut labels = HashSet::new();
+    for_each_prose_line(&lines, |line| {
+        let text = line.text.trim_start();
+        if let Some(heading) = text.strip_prefix('#') {
+            headings.insert(anchor(heading.trim_start_matches('#').trim()));
+        } else if let Some((label, _)) = definition(text) {
+            labels.insert(label.to_lowercase());
+        }
+    });
+    let resolver = Resolver {
+        context,
+        index,
+        headings,
+        labels,
+    };
+
+    let mut rewritten = Vec::with_capacity(lines.len());
+    let mut fence: Option<String> = None;
+    for mut line in lines {
+        match &fence {
+            Some(delimiter) => {
+                if line.text.trim() == delimiter {
+                    fence = None;
+                }
+            }
+            None => {
+                if let Some((delimiter, _)) = docs::fence_start(line.text.trim_start()) {
+                    fence = Some(delimiter.to_string());
+                } else {
+                    line.text = resolver.rewrite_line(&line)?;
+                }
+            }
+        }
+        rewritten.push(line);
+    }
+
+    Ok(rewritten)
+}
+
+// Calls `f` with every line that is outside of a code block.
+fn for_each_prose_line<F>(lines: &[DocLine], mut f: F)
+where
+    F: FnMut(&DocLine),
+{
+    let mut fence: Option<&str> = None;
+    for line in lines {
+        match fence {
+            Some(delimiter) if line.text.trim() == delimiter => fence = None,
+            Some(_) => {}
+            None => match docs::fence_start(line.text.trim_start()) {
+                Some((delimiter, _)) => fence = Some(delimiter),
+                None => f(line),
+            },
+        }
+    }
+}
+
+// Parses a reference definition, `[label]: target`.
+fn definition(text: &str) -> Option<(&str, &str)> {
+    let rest = text.strip_prefix('[')?;
+    let (label, target) = rest.split_once("]:")?;
+    Some((label, target.trim()))
+}
+
+struct Resolver<'a> {
+    context: &'a Context<'a>,
+    index: &'a ItemIndex,
+    headings: HashSet<String>,
+    labels: HashSet<String>,
+}
+
+impl Resolver<'_> {
+    fn rewrite_line(&self, line: &DocLine) -> Result<String> {
+        let text = &line.text;
+
+        // A reference definition with an intra-doc target.
+        if let Some((label, target)) = definition(text.trim_start()) {
+            if let Some(url) = self.url(line, target)? {
+                let indent = &text[..text.len() - text.trim_start().len()];
+                return Ok(format!("{}[{}]: {}", indent, label, url));
+            }
+            return Ok(text.clone());
+        }
+
+        let mut output = String::new();
+        let mut rest = text.as_str();
+        while let Some(c) = rest.chars().next() {
+            match c {
+                '\\' => {
+                    let escaped = rest.chars().nth(1).map(char::len_utf8).unwrap_or_default();
+                    output.push_str(&rest[..1 + escaped]);
+                    rest = &rest[1 + escaped..];
+                }
+                '`' => {
+                    let span = code_span(rest);
+                    output.push_str(&rest[..span]);
+                    rest = &rest[span..];
+                }
+                '[' => match closing_bracket(rest) {
+                    Some(close) => {
+                        let link_text = &rest[1..close];
+                        let after = &rest[close + 1..];
+                        let consumed = self.rewrite_link(line, link_text, after, &mut output)?;
+                        rest = &rest[close + 1 + consumed..];
+                    }
+                    None => {
+                        output.push('[');
+                        rest = &rest[1..];
+                    }
+                },
+                c => {
+                    output.push(c);
+                    rest = &rest[c.len_utf8()..];
+                }
+            }
+        }
+
+        Ok(output)
+    }
+
+    // Writes the link whose text is `link_text`, and returns how much of `after` it used.
+    fn rewrite_link(
+        &self,
+        line: &DocLine,
+        link_text: &str,
+        after: &str,
+        output: &mut String,
+    ) -> Result<usize> {
+        // An inline link, `[text](target)`.
+        if let Some(rest) = after.strip_prefix('(') {
+            if let Some(end) = rest.find(')') {
+                let target = &rest[..end];
+                let url = self.url(line, target)?;
+                let _ = std::fmt::Write::write_fmt(
+                    output,
+                    format_args!("[{}]({})", link_text, url.as_deref().unwrap_or(target)),
+                );
+                return Ok(end + 2);
+            }
+        }
+
+        // A reference link, `[text][target]`, whose target isn't a defined label.
+        if let Some(rest) = after.strip_prefix('[') {
+            if let Some(end) = rest.find(']') {
+                let target = &rest[..end];
+                let label = if target.is_empty() { link_text } else { target };
+                if !self.labels.contains(&label.to_lowercase()) {
+                    if let Some(url) = self.url(line, label)? {
+                        let _ = std::fmt::Write::write_fmt(
+                            output,
+                            format_args!("[{}]({})", link_text, url),
+                        );
+                        return Ok(end + 2);
+                    }
+                }
+                output.push_str(&format!("[{}][{}]", link_text, target));
+                return Ok(end + 2);
+            }
+        }
+
+        // A shortcut link, `[target]`.
+        if !self.labels.contains(&link_text.to_lowercase()) {
+            if let Some(url) = self.url(line, link_text)? {
+                output.push_str(&format!("[{}]({})", link_text, url));
+                return Ok(0);
+            }
+        }
+        output.push('[');
+        output.push_str(link_text);
+        output.push(']');
+        Ok(0)
+    }
+
+    // Returns the URL for an intra-doc link target, or `None` if the target is a URL or a path
+    // that isn't Rust. Fails if the target looks like a Rust path but resolves to nothing.
+    fn url(&self, line: &DocLine, target: &str) -> Result<Option<String>> {
+        let target = target.trim();
+        let Some(segments) = rust_path(target) else {
+            return Ok(None);
+        };
+
+        let url = self.item_url(&segments).or_else(|| {
+            let heading = anchor(target.trim_matches('`'));
+            self.headings
+                .contains(&heading)
+                .then(|| format!("#{}", heading))
+        });
+        ensure!(
+            url.is_some(),
+            error::DocLinkSnafu {
+                file: &line.file,
+                line: line.line,
+                link: target,
+            }
+        );
+        Ok(url)
+    }
+
+    fn item_url(&self, segments: &[String]) -> Option<String> {
+        let (first, rest) = segments.split_first()?;
+        match first.as_str() {
+            "std" | "core" | "alloc" => {
+                return Some(format!(
+                    "https://doc.rust-lang.org/stable/{}/index.html?search={}",
+                    first,
+                    segments.join("::")
+                ));
+            }
+            _ => {}
+        }
+        if let Some(dependency) = self
+            .context
+            .dependencies
+            .iter()
+            .find(|dependency| dependency.replace('-', "_") == *first)
+        {
+            let mut url = format!("https://docs.rs/{}/latest/{}/", dependency, first);
+            if !rest.is_empty() {
+                url.push_str(&format!("?search={}", rest.join("::")));
+            }
+            return Some(url);
+        }
+
+        let segments = match first.as_str() {
+            "crate" | "self" => rest,
+            _ => segments,
+        };
+        let (name, module) = segments.split_last()?;
+        if let Some(item) = self.index.get(module, name) {
+            return Some(self.own_item_url(module, name, item, None));
+        }
+        // A member of a type, like `module::Type::method`.
+        let (type_name, module) = module.split_last()?;
+        let item = self.index.get(module, type_name)?;
+        let member_kind = item.members.get(name)?;
+        Some(self.own_item_url(module, type_name, item, Some((member_kind, name))))
+    }
+
+    fn own_item_url(
+        &self,
+        module: &[String],
+        name: &str,
+        item: &ItemInfo,
+        member: Option<(&str, &str)>,
+    ) -> String {
+        if !self.context.published {
+            // There are no docs online, so point at the source in the repository.
+            return item.file.display().to_string();
+        }
+
+        let mut url = format!(
+            "https://docs.rs/{}/{}/{}/",
+            self.context.name,
+            self.context.version,
+            self.context.name.replace('-', "_")
+        );
+        for module in module {
+            url.push_str(module);
+            url.push('/');
+        }
+        if item.kind == "mod" {
+            url.push_str(&format!("{}/index.html", name));
+        } else {
+            url.push_str(&format!("{}.{}.html", item.kind, name));
+        }
+        if let Some((kind, member)) = member {
+            url.push_str(&format!("#{}.{}", kind, member));
+        }
+        url
+    }
+}
+
+// Returns the segments of a link target that looks like a Rust path, like `module::Type`,
+// `` `Type::method()` ``, `struct@Type` or `macro!`.
+fn rust_path(target: &str) -> Option<Vec<String>> {
+    let target = target.trim_matches('`');
+    let target = match target.split_once('@') {
+        Some((disambiguator, path)) if disambiguator.chars().all(|c| c.is_ascii_lowercase()) => {
+            path
+        }
+        _ => target,
+    };
+    let target = target
+        .strip_suffix("()")
+        .or_else(|| target.strip_suffix('!'))
+        .unwrap_or(target);
+
+    let segments: Vec<String> = target.split("::").map(str::to_string).collect();
+    let is_identifier = |segment: &String| {
+        segment
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_alphabetic() || c == '_')
+            && segment.chars().all(|c| c.is_alphanumeric() || c == '_')
+    };
+    segments.iter().all(is_identifier).then_some(segments)
+}
+
+// The length of the code span at the start of `text`, or of its opening backticks if it isn't
+// closed.
+pub(crate) fn code_span(text: &str) -> usize {
+    let ticks = text.chars().take_while(|c| *c == '`').count();
+    let delimiter = &text[..ticks];
+    match text[ticks..].find(delimiter) {
+        Some(end) => ticks + end + ticks,
+        None => ticks,
+    }
+}
+
+// The position of the `]` that closes the `[` at the start of `text`, skipping code spans.
+pub(crate) fn closing_bracket(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut position = 0;
+    while position < text.len() {
+        let rest = &text[position..];
+        let c = rest.chars().next()?;
+        match c {
+            '`' => {
+                position += code_span(rest);
+                continue;
+            }
+            '\\' => position += 1,
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(position);
+                }
+            }
+            _ => {}
+        }
+        position += c.len_utf8();
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SOURCE: &str = r#"
+//! Docs.
+
+pub mod gpu {
+    pub struct MigGpu { pub index: u32 }
+    impl MigGpu {
+        pub fn reset(&self) {}
+    }
+    pub enum State { Enabled, Disabled }
+    pub fn get_gpu_info() {}
+}
+"#;
+
+    fn rewrite_text(text: &str, published: bool) -> Result<String> {
+        let index = ItemIndex::read(Path::new("src/lib.rs"), SOURCE).unwrap();
+        let dependencies = vec!["serde-json".to_string()];
+        let context = Context {
+            name: "my-crate",
+            version: "0.1.0",
+            published,
+            dependencies: &dependencies,
+        };
+        let lines = text
+            .lines()
+            .enumerate()
+            .map(|(index, text)| DocLine {
+                text: text.to_string(),
+                file: PathBuf::from("src/lib.rs"),
+                line: index + 1,
+            })
+            .collect();
+        let lines = super::rewrite(lines, &context, &index)?;
+        Ok(lines
+            .into_iter()
+            .map(|line| line.text)
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+
+    #[test]
+    fn test_rewrite_published() {
+        let text = "\
+# Usage
+See [`gpu::MigGpu`], [`gpu::MigGpu::reset()`] and [the enum](gpu::State::Enabled).
+[`gpu`] has [`serde_json::Value`] and [`std::fs::read`], [Usage] and `[not a link]`.
+```
+[`not::a::link`]
+```
+[get]: gpu::get_gpu_info";
+        let expected = "\
+# Usage
+See [`gpu::MigGpu`](https://docs.rs/my-crate/0.1.0/my_crate/gpu/struct.MigGpu.html), \
+[`gpu::MigGpu::reset()`](https://docs.rs/my-crate/0.1.0/my_crate/gpu/struct.MigGpu.html#method.reset) \
+and [the enum](https://docs.rs/my-crate/0.1.0/my_crate/gpu/enum.State.html#variant.Enabled).
+[`gpu`](https://docs.rs/my-crate/0.1.0/my_crate/gpu/index.html) has \
+[`serde_json::Value`](https://docs.rs/serde-json/latest/serde_json/?search=Value) and \
+[`std::fs::read`](https://doc.rust-lang.org/stable/std/index.html?search=std::fs::read), \
+[Usage](#usage) and `[not a link]`.
+```
+[`not::a::link`]
+```
+[get]: https://docs.rs/my-crate/0.1.0/my_crate/gpu/fn.get_gpu_info.html";
+        assert_eq!(rewrite_text(text, true).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_rewrite_unpublished() {
+        assert_eq!(
+            rewrite_text(
+                "[`gpu::get_gpu_info`] and [docs](https://example.com)",
+                false
+            )
+            .unwrap(),
+            "[`gpu::get_gpu_info`](src/lib.rs) and [docs](https://example.com)"
+        );
+
+        let error = rewrite_text("Text\nSee [`gpu::Missing`].", false).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "src/lib.rs:2: unresolved intra-doc link to '`gpu::Missing`'"
+        );
+    }
+}
@@ -0,0 +1,33 @@
+/*!
+Generates the READMEs of the crates in a Cargo workspace, and the index README of the workspace.
+See [`generate_readme::from_workspace`].
+!*/
+
+use argh::FromArgs;
+use std::path::PathBuf;
+use std::process;
+
+/// Generates the READMEs of a Cargo workspace and its crates
+#[derive(FromArgs, PartialEq, Debug)]
+struct Args {
+    /// fail if a README differs from the generated one, instead of writing it
+    #[argh(switch)]
+    check: bool,
+
+    /// the directory of the workspace
+    #[argh(positional, default = "PathBuf::from(\".\")")]
+    workspace: PathBuf,
+}
+
+fn main() {
+    let args: Args = argh::from_env();
+    let result = if args.check {
+        generate_readme::from_workspace_checked(&args.workspace)
+    } else {
+        generate_readme::from_workspace(&args.workspace)
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,361 @@
+//! Renders the generated README as a roff man page, for hosts that can't reach the READMEs online.
+//!
+//! The Markdown that the README uses is converted: headings become sections, fenced code blocks
+//! are shown as is, tables are laid out with `tbl`, and bullet lists become indented paragraphs.
+//! Links keep their text, followed by the target if it isn't an anchor in the same document.
+
+use std::fmt::Write;
+
+/// What goes into the header and the NAME section of the man page.
+pub(crate) struct Page<'a> {
+    pub(crate) name: &'a str,
+    pub(crate) section: u8,
+    pub(crate) version: &'a str,
+    /// A one-line summary for the NAME section. The first sentence of the README is used if empty.
+    pub(crate) summary: &'a str,
+}
+
+/// Returns the man page for `readme`. The first top-level heading of the README, which names the
+/// crate, is replaced by the man page header.
+pub(crate) fn render(page: &Page, readme: &str) -> String {
+    let blocks = blocks(readme);
+    let summary = if page.summary.is_empty() {
+        first_sentence(&blocks)
+    } else {
+        page.summary.to_string()
+    };
+
+    // The first line tells `man` to run the page through `tbl`, for the tables.
+    let mut output = String::from("'\\\" t\n");
+    let _ = writeln!(
+        output,
+        ".TH \"{}\" \"{}\" \"\" \"{} {}\"",
+        escape(&page.name.to_uppercase()),
+        page.section,
+        escape(page.name),
+        escape(page.version)
+    );
+    let _ = writeln!(
+        output,
+        ".SH NAME\n{} \\- {}",
+        escape(page.name),
+        inline(&summary)
+    );
+
+    let mut title_seen = false;
+    for block in &blocks {
+        match block {
+            Block::Heading(1, _) if !title_seen => title_seen = true,
+            Block::Heading(level, text) if *level <= 2 => {
+                let _ = writeln!(output, ".SH \"{}\"", inline(&text.to_uppercase()));
+            }
+            Block::Heading(_, text) => {
+                let _ = writeln!(output, ".SS \"{}\"", inline(text));
+            }
+            Block::Paragraph(lines) => {
+                output.push_str(".PP\n");
+                for line in lines {
+                    output.push_str(&protect(&inline(line)));
+                    output.push('\n');
+                }
+            }
+            Block::Code(lines) => {
+                output.push_str(".PP\n.RS 4\n.nf\n");
+                for line in lines {
+                    output.push_str(&protect(&escape(line)));
+                    output.push('\n');
+                }
+                output.push_str(".fi\n.RE\n");
+            }
+            Block::Item(lines) => {
+                output.push_str(".IP \\(bu 2\n");
+                for line in lines {
+                    output.push_str(&protect(&inline(line)));
+                    output.push('\n');
+                }
+            }
+            Block::Table(rows) => table(rows, &mut output),
+        }
+    }
+
+    output
+}
+
+#[derive(Debug, PartialEq)]
+enum Block {
+    Heading(usize, String),
+    Paragraph(Vec<String>),
+    Code(Vec<String>),
+    Item(Vec<String>),
+    Table(Vec<Vec<String>>),
+}
+
+// Splits the Markdown into blocks.
+fn blocks(markdown: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut lines = markdown.lines().peekable();
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+
+        if trimmed.starts_with("```") {
+            let mut code = Vec::new();
+            for line in lines.by_ref() {
+                if line.trim_start().starts_with("```") {
+                    break;
+                }
+                code.push(line.to_string());
+            }
+            blocks.push(Block::Code(code));
+        } else if let Some((level, text)) = heading(trimmed) {
+            blocks.push(Block::Heading(level, text.to_string()));
+        } else if trimmed.starts_with('|') {
+            let mut rows = vec![cells(trimmed)];
+            while let Some(line) = lines.next_if(|line| line.trim_start().starts_with('|')) {
+                let row = cells(line.trim());
+                // Skip the line between the header and the body.
+                if !row
+                    .iter()
+                    .all(|cell| !cell.is_empty() && cell.chars().all(|c| c == '-' || c == ':'))
+                {
+                    rows.push(row);
+                }
+            }
+            blocks.push(Block::Table(rows));
+        } else if let Some(item) = trimmed
+            .strip_prefix("* ")
+            .or_else(|| trimmed.strip_prefix("- "))
+        {
+            let mut item = vec![item.to_string()];
+            while let Some(line) =
+                lines.next_if(|line| line.starts_with(' ') && !line.trim().is_empty())
+            {
+                item.push(line.trim().to_string());
+            }
+            blocks.push(Block::Item(item));
+        } else {
+            let mut paragraph = vec![trimmed.to_string()];
+            while let Some(line) = lines.next_if(|line| {
+                let trimmed = line.trim();
+                !trimmed.is_empty()
+                    && !trimmed.starts_with("```")
+                    && !trimmed.starts_with('|')
+                    && !trimmed.starts_with("* ")
+                    && !trimmed.starts_with("- ")
+                    && heading(trimmed).is_none()
+            }) {
+                paragraph.push(line.trim().to_string());
+            }
+            blocks.push(Block::Paragraph(paragraph));
+        }
+    }
+
+    blocks
+}
+
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    let text = line[level..].strip_prefix(' ')?;
+    (1..=6).contains(&level).then(|| (level, text.trim()))
+}
+
+// The cells of a table row, with escaped pipes restored.
+fn cells(row: &str) -> Vec<String> {
+    let row = row.trim_start_matches('|');
+    let row = row.strip_suffix('|').unwrap_or(row);
+    let mut cells = vec![String::new()];
+    let mut chars = row.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'|') => {
+                chars.next();
+                if let Some(cell) = cells.last_mut() {
+                    cell.push('|');
+                }
+            }
+            '|' => cells.push(String::new()),
+            c => {
+                if let Some(cell) = cells.last_mut() {
+                    cell.push(c);
+                }
+            }
+        }
+    }
+    cells.iter().map(|cell| cell.trim().to_string()).collect()
+}
+
+// Lays out a table with `tbl`, with a bold header row. Cells are text blocks, so that long cells
+// wrap.
+fn table(rows: &[Vec<String>], output: &mut String) {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or_default();
+    if columns == 0 {
+        return;
+    }
+    output.push_str(".PP\n.TS\nallbox tab(\t);\n");
+    let format = |style: &str| vec![style; columns].join(" ");
+    let _ = writeln!(output, "{}\n{}.", format("lb"), format("lx"));
+    for row in rows {
+        let cells: Vec<_> = (0..columns)
+            .map(|column| {
+                let cell = row.get(column).map(String::as_str).unwrap_or_default();
+                format!("T{{\n{}\nT}}", protect(&inline(cell)))
+            })
+            .collect();
+        output.push_str(&cells.join("\t"));
+        output.push('\n');
+    }
+    output.push_str(".TE\n");
+}
+
+// Converts the inline Markdown of a line: code spans become bold, emphasis italic, and links keep
+// their text.
+fn inline(text: &str) -> String {
+    let mut output = String::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if c == '`' {
+            if let Some(end) = rest[1..].find('`') {
+                let _ = write!(output, "\\fB{}\\fR", escape(&rest[1..=end]));
+                rest = &rest[end + 2..];
+                continue;
+            }
+        } else if let Some(after) = rest.strip_prefix("**") {
+            if let Some(end) = after.find("**") {
+                let _ = write!(output, "\\fB{}\\fR", inline(&after[..end]));
+                rest = &after[end + 2..];
+                continue;
+            }
+        } else if c == '[' {
+            if let Some((link_text, target, len)) = link(rest) {
+                output.push_str(&inline(link_text));
+                // Anchors and intra-doc links only make sense in the README.
+                if target.contains("://") {
+                    let _ = write!(output, " <{}>", escape(target));
+                }
+                rest = &rest[len..];
+                continue;
+            }
+        }
+        output.push_str(&escape(&c.to_string()));
+        rest = &rest[c.len_utf8()..];
+    }
+    output
+}
+
+// Parses `[text](target)` at the start of `text`, returning the text, the target and the length of
+// the link.
+fn link(text: &str) -> Option<(&str, &str, usize)> {
+    let close = text.find("](")?;
+    let end = text[close..].find(')')? + close;
+    Some((&text[1..close], &text[close + 2..end], end + 1))
+}
+
+// Escapes the characters that roff would interpret.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\e").replace('-', "\\-")
+}
+
+// Keeps lines that start with a period or an apostrophe from being read as requests.
+fn protect(line: &str) -> String {
+    if line.starts_with('.') || line.starts_with('\'') {
+        format!("\\&{}", line)
+    } else {
+        line.to_string()
+    }
+}
+
+// The first sentence of the docs, without Markdown. Paragraphs before the first section come from
+// the template, such as the version, and are skipped.
+fn first_sentence(blocks: &[Block]) -> String {
+    let paragraph = blocks
+        .iter()
+        .skip_while(|block| !matches!(block, Block::Heading(level, _) if *level >= 2))
+        .find_map(|block| match block {
+            Block::Paragraph(lines) => Some(lines.join(" ")),
+            _ => None,
+        })
+        .unwrap_or_default();
+    let sentence = paragraph
+        .split_once(". ")
+        .map(|(sentence, _)| sentence)
+        .unwrap_or(&paragraph)
+        .trim_end_matches('.');
+    sentence.replace('`', "")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let readme = "\
+# tool
+
+Current version: 0.1.0
+
+## Tool
+`tool` configures the widgets. It runs at boot.
+
+### Options
+* **Fast**: see [`run`](#run) and
+  [the docs](https://example.com/docs).
+
+```shell
+tool --fast
+.hidden
+```
+
+| Option | Description |
+| --- | --- |
+| `--level` | trace\\|debug |
+";
+        let page = Page {
+            name: "tool",
+            section: 8,
+            version: "0.1.0",
+            summary: "",
+        };
+        let expected = "\
+'\\\" t
+.TH \"TOOL\" \"8\" \"\" \"tool 0.1.0\"
+.SH NAME
+tool \\- tool configures the widgets
+.PP
+Current version: 0.1.0
+.SH \"TOOL\"
+.PP
+\\fBtool\\fR configures the widgets. It runs at boot.
+.SS \"Options\"
+.IP \\(bu 2
+\\fBFast\\fR: see \\fBrun\\fR and
+the docs <https://example.com/docs>.
+.PP
+.RS 4
+.nf
+tool \\-\\-fast
+\\&.hidden
+.fi
+.RE
+.PP
+.TS
+allbox tab(\t);
+lb lb
+lx lx.
+T{
+Option
+T}\tT{
+Description
+T}
+T{
+\\fB\\-\\-level\\fR
+T}\tT{
+trace|debug
+T}
+.TE
+";
+        assert_eq!(render(&page, readme), expected);
+    }
+}
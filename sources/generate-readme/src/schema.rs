@@ -0,0 +1,921 @@
+//! Describes a config type from the `serde::Deserialize` definitions in a crate's source, as a
+//! Markdown table of keys for the README and as a JSON Schema.
+//!
+//! Like the command-line reference, the description is read from the source, since a build script
+//! runs before the crate is compiled. Field names follow `rename` and `rename_all`, fields with
+//! `default` or an `Option` type are optional, `skip` fields are left out, and types with `from` or
+//! `try_from` are described by the type they are converted from. Types are looked up in the same
+//! module, then through `use` declarations, then anywhere in the crate.
+
+use crate::cli::{doc_text, escape_cell};
+use crate::error;
+use crate::Result;
+use serde_json::{json, Map, Value};
+use snafu::{OptionExt, ResultExt};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use syn::{Attribute, Fields, GenericArgument, Item, PathArguments, Type, UseTree};
+
+const JSON_SCHEMA_DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// The rendered descriptions of a config type.
+#[derive(Debug)]
+pub(crate) struct Schema {
+    /// The Markdown table of keys.
+    pub(crate) markdown: String,
+    /// The JSON Schema, pretty-printed.
+    pub(crate) json: String,
+}
+
+// The shape of a value in the config.
+#[derive(Debug, Clone, PartialEq)]
+enum Shape {
+    Any,
+    String,
+    Integer { unsigned: bool },
+    Number,
+    Boolean,
+    Array(Box<Shape>),
+    Map(Box<Shape>),
+    Named(String),
+}
+
+// A named type in the config.
+#[derive(Debug, Clone, PartialEq)]
+struct Def {
+    description: String,
+    kind: DefKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum DefKind {
+    Object {
+        fields: Vec<Field>,
+        deny_unknown_fields: bool,
+    },
+    /// An enum of unit variants, given as one of the strings.
+    Strings(Vec<String>),
+    /// Any of the shapes, as for untagged enums.
+    AnyOf(Vec<Shape>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Field {
+    key: String,
+    shape: Shape,
+    description: String,
+    required: bool,
+    default: Option<Value>,
+}
+
+// A `Deserialize` type found in the source, and the module that defines it.
+struct SourceType {
+    module: Vec<String>,
+    item: Item,
+}
+
+// The `Deserialize` types of a crate, and the imports of each of its modules.
+#[derive(Default)]
+struct Source {
+    types: Vec<SourceType>,
+    imports: HashMap<Vec<String>, HashMap<String, Vec<String>>>,
+}
+
+/// Describes the type at `type_path`, such as `other_crate::config::Config`. The type is read from
+/// the crate at `crate_dir`.
+pub(crate) fn render(crate_dir: &Path, type_path: &str) -> Result<Schema> {
+    let mut source = Source::default();
+    read_module(&crate_dir.join("src"), &mut source)?;
+
+    let name = type_path.rsplit("::").next().unwrap_or(type_path);
+    let module: Vec<_> = type_path.split("::").skip(1).map(str::to_string).collect();
+    let module = &module[..module.len().saturating_sub(1)];
+
+    let mut defs = Vec::new();
+    let root = resolve(&source, module, name, &mut defs).context(error::SchemaTypeSnafu {
+        type_path,
+        crate_dir,
+    })?;
+
+    Ok(Schema {
+        markdown: markdown(&root, &defs),
+        json: json_schema(name, &root, &defs),
+    })
+}
+
+// Reads the `Deserialize` types and imports of the crate whose sources are in `src`.
+fn read_module(src: &Path, source: &mut Source) -> Result<()> {
+    let mut files = Vec::new();
+    find_rust_files(src, &mut files)?;
+    files.sort();
+    for file in files {
+        let relative = file.strip_prefix(src).unwrap_or(&file);
+        let mut module: Vec<_> = relative
+            .with_extension("")
+            .iter()
+            .map(|part| part.to_string_lossy().into_owned())
+            .collect();
+        if matches!(
+            module.last().map(String::as_str),
+            Some("lib") | Some("main") | Some("mod")
+        ) {
+            module.pop();
+        }
+
+        let content =
+            fs::read_to_string(&file).context(error::SchemaSourceReadSnafu { file: &file })?;
+        let parsed = syn::parse_file(&content).context(error::SchemaParseSnafu { file: &file })?;
+        collect_items(&parsed.items, module, source);
+    }
+
+    Ok(())
+}
+
+fn find_rust_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let entries = fs::read_dir(dir).context(error::SchemaSourceReadSnafu { file: dir })?;
+    for entry in entries {
+        let path = entry
+            .context(error::SchemaSourceReadSnafu { file: dir })?
+            .path();
+        if path.is_dir() {
+            find_rust_files(&path, files)?;
+        } else if path.extension().is_some_and(|extension| extension == "rs") {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+fn collect_items(items: &[Item], module: Vec<String>, source: &mut Source) {
+    for item in items {
+        match item {
+            Item::Struct(syn::ItemStruct { attrs, .. })
+            | Item::Enum(syn::ItemEnum { attrs, .. })
+                if derives_deserialize(attrs) =>
+            {
+                source.types.push(SourceType {
+                    module: module.clone(),
+                    item: item.clone(),
+                });
+            }
+            Item::Use(item) => {
+                let mut imports = Vec::new();
+                use_paths(&item.tree, Vec::new(), &mut imports);
+                let module_imports = source.imports.entry(module.clone()).or_default();
+                for path in imports {
+                    if let Some(name) = path.last() {
+                        module_imports.insert(name.clone(), path);
+                    }
+                }
+            }
+            Item::Mod(item) => {
+                if let Some((_, items)) = &item.content {
+                    let mut module = module.clone();
+                    module.push(item.ident.to_string());
+                    collect_items(items, module, source);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn derives_deserialize(attrs: &[Attribute]) -> bool {
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("derive"))
+        .any(|attr| {
+            let mut found = false;
+            let _ = attr.parse_nested_meta(|meta| {
+                if meta.path.segments.last().map(|s| s.ident == "Deserialize") == Some(true) {
+                    found = true;
+                }
+                Ok(())
+            });
+            found
+        })
+}
+
+// Flattens a `use` tree into the full path of every imported item.
+fn use_paths(tree: &UseTree, prefix: Vec<String>, imports: &mut Vec<Vec<String>>) {
+    match tree {
+        UseTree::Path(path) => {
+            let mut prefix = prefix;
+            prefix.push(path.ident.to_string());
+            use_paths(&path.tree, prefix, imports);
+        }
+        UseTree::Name(name) => {
+            let mut path = prefix;
+            path.push(name.ident.to_string());
+            imports.push(path);
+        }
+        UseTree::Group(group) => {
+            for tree in &group.items {
+                use_paths(tree, prefix.clone(), imports);
+            }
+        }
+        UseTree::Rename(_) | UseTree::Glob(_) => {}
+    }
+}
+
+// Finds the type `name` as seen from `module`, adds it and the types it uses to `defs`, and
+// returns its shape. Returns `None` if the type isn't defined in the crate.
+fn resolve(
+    source: &Source,
+    module: &[String],
+    name: &str,
+    defs: &mut Vec<(String, Def)>,
+) -> Option<Shape> {
+    let imported = source
+        .imports
+        .get(module)
+        .and_then(|imports| imports.get(name))
+        .and_then(|path| match path.first().map(String::as_str) {
+            Some("crate") => Some(path[1..path.len() - 1].to_vec()),
+            Some("self") => Some([module, &path[1..path.len() - 1]].concat()),
+            _ => None,
+        });
+    let source_type = [Some(module.to_vec()), imported]
+        .iter()
+        .flatten()
+        .find_map(|module| {
+            source.types.iter().find(|source_type| {
+                &source_type.module == module && ident(&source_type.item) == name
+            })
+        })
+        .or_else(|| {
+            source
+                .types
+                .iter()
+                .find(|source_type| ident(&source_type.item) == name)
+        })?;
+
+    if defs.iter().any(|(def_name, _)| def_name == name) {
+        return Some(Shape::Named(name.to_string()));
+    }
+
+    // Types converted with `from` or `try_from` are described by their source type, under their
+    // own name and docs.
+    if let Some(from) = serde_container_value(attrs(&source_type.item), &["from", "try_from"]) {
+        let from_type: Type = syn::parse_str(&from).ok()?;
+        let known = defs.len();
+        let from_shape = shape(source, &source_type.module, &from_type, defs);
+        let Shape::Named(from_name) = &from_shape else {
+            return Some(from_shape);
+        };
+        let from_def = find_def(defs, from_name)?.clone();
+        // The source type is usually private, so it's left out unless something else uses it.
+        if let Some(position) = defs.iter().position(|(def_name, _)| def_name == from_name) {
+            if position >= known {
+                defs.remove(position);
+            }
+        }
+        let description = doc_text(attrs(&source_type.item));
+        defs.push((
+            name.to_string(),
+            Def {
+                description: if description.is_empty() {
+                    from_def.description
+                } else {
+                    description
+                },
+                kind: from_def.kind,
+            },
+        ));
+        return Some(Shape::Named(name.to_string()));
+    }
+
+    // Add a placeholder first, so that recursive types terminate.
+    defs.push((
+        name.to_string(),
+        Def {
+            description: String::new(),
+            kind: DefKind::AnyOf(Vec::new()),
+        },
+    ));
+    let def = def(source, source_type, defs);
+    if let Some(entry) = defs.iter_mut().find(|(def_name, _)| def_name == name) {
+        entry.1 = def;
+    }
+
+    Some(Shape::Named(name.to_string()))
+}
+
+fn ident(item: &Item) -> String {
+    match item {
+        Item::Struct(item) => item.ident.to_string(),
+        Item::Enum(item) => item.ident.to_string(),
+        _ => String::new(),
+    }
+}
+
+fn attrs(item: &Item) -> &[Attribute] {
+    match item {
+        Item::Struct(item) => &item.attrs,
+        Item::Enum(item) => &item.attrs,
+        _ => &[],
+    }
+}
+
+fn def(source: &Source, source_type: &SourceType, defs: &mut Vec<(String, Def)>) -> Def {
+    let module = &source_type.module;
+    let container_attrs = attrs(&source_type.item);
+    let rename_all = serde_container_value(container_attrs, &["rename_all"]);
+    let description = doc_text(container_attrs);
+
+    let kind = match &source_type.item {
+        Item::Struct(item) => {
+            let container_default = has_serde_flag(container_attrs, "default");
+            let mut fields = Vec::new();
+            for field in &item.fields {
+                let Some(ident) = &field.ident else { continue };
+                if has_serde_flag(&field.attrs, "skip")
+                    || has_serde_flag(&field.attrs, "skip_deserializing")
+                {
+                    continue;
+                }
+                let key = serde_field_value(&field.attrs, "rename")
+                    .unwrap_or_else(|| rename(&ident.to_string(), rename_all.as_deref(), false));
+                let optional = option_inner(&field.ty).is_some();
+                let field_type = option_inner(&field.ty).unwrap_or(&field.ty);
+                let shape = shape(source, module, field_type, defs);
+                let default_attr = serde_field_value(&field.attrs, "default");
+                let has_default = container_default
+                    || has_serde_flag(&field.attrs, "default")
+                    || default_attr.is_some();
+                // Only `Default::default()` has a value that is known from the source.
+                let default = (has_default && default_attr.is_none() && !optional)
+                    .then(|| default_value(&shape))
+                    .flatten();
+                fields.push(Field {
+                    key,
+                    shape,
+                    description: doc_text(&field.attrs),
+                    required: !optional && !has_default,
+                    default,
+                });
+            }
+            DefKind::Object {
+                fields,
+                deny_unknown_fields: has_serde_flag(container_attrs, "deny_unknown_fields"),
+            }
+        }
+        Item::Enum(item) => {
+            let variants: Vec<_> = item
+                .variants
+                .iter()
+                .filter(|variant| !has_serde_flag(&variant.attrs, "skip"))
+                .collect();
+            if has_serde_flag(container_attrs, "untagged") {
+                DefKind::AnyOf(
+                    variants
+                        .iter()
+                        .map(|variant| match &variant.fields {
+                            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                                shape(source, module, &fields.unnamed[0].ty, defs)
+                            }
+                            _ => Shape::Any,
+                        })
+                        .collect(),
+                )
+            } else if variants
+                .iter()
+                .all(|variant| matches!(variant.fields, Fields::Unit))
+            {
+                DefKind::Strings(
+                    variants
+                        .iter()
+                        .map(|variant| {
+                            serde_field_value(&variant.attrs, "rename").unwrap_or_else(|| {
+                                rename(&variant.ident.to_string(), rename_all.as_deref(), true)
+                            })
+                        })
+                        .collect(),
+                )
+            } else {
+                // Tagged enums with data aren't described further.
+                DefKind::AnyOf(vec![Shape::Any])
+            }
+        }
+        _ => DefKind::AnyOf(vec![Shape::Any]),
+    };
+
+    Def { description, kind }
+}
+
+// Returns the shape of `ty`, as seen from `module`.
+fn shape(source: &Source, module: &[String], ty: &Type, defs: &mut Vec<(String, Def)>) -> Shape {
+    let path = match ty {
+        Type::Path(path) => &path.path,
+        Type::Reference(reference) => return shape(source, module, &reference.elem, defs),
+        _ => return Shape::Any,
+    };
+    let Some(segment) = path.segments.last() else {
+        return Shape::Any;
+    };
+    let arguments: Vec<&Type> = match &segment.arguments {
+        PathArguments::AngleBracketed(arguments) => arguments
+            .args
+            .iter()
+            .filter_map(|argument| match argument {
+                GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    let name = segment.ident.to_string();
+    match (name.as_str(), arguments.as_slice()) {
+        ("String" | "str" | "PathBuf" | "Path" | "OsString" | "char", _) => Shape::String,
+        ("bool", _) => Shape::Boolean,
+        ("u8" | "u16" | "u32" | "u64" | "u128" | "usize", _) => Shape::Integer { unsigned: true },
+        ("i8" | "i16" | "i32" | "i64" | "i128" | "isize", _) => Shape::Integer { unsigned: false },
+        ("f32" | "f64", _) => Shape::Number,
+        ("Option" | "Box" | "Rc" | "Arc", [inner]) => shape(source, module, inner, defs),
+        ("Vec" | "HashSet" | "BTreeSet" | "VecDeque", [inner]) => {
+            Shape::Array(Box::new(shape(source, module, inner, defs)))
+        }
+        ("HashMap" | "BTreeMap", [_, value]) => {
+            Shape::Map(Box::new(shape(source, module, value, defs)))
+        }
+        _ => resolve(source, module, &name, defs).unwrap_or(Shape::Any),
+    }
+}
+
+// Returns `T` for `Option<T>`.
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else { return None };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+    match &segment.arguments {
+        PathArguments::AngleBracketed(arguments) => {
+            arguments.args.iter().find_map(|argument| match argument {
+                GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            })
+        }
+        _ => None,
+    }
+}
+
+// The value of `Default::default()` for the shape, if it doesn't depend on a type in the crate.
+fn default_value(shape: &Shape) -> Option<Value> {
+    match shape {
+        Shape::String => Some(json!("")),
+        Shape::Integer { .. } => Some(json!(0)),
+        Shape::Number => Some(json!(0.0)),
+        Shape::Boolean => Some(json!(false)),
+        Shape::Array(_) => Some(json!([])),
+        Shape::Map(_) => Some(json!({})),
+        Shape::Any | Shape::Named(_) => None,
+    }
+}
+
+// Whether a `#[serde(...)]` attribute has the flag, such as `default` or `untagged`.
+fn has_serde_flag(attrs: &[Attribute], flag: &str) -> bool {
+    let mut found = false;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+        let _ = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident(flag) {
+                found = true;
+            }
+            skip_value(&meta)
+        });
+    }
+    found
+}
+
+// The string value of a `#[serde(key = "...")]` attribute of a field or variant.
+fn serde_field_value(attrs: &[Attribute], key: &str) -> Option<String> {
+    let mut value = None;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+        let _ = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident(key) && meta.input.peek(syn::Token![=]) {
+                value = Some(meta.value()?.parse::<syn::LitStr>()?.value());
+                return Ok(());
+            }
+            skip_value(&meta)
+        });
+    }
+    value
+}
+
+// The string value of the first of `keys` in a container's `#[serde(...)]` attribute. For
+// `rename_all(deserialize = "...")`, the deserialize form is used.
+fn serde_container_value(attrs: &[Attribute], keys: &[&str]) -> Option<String> {
+    let mut value = None;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+        let _ = attr.parse_nested_meta(|meta| {
+            if !keys.iter().any(|key| meta.path.is_ident(key)) {
+                return skip_value(&meta);
+            }
+            if meta.input.peek(syn::Token![=]) {
+                value = Some(meta.value()?.parse::<syn::LitStr>()?.value());
+            } else {
+                meta.parse_nested_meta(|inner| {
+                    if inner.path.is_ident("deserialize") {
+                        value = Some(inner.value()?.parse::<syn::LitStr>()?.value());
+                    } else {
+                        skip_value(&inner)?;
+                    }
+                    Ok(())
+                })?;
+            }
+            Ok(())
+        });
+    }
+    value
+}
+
+fn skip_value(meta: &syn::meta::ParseNestedMeta) -> syn::Result<()> {
+    if meta.input.peek(syn::Token![=]) {
+        meta.value()?.parse::<syn::Expr>()?;
+    } else if meta.input.peek(syn::token::Paren) {
+        meta.input.step(|cursor| {
+            cursor
+                .token_tree()
+                .map(|(_, rest)| ((), rest))
+                .ok_or_else(|| cursor.error("expected arguments"))
+        })?;
+    }
+    Ok(())
+}
+
+// Applies a serde `rename_all` rule to a field name (`snake_case`) or a variant name
+// (`PascalCase`).
+fn rename(name: &str, rule: Option<&str>, variant: bool) -> String {
+    let Some(rule) = rule else {
+        return name.to_string();
+    };
+    let words: Vec<String> = if variant {
+        let mut words: Vec<String> = Vec::new();
+        for c in name.chars() {
+            match words.last_mut() {
+                Some(word) if !c.is_uppercase() => word.push(c),
+                _ => words.push(c.to_string()),
+            }
+        }
+        words.iter().map(|word| word.to_lowercase()).collect()
+    } else {
+        name.split('_').map(str::to_string).collect()
+    };
+    let capitalize = |word: &String| {
+        let mut chars = word.chars();
+        chars
+            .next()
+            .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+            .unwrap_or_default()
+    };
+
+    match rule {
+        "lowercase" => words.concat(),
+        "UPPERCASE" => words.concat().to_uppercase(),
+        "PascalCase" => words.iter().map(capitalize).collect(),
+        "camelCase" => words
+            .iter()
+            .enumerate()
+            .map(|(i, word)| {
+                if i == 0 {
+                    word.clone()
+                } else {
+                    capitalize(word)
+                }
+            })
+            .collect(),
+        "snake_case" => words.join("_"),
+        "SCREAMING_SNAKE_CASE" => words.join("_").to_uppercase(),
+        "kebab-case" => words.join("-"),
+        "SCREAMING-KEBAB-CASE" => words.join("-").to_uppercase(),
+        _ => name.to_string(),
+    }
+}
+
+fn find_def<'a>(defs: &'a [(String, Def)], name: &str) -> Option<&'a Def> {
+    defs.iter()
+        .find(|(def_name, _)| def_name == name)
+        .map(|(_, def)| def)
+}
+
+// Renders the keys of the root type as a Markdown table. Keys of nested tables are dotted, keys of
+// tables in a list are written `key[].nested`, and keys of tables in a map `key.<name>.nested`.
+fn markdown(root: &Shape, defs: &[(String, Def)]) -> String {
+    let mut output =
+        String::from("| Key | Type | Default | Description |\n| --- | --- | --- | --- |\n");
+    let mut visiting = Vec::new();
+    markdown_rows(root, "", defs, &mut visiting, &mut output);
+    output
+}
+
+fn markdown_rows(
+    shape: &Shape,
+    prefix: &str,
+    defs: &[(String, Def)],
+    visiting: &mut Vec<String>,
+    output: &mut String,
+) {
+    let (name, prefix) = match shape {
+        Shape::Named(name) => (name, prefix.to_string()),
+        Shape::Array(inner) => {
+            return markdown_rows(inner, &format!("{}[]", prefix), defs, visiting, output)
+        }
+        Shape::Map(inner) => {
+            return markdown_rows(inner, &format!("{}.<name>", prefix), defs, visiting, output)
+        }
+        _ => return,
+    };
+    let Some(Def {
+        kind: DefKind::Object { fields, .. },
+        ..
+    }) = find_def(defs, name)
+    else {
+        return;
+    };
+    // Recursive types are only expanded once.
+    if visiting.contains(name) {
+        return;
+    }
+    visiting.push(name.clone());
+
+    for field in fields {
+        let key = if prefix.is_empty() {
+            field.key.clone()
+        } else {
+            format!("{}.{}", prefix, field.key)
+        };
+        let default = match (&field.default, field.required) {
+            (Some(default), _) => format!("`{}`", default),
+            (None, true) => "required".to_string(),
+            (None, false) => String::new(),
+        };
+        let _ = writeln!(
+            output,
+            "| `{}` | {} | {} | {} |",
+            key,
+            escape_cell(&type_name(&field.shape, defs)),
+            default,
+            escape_cell(&field.description)
+        );
+        markdown_rows(&field.shape, &key, defs, visiting, output);
+    }
+
+    visiting.pop();
+}
+
+// A short name for the type of a value, in the words of TOML.
+fn type_name(shape: &Shape, defs: &[(String, Def)]) -> String {
+    match shape {
+        Shape::Any => "any".to_string(),
+        Shape::String => "string".to_string(),
+        Shape::Integer { unsigned: true } => "non-negative integer".to_string(),
+        Shape::Integer { unsigned: false } => "integer".to_string(),
+        Shape::Number => "number".to_string(),
+        Shape::Boolean => "boolean".to_string(),
+        Shape::Array(inner) => format!("list of {}", type_name(inner, defs)),
+        Shape::Map(inner) => format!("table of {}", type_name(inner, defs)),
+        Shape::Named(name) => match find_def(defs, name).map(|def| &def.kind) {
+            Some(DefKind::Object { .. }) => "table".to_string(),
+            Some(DefKind::Strings(values)) => format!(
+                "one of {}",
+                values
+                    .iter()
+                    .map(|value| format!("`{}`", value))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Some(DefKind::AnyOf(shapes)) if !shapes.is_empty() => shapes
+                .iter()
+                .map(|shape| type_name(shape, defs))
+                .collect::<Vec<_>>()
+                .join(" or "),
+            _ => "any".to_string(),
+        },
+    }
+}
+
+fn json_schema(title: &str, root: &Shape, defs: &[(String, Def)]) -> String {
+    let mut schema = Map::new();
+    schema.insert("$schema".to_string(), json!(JSON_SCHEMA_DIALECT));
+    schema.insert("title".to_string(), json!(title));
+    // The root type is described inline, and the types it uses under `$defs`.
+    let root_def = match root {
+        Shape::Named(name) => find_def(defs, name),
+        _ => None,
+    };
+    match root_def {
+        Some(def) => schema.extend(def_schema(def)),
+        None => schema.extend(shape_schema(root)),
+    }
+    let nested: Map<String, Value> = defs
+        .iter()
+        .filter(|(_, def)| Some(def) != root_def)
+        .map(|(name, def)| (name.clone(), Value::Object(def_schema(def))))
+        .collect();
+    if !nested.is_empty() {
+        schema.insert("$defs".to_string(), Value::Object(nested));
+    }
+
+    let mut json = serde_json::to_string_pretty(&Value::Object(schema)).unwrap_or_default();
+    json.push('\n');
+    json
+}
+
+fn def_schema(def: &Def) -> Map<String, Value> {
+    let mut schema = Map::new();
+    if !def.description.is_empty() {
+        schema.insert("description".to_string(), json!(def.description));
+    }
+    match &def.kind {
+        DefKind::Object {
+            fields,
+            deny_unknown_fields,
+        } => {
+            schema.insert("type".to_string(), json!("object"));
+            let properties: Map<String, Value> = fields
+                .iter()
+                .map(|field| {
+                    let mut property = shape_schema(&field.shape);
+                    if !field.description.is_empty() {
+                        property.insert("description".to_string(), json!(field.description));
+                    }
+                    if let Some(default) = &field.default {
+                        property.insert("default".to_string(), default.clone());
+                    }
+                    (field.key.clone(), Value::Object(property))
+                })
+                .collect();
+            schema.insert("properties".to_string(), Value::Object(properties));
+            let required: Vec<_> = fields
+                .iter()
+                .filter(|field| field.required)
+                .map(|field| json!(field.key))
+                .collect();
+            if !required.is_empty() {
+                schema.insert("required".to_string(), Value::Array(required));
+            }
+            if *deny_unknown_fields {
+                schema.insert("additionalProperties".to_string(), json!(false));
+            }
+        }
+        DefKind::Strings(values) => {
+            schema.insert("type".to_string(), json!("string"));
+            schema.insert("enum".to_string(), json!(values));
+        }
+        DefKind::AnyOf(shapes) => {
+            if !shapes.is_empty() && !shapes.contains(&Shape::Any) {
+                let any_of: Vec<_> = shapes
+                    .iter()
+                    .map(|shape| Value::Object(shape_schema(shape)))
+                    .collect();
+                schema.insert("anyOf".to_string(), Value::Array(any_of));
+            }
+        }
+    }
+    schema
+}
+
+fn shape_schema(shape: &Shape) -> Map<String, Value> {
+    let schema = match shape {
+        Shape::Any => json!({}),
+        Shape::String => json!({ "type": "string" }),
+        Shape::Integer { unsigned: true } => json!({ "type": "integer", "minimum": 0 }),
+        Shape::Integer { unsigned: false } => json!({ "type": "integer" }),
+        Shape::Number => json!({ "type": "number" }),
+        Shape::Boolean => json!({ "type": "boolean" }),
+        Shape::Array(inner) => json!({ "type": "array", "items": shape_schema(inner) }),
+        Shape::Map(inner) => {
+            json!({ "type": "object", "additionalProperties": shape_schema(inner) })
+        }
+        Shape::Named(name) => json!({ "$ref": format!("#/$defs/{}", name) }),
+    };
+    match schema {
+        Value::Object(map) => map,
+        _ => Map::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SOURCE: &str = r#"
+use serde::Deserialize;
+use crate::paths::Overrides;
+
+/// The settings of the tool.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Config {
+    /// How the widgets are split.
+    #[serde(default)]
+    pub split_strategy: String,
+    /// Widget counts by model.
+    #[serde(default)]
+    pub counts: HashMap<String, u32>,
+    /// Where things are.
+    #[serde(default)]
+    pub paths: Overrides,
+    pub mode: Mode,
+    #[serde(skip)]
+    pub cache: Vec<String>,
+    /// Widgets to ignore.
+    pub exclude: Option<Vec<Selector>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Mode {
+    AllAtOnce,
+    #[serde(rename = "one")]
+    OneByOne,
+}
+
+#[derive(Deserialize)]
+#[serde(from = "RawSelector")]
+pub enum Selector {
+    Index(u32),
+    Name(String),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawSelector {
+    Index(u32),
+    Text(String),
+}
+
+mod paths {
+    #[derive(Default, Deserialize)]
+    #[serde(rename_all = "kebab-case", deny_unknown_fields)]
+    pub struct Overrides {
+        /// The state directory.
+        pub state_dir: Option<PathBuf>,
+    }
+}
+"#;
+
+    fn source() -> Source {
+        let mut source = Source::default();
+        collect_items(
+            &syn::parse_file(SOURCE).unwrap().items,
+            Vec::new(),
+            &mut source,
+        );
+        source
+    }
+
+    #[test]
+    fn test_markdown() {
+        let mut defs = Vec::new();
+        let root = resolve(&source(), &[], "Config", &mut defs).unwrap();
+        let expected = "\
+| Key | Type | Default | Description |
+| --- | --- | --- | --- |
+| `split-strategy` | string | `\"\"` | How the widgets are split. |
+| `counts` | table of non-negative integer | `{}` | Widget counts by model. |
+| `paths` | table |  | Where things are. |
+| `paths.state-dir` | string |  | The state directory. |
+| `mode` | one of `all-at-once`, `one` | required |  |
+| `exclude` | list of non-negative integer or string |  | Widgets to ignore. |
+";
+        assert_eq!(markdown(&root, &defs), expected);
+    }
+
+    #[test]
+    fn test_json_schema() {
+        let mut defs = Vec::new();
+        let root = resolve(&source(), &[], "Config", &mut defs).unwrap();
+        let schema: Value = serde_json::from_str(&json_schema("Config", &root, &defs)).unwrap();
+
+        assert_eq!(schema["description"], "The settings of the tool.");
+        assert_eq!(schema["required"], json!(["mode"]));
+        assert_eq!(schema["properties"]["split-strategy"]["default"], "");
+        assert_eq!(
+            schema["properties"]["counts"]["additionalProperties"],
+            json!({ "type": "integer", "minimum": 0 })
+        );
+        assert_eq!(
+            schema["properties"]["exclude"]["items"],
+            json!({ "$ref": "#/$defs/Selector" })
+        );
+        assert!(schema["$defs"].get("RawSelector").is_none());
+        assert_eq!(
+            schema["$defs"]["Selector"]["anyOf"],
+            json!([{ "type": "integer", "minimum": 0 }, { "type": "string" }])
+        );
+        assert_eq!(
+            schema["$defs"]["Mode"]["enum"],
+            json!(["all-at-once", "one"])
+        );
+        assert_eq!(
+            schema["$defs"]["Overrides"]["additionalProperties"],
+            json!(false)
+        );
+        assert!(schema["$defs"].get("Config").is_none());
+    }
+}
@@ -0,0 +1,239 @@
+//! Lists the crates of a Cargo workspace, and renders the index README that links to their READMEs.
+
+use crate::cli::escape_cell;
+use crate::docs::{self, DocLine};
+use crate::error;
+use crate::links::{closing_bracket, code_span};
+use crate::Result;
+use serde::Deserialize;
+use snafu::{ensure, ResultExt};
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Replaced in the workspace's `README.tpl` by the table of crates.
+const CRATES_PLACEHOLDER: &str = "{{crates}}";
+
+#[derive(Deserialize)]
+struct Manifest {
+    workspace: Workspace,
+}
+
+#[derive(Deserialize)]
+struct Workspace {
+    members: Vec<String>,
+    #[serde(default)]
+    exclude: Vec<String>,
+}
+
+/// A row of the index.
+#[derive(Debug)]
+pub(crate) struct Crate {
+    pub(crate) name: String,
+    /// The path of the crate's README, or of its directory if it has none, relative to the
+    /// workspace.
+    pub(crate) link: String,
+    pub(crate) summary: String,
+}
+
+/// Returns the directories of the members of the workspace in `dir`, in the order of its
+/// `Cargo.toml`. Members may end with a `*`, which matches the crates in a directory.
+pub(crate) fn members(dir: &Path) -> Result<Vec<PathBuf>> {
+    let file = dir.join("Cargo.toml");
+    let manifest = fs::read_to_string(&file).context(error::WorkspaceReadSnafu { file: &file })?;
+    let workspace = toml::from_str::<Manifest>(&manifest)
+        .context(error::WorkspaceParseSnafu { file: &file })?
+        .workspace;
+
+    let mut members = Vec::new();
+    for member in &workspace.members {
+        match member.strip_suffix('*') {
+            Some(prefix) => {
+                let parent = dir.join(prefix.trim_end_matches('/'));
+                let entries =
+                    fs::read_dir(&parent).context(error::WorkspaceReadSnafu { file: &parent })?;
+                let mut found: Vec<_> = entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| path.join("Cargo.toml").is_file())
+                    .collect();
+                found.sort();
+                members.extend(found);
+            }
+            None => members.push(dir.join(member)),
+        }
+    }
+    members.retain(|member| {
+        !workspace
+            .exclude
+            .iter()
+            .any(|exclude| *member == dir.join(exclude))
+    });
+    Ok(members)
+}
+
+/// The source file with the crate-level docs of the crate in `dir`: the `docs-file` declared in
+/// its `[package.metadata.generate-readme]`, or else the library if there is one, and otherwise
+/// the binary.
+pub(crate) fn crate_root(dir: &Path, docs_file: Option<&Path>) -> Option<PathBuf> {
+    if let Some(docs_file) = docs_file {
+        return Some(dir.join(docs_file));
+    }
+    ["src/lib.rs", "src/main.rs"]
+        .into_iter()
+        .map(|path| dir.join(path))
+        .find(|path| path.is_file())
+}
+
+/// The first paragraph of the docs, on one line and without links.
+pub(crate) fn summary(lines: &[DocLine]) -> String {
+    let paragraph: Vec<&str> = lines
+        .iter()
+        .map(|line| line.text.trim())
+        .skip_while(|text| text.is_empty() || text.starts_with('#'))
+        .take_while(|text| {
+            !text.is_empty() && !text.starts_with('#') && docs::fence_start(text).is_none()
+        })
+        .collect();
+    strip_links(&paragraph.join(" "))
+}
+
+// Replaces the links in `text` by their text, since the targets are relative to the crate.
+fn strip_links(text: &str) -> String {
+    let mut output = String::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '`' => {
+                let span = code_span(rest);
+                output.push_str(&rest[..span]);
+                rest = &rest[span..];
+            }
+            '[' => match closing_bracket(rest) {
+                Some(close) => {
+                    output.push_str(&rest[1..close]);
+                    rest = &rest[close + 1..];
+                    let target_end = match rest.chars().next() {
+                        Some('(') => rest.find(')'),
+                        Some('[') => rest.find(']'),
+                        _ => None,
+                    };
+                    if let Some(end) = target_end {
+                        rest = &rest[end + 1..];
+                    }
+                }
+                None => {
+                    output.push('[');
+                    rest = &rest[1..];
+                }
+            },
+            c => {
+                output.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    output
+}
+
+/// Renders the index README from the workspace's `template`.
+pub(crate) fn index(template_file: &Path, template: &str, crates: &[Crate]) -> Result<String> {
+    ensure!(
+        template.contains(CRATES_PLACEHOLDER),
+        error::WorkspaceTemplateSnafu {
+            file: template_file
+        }
+    );
+
+    let mut table = String::from("| Crate | Summary |\n| --- | --- |\n");
+    for krate in crates {
+        let _ = writeln!(
+            table,
+            "| [`{}`]({}) | {} |",
+            krate.name,
+            krate.link,
+            escape_cell(&krate.summary)
+        );
+    }
+
+    let mut content = template
+        .trim_end_matches('\n')
+        .replace(CRATES_PLACEHOLDER, table.trim_end());
+    content.push('\n');
+    Ok(content)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_members() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        fs::write(
+            dir.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"tool\", \"libs/*\"]\nexclude = [\"libs/old\"]\n",
+        )
+        .unwrap();
+        for member in ["tool", "libs/b", "libs/a", "libs/old"] {
+            fs::create_dir_all(dir.join(member)).unwrap();
+            fs::write(dir.join(member).join("Cargo.toml"), "").unwrap();
+        }
+        fs::create_dir_all(dir.join("libs/not-a-crate")).unwrap();
+
+        assert_eq!(
+            members(dir).unwrap(),
+            vec![dir.join("tool"), dir.join("libs/a"), dir.join("libs/b")]
+        );
+    }
+
+    #[test]
+    fn test_crate_root() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        assert_eq!(crate_root(dir, None), None);
+
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src/main.rs"), "").unwrap();
+        assert_eq!(crate_root(dir, None), Some(dir.join("src/main.rs")));
+
+        // The library wins over the binary, unless the crate declares its docs file.
+        fs::write(dir.join("src/lib.rs"), "").unwrap();
+        assert_eq!(crate_root(dir, None), Some(dir.join("src/lib.rs")));
+        assert_eq!(
+            crate_root(dir, Some(Path::new("src/main.rs"))),
+            Some(dir.join("src/main.rs"))
+        );
+    }
+
+    #[test]
+    fn test_index() {
+        let text = "# Tool\n\n`tool` uses [`config::Config`] and\n[the docs](https://example.com).\n\nMore.";
+        let lines: Vec<DocLine> = text
+            .lines()
+            .map(|text| DocLine {
+                text: text.to_string(),
+                file: PathBuf::from("src/lib.rs"),
+                line: 1,
+            })
+            .collect();
+        let crates = vec![Crate {
+            name: "tool".to_string(),
+            link: "tool/README.md".to_string(),
+            summary: summary(&lines),
+        }];
+
+        let readme = index(
+            Path::new("README.tpl"),
+            "# Crates\n\n{{crates}}\n\n",
+            &crates,
+        );
+        assert_eq!(
+            readme.unwrap(),
+            "# Crates\n\n| Crate | Summary |\n| --- | --- |\n\
+             | [`tool`](tool/README.md) | `tool` uses `config::Config` and the docs. |\n"
+        );
+
+        assert!(index(Path::new("README.tpl"), "# Crates\n", &crates).is_err());
+    }
+}
@@ -0,0 +1,13 @@
+# {{crate}}
+
+Current version: {{version}}
+
+{{readme}}
+
+## Command-line reference
+
+{{cli}}
+
+## Colophon
+
+This text was generated from `README.tpl` by the `generate-readme` crate of this workspace, and includes the rustdoc from `src/main.rs`.
@@ -0,0 +1,6 @@
+fn main() {
+    if let Err(e) = generate_readme::from_main() {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
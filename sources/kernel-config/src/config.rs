@@ -0,0 +1,226 @@
+//! The `config` module parses Kconfig `.config` files, as written by `make olddefconfig` and
+//! installed as `/boot/config`.
+
+use crate::{error, Result};
+use serde::{Serialize, Serializer};
+use snafu::ResultExt;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+// How Kconfig writes an option that is off.
+const NOT_SET_SUFFIX: &str = " is not set";
+
+/// The value of an option.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Value {
+    /// `y`
+    Builtin,
+    /// `m`
+    Module,
+    /// `n`, which Kconfig writes as `# CONFIG_X is not set` instead.
+    No,
+    /// `# CONFIG_X is not set`
+    NotSet,
+    /// A string, in quotes, or a number, as written.
+    Text(String),
+}
+
+impl Value {
+    /// Whether the option is on, or has a value.
+    pub fn is_set(&self) -> bool {
+        !matches!(self, Value::No | Value::NotSet)
+    }
+
+    fn parse(text: &str) -> Self {
+        match text {
+            "y" => Value::Builtin,
+            "m" => Value::Module,
+            "n" => Value::No,
+            text => Value::Text(text.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Builtin => f.write_str("y"),
+            Value::Module => f.write_str("m"),
+            Value::No => f.write_str("n"),
+            Value::NotSet => f.write_str("is not set"),
+            Value::Text(text) => f.write_str(text),
+        }
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+/// An option of a config, and the line that set it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub value: Value,
+    pub line: usize,
+}
+
+/// The options of a `.config` file, by name, such as `CONFIG_EXT4_FS`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    options: BTreeMap<String, Entry>,
+}
+
+impl Config {
+    /// Reads the config at `path`.
+    pub fn from_file<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).context(error::ReadSnafu { path })?;
+        Self::parse(&text, path)
+    }
+
+    /// Parses the text of a config. `path` is only used in errors. Comments other than
+    /// `# CONFIG_X is not set` are skipped. If an option is set twice, the last line wins, as in
+    /// Kconfig.
+    pub fn parse(text: &str, path: &Path) -> Result<Self> {
+        let mut options = BTreeMap::new();
+        for (index, line) in text.lines().enumerate() {
+            match Line::parse(line) {
+                Line::Empty => {}
+                Line::Option(name, value) => {
+                    options.insert(
+                        name.to_string(),
+                        Entry {
+                            value,
+                            line: index + 1,
+                        },
+                    );
+                }
+                Line::Invalid => {
+                    return error::ConfigLineSnafu {
+                        path,
+                        line: index + 1,
+                        text: line,
+                    }
+                    .fail()
+                }
+            }
+        }
+        Ok(Config { options })
+    }
+
+    /// Returns the option named `name`, if the config lists it.
+    pub fn get(&self, name: &str) -> Option<&Entry> {
+        self.options.get(name)
+    }
+
+    /// Returns the value of `name` if it is set, treating `n` and options left out as not set.
+    pub fn value(&self, name: &str) -> Option<&Value> {
+        self.get(name)
+            .map(|entry| &entry.value)
+            .filter(|value| value.is_set())
+    }
+
+    /// Iterates over the options in the order of their names.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Entry)> {
+        self.options
+            .iter()
+            .map(|(name, entry)| (name.as_str(), entry))
+    }
+}
+
+/// A line of a config, or of a config fragment.
+#[derive(Debug, PartialEq)]
+pub enum Line<'a> {
+    /// A blank line, or a comment.
+    Empty,
+    /// An option, `CONFIG_X=value` or `# CONFIG_X is not set`.
+    Option(&'a str, Value),
+    /// Anything else.
+    Invalid,
+}
+
+impl<'a> Line<'a> {
+    /// Parses a line. Leading and trailing whitespace is ignored.
+    pub fn parse(line: &'a str) -> Self {
+        let line = line.trim();
+        if let Some(comment) = line.strip_prefix('#') {
+            return match comment.trim_start().strip_suffix(NOT_SET_SUFFIX) {
+                Some(name) if is_name(name) => Line::Option(name, Value::NotSet),
+                _ => Line::Empty,
+            };
+        }
+        if line.is_empty() {
+            return Line::Empty;
+        }
+
+        match line.split_once('=') {
+            Some((name, value)) if is_name(name) && !value.is_empty() => {
+                Line::Option(name, Value::parse(value))
+            }
+            _ => Line::Invalid,
+        }
+    }
+}
+
+fn is_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let text = "\
+#
+# Automatically generated file; DO NOT EDIT.
+#
+CONFIG_EXT4_FS=y
+CONFIG_BTRFS_FS=m
+# CONFIG_SQUASHFS_FILE_DIRECT is not set
+CONFIG_SQUASHFS_FRAGMENT_CACHE_SIZE=3
+CONFIG_LOCALVERSION=\"\"
+CONFIG_BTRFS_FS=y
+";
+        let config = Config::parse(text, Path::new("config")).unwrap();
+        assert_eq!(config.value("CONFIG_EXT4_FS"), Some(&Value::Builtin));
+        assert_eq!(
+            config.get("CONFIG_BTRFS_FS"),
+            Some(&Entry {
+                value: Value::Builtin,
+                line: 9
+            })
+        );
+        assert_eq!(
+            config.get("CONFIG_SQUASHFS_FILE_DIRECT").unwrap().value,
+            Value::NotSet
+        );
+        assert_eq!(config.value("CONFIG_SQUASHFS_FILE_DIRECT"), None);
+        assert_eq!(
+            config.value("CONFIG_SQUASHFS_FRAGMENT_CACHE_SIZE"),
+            Some(&Value::Text("3".to_string()))
+        );
+        assert_eq!(
+            config.value("CONFIG_LOCALVERSION").unwrap().to_string(),
+            "\"\""
+        );
+        assert_eq!(config.iter().count(), 5);
+
+        let error = Config::parse("CONFIG_A=y\nCONFIG_B\n", Path::new("config")).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "config:2: not a config option: 'CONFIG_B'"
+        );
+    }
+}
@@ -0,0 +1,112 @@
+//! The `diff` module compares two configs by what the options mean rather than by their lines.
+
+use crate::config::{Config, Value};
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+/// How an option changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChangeKind {
+    /// The option was not set, and now is.
+    Added,
+    /// The option was set, and now isn't.
+    Removed,
+    /// The option changed from `m` to `y`.
+    ModuleToBuiltin,
+    /// The option changed from `y` to `m`.
+    BuiltinToModule,
+    /// The option has a different value, such as a number or a string.
+    ValueChanged,
+}
+
+impl ChangeKind {
+    /// A short description, for reports.
+    pub fn describe(&self) -> &'static str {
+        match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::ModuleToBuiltin => "m → y",
+            ChangeKind::BuiltinToModule => "y → m",
+            ChangeKind::ValueChanged => "value changed",
+        }
+    }
+}
+
+/// An option that differs between two configs. Options that aren't set have no value.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub struct Change {
+    pub option: String,
+    pub kind: ChangeKind,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+/// Returns the options that differ between `before` and `after`, in the order of their names.
+/// Options that are `n`, `# ... is not set`, or left out are all not set, so moving between these
+/// isn't a change.
+pub fn diff(before: &Config, after: &Config) -> Vec<Change> {
+    let names: BTreeSet<&str> = before
+        .iter()
+        .chain(after.iter())
+        .map(|(name, _)| name)
+        .collect();
+
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let old = before.value(name);
+            let new = after.value(name);
+            let kind = match (old, new) {
+                (None, None) => return None,
+                (Some(old), Some(new)) if old == new => return None,
+                (None, Some(_)) => ChangeKind::Added,
+                (Some(_), None) => ChangeKind::Removed,
+                (Some(Value::Module), Some(Value::Builtin)) => ChangeKind::ModuleToBuiltin,
+                (Some(Value::Builtin), Some(Value::Module)) => ChangeKind::BuiltinToModule,
+                (Some(_), Some(_)) => ChangeKind::ValueChanged,
+            };
+            Some(Change {
+                option: name.to_string(),
+                kind,
+                before: old.cloned(),
+                after: new.cloned(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_diff() {
+        let before = Config::parse(
+            "CONFIG_A=y\nCONFIG_B=m\nCONFIG_C=y\nCONFIG_D=3\n# CONFIG_E is not set\nCONFIG_F=y\n",
+            Path::new("before"),
+        )
+        .unwrap();
+        let after = Config::parse(
+            "CONFIG_B=y\nCONFIG_C=m\nCONFIG_D=4\nCONFIG_F=y\nCONFIG_G=n\nCONFIG_H=\"x\"\n",
+            Path::new("after"),
+        )
+        .unwrap();
+
+        let changes: Vec<_> = diff(&before, &after)
+            .into_iter()
+            .map(|change| (change.option, change.kind))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("CONFIG_A".to_string(), ChangeKind::Removed),
+                ("CONFIG_B".to_string(), ChangeKind::ModuleToBuiltin),
+                ("CONFIG_C".to_string(), ChangeKind::BuiltinToModule),
+                ("CONFIG_D".to_string(), ChangeKind::ValueChanged),
+                ("CONFIG_H".to_string(), ChangeKind::Added),
+            ]
+        );
+    }
+}
@@ -0,0 +1,416 @@
+//! The `drift` module aligns the fragments and the final configs of every kernel series, to find
+//! the options that differ between them, such as an option hardened in 6.1 but not in 5.15.
+//!
+//! Differences that are intended, such as options that only exist in newer kernels, go into an
+//! allowlist, a TOML file with a list of entries that explain why the options may differ:
+//!
+//! ```toml
+//! [[allow]]
+//! description = "erofs is only used from 6.1"
+//! options = ["CONFIG_EROFS_FS"]
+//! ```
+
+use crate::config::{Config, Value};
+use crate::fragment::Fragment;
+use crate::{error, Result};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+/// The intended differences between kernel series.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Allowlist {
+    #[serde(rename = "allow", default)]
+    pub entries: Vec<Allow>,
+}
+
+/// Options that may differ between kernel series.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Allow {
+    /// Why the options differ.
+    pub description: String,
+    pub options: Vec<String>,
+}
+
+impl Allowlist {
+    /// Reads the allowlist at `path`.
+    pub fn from_file<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).context(error::ReadSnafu { path })?;
+        toml::from_str(&text)
+            .map_err(Box::new)
+            .context(error::AllowlistParseSnafu { path })
+    }
+
+    // The description of the entry that allows `option` to differ.
+    fn allows(&self, option: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|entry| entry.options.iter().any(|allowed| allowed == option))
+            .map(|entry| entry.description.as_str())
+    }
+}
+
+/// A fragment, or the final config of an architecture, of a kernel series.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Column {
+    pub version: String,
+    /// The architecture of a final config, or none for the fragment, which applies to all.
+    pub arch: Option<String>,
+}
+
+/// How an option differs between kernel series.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DriftKind {
+    /// Fragments, or the final configs of an architecture, set the option to different values.
+    Differs,
+    /// Some fragments set the option, and the others inherit it from the config of their source
+    /// package, such as a change that wasn't ported to the other series. Since the inherited
+    /// value may well be the same, this doesn't count as drift.
+    Inherited,
+}
+
+/// An option that differs between kernel series, with its value in every column. Options that a
+/// fragment doesn't set, or that a kernel doesn't have, have no value; `n` is `is not set`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Row {
+    pub option: String,
+    pub kind: DriftKind,
+    pub values: Vec<Option<Value>>,
+    /// The description of the allowlist entry, if the difference is intended.
+    pub allowed: Option<String>,
+}
+
+/// The options that differ between kernel series.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Drift {
+    pub columns: Vec<Column>,
+    pub rows: Vec<Row>,
+    /// Options of the allowlist that no longer differ, which can be removed from it.
+    pub stale: Vec<String>,
+}
+
+impl Drift {
+    /// Aligns the fragments and the final configs of the kernel series, given with their versions
+    /// and, for the configs, their architectures.
+    ///
+    /// Fragments differ if they set an option to different values. If some of them don't set it
+    /// at all, they inherit it from their source package, which is listed as its own kind of
+    /// row. Final configs are only compared with the ones of the same architecture, since every
+    /// architecture has its own options, and only where the kernel has the option, since newer
+    /// kernels add options. The columns of the other architectures still show their values.
+    ///
+    /// Kconfig leaves options whose dependencies aren't met out of a config, so an option missing
+    /// from a config is not set, unless no config of the series has it: then the kernel doesn't
+    /// have it.
+    pub fn new(
+        fragments: &[(String, Fragment)],
+        configs: &[(String, String, Config)],
+        allowlist: &Allowlist,
+    ) -> Self {
+        let mut columns = Vec::new();
+        // The values of every column, by option.
+        let mut values: Vec<BTreeMap<&str, Value>> = Vec::new();
+        for (version, fragment) in fragments {
+            columns.push(Column {
+                version: version.clone(),
+                arch: None,
+            });
+            // The last line of an option wins, as in the merge.
+            values.push(
+                fragment
+                    .lines
+                    .iter()
+                    .map(|line| (line.option.as_str(), normalize(&line.value)))
+                    .collect(),
+            );
+        }
+        // The options that the configs of every series have, by version.
+        let mut defined: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        for (version, _, config) in configs {
+            defined
+                .entry(version)
+                .or_default()
+                .extend(config.iter().map(|(option, _)| option));
+        }
+        let mut sorted: Vec<_> = configs.iter().collect();
+        sorted.sort_by(|(a_version, a_arch, _), (b_version, b_arch, _)| {
+            a_arch
+                .cmp(b_arch)
+                .then_with(|| crate::matrix::compare_versions(a_version, b_version))
+        });
+        for (version, arch, config) in sorted {
+            columns.push(Column {
+                version: version.clone(),
+                arch: Some(arch.clone()),
+            });
+            values.push(
+                config
+                    .iter()
+                    .map(|(option, entry)| (option, normalize(&entry.value)))
+                    .collect(),
+            );
+        }
+
+        let options: BTreeSet<&str> = values
+            .iter()
+            .flat_map(|column| column.keys())
+            .copied()
+            .collect();
+        let mut rows = Vec::new();
+        for option in options {
+            let row: Vec<Option<Value>> = columns
+                .iter()
+                .zip(&values)
+                .map(|(column, column_values)| match column_values.get(option) {
+                    Some(value) => Some(value.clone()),
+                    None if column.arch.is_some()
+                        && defined[column.version.as_str()].contains(option) =>
+                    {
+                        Some(Value::NotSet)
+                    }
+                    None => None,
+                })
+                .collect();
+            let Some(kind) = drift_kind(&columns, &row) else {
+                continue;
+            };
+            rows.push(Row {
+                option: option.to_string(),
+                kind,
+                values: row,
+                allowed: allowlist.allows(option).map(str::to_string),
+            });
+        }
+
+        let stale = allowlist
+            .entries
+            .iter()
+            .flat_map(|entry| &entry.options)
+            .filter(|option| !rows.iter().any(|row| &row.option == *option))
+            .cloned()
+            .collect();
+        Drift {
+            columns,
+            rows,
+            stale,
+        }
+    }
+
+    /// Whether any options differ that the allowlist doesn't allow. Options that only some
+    /// fragments set don't count.
+    pub fn has_drift(&self) -> bool {
+        self.rows
+            .iter()
+            .any(|row| row.kind == DriftKind::Differs && row.allowed.is_none())
+    }
+}
+
+// Kconfig writes `n` as not set.
+fn normalize(value: &Value) -> Value {
+    match value {
+        Value::No => Value::NotSet,
+        value => value.clone(),
+    }
+}
+
+// How the fragments of a row differ, or the final configs of an architecture do, if they do.
+// Values that differ win over fragments that don't set the option. Final configs without a value
+// are left out, since the kernel is older.
+fn drift_kind(columns: &[Column], row: &[Option<Value>]) -> Option<DriftKind> {
+    let mut groups: BTreeMap<Option<&str>, BTreeSet<&Value>> = BTreeMap::new();
+    let mut inherited = false;
+    for (column, value) in columns.iter().zip(row) {
+        match value {
+            Some(value) => {
+                groups
+                    .entry(column.arch.as_deref())
+                    .or_default()
+                    .insert(value);
+            }
+            None => inherited |= column.arch.is_none(),
+        }
+    }
+    if groups.values().any(|distinct| distinct.len() > 1) {
+        Some(DriftKind::Differs)
+    } else if inherited && groups.contains_key(&None) {
+        Some(DriftKind::Inherited)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_drift() {
+        let fragment = |text| Fragment::parse(text, Path::new("config-bottlerocket")).unwrap();
+        let fragments = vec![
+            ("5.10".to_string(), fragment("CONFIG_A=y\nCONFIG_C=y\n")),
+            (
+                "6.1".to_string(),
+                fragment("CONFIG_A=y\nCONFIG_B=y\nCONFIG_C=n\n"),
+            ),
+        ];
+        let config = |text| Config::parse(text, Path::new("config")).unwrap();
+        let configs = vec![
+            (
+                "6.1".to_string(),
+                "x86_64".to_string(),
+                config("CONFIG_A=y\nCONFIG_B=y\nCONFIG_D=m\nCONFIG_E=y\n"),
+            ),
+            (
+                "5.10".to_string(),
+                "x86_64".to_string(),
+                config("CONFIG_A=y\nCONFIG_D=y\n"),
+            ),
+            (
+                "6.1".to_string(),
+                "aarch64".to_string(),
+                config("CONFIG_A=y\nCONFIG_F=y\n"),
+            ),
+        ];
+        let allowlist: Allowlist = toml::from_str(
+            r#"
+[[allow]]
+description = "B is new in 6.1"
+options = ["CONFIG_B", "CONFIG_Z"]
+"#,
+        )
+        .unwrap();
+
+        let drift = Drift::new(&fragments, &configs, &allowlist);
+        let columns: Vec<_> = drift
+            .columns
+            .iter()
+            .map(|column| (column.version.as_str(), column.arch.as_deref()))
+            .collect();
+        assert_eq!(
+            columns,
+            vec![
+                ("5.10", None),
+                ("6.1", None),
+                ("6.1", Some("aarch64")),
+                ("5.10", Some("x86_64")),
+                ("6.1", Some("x86_64")),
+            ]
+        );
+
+        // The 5.10 fragment inherits B. E and F only exist in one kernel of their architecture, so
+        // they don't differ.
+        let rows: Vec<_> = drift
+            .rows
+            .iter()
+            .map(|row| (row.option.as_str(), row.kind, row.allowed.as_deref()))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                ("CONFIG_B", DriftKind::Inherited, Some("B is new in 6.1")),
+                ("CONFIG_C", DriftKind::Differs, None),
+                ("CONFIG_D", DriftKind::Differs, None),
+            ]
+        );
+        assert_eq!(
+            drift.rows[1].values,
+            vec![Some(Value::Builtin), Some(Value::NotSet), None, None, None]
+        );
+        assert_eq!(drift.stale, vec!["CONFIG_Z".to_string()]);
+        assert!(drift.has_drift());
+    }
+
+    #[test]
+    fn test_drift_inherited() {
+        let fragment = |text| Fragment::parse(text, Path::new("config-bottlerocket")).unwrap();
+        let fragments = vec![
+            ("5.10".to_string(), fragment("CONFIG_A=y\n")),
+            ("5.15".to_string(), fragment("CONFIG_A=y\n")),
+            ("6.1".to_string(), fragment("CONFIG_A=y\nCONFIG_B=y\n")),
+        ];
+        let config = |text| Config::parse(text, Path::new("config")).unwrap();
+        let configs = vec![
+            (
+                "5.10".to_string(),
+                "x86_64".to_string(),
+                config("CONFIG_A=y\nCONFIG_B=m\n"),
+            ),
+            (
+                "6.1".to_string(),
+                "x86_64".to_string(),
+                config("CONFIG_A=y\nCONFIG_B=y\n"),
+            ),
+        ];
+
+        // Only the 6.1 fragment sets B, and the final configs show that the 5.10 kernel inherits
+        // it as a module from its source package.
+        let drift = Drift::new(&fragments, &configs, &Allowlist::default());
+        assert_eq!(drift.rows.len(), 1);
+        assert_eq!(drift.rows[0].option, "CONFIG_B");
+        assert_eq!(drift.rows[0].kind, DriftKind::Differs);
+        assert_eq!(
+            drift.rows[0].values,
+            vec![
+                None,
+                None,
+                Some(Value::Builtin),
+                Some(Value::Module),
+                Some(Value::Builtin)
+            ]
+        );
+
+        // Without the final configs, B is still listed, but doesn't count as drift.
+        let drift = Drift::new(&fragments, &[], &Allowlist::default());
+        assert_eq!(drift.rows.len(), 1);
+        assert_eq!(drift.rows[0].kind, DriftKind::Inherited);
+        assert_eq!(drift.rows[0].values, vec![None, None, Some(Value::Builtin)]);
+        assert!(!drift.has_drift());
+    }
+
+    #[test]
+    fn test_drift_dropped() {
+        let config = |text| Config::parse(text, Path::new("config")).unwrap();
+        let configs = vec![
+            (
+                "5.15".to_string(),
+                "x86_64".to_string(),
+                config("CONFIG_A=y\n"),
+            ),
+            (
+                "5.15".to_string(),
+                "aarch64".to_string(),
+                config("CONFIG_A=y\nCONFIG_G=y\n"),
+            ),
+            (
+                "6.1".to_string(),
+                "x86_64".to_string(),
+                config("CONFIG_A=y\nCONFIG_G=y\nCONFIG_H=y\n"),
+            ),
+        ];
+
+        // 5.15 has G, but its dependencies left it out of the x86_64 config, so it isn't set
+        // there. No 5.15 config has H, so the kernel doesn't have it.
+        let drift = Drift::new(&[], &configs, &Allowlist::default());
+        assert_eq!(drift.rows.len(), 1);
+        assert_eq!(drift.rows[0].option, "CONFIG_G");
+        assert_eq!(drift.rows[0].kind, DriftKind::Differs);
+        assert_eq!(
+            drift.rows[0].values,
+            vec![
+                Some(Value::Builtin),
+                Some(Value::NotSet),
+                Some(Value::Builtin)
+            ]
+        );
+        assert!(drift.has_drift());
+    }
+}
@@ -0,0 +1,171 @@
+//! The `fragment` module reads config fragments, such as `packages/kernel-6.1/config-bottlerocket`,
+//! which are merged over the config of the kernel's source package to build the final config.
+
+use crate::config::{Line, Value};
+use crate::{error, Result};
+use serde::Serialize;
+use snafu::{ensure, ResultExt};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The name of the fragment in the directory of a kernel package.
+pub const FRAGMENT_FILE: &str = "config-bottlerocket";
+
+// Kernel packages are named like `kernel-6.1`.
+const KERNEL_PACKAGE_PREFIX: &str = "kernel-";
+
+/// An option set by a fragment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FragmentLine {
+    pub option: String,
+    pub value: Value,
+    pub line: usize,
+}
+
+/// A line of a file, such as a fragment.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Location {
+    pub path: PathBuf,
+    pub line: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.path.display(), self.line)
+    }
+}
+
+/// The options of a fragment, in the order of its lines.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fragment {
+    pub path: PathBuf,
+    pub lines: Vec<FragmentLine>,
+}
+
+impl Fragment {
+    /// Reads the fragment at `path`.
+    pub fn from_file<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).context(error::ReadSnafu { path })?;
+        Self::parse(&text, path)
+    }
+
+    /// Parses the text of the fragment at `path`.
+    pub fn parse(text: &str, path: &Path) -> Result<Self> {
+        let mut lines = Vec::new();
+        for (index, text) in text.lines().enumerate() {
+            match Line::parse(text) {
+                Line::Empty => {}
+                Line::Option(option, value) => lines.push(FragmentLine {
+                    option: option.to_string(),
+                    value,
+                    line: index + 1,
+                }),
+                Line::Invalid => {
+                    return error::ConfigLineSnafu {
+                        path,
+                        line: index + 1,
+                        text,
+                    }
+                    .fail()
+                }
+            }
+        }
+        Ok(Fragment {
+            path: path.to_path_buf(),
+            lines,
+        })
+    }
+
+    /// Returns the line that sets `option`. If it is set more than once, the last line wins, as
+    /// when the fragment is merged.
+    pub fn get(&self, option: &str) -> Option<&FragmentLine> {
+        self.lines.iter().rev().find(|line| line.option == option)
+    }
+
+    /// Returns the location of `line` of the fragment.
+    pub fn location(&self, line: &FragmentLine) -> Location {
+        Location {
+            path: self.path.clone(),
+            line: line.line,
+        }
+    }
+}
+
+/// Reads the fragments of the kernel packages in `packages_dir`, with their kernel versions, in
+/// the order of the versions.
+pub fn kernel_fragments<P>(packages_dir: P) -> Result<Vec<(String, Fragment)>>
+where
+    P: AsRef<Path>,
+{
+    let packages_dir = packages_dir.as_ref();
+    let entries = fs::read_dir(packages_dir).context(error::ReadSnafu { path: packages_dir })?;
+
+    let mut fragments = Vec::new();
+    for entry in entries {
+        let entry = entry.context(error::ReadSnafu { path: packages_dir })?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        let Some(version) = file_name.strip_prefix(KERNEL_PACKAGE_PREFIX) else {
+            continue;
+        };
+        let path = entry.path().join(FRAGMENT_FILE);
+        if path.is_file() {
+            fragments.push((version.to_string(), Fragment::from_file(path)?));
+        }
+    }
+
+    ensure!(
+        !fragments.is_empty(),
+        error::FragmentsEmptySnafu { dir: packages_dir }
+    );
+    fragments.sort_by(|(a, _), (b, _)| crate::matrix::compare_versions(a, b));
+    Ok(fragments)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_kernel_fragments() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        for (package, text) in [
+            (
+                "kernel-6.1",
+                "# Comment\nCONFIG_A=y\n\n# CONFIG_B is not set\nCONFIG_A=m\n",
+            ),
+            ("kernel-5.10", "CONFIG_A=y\n"),
+            ("kernel-kit", ""),
+        ] {
+            fs::create_dir(dir.join(package)).unwrap();
+            if !text.is_empty() {
+                fs::write(dir.join(package).join(FRAGMENT_FILE), text).unwrap();
+            }
+        }
+
+        let fragments = kernel_fragments(dir).unwrap();
+        let versions: Vec<_> = fragments
+            .iter()
+            .map(|(version, _)| version.as_str())
+            .collect();
+        assert_eq!(versions, vec!["5.10", "6.1"]);
+
+        let fragment = &fragments[1].1;
+        assert_eq!(fragment.lines.len(), 3);
+        assert_eq!(
+            fragment.get("CONFIG_A"),
+            Some(&FragmentLine {
+                option: "CONFIG_A".to_string(),
+                value: Value::Module,
+                line: 5
+            })
+        );
+        assert_eq!(fragment.get("CONFIG_B").unwrap().value, Value::NotSet);
+        assert_eq!(fragment.get("CONFIG_C"), None);
+    }
+}
@@ -0,0 +1,1104 @@
+//! The `kconfig` module parses the `Kconfig` files of a kernel source tree, to explain why Kconfig
+//! didn't give an option the value that a fragment asked for: the dependencies it doesn't meet, the
+//! options that select it, or its type.
+//!
+//! Only the parts of the language that decide whether a value sticks are kept: the type and the
+//! prompt of each option, its dependencies, including the ones of the enclosing `if` and `menu`
+//! blocks, the `select`s, and the `choice` it is in. Defaults and help texts are skipped.
+
+use crate::config::{Config, Value};
+use crate::fragment::Location;
+use crate::{error, Result};
+use snafu::{OptionExt, ResultExt};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// The prefix of the options in a `.config`, which Kconfig files leave out.
+const CONFIG_PREFIX: &str = "CONFIG_";
+
+// The top-level Kconfig of a source tree.
+const KCONFIG_FILE: &str = "Kconfig";
+
+// The `.config` options that tell the architecture of a config, with its directory in `arch/`.
+const SRCARCHES: &[(&str, &str)] = &[("CONFIG_X86", "x86"), ("CONFIG_ARM64", "arm64")];
+
+/// The type of an option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Bool,
+    Tristate,
+    String,
+    Int,
+    Hex,
+    /// The option has no type line, which Kconfig warns about.
+    Unknown,
+}
+
+/// A `config` or `menuconfig` entry. Options may be defined more than once, such as in the Kconfig
+/// of several architectures.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    pub location: Location,
+    /// The dependencies of the entry, and of the blocks around it.
+    pub depends: Option<Expr>,
+    /// Whether the entry has a prompt. Kconfig ignores the values that configs give to options
+    /// without one.
+    pub prompt: bool,
+    /// The condition of the prompt, as in `bool "Prompt" if EXPERT`.
+    pub prompt_if: Option<Expr>,
+    /// The index of the `choice` that the entry is in.
+    pub choice: Option<usize>,
+}
+
+/// An option that selects another one, if the condition of the `select` holds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Select {
+    pub by: String,
+    pub condition: Option<Expr>,
+}
+
+/// An option, by the name used in Kconfig files, without `CONFIG_`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub kind: Kind,
+    pub definitions: Vec<Definition>,
+    pub selected_by: Vec<Select>,
+}
+
+/// A Kconfig expression, as in `depends on PCI && (X86 || COMPILE_TEST)`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    /// An option, a constant such as `y` or `0x10`, or a quoted string.
+    Term(Term),
+    /// `A = B`, `A != B`, `A < B` and so on.
+    Compare(Compare, Term, Term),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+/// An operand of an expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Term {
+    /// An option or an unquoted constant.
+    Symbol(String),
+    /// A quoted string.
+    Quoted(String),
+    /// A macro such as `$(cc-option,-mfoo)`, which depends on the toolchain, and is taken as `y`.
+    Macro(String),
+}
+
+/// The operator of a comparison.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compare {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+/// The options defined by the Kconfig files of a source tree, by name.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Kconfig {
+    pub symbols: HashMap<String, Symbol>,
+}
+
+/// Returns the directory in `arch/` of the kernel source for the architecture of `config`.
+pub fn srcarch(config: &Config, path: &Path) -> Result<&'static str> {
+    SRCARCHES
+        .iter()
+        .find(|(option, _)| config.value(option) == Some(&Value::Builtin))
+        .map(|(_, srcarch)| *srcarch)
+        .context(error::KconfigArchSnafu { path })
+}
+
+impl Kconfig {
+    /// Reads the Kconfig files of the kernel source in `dir`, starting from the top-level
+    /// `Kconfig`. `srcarch` is the directory in `arch/` of the architecture, such as `x86`, which
+    /// replaces `$(SRCARCH)` in `source` statements.
+    pub fn from_source<P>(dir: P, srcarch: &str) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let mut parser = Parser {
+            srctree: dir.as_ref().to_path_buf(),
+            srcarch: srcarch.to_string(),
+            kconfig: Kconfig::default(),
+            frames: Vec::new(),
+            choices: Vec::new(),
+            choice_count: 0,
+        };
+        parser.parse_file(&dir.as_ref().join(KCONFIG_FILE))?;
+        Ok(parser.kconfig)
+    }
+
+    /// Explains why `config` doesn't give `option`, such as `CONFIG_PCI`, the `requested` value.
+    /// The explanations are empty if the Kconfig files don't tell.
+    pub fn explain(&self, option: &str, requested: &Value, config: &Config) -> Vec<String> {
+        let name = option.strip_prefix(CONFIG_PREFIX).unwrap_or(option);
+        let Some(symbol) = self.symbols.get(name) else {
+            return vec![format!(
+                "`{}` isn't defined by the Kconfig of this kernel",
+                option
+            )];
+        };
+        let eval = Eval {
+            kconfig: self,
+            config,
+        };
+        let wanted = level(Some(requested).filter(|value| value.is_set()));
+        let actual = level(config.value(option));
+
+        let mut reasons = Vec::new();
+        if *requested == Value::Module && symbol.kind == Kind::Bool {
+            reasons.push(format!("`{}` is a bool, so it can't be a module", option));
+        } else if *requested == Value::Module && config.value("CONFIG_MODULES").is_none() {
+            reasons.push("`CONFIG_MODULES` is not set, so nothing can be a module".to_string());
+        }
+
+        if wanted > actual {
+            // Kconfig takes the dependencies of all definitions, so it's only worth listing them
+            // if none of them is met.
+            if symbol
+                .definitions
+                .iter()
+                .all(|definition| eval.expr_or_y(definition.depends.as_ref()) < wanted)
+            {
+                for definition in &symbol.definitions {
+                    if let Some(depends) = &definition.depends {
+                        eval.unmet(depends, wanted, &mut reasons);
+                    }
+                }
+            }
+
+            // Options of a choice exclude each other.
+            for choice in symbol
+                .definitions
+                .iter()
+                .filter_map(|definition| definition.choice)
+            {
+                for other in self.choice_members(choice) {
+                    let other_option = format!("{}{}", CONFIG_PREFIX, other);
+                    if other != name && config.value(&other_option) == Some(&Value::Builtin) {
+                        reasons.push(format!(
+                            "`{}` is in a choice with `{}`, which is set",
+                            option, other_option
+                        ));
+                    }
+                }
+            }
+        } else if wanted < actual {
+            for select in &symbol.selected_by {
+                let by = eval.term(&Term::Symbol(select.by.clone()));
+                if by.min(eval.expr_or_y(select.condition.as_ref())) > wanted {
+                    let mut reason = format!("selected by `{}{}`", CONFIG_PREFIX, select.by);
+                    if let Some(condition) = &select.condition {
+                        reason.push_str(&format!(" if `{}`", condition));
+                    }
+                    reasons.push(reason);
+                }
+            }
+        }
+
+        if !symbol
+            .definitions
+            .iter()
+            .any(|definition| definition.prompt)
+        {
+            reasons.push(format!(
+                "`{}` has no prompt, so only its defaults and selects set it",
+                option
+            ));
+        } else if symbol.definitions.iter().all(|definition| {
+            !definition.prompt || eval.expr_or_y(definition.prompt_if.as_ref()) == 0
+        }) {
+            for definition in &symbol.definitions {
+                if let Some(prompt_if) = &definition.prompt_if {
+                    reasons.push(format!(
+                        "the prompt of `{}` depends on `{}`",
+                        option, prompt_if
+                    ));
+                }
+            }
+        }
+
+        reasons.dedup();
+        reasons
+    }
+
+    // The names of the options of the choice with `index`, in order.
+    fn choice_members(&self, index: usize) -> BTreeSet<&str> {
+        self.symbols
+            .iter()
+            .filter(|(_, symbol)| {
+                symbol
+                    .definitions
+                    .iter()
+                    .any(|definition| definition.choice == Some(index))
+            })
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+}
+
+// The tristate level of a value, 2 for `y`, 1 for `m` and 0 for not set. Strings and numbers only
+// need their dependencies not to be `n`.
+fn level(value: Option<&Value>) -> u8 {
+    match value {
+        Some(Value::Builtin) => 2,
+        Some(Value::Module) | Some(Value::Text(_)) => 1,
+        _ => 0,
+    }
+}
+
+// Evaluates expressions against the values of a config.
+struct Eval<'a> {
+    kconfig: &'a Kconfig,
+    config: &'a Config,
+}
+
+impl Eval<'_> {
+    fn expr(&self, expr: &Expr) -> u8 {
+        match expr {
+            Expr::Term(term) => self.term(term),
+            Expr::Compare(compare, left, right) => {
+                let (left, right) = (self.text(left), self.text(right));
+                let ordering = match (parse_number(&left), parse_number(&right)) {
+                    (Some(left), Some(right)) => left.cmp(&right),
+                    _ => left.cmp(&right),
+                };
+                let holds = match compare {
+                    Compare::Equal => ordering.is_eq(),
+                    Compare::NotEqual => ordering.is_ne(),
+                    Compare::Less => ordering.is_lt(),
+                    Compare::LessEqual => ordering.is_le(),
+                    Compare::Greater => ordering.is_gt(),
+                    Compare::GreaterEqual => ordering.is_ge(),
+                };
+                if holds {
+                    2
+                } else {
+                    0
+                }
+            }
+            Expr::Not(expr) => 2 - self.expr(expr),
+            Expr::And(left, right) => self.expr(left).min(self.expr(right)),
+            Expr::Or(left, right) => self.expr(left).max(self.expr(right)),
+        }
+    }
+
+    // Missing dependencies and conditions are always met.
+    fn expr_or_y(&self, expr: Option<&Expr>) -> u8 {
+        expr.map_or(2, |expr| self.expr(expr))
+    }
+
+    fn term(&self, term: &Term) -> u8 {
+        match term {
+            Term::Symbol(name) => match name.as_str() {
+                "y" => 2,
+                "m" => 1,
+                "n" => 0,
+                name => match self.config.value(&format!("{}{}", CONFIG_PREFIX, name)) {
+                    Some(Value::Builtin) => 2,
+                    Some(Value::Module) => 1,
+                    _ => 0,
+                },
+            },
+            Term::Quoted(_) => 0,
+            Term::Macro(_) => 2,
+        }
+    }
+
+    // The value of a term in a comparison: the value of an option, or the constant itself.
+    fn text(&self, term: &Term) -> String {
+        match term {
+            Term::Symbol(name) if self.kconfig.symbols.contains_key(name) => {
+                match self.config.value(&format!("{}{}", CONFIG_PREFIX, name)) {
+                    Some(Value::Text(text)) => text.trim_matches('"').to_string(),
+                    Some(value) => value.to_string(),
+                    None => "n".to_string(),
+                }
+            }
+            Term::Symbol(text) | Term::Quoted(text) | Term::Macro(text) => text.clone(),
+        }
+    }
+
+    // Lists the parts of `expr` that keep it below `wanted`. The operands of `&&` are listed on
+    // their own, so that only the ones that aren't met show up.
+    fn unmet(&self, expr: &Expr, wanted: u8, reasons: &mut Vec<String>) {
+        match expr {
+            Expr::And(left, right) => {
+                for operand in [left, right] {
+                    if self.expr(operand) < wanted {
+                        self.unmet(operand, wanted, reasons);
+                    }
+                }
+            }
+            expr => {
+                let mut names = BTreeSet::new();
+                expr.symbols(&mut names);
+                let values: Vec<_> = names
+                    .into_iter()
+                    .filter(|name| self.kconfig.symbols.contains_key(*name))
+                    .map(|name| {
+                        let option = format!("{}{}", CONFIG_PREFIX, name);
+                        match self.config.value(&option) {
+                            Some(value) => format!("`{}` is `{}`", option, value),
+                            None => format!("`{}` is not set", option),
+                        }
+                    })
+                    .collect();
+                let mut reason = format!("depends on `{}`", expr);
+                if !values.is_empty() {
+                    reason.push_str(&format!(", but {}", values.join(" and ")));
+                }
+                reasons.push(reason);
+            }
+        }
+    }
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+impl Expr {
+    /// Parses an expression, such as the one after `depends on`.
+    pub fn parse(text: &str) -> Option<Self> {
+        let tokens = tokenize(text)?;
+        let mut parser = ExprParser {
+            tokens: &tokens,
+            position: 0,
+        };
+        let expr = parser.or()?;
+        (parser.position == tokens.len()).then_some(expr)
+    }
+
+    // Collects the names of the options in the expression.
+    fn symbols<'a>(&'a self, names: &mut BTreeSet<&'a str>) {
+        let mut term = |term: &'a Term| {
+            if let Term::Symbol(name) = term {
+                names.insert(name.as_str());
+            }
+        };
+        match self {
+            Expr::Term(operand) => term(operand),
+            Expr::Compare(_, left, right) => {
+                term(left);
+                term(right);
+            }
+            Expr::Not(expr) => expr.symbols(names),
+            Expr::And(left, right) | Expr::Or(left, right) => {
+                left.symbols(names);
+                right.symbols(names);
+            }
+        }
+    }
+
+    // Higher binds tighter, for parentheses when displaying.
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Or(..) => 0,
+            Expr::And(..) => 1,
+            _ => 2,
+        }
+    }
+
+    fn fmt_operand(&self, f: &mut fmt::Formatter<'_>, precedence: u8) -> fmt::Result {
+        if self.precedence() < precedence {
+            write!(f, "({})", self)
+        } else {
+            write!(f, "{}", self)
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Term(term) => write!(f, "{}", term),
+            Expr::Compare(compare, left, right) => write!(f, "{} {} {}", left, compare, right),
+            Expr::Not(expr) => {
+                f.write_str("!")?;
+                expr.fmt_operand(f, 2)
+            }
+            Expr::And(left, right) => {
+                left.fmt_operand(f, 1)?;
+                f.write_str(" && ")?;
+                right.fmt_operand(f, 1)
+            }
+            Expr::Or(left, right) => {
+                left.fmt_operand(f, 0)?;
+                f.write_str(" || ")?;
+                right.fmt_operand(f, 0)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Term::Symbol(text) | Term::Macro(text) => f.write_str(text),
+            Term::Quoted(text) => write!(f, "\"{}\"", text),
+        }
+    }
+}
+
+impl fmt::Display for Compare {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Compare::Equal => "=",
+            Compare::NotEqual => "!=",
+            Compare::Less => "<",
+            Compare::LessEqual => "<=",
+            Compare::Greater => ">",
+            Compare::GreaterEqual => ">=",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Term(Term),
+    Compare(Compare),
+    Not,
+    And,
+    Or,
+    Open,
+    Close,
+}
+
+fn tokenize(text: &str) -> Option<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let next = chars.peek().map(|(_, next)| *next);
+        let token = match (c, next) {
+            (c, _) if c.is_whitespace() => continue,
+            ('(', _) => Token::Open,
+            (')', _) => Token::Close,
+            ('&', Some('&')) => {
+                chars.next();
+                Token::And
+            }
+            ('|', Some('|')) => {
+                chars.next();
+                Token::Or
+            }
+            ('!', Some('=')) => {
+                chars.next();
+                Token::Compare(Compare::NotEqual)
+            }
+            ('!', _) => Token::Not,
+            ('=', _) => Token::Compare(Compare::Equal),
+            ('<', Some('=')) => {
+                chars.next();
+                Token::Compare(Compare::LessEqual)
+            }
+            ('<', _) => Token::Compare(Compare::Less),
+            ('>', Some('=')) => {
+                chars.next();
+                Token::Compare(Compare::GreaterEqual)
+            }
+            ('>', _) => Token::Compare(Compare::Greater),
+            ('"' | '\'', _) => {
+                let mut quoted = String::new();
+                loop {
+                    match chars.next()? {
+                        (_, '\\') => quoted.push(chars.next()?.1),
+                        (_, end) if end == c => break,
+                        (_, other) => quoted.push(other),
+                    }
+                }
+                Token::Term(Term::Quoted(quoted))
+            }
+            ('$', Some('(')) => {
+                // Macros may nest, as in `$(success,$(CC) -v)`.
+                let mut depth = 0;
+                let mut end = start;
+                for (index, c) in chars.by_ref() {
+                    match c {
+                        '(' => depth += 1,
+                        ')' => depth -= 1,
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        end = index;
+                        break;
+                    }
+                }
+                if depth != 0 {
+                    return None;
+                }
+                Token::Term(Term::Macro(text[start..=end].to_string()))
+            }
+            _ => {
+                let mut end = start + c.len_utf8();
+                while let Some((index, c)) = chars.peek() {
+                    if c.is_whitespace() || "()&|!=<>\"'".contains(*c) {
+                        break;
+                    }
+                    end = index + c.len_utf8();
+                    chars.next();
+                }
+                Token::Term(Term::Symbol(text[start..end].to_string()))
+            }
+        };
+        tokens.push(token);
+    }
+    Some(tokens)
+}
+
+// A recursive descent parser: `||` binds looser than `&&`, which binds looser than `!`.
+struct ExprParser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+}
+
+impl ExprParser<'_> {
+    fn next_if(&mut self, token: &Token) -> bool {
+        let matches = self.tokens.get(self.position) == Some(token);
+        if matches {
+            self.position += 1;
+        }
+        matches
+    }
+
+    fn or(&mut self) -> Option<Expr> {
+        let mut expr = self.and()?;
+        while self.next_if(&Token::Or) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Some(expr)
+    }
+
+    fn and(&mut self) -> Option<Expr> {
+        let mut expr = self.unary()?;
+        while self.next_if(&Token::And) {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Some(expr)
+    }
+
+    fn unary(&mut self) -> Option<Expr> {
+        if self.next_if(&Token::Not) {
+            return Some(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.next_if(&Token::Open) {
+            let expr = self.or()?;
+            return self.next_if(&Token::Close).then_some(expr);
+        }
+        let left = self.term()?;
+        if let Some(Token::Compare(compare)) = self.tokens.get(self.position) {
+            self.position += 1;
+            return Some(Expr::Compare(*compare, left, self.term()?));
+        }
+        Some(Expr::Term(left))
+    }
+
+    fn term(&mut self) -> Option<Term> {
+        match self.tokens.get(self.position) {
+            Some(Token::Term(term)) => {
+                self.position += 1;
+                Some(term.clone())
+            }
+            _ => None,
+        }
+    }
+}
+
+// A `config` entry whose attributes are still being read.
+struct Entry {
+    name: String,
+    kind: Kind,
+    definition: Definition,
+    selects: Vec<(String, Option<Expr>)>,
+}
+
+// The block that the lines being read belong to.
+enum Block {
+    None,
+    Config(Box<Entry>),
+    // `menu` and `choice` take `depends on` lines before their entries.
+    Menu(Vec<Expr>),
+    Choice(Vec<Expr>),
+    Comment,
+}
+
+// A line of a Kconfig file, after joining continuation lines and removing comments.
+struct SourceLine<'a> {
+    path: &'a Path,
+    line: usize,
+    text: String,
+}
+
+struct Parser {
+    srctree: PathBuf,
+    srcarch: String,
+    kconfig: Kconfig,
+    // The conditions of the enclosing `if`, `menu` and `choice` blocks.
+    frames: Vec<Option<Expr>>,
+    // The indices of the enclosing `choice` blocks.
+    choices: Vec<usize>,
+    choice_count: usize,
+}
+
+impl Parser {
+    fn parse_file(&mut self, path: &Path) -> Result<()> {
+        let text = fs::read_to_string(path).context(error::ReadSnafu { path })?;
+        let mut block = Block::None;
+        let mut help_indent: Option<Option<usize>> = None;
+        let mut lines = text.split('\n').enumerate();
+
+        while let Some((index, raw)) = lines.next() {
+            // Help texts end at the first line that is indented less than their first line.
+            if let Some(indent) = help_indent {
+                if raw.trim().is_empty() {
+                    continue;
+                }
+                let line_indent = indentation(raw);
+                match indent {
+                    None => {
+                        help_indent = Some(Some(line_indent));
+                        continue;
+                    }
+                    Some(indent) if line_indent >= indent => continue,
+                    Some(_) => help_indent = None,
+                }
+            }
+
+            let mut text = raw.to_string();
+            while text.ends_with('\\') {
+                text.pop();
+                match lines.next() {
+                    Some((_, next)) => text.push_str(next),
+                    None => break,
+                }
+            }
+            let text = strip_comment(&text).trim().to_string();
+            if text.is_empty() {
+                continue;
+            }
+            let line = SourceLine {
+                path,
+                line: index + 1,
+                text,
+            };
+
+            let (keyword, rest) = split_word(&line.text);
+            match keyword {
+                "config" | "menuconfig" => {
+                    self.finish(block);
+                    block = Block::Config(Box::new(Entry {
+                        name: rest.to_string(),
+                        kind: Kind::Unknown,
+                        definition: Definition {
+                            location: Location {
+                                path: path.to_path_buf(),
+                                line: line.line,
+                            },
+                            depends: None,
+                            prompt: false,
+                            prompt_if: None,
+                            choice: self.choices.last().copied(),
+                        },
+                        selects: Vec::new(),
+                    }));
+                }
+                "choice" => {
+                    self.finish(block);
+                    block = Block::Choice(Vec::new());
+                }
+                "menu" => {
+                    self.finish(block);
+                    block = Block::Menu(Vec::new());
+                }
+                "comment" => {
+                    self.finish(block);
+                    block = Block::Comment;
+                }
+                "if" => {
+                    self.finish(block);
+                    block = Block::None;
+                    let condition = parse_expr(&line, rest)?;
+                    self.frames.push(Some(condition));
+                }
+                "endif" | "endmenu" | "endchoice" => {
+                    self.finish(block);
+                    block = Block::None;
+                    self.frames.pop();
+                    if keyword == "endchoice" {
+                        self.choices.pop();
+                    }
+                }
+                "source" | "rsource" | "osource" | "orsource" => {
+                    self.finish(block);
+                    block = Block::None;
+                    let (file, _) = unquote(rest);
+                    let file = file.replace("$(SRCARCH)", &self.srcarch);
+                    // `rsource` is relative to the current file, `source` to the top of the tree.
+                    let file = if keyword.ends_with("rsource") {
+                        path.parent().unwrap_or(Path::new("")).join(file)
+                    } else {
+                        self.srctree.join(file)
+                    };
+                    if keyword.starts_with('o') && !file.is_file() {
+                        continue;
+                    }
+                    self.parse_file(&file)?;
+                }
+                "help" | "---help---" => help_indent = Some(None),
+                _ => match &mut block {
+                    Block::Config(entry) => Self::attribute(entry, &line, keyword, rest)?,
+                    Block::Menu(depends) | Block::Choice(depends) => {
+                        if let Some(expr) =
+                            rest.strip_prefix("on ").filter(|_| keyword == "depends")
+                        {
+                            depends.push(parse_expr(&line, expr)?);
+                        }
+                    }
+                    Block::None | Block::Comment => {}
+                },
+            }
+        }
+        self.finish(block);
+        Ok(())
+    }
+
+    // Reads an attribute of a `config` entry.
+    fn attribute(entry: &mut Entry, line: &SourceLine, keyword: &str, rest: &str) -> Result<()> {
+        let kind = match keyword {
+            "bool" | "boolean" | "def_bool" => Some(Kind::Bool),
+            "tristate" | "def_tristate" => Some(Kind::Tristate),
+            "string" => Some(Kind::String),
+            "int" => Some(Kind::Int),
+            "hex" => Some(Kind::Hex),
+            _ => None,
+        };
+        if let Some(kind) = kind {
+            entry.kind = kind;
+        }
+
+        match keyword {
+            "bool" | "boolean" | "tristate" | "string" | "int" | "hex" | "prompt"
+                if !rest.is_empty() =>
+            {
+                let (_, condition) = unquote(rest);
+                entry.definition.prompt = true;
+                if let Some(condition) = condition.strip_prefix("if ") {
+                    entry.definition.prompt_if = Some(parse_expr(line, condition)?);
+                }
+            }
+            "depends" => {
+                if let Some(expr) = rest.strip_prefix("on ") {
+                    let expr = parse_expr(line, expr)?;
+                    entry.definition.depends =
+                        all(entry.definition.depends.take().into_iter().chain([expr]));
+                }
+            }
+            "select" => {
+                let (name, condition) = split_word(rest);
+                let condition = match condition.strip_prefix("if ") {
+                    Some(condition) => Some(parse_expr(line, condition)?),
+                    None => None,
+                };
+                entry.selects.push((name.to_string(), condition));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // Adds the entry that was being read, or opens the block of a `menu` or `choice`.
+    fn finish(&mut self, block: Block) {
+        match block {
+            Block::Config(mut entry) => {
+                entry.definition.depends = all(self
+                    .frames
+                    .iter()
+                    .flatten()
+                    .cloned()
+                    .chain(entry.definition.depends.take()));
+
+                for (selected, condition) in entry.selects {
+                    self.symbol(&selected).selected_by.push(Select {
+                        by: entry.name.clone(),
+                        condition,
+                    });
+                }
+                let symbol = self.symbol(&entry.name);
+                if symbol.kind == Kind::Unknown {
+                    symbol.kind = entry.kind;
+                }
+                symbol.definitions.push(entry.definition);
+            }
+            Block::Menu(depends) => self.frames.push(all(depends)),
+            Block::Choice(depends) => {
+                self.frames.push(all(depends));
+                self.choices.push(self.choice_count);
+                self.choice_count += 1;
+            }
+            Block::None | Block::Comment => {}
+        }
+    }
+
+    fn symbol(&mut self, name: &str) -> &mut Symbol {
+        self.kconfig
+            .symbols
+            .entry(name.to_string())
+            .or_insert_with(|| Symbol {
+                kind: Kind::Unknown,
+                definitions: Vec::new(),
+                selected_by: Vec::new(),
+            })
+    }
+}
+
+// Joins expressions with `&&`.
+fn all<I>(exprs: I) -> Option<Expr>
+where
+    I: IntoIterator<Item = Expr>,
+{
+    exprs
+        .into_iter()
+        .reduce(|left, right| Expr::And(Box::new(left), Box::new(right)))
+}
+
+fn parse_expr(line: &SourceLine, text: &str) -> Result<Expr> {
+    Expr::parse(text).context(error::KconfigExprSnafu {
+        path: line.path,
+        line: line.line,
+        text,
+    })
+}
+
+// Splits the first word off a line.
+fn split_word(text: &str) -> (&str, &str) {
+    match text.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim_start()),
+        None => (text, ""),
+    }
+}
+
+// Splits a leading quoted string off `text`, returning its contents and the rest.
+fn unquote(text: &str) -> (String, &str) {
+    let mut chars = text.char_indices();
+    let quote = match chars.next() {
+        Some((_, quote @ ('"' | '\''))) => quote,
+        _ => {
+            let (word, rest) = split_word(text);
+            return (word.to_string(), rest);
+        }
+    };
+    let mut contents = String::new();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some((_, escaped)) = chars.next() {
+                    contents.push(escaped);
+                }
+            }
+            c if c == quote => return (contents, text[index + 1..].trim_start()),
+            c => contents.push(c),
+        }
+    }
+    (contents, "")
+}
+
+// Removes a `#` comment that isn't in quotes.
+fn strip_comment(text: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (index, c) in text.char_indices() {
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => return &text[..index],
+            _ => {}
+        }
+    }
+    text
+}
+
+// The indentation of a line, with tabs to the next multiple of 8, as Kconfig counts it.
+fn indentation(line: &str) -> usize {
+    let mut width = 0;
+    for c in line.chars() {
+        match c {
+            ' ' => width += 1,
+            '\t' => width = (width / 8 + 1) * 8,
+            _ => break,
+        }
+    }
+    width
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const KCONFIG: &str = r#"mainmenu "Linux/$(ARCH) Kernel Configuration"
+
+source "arch/$(SRCARCH)/Kconfig"
+
+config MODULES
+	bool "Enable loadable module support"
+	help
+	  Not an option:
+
+	  config NOT_AN_OPTION
+
+config PCI
+	bool "PCI support"
+	depends on HAVE_PCI
+
+menu "Drivers"
+	depends on !UML
+
+rsource "drivers/Kconfig"
+
+endmenu
+"#;
+
+    const ARCH_KCONFIG: &str = "\
+config X86
+	def_bool y
+	select HAVE_PCI
+	select ACPI if PCI
+
+config HAVE_PCI
+	bool
+
+config ACPI
+	bool \"ACPI\"
+";
+
+    const DRIVERS_KCONFIG: &str = r#"if PCI
+
+config NVME
+	tristate "NVMe" # Comment
+	depends on BLOCK && \
+		(X86 || ARM64)
+
+config NVME_HWMON
+	bool "NVMe hwmon"
+	depends on NVME = y
+
+endif
+
+config BLOCK
+	bool "Block layer" if EXPERT
+
+config EXPERT
+	bool "Expert"
+
+choice
+	prompt "Compression"
+
+config GZIP
+	bool "gzip"
+
+config XZ
+	bool "xz"
+
+endchoice
+"#;
+
+    fn explain(kconfig: &Kconfig, option: &str, requested: Value, config: &str) -> Vec<String> {
+        let config = Config::parse(config, Path::new("config")).unwrap();
+        kconfig.explain(option, &requested, &config)
+    }
+
+    #[test]
+    fn test_explain() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        fs::create_dir_all(dir.join("arch/x86")).unwrap();
+        fs::create_dir(dir.join("drivers")).unwrap();
+        fs::write(dir.join("Kconfig"), KCONFIG).unwrap();
+        fs::write(dir.join("arch/x86/Kconfig"), ARCH_KCONFIG).unwrap();
+        fs::write(dir.join("drivers/Kconfig"), DRIVERS_KCONFIG).unwrap();
+
+        let config =
+            "CONFIG_X86=y\nCONFIG_HAVE_PCI=y\nCONFIG_PCI=y\nCONFIG_ACPI=y\nCONFIG_MODULES=y\n\
+                      CONFIG_GZIP=y\n";
+        let parsed = Config::parse(config, Path::new("config")).unwrap();
+        let srcarch = srcarch(&parsed, Path::new("config")).unwrap();
+        assert_eq!(srcarch, "x86");
+        let kconfig = Kconfig::from_source(dir, srcarch).unwrap();
+
+        assert_eq!(
+            kconfig.symbols["NVME"].definitions[0]
+                .depends
+                .as_ref()
+                .unwrap()
+                .to_string(),
+            "!UML && PCI && BLOCK && (X86 || ARM64)"
+        );
+        assert_eq!(
+            explain(&kconfig, "CONFIG_NVME", Value::Module, config),
+            vec!["depends on `BLOCK`, but `CONFIG_BLOCK` is not set"]
+        );
+        assert_eq!(
+            explain(
+                &kconfig,
+                "CONFIG_NVME_HWMON",
+                Value::Builtin,
+                "CONFIG_PCI=y\nCONFIG_BLOCK=y\nCONFIG_X86=y\nCONFIG_NVME=m\n"
+            ),
+            vec!["depends on `NVME = y`, but `CONFIG_NVME` is `m`"]
+        );
+        assert_eq!(
+            explain(&kconfig, "CONFIG_BLOCK", Value::Builtin, config),
+            vec!["the prompt of `CONFIG_BLOCK` depends on `EXPERT`"]
+        );
+        assert_eq!(
+            explain(&kconfig, "CONFIG_ACPI", Value::NotSet, config),
+            vec!["selected by `CONFIG_X86` if `PCI`"]
+        );
+        assert_eq!(
+            explain(&kconfig, "CONFIG_HAVE_PCI", Value::NotSet, config),
+            vec![
+                "selected by `CONFIG_X86`",
+                "`CONFIG_HAVE_PCI` has no prompt, so only its defaults and selects set it"
+            ]
+        );
+        assert_eq!(
+            explain(&kconfig, "CONFIG_PCI", Value::Module, config),
+            vec!["`CONFIG_PCI` is a bool, so it can't be a module"]
+        );
+        assert_eq!(
+            explain(&kconfig, "CONFIG_XZ", Value::Builtin, config),
+            vec!["`CONFIG_XZ` is in a choice with `CONFIG_GZIP`, which is set"]
+        );
+        assert_eq!(
+            explain(&kconfig, "CONFIG_NOT_AN_OPTION", Value::Builtin, config),
+            vec!["`CONFIG_NOT_AN_OPTION` isn't defined by the Kconfig of this kernel"]
+        );
+    }
+
+    #[test]
+    fn test_expr() {
+        let text = "!(A || B) && C != \"x\" || $(cc-option,-m(64)) && D<=0x10";
+        let expr = Expr::parse(text).unwrap();
+        assert_eq!(
+            expr.to_string(),
+            "!(A || B) && C != \"x\" || $(cc-option,-m(64)) && D <= 0x10"
+        );
+        assert_eq!(Expr::parse(&expr.to_string()), Some(expr));
+
+        assert_eq!(Expr::parse("A &&"), None);
+        assert_eq!(Expr::parse("(A || B"), None);
+        assert_eq!(Expr::parse("A B"), None);
+    }
+}
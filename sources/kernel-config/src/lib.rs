@@ -0,0 +1,98 @@
+/*!
+`kernel-config` reads the `.config` files of kernel builds and compares them. It is the library
+behind the `kernel-config` tool, which reports how a change to the kit affects the configs of its
+kernels.
+
+* [`config::Config`] holds the options of a `.config` file, including the ones that are
+  `# CONFIG_X is not set`, with the line that set them.
+* [`diff::diff`] compares two configs semantically: options that were added or removed, that
+  changed between built-in and module, or whose value changed. Whether an unset option is listed
+  as not set or left out doesn't matter.
+* [`matrix::Matrix`] holds the diffs of every architecture and kernel version of a pair of builds,
+  as collected by `tools/diff-kernel-config`.
+* [`drift::Drift`] aligns the fragments and the final configs of every kernel series, and lists
+  the options that differ between them, unless an allowlist says the difference is intended.
+* [`policy::Policy`] checks the fragments of the kernel packages, and the final configs, for
+  options that must be set, must not be set, or must be built in. Violations point to the line of
+  the [`fragment::Fragment`] that set the option.
+* [`merge::check_merge`] lists the fragment lines whose values didn't stick in the final config,
+  and [`kconfig::Kconfig`] explains why from the Kconfig files of the kernel source.
+* [`report`] renders diffs, drift, violations and overrides as Markdown tables for pull requests, or as JSON.
+*/
+
+pub mod config;
+pub mod diff;
+pub mod drift;
+pub mod fragment;
+pub mod kconfig;
+pub mod matrix;
+pub mod merge;
+pub mod policy;
+pub mod report;
+
+pub type Result<T> = std::result::Result<T, error::Error>;
+
+pub mod error {
+    use snafu::Snafu;
+    use std::path::PathBuf;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(crate)))]
+    pub enum Error {
+        #[snafu(display("Failed to read {}: {}", path.display(), source))]
+        Read {
+            path: PathBuf,
+            source: std::io::Error,
+        },
+
+        #[snafu(display("Failed to parse the allowlist at {}: {}", path.display(), source))]
+        AllowlistParse {
+            path: PathBuf,
+            source: Box<toml::de::Error>,
+        },
+
+        #[snafu(display("{}:{}: not a config option: '{}'", path.display(), line, text))]
+        ConfigLine {
+            path: PathBuf,
+            line: usize,
+            text: String,
+        },
+
+        #[snafu(display(
+            "Unable to tell the architecture and kernel version of {} from its name",
+            path.display()
+        ))]
+        ConfigName { path: PathBuf },
+
+        #[snafu(display("No kernel packages with a config fragment found in {}", dir.display()))]
+        FragmentsEmpty { dir: PathBuf },
+
+        #[snafu(display(
+            "Unable to tell the architecture of {}: neither CONFIG_X86 nor CONFIG_ARM64 is set",
+            path.display()
+        ))]
+        KconfigArch { path: PathBuf },
+
+        #[snafu(display("{}:{}: unable to parse the expression '{}'", path.display(), line, text))]
+        KconfigExpr {
+            path: PathBuf,
+            line: usize,
+            text: String,
+        },
+
+        #[snafu(display("No configs named config-ARCH-VERSION-STATE found in {}", dir.display()))]
+        MatrixEmpty { dir: PathBuf },
+
+        #[snafu(display("{} has no matching '{}' config", path.display(), state))]
+        MatrixState { path: PathBuf, state: String },
+
+        #[snafu(display("Failed to parse the policy at {}: {}", path.display(), source))]
+        PolicyParse {
+            path: PathBuf,
+            source: Box<toml::de::Error>,
+        },
+
+        #[snafu(display("Failed to serialize the report: {}", source))]
+        Serialize { source: serde_json::Error },
+    }
+}
@@ -0,0 +1,379 @@
+/*!
+# Kernel config
+`kernel-config` compares the configs of the kernels that the kit builds, and reports how a change
+affects them. Unlike a line diff, the comparison is semantic: it lists the options that were added
+or removed, that moved between built-in (`y`) and module (`m`), and whose value changed. An option
+that is `# CONFIG_X is not set` and one that isn't listed at all are the same.
+
+The reports are Markdown tables, to paste into pull requests, or JSON.
+
+## Comparing two configs
+`diff` compares two `.config` files, such as `/boot/config` of two kernel packages:
+```shell
+kernel-config diff config-before config-after
+```
+
+## Comparing two builds of the kit
+`tools/diff-kernel-config` builds the kernels of two Git revisions for every architecture and
+kernel version, and extracts their configs into a directory, as `config-ARCH-VERSION-before` and
+`config-ARCH-VERSION-after`. `report` compares every pair in such a directory:
+```shell
+kernel-config report --format json configs
+```
+The Markdown report has a summary of the changes to each config, then a table with a row per
+change, which marks the configs it applies to.
+
+## Checking the policy
+The comments of the `config-bottlerocket` fragments explain why options are set, such as the
+modules required to mount the root filesystem, which must be built in since Bottlerocket has no
+initramfs. `policy.toml` in this crate's directory turns these into rules: the options that must
+be set, must not be set, or must be built in, for all kernels or only for some versions and
+architectures.
+
+`lint` checks the fragments of the kernel packages against the policy, and the final configs if
+any are given, such as the ones that `tools/collect-kernel-config` extracts. The architecture and
+kernel version of a config are taken from its file name. Every violation names the fragment line
+that set the option, or tells that the fragment doesn't set it, so that the value comes from the
+config of the kernel's source package:
+```shell
+kernel-config lint --policy kernel-config/policy.toml --packages ../packages configs/config-*
+```
+`lint` exits with 1 if there are violations, and with 2 if it fails to check them.
+
+## Finding drift between kernel series
+The fragments of the kernel series are maintained by hand, and drift apart, such as when an option
+is hardened in 6.1 but not in 5.15. `drift` aligns the fragments of every kernel package, and the
+final configs if any are given, and prints a matrix of the options that differ between the series,
+with a column per fragment, and per architecture and series of the final configs:
+```shell
+kernel-config drift --packages ../packages --allowlist kernel-config/drift-allowlist.toml \
+  configs/config-*
+```
+Options that only some fragments set are listed on their own: the other series inherit them from
+their source package, which may or may not match, as the final configs show. They don't fail the
+check. Final configs are only compared with the ones of the same architecture, and only where the
+kernel has the option. An option that Kconfig left out of a config, since its dependencies aren't
+met, is not set; only one that no config of the series has is taken as missing from the kernel.
+Intended differences go into the allowlist with the reason for them; they get a table of their
+own, and so do allowlist entries that no longer differ. `drift` exits with 1 if options differ
+that the allowlist doesn't allow, and with 2 if it fails to compare them.
+
+## Checking the merge of the fragments
+The kernel packages merge their fragments over the config of the source package, then Kconfig
+resolves the dependencies of the options. An option whose dependencies aren't met is dropped
+without an error. `merge` takes the base config, the fragments in the order they were merged, and
+the final config, and lists every fragment line whose value didn't stick:
+```shell
+kernel-config merge --base config-x86_64 --fragment config-microcode \
+  --fragment config-bottlerocket --kconfig linux-6.1 config
+```
+With `--kconfig`, the Kconfig files of the kernel source in that directory explain why: the
+dependencies that aren't met, the options that select the option, or that it is a bool that was
+asked to be a module. `merge` exits with 1 if a line wasn't honored, and with 2 if it fails to
+check the lines.
+
+Errors, such as a file that can't be read or invalid arguments, exit with 2 for every subcommand,
+so that CI can tell them from findings.
+*/
+
+use argh::FromArgs;
+use kernel_config::config::Config;
+use kernel_config::diff::diff;
+use kernel_config::drift::{Allowlist, Drift};
+use kernel_config::fragment::{self, Fragment};
+use kernel_config::kconfig::{self, Kconfig};
+use kernel_config::matrix::{self, Matrix};
+use kernel_config::merge::check_merge;
+use kernel_config::policy::Policy;
+use kernel_config::{report, Result};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+
+/// Compares and reports on kernel configs
+#[derive(FromArgs, PartialEq, Debug)]
+struct Args {
+    #[argh(subcommand)]
+    subcommand: Subcommand,
+}
+
+#[derive(FromArgs, Debug, PartialEq)]
+#[argh(subcommand)]
+enum Subcommand {
+    Diff(DiffArgs),
+    Drift(DriftArgs),
+    Lint(LintArgs),
+    Merge(MergeArgs),
+    Report(ReportArgs),
+}
+
+/// Prints the changes between two configs
+#[derive(FromArgs, Debug, PartialEq)]
+#[argh(subcommand, name = "diff")]
+struct DiffArgs {
+    /// output format, markdown or json
+    #[argh(option, default = "Format::Markdown")]
+    format: Format,
+    /// the config before the changes
+    #[argh(positional)]
+    before: PathBuf,
+    /// the config after the changes
+    #[argh(positional)]
+    after: PathBuf,
+}
+
+/// Prints the options that differ between the fragments and final configs of the kernel series
+#[derive(FromArgs, Debug, PartialEq)]
+#[argh(subcommand, name = "drift")]
+struct DriftArgs {
+    /// output format, markdown or json
+    #[argh(option, default = "Format::Markdown")]
+    format: Format,
+    /// the directory with the kernel packages, such as kernel-6.1, and their config fragments
+    #[argh(option)]
+    packages: PathBuf,
+    /// the options that may differ between the series; none if not given
+    #[argh(option)]
+    allowlist: Option<PathBuf>,
+    /// final configs to compare, named like config-ARCH-VERSION or config-VERSION-ARCH.config
+    #[argh(positional)]
+    configs: Vec<PathBuf>,
+}
+
+/// Checks the kernel config fragments and final configs against a policy
+#[derive(FromArgs, Debug, PartialEq)]
+#[argh(subcommand, name = "lint")]
+struct LintArgs {
+    /// output format, markdown or json
+    #[argh(option, default = "Format::Markdown")]
+    format: Format,
+    /// the policy file
+    #[argh(option)]
+    policy: PathBuf,
+    /// the directory with the kernel packages, such as kernel-6.1, and their config fragments
+    #[argh(option)]
+    packages: PathBuf,
+    /// final configs to check, named like config-ARCH-VERSION or config-VERSION-ARCH.config
+    #[argh(positional)]
+    configs: Vec<PathBuf>,
+}
+
+/// Checks which lines of the config fragments the final config doesn't honor
+#[derive(FromArgs, Debug, PartialEq)]
+#[argh(subcommand, name = "merge")]
+struct MergeArgs {
+    /// output format, markdown or json
+    #[argh(option, default = "Format::Markdown")]
+    format: Format,
+    /// the config that the fragments were merged over
+    #[argh(option)]
+    base: PathBuf,
+    /// a fragment, in the order of the merge; may be repeated
+    #[argh(option)]
+    fragment: Vec<PathBuf>,
+    /// the kernel source, to explain with its Kconfig files why lines weren't honored; unexplained
+    /// if not given
+    #[argh(option)]
+    kconfig: Option<PathBuf>,
+    /// the final config
+    #[argh(positional)]
+    config: PathBuf,
+}
+
+/// Prints the changes to every pair of configs collected by tools/diff-kernel-config
+#[derive(FromArgs, Debug, PartialEq)]
+#[argh(subcommand, name = "report")]
+struct ReportArgs {
+    /// output format, markdown or json
+    #[argh(option, default = "Format::Markdown")]
+    format: Format,
+    /// the directory with the configs
+    #[argh(positional)]
+    dir: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Markdown,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "markdown" => Ok(Format::Markdown),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown format '{}', expected markdown or json", s)),
+        }
+    }
+}
+
+// Aligns the fragments and the configs, and returns the report and whether options differ that
+// the allowlist doesn't allow.
+fn drift(args: &DriftArgs) -> Result<(String, bool)> {
+    let fragments = fragment::kernel_fragments(&args.packages)?;
+    let allowlist = match &args.allowlist {
+        Some(path) => Allowlist::from_file(path)?,
+        None => Allowlist::default(),
+    };
+    let mut configs = Vec::new();
+    for path in &args.configs {
+        let (arch, version) = matrix::arch_and_version(path)?;
+        configs.push((version, arch, Config::from_file(path)?));
+    }
+
+    let drift = Drift::new(&fragments, &configs, &allowlist);
+    let output = match args.format {
+        Format::Markdown => report::drift_markdown(&drift),
+        Format::Json => report::json(&drift)?,
+    };
+    Ok((output, drift.has_drift()))
+}
+
+// Checks the fragments and the configs, and returns the report and whether it has violations.
+fn lint(args: &LintArgs) -> Result<(String, bool)> {
+    let policy = Policy::from_file(&args.policy)?;
+    let fragments = fragment::kernel_fragments(&args.packages)?;
+    let fragment_of = |version: &str| -> Option<&Fragment> {
+        fragments
+            .iter()
+            .find(|(fragment_version, _)| fragment_version == version)
+            .map(|(_, fragment)| fragment)
+    };
+
+    let mut violations = Vec::new();
+    for (version, fragment) in &fragments {
+        violations.extend(policy.check_fragment(fragment, version));
+    }
+    for path in &args.configs {
+        let (arch, version) = matrix::arch_and_version(path)?;
+        let config = Config::from_file(path)?;
+        violations.extend(policy.check_config(
+            &config,
+            path,
+            &version,
+            &arch,
+            fragment_of(&version),
+        ));
+    }
+
+    let output = match args.format {
+        Format::Markdown => report::violations_markdown(&violations),
+        Format::Json => report::json(&violations)?,
+    };
+    Ok((output, !violations.is_empty()))
+}
+
+// Checks the merge of the fragments, and returns the report and whether a line wasn't honored.
+fn merge(args: &MergeArgs) -> Result<(String, bool)> {
+    let base = Config::from_file(&args.base)?;
+    let fragments = args
+        .fragment
+        .iter()
+        .map(Fragment::from_file)
+        .collect::<Result<Vec<_>>>()?;
+    let final_config = Config::from_file(&args.config)?;
+    let kconfig = match &args.kconfig {
+        Some(dir) => Some(Kconfig::from_source(
+            dir,
+            kconfig::srcarch(&final_config, &args.config)?,
+        )?),
+        None => None,
+    };
+
+    let overrides = check_merge(&base, &fragments, &final_config, kconfig.as_ref());
+    let output = match args.format {
+        Format::Markdown => report::overrides_markdown(&overrides),
+        Format::Json => report::json(&overrides)?,
+    };
+    Ok((output, !overrides.is_empty()))
+}
+
+// The exit code of `lint`, `drift` and `merge` when they have findings.
+const EXIT_FINDINGS: i32 = 1;
+// The exit code of every subcommand when it fails, so that errors can't pass for findings.
+const EXIT_ERROR: i32 = 2;
+
+// Like `argh::from_env`, but exits with `EXIT_ERROR` for invalid arguments rather than 1.
+fn parse_args() -> Args {
+    let strings: Vec<String> = env::args().collect();
+    let command = strings
+        .first()
+        .and_then(|arg0| Path::new(arg0).file_name())
+        .and_then(|name| name.to_str())
+        .unwrap_or("kernel-config");
+    let args: Vec<&str> = strings.iter().skip(1).map(String::as_str).collect();
+    Args::from_args(&[command], &args).unwrap_or_else(|early_exit| match early_exit.status {
+        Ok(()) => {
+            println!("{}", early_exit.output);
+            process::exit(0);
+        }
+        Err(()) => {
+            eprintln!(
+                "{}\nRun {} --help for more information.",
+                early_exit.output, command
+            );
+            process::exit(EXIT_ERROR);
+        }
+    })
+}
+
+fn exit_code(has_findings: bool) -> i32 {
+    if has_findings {
+        EXIT_FINDINGS
+    } else {
+        0
+    }
+}
+
+fn run() -> Result<i32> {
+    let args = parse_args();
+    let output = match args.subcommand {
+        Subcommand::Diff(diff_args) => {
+            let before = Config::from_file(&diff_args.before)?;
+            let after = Config::from_file(&diff_args.after)?;
+            let changes = diff(&before, &after);
+            match diff_args.format {
+                Format::Markdown => report::changes_markdown(&changes),
+                Format::Json => report::json(&changes)?,
+            }
+        }
+        Subcommand::Drift(drift_args) => {
+            let (output, has_drift) = drift(&drift_args)?;
+            print!("{}", output);
+            return Ok(exit_code(has_drift));
+        }
+        Subcommand::Lint(lint_args) => {
+            let (output, has_violations) = lint(&lint_args)?;
+            print!("{}", output);
+            return Ok(exit_code(has_violations));
+        }
+        Subcommand::Merge(merge_args) => {
+            let (output, has_overrides) = merge(&merge_args)?;
+            print!("{}", output);
+            return Ok(exit_code(has_overrides));
+        }
+        Subcommand::Report(report_args) => {
+            let matrix = Matrix::read(&report_args.dir)?;
+            match report_args.format {
+                Format::Markdown => report::matrix_markdown(&matrix),
+                Format::Json => report::json(&matrix)?,
+            }
+        }
+    };
+    print!("{}", output);
+    Ok(0)
+}
+
+fn main() {
+    match run() {
+        Ok(exit_code) => process::exit(exit_code),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(EXIT_ERROR);
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! The `matrix` module compares the configs of every architecture and kernel version of two builds.
+//!
+//! The configs are read from a directory laid out as `tools/diff-kernel-config` writes it: one
+//! file per architecture, version and state, named like `config-x86_64-6.1-before` and
+//! `config-x86_64-6.1-after`, and optionally a `kver_mapping` file with the full kernel release of
+//! each config, with lines like `config-x86_64-6.1-after -> 6.1.128-1.1714433185.br1`.
+
+use crate::config::Config;
+use crate::diff::{diff, Change};
+use crate::{error, Result};
+use serde::Serialize;
+use snafu::{ensure, OptionExt, ResultExt};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// The state before the changes that are compared.
+pub const BEFORE: &str = "before";
+/// The state after the changes that are compared.
+pub const AFTER: &str = "after";
+
+const PREFIX: &str = "config-";
+const RELEASE_FILE: &str = "kver_mapping";
+
+/// The changes to the config of one architecture and kernel version.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Cell {
+    pub arch: String,
+    pub version: String,
+    /// The kernel release of the config before the changes, if known.
+    pub before_release: Option<String>,
+    /// The kernel release of the config after the changes, if known.
+    pub after_release: Option<String>,
+    pub changes: Vec<Change>,
+}
+
+/// The changes to the configs of every architecture and kernel version, by version and then
+/// architecture.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Matrix {
+    pub cells: Vec<Cell>,
+}
+
+impl Matrix {
+    /// Reads the pairs of configs in `dir`, and compares them. Fails if a config has no
+    /// counterpart in the other state.
+    pub fn read<P>(dir: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let dir = dir.as_ref();
+        let releases = read_releases(dir)?;
+        let entries = fs::read_dir(dir).context(error::ReadSnafu { path: dir })?;
+
+        let mut cells = Vec::new();
+        for entry in entries {
+            let entry = entry.context(error::ReadSnafu { path: dir })?;
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let Some((arch, version, state)) = parse_name(&file_name) else {
+                continue;
+            };
+            // Every pair is read once, from its "before" file.
+            match state {
+                BEFORE => {}
+                AFTER => {
+                    let before_path = dir.join(config_name(arch, version, BEFORE));
+                    ensure!(
+                        before_path.is_file(),
+                        error::MatrixStateSnafu {
+                            path: entry.path(),
+                            state: BEFORE
+                        }
+                    );
+                    continue;
+                }
+                _ => continue,
+            }
+
+            let after_path = dir.join(config_name(arch, version, AFTER));
+            ensure!(
+                after_path.is_file(),
+                error::MatrixStateSnafu {
+                    path: entry.path(),
+                    state: AFTER
+                }
+            );
+            let before = Config::from_file(entry.path())?;
+            let after = Config::from_file(&after_path)?;
+            cells.push(Cell {
+                arch: arch.to_string(),
+                version: version.to_string(),
+                before_release: releases.get(&file_name).cloned(),
+                after_release: releases.get(&config_name(arch, version, AFTER)).cloned(),
+                changes: diff(&before, &after),
+            });
+        }
+
+        ensure!(!cells.is_empty(), error::MatrixEmptySnafu { dir });
+        cells.sort_by(|a, b| {
+            compare_versions(&a.version, &b.version).then_with(|| a.arch.cmp(&b.arch))
+        });
+        Ok(Matrix { cells })
+    }
+}
+
+/// The architectures that the kit builds kernels for.
+pub const ARCHES: &[&str] = &["aarch64", "x86_64"];
+
+/// Returns the architecture and kernel version of a config from its file name, either
+/// `config-ARCH-VERSION-STATE` as `tools/diff-kernel-config` names them, or
+/// `config-VERSION-ARCH.config` as `tools/collect-kernel-config` does.
+pub fn arch_and_version(path: &Path) -> Result<(String, String)> {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    parse_arch_and_version(&file_name)
+        .map(|(arch, version)| (arch.to_string(), version.to_string()))
+        .context(error::ConfigNameSnafu { path })
+}
+
+fn parse_arch_and_version(file_name: &str) -> Option<(&str, &str)> {
+    let name = file_name.strip_prefix(PREFIX)?;
+    let name = name.strip_suffix(".config").unwrap_or(name);
+    let parts: Vec<&str> = name.split('-').collect();
+    let arch = parts.iter().find(|part| ARCHES.contains(part))?;
+    let version = parts.iter().find(|part| {
+        part.starts_with(|c: char| c.is_ascii_digit())
+            && part.chars().all(|c| c.is_ascii_digit() || c == '.')
+    })?;
+    Some((arch, version))
+}
+
+/// The file name of the config of `arch`, `version` and `state`.
+pub fn config_name(arch: &str, version: &str, state: &str) -> String {
+    format!("{}{}-{}-{}", PREFIX, arch, version, state)
+}
+
+// Splits a config file name into its architecture, version and state. Architectures such as
+// `x86_64` have no dashes, so the first dash ends the architecture.
+fn parse_name(file_name: &str) -> Option<(&str, &str, &str)> {
+    let rest = file_name.strip_prefix(PREFIX)?;
+    let (arch, rest) = rest.split_once('-')?;
+    let (version, state) = rest.rsplit_once('-')?;
+    (!arch.is_empty() && !version.is_empty()).then_some((arch, version, state))
+}
+
+// Reads the kernel releases of the configs, by file name. The file is optional.
+fn read_releases(dir: &Path) -> Result<HashMap<String, String>> {
+    let path = dir.join(RELEASE_FILE);
+    if !path.is_file() {
+        return Ok(HashMap::new());
+    }
+    let text = fs::read_to_string(&path).context(error::ReadSnafu { path: &path })?;
+    Ok(text
+        .lines()
+        .filter_map(|line| line.split_once(" -> "))
+        .map(|(name, release)| (name.trim().to_string(), release.trim().to_string()))
+        .collect())
+}
+
+/// Compares kernel versions such as `5.10` and `6.1` by their numbers.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let numbers = |version: &str| -> Vec<u64> {
+        version
+            .split('.')
+            .map(|part| part.parse().unwrap_or_default())
+            .collect()
+    };
+    numbers(a).cmp(&numbers(b)).then_with(|| a.cmp(b))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::diff::ChangeKind;
+
+    #[test]
+    fn test_read() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let write = |name: &str, text: &str| fs::write(dir.join(name), text).unwrap();
+        write("config-x86_64-6.1-before", "CONFIG_A=m\n");
+        write("config-x86_64-6.1-after", "CONFIG_A=y\n");
+        write("config-x86_64-5.10-before", "CONFIG_A=m\n");
+        write("config-x86_64-5.10-after", "CONFIG_A=m\n");
+        write("config-aarch64-6.1-before", "CONFIG_A=m\n");
+        write("config-aarch64-6.1-after", "# CONFIG_A is not set\n");
+        write(
+            "kver_mapping",
+            "config-x86_64-6.1-after -> 6.1.128-1.br1\nconfig-x86_64-6.1-before -> 6.1.127-1.br1\n",
+        );
+        write("diff-report.md", "");
+
+        let matrix = Matrix::read(dir).unwrap();
+        let cells: Vec<_> = matrix
+            .cells
+            .iter()
+            .map(|cell| {
+                (
+                    cell.version.as_str(),
+                    cell.arch.as_str(),
+                    cell.changes
+                        .iter()
+                        .map(|change| change.kind)
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            cells,
+            vec![
+                ("5.10", "x86_64", vec![]),
+                ("6.1", "aarch64", vec![ChangeKind::Removed]),
+                ("6.1", "x86_64", vec![ChangeKind::ModuleToBuiltin]),
+            ]
+        );
+        assert_eq!(
+            matrix.cells[2].before_release.as_deref(),
+            Some("6.1.127-1.br1")
+        );
+        assert_eq!(
+            matrix.cells[2].after_release.as_deref(),
+            Some("6.1.128-1.br1")
+        );
+
+        fs::remove_file(dir.join("config-aarch64-6.1-after")).unwrap();
+        assert!(Matrix::read(dir).is_err());
+    }
+
+    #[test]
+    fn test_arch_and_version() {
+        assert_eq!(
+            arch_and_version(Path::new("configs/config-x86_64-5.10-after")).unwrap(),
+            ("x86_64".to_string(), "5.10".to_string())
+        );
+        assert_eq!(
+            arch_and_version(Path::new("config-6.1-aarch64.config")).unwrap(),
+            ("aarch64".to_string(), "6.1".to_string())
+        );
+        assert!(arch_and_version(Path::new("config-riscv64-6.1")).is_err());
+        assert!(arch_and_version(Path::new("kver_mapping")).is_err());
+    }
+}
@@ -0,0 +1,133 @@
+//! The `merge` module checks how config fragments were merged. The kernel packages merge their
+//! fragments over the config of the source package with `scripts/kconfig/merge_config.sh`, then
+//! Kconfig resolves the dependencies of the options, which can silently change the values that a
+//! fragment asked for. Comparing the fragments with the final config finds those lines.
+
+use crate::config::{Config, Value};
+use crate::fragment::{Fragment, Location};
+use crate::kconfig::Kconfig;
+use serde::Serialize;
+
+/// A fragment line whose value isn't the one in the final config.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Override {
+    pub location: Location,
+    pub option: String,
+    /// The value in the base config, if it is set.
+    pub base: Option<Value>,
+    /// The value that the fragment line asked for.
+    pub requested: Value,
+    /// The value in the final config, if it is set.
+    #[serde(rename = "final")]
+    pub final_value: Option<Value>,
+    /// Why the value didn't stick, if known.
+    pub reasons: Vec<String>,
+}
+
+/// Merges `fragments` over `base`, in order, as `merge_config.sh` does, and returns the fragment
+/// lines that `final_config` doesn't honor. A line is honored if the option has the requested
+/// value, or isn't set if the line asks for that. If the Kconfig files of the kernel are given,
+/// they explain why lines weren't honored.
+pub fn check_merge(
+    base: &Config,
+    fragments: &[Fragment],
+    final_config: &Config,
+    kconfig: Option<&Kconfig>,
+) -> Vec<Override> {
+    // The lines of the fragments in the order of the merge, where later lines win.
+    let lines: Vec<_> = fragments
+        .iter()
+        .flat_map(|fragment| fragment.lines.iter().map(move |line| (fragment, line)))
+        .collect();
+
+    let mut overrides = Vec::new();
+    for (index, (fragment, line)) in lines.iter().enumerate() {
+        let requested = Some(&line.value).filter(|value| value.is_set());
+        let final_value = final_config.value(&line.option);
+        if requested == final_value {
+            continue;
+        }
+
+        let later = lines[index + 1..]
+            .iter()
+            .rev()
+            .find(|(_, later)| later.option == line.option);
+        let reasons = match (later, kconfig) {
+            (Some((later_fragment, later)), _) => {
+                vec![format!("redefined at `{}`", later_fragment.location(later))]
+            }
+            (None, Some(kconfig)) => kconfig.explain(&line.option, &line.value, final_config),
+            (None, None) if requested.is_some() && final_config.get(&line.option).is_none() => {
+                vec![format!(
+                    "`{}` isn't in the final config, so this kernel may not have it",
+                    line.option
+                )]
+            }
+            (None, None) => Vec::new(),
+        };
+
+        overrides.push(Override {
+            location: fragment.location(line),
+            option: line.option.clone(),
+            base: base.value(&line.option).cloned(),
+            requested: line.value.clone(),
+            final_value: final_value.cloned(),
+            reasons,
+        });
+    }
+    overrides
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_check_merge() {
+        let base = Config::parse(
+            "CONFIG_A=m\nCONFIG_B=y\n# CONFIG_C is not set\n",
+            Path::new("base"),
+        )
+        .unwrap();
+        let microcode = Fragment::parse("CONFIG_E=\"first\"\n", Path::new("microcode")).unwrap();
+        let fragment = Fragment::parse(
+            "CONFIG_A=y\n# CONFIG_B is not set\nCONFIG_C=y\nCONFIG_D=y\nCONFIG_E=\"second\"\n",
+            Path::new("fragment"),
+        )
+        .unwrap();
+        let final_config = Config::parse(
+            "CONFIG_A=y\nCONFIG_B=y\n# CONFIG_C is not set\nCONFIG_E=\"second\"\n",
+            Path::new("final"),
+        )
+        .unwrap();
+
+        let overrides = check_merge(&base, &[microcode, fragment], &final_config, None);
+        let lines: Vec<_> = overrides
+            .iter()
+            .map(|item| (item.location.to_string(), item.reasons.clone()))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                (
+                    "microcode:1".to_string(),
+                    vec!["redefined at `fragment:5`".to_string()]
+                ),
+                ("fragment:2".to_string(), vec![]),
+                ("fragment:3".to_string(), vec![]),
+                (
+                    "fragment:4".to_string(),
+                    vec![
+                        "`CONFIG_D` isn't in the final config, so this kernel may not have it"
+                            .to_string()
+                    ]
+                ),
+            ]
+        );
+        assert_eq!(overrides[1].base, Some(Value::Builtin));
+        assert_eq!(overrides[1].requested, Value::NotSet);
+        assert_eq!(overrides[1].final_value, Some(Value::Builtin));
+        assert_eq!(overrides[2].base, None);
+    }
+}
@@ -0,0 +1,300 @@
+//! The `policy` module checks configs and fragments against a policy: the options that each kernel
+//! version and architecture requires, forbids, or requires to be built in.
+//!
+//! A policy is a TOML file with a list of rules. Every rule explains why it exists, and applies to
+//! all kernels unless it lists the versions or architectures it applies to:
+//!
+//! ```toml
+//! [[rule]]
+//! description = "Without an initramfs, the root filesystem has to be built in"
+//! builtin = ["CONFIG_EXT4_FS"]
+//!
+//! [[rule]]
+//! description = "erofs is only used from 6.1"
+//! versions = ["6.1"]
+//! required = ["CONFIG_EROFS_FS"]
+//! ```
+
+use crate::config::{Config, Value};
+use crate::fragment::{Fragment, Location};
+use crate::{error, Result};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The rules of a policy file.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    #[serde(rename = "rule", default)]
+    pub rules: Vec<Rule>,
+}
+
+/// Options that must be, or must not be, set.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// Why the options are required or forbidden.
+    pub description: String,
+    /// The kernel versions the rule applies to, such as `6.1`. All of them if empty.
+    #[serde(default)]
+    pub versions: Vec<String>,
+    /// The architectures the rule applies to, such as `x86_64`. All of them if empty.
+    #[serde(default)]
+    pub arches: Vec<String>,
+    /// Options that must be set, as a module, built in, or to a value.
+    #[serde(default)]
+    pub required: Vec<String>,
+    /// Options that must not be set.
+    #[serde(default)]
+    pub forbidden: Vec<String>,
+    /// Options that must be built in, `y`.
+    #[serde(default)]
+    pub builtin: Vec<String>,
+}
+
+impl Rule {
+    // Whether the rule applies to `version`, and to `arch` if the config is for one architecture.
+    fn applies_to(&self, version: &str, arch: Option<&str>) -> bool {
+        (self.versions.is_empty() || self.versions.iter().any(|v| v == version))
+            && match arch {
+                Some(arch) => self.arches.is_empty() || self.arches.iter().any(|a| a == arch),
+                None => true,
+            }
+    }
+
+    // The options of the rule, with what they require.
+    fn requirements(&self) -> impl Iterator<Item = (&str, Requirement)> {
+        fn with(
+            options: &[String],
+            requirement: Requirement,
+        ) -> impl Iterator<Item = (&str, Requirement)> {
+            options
+                .iter()
+                .map(move |option| (option.as_str(), requirement))
+        }
+        with(&self.required, Requirement::Required)
+            .chain(with(&self.forbidden, Requirement::Forbidden))
+            .chain(with(&self.builtin, Requirement::Builtin))
+    }
+}
+
+/// What a rule requires of an option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Requirement {
+    Required,
+    Forbidden,
+    Builtin,
+}
+
+impl Requirement {
+    // Whether `value` meets the requirement. Options without a value aren't set.
+    fn is_met(&self, value: Option<&Value>) -> bool {
+        match self {
+            Requirement::Required => value.is_some(),
+            Requirement::Forbidden => value.is_none(),
+            Requirement::Builtin => value == Some(&Value::Builtin),
+        }
+    }
+}
+
+impl fmt::Display for Requirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Requirement::Required => f.write_str("must be set"),
+            Requirement::Forbidden => f.write_str("must not be set"),
+            Requirement::Builtin => f.write_str("must be built in"),
+        }
+    }
+}
+
+/// An option that doesn't meet a rule.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Violation {
+    /// The config or fragment that was checked.
+    pub file: PathBuf,
+    pub option: String,
+    pub requirement: Requirement,
+    /// The value of the option, if it is set.
+    pub value: Option<Value>,
+    /// The line of the fragment that set the option, if the fragment sets it. Otherwise, the
+    /// value comes from the config of the kernel's source package.
+    pub fragment_line: Option<Location>,
+    /// The description of the rule.
+    pub rule: String,
+}
+
+impl Policy {
+    /// Reads the policy at `path`.
+    pub fn from_file<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).context(error::ReadSnafu { path })?;
+        toml::from_str(&text)
+            .map_err(Box::new)
+            .context(error::PolicyParseSnafu { path })
+    }
+
+    /// Checks the options that the fragment of kernel `version` sets. Since a fragment applies to
+    /// every architecture, so do the rules for any architecture. Options that the fragment doesn't
+    /// set come from the config of the source package, and can only be checked in the final
+    /// config.
+    pub fn check_fragment(&self, fragment: &Fragment, version: &str) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        for rule in self
+            .rules
+            .iter()
+            .filter(|rule| rule.applies_to(version, None))
+        {
+            for (option, requirement) in rule.requirements() {
+                let Some(line) = fragment.get(option) else {
+                    continue;
+                };
+                let value = Some(&line.value).filter(|value| value.is_set());
+                if !requirement.is_met(value) {
+                    violations.push(Violation {
+                        file: fragment.path.clone(),
+                        option: option.to_string(),
+                        requirement,
+                        value: value.cloned(),
+                        fragment_line: Some(fragment.location(line)),
+                        rule: rule.description.clone(),
+                    });
+                }
+            }
+        }
+        violations
+    }
+
+    /// Checks the final config at `path` of kernel `version` for `arch`. If the fragment of the
+    /// kernel is given, violations point to the line of the fragment that set the option.
+    pub fn check_config(
+        &self,
+        config: &Config,
+        path: &Path,
+        version: &str,
+        arch: &str,
+        fragment: Option<&Fragment>,
+    ) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        for rule in self
+            .rules
+            .iter()
+            .filter(|rule| rule.applies_to(version, Some(arch)))
+        {
+            for (option, requirement) in rule.requirements() {
+                let value = config.value(option);
+                if !requirement.is_met(value) {
+                    let fragment_line = fragment.and_then(|fragment| {
+                        fragment.get(option).map(|line| fragment.location(line))
+                    });
+                    violations.push(Violation {
+                        file: path.to_path_buf(),
+                        option: option.to_string(),
+                        requirement,
+                        value: value.cloned(),
+                        fragment_line,
+                        rule: rule.description.clone(),
+                    });
+                }
+            }
+        }
+        violations
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const POLICY: &str = r#"
+[[rule]]
+description = "Boot"
+builtin = ["CONFIG_EXT4_FS", "CONFIG_NVME"]
+
+[[rule]]
+description = "Security"
+versions = ["6.1"]
+forbidden = ["CONFIG_SELINUX_DEVELOP"]
+
+[[rule]]
+description = "EFI"
+arches = ["x86_64"]
+required = ["CONFIG_EFI_MIXED"]
+"#;
+
+    fn problems(violations: &[Violation]) -> Vec<(String, Requirement, Option<usize>)> {
+        violations
+            .iter()
+            .map(|violation| {
+                (
+                    violation.option.clone(),
+                    violation.requirement,
+                    violation
+                        .fragment_line
+                        .as_ref()
+                        .map(|location| location.line),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_check() {
+        let policy: Policy = toml::from_str(POLICY).unwrap();
+        let fragment = Fragment::parse(
+            "CONFIG_EXT4_FS=m\nCONFIG_SELINUX_DEVELOP=y\n# CONFIG_EFI_MIXED is not set\n",
+            Path::new("config-bottlerocket"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            problems(&policy.check_fragment(&fragment, "6.1")),
+            vec![
+                ("CONFIG_EXT4_FS".to_string(), Requirement::Builtin, Some(1)),
+                (
+                    "CONFIG_SELINUX_DEVELOP".to_string(),
+                    Requirement::Forbidden,
+                    Some(2)
+                ),
+                (
+                    "CONFIG_EFI_MIXED".to_string(),
+                    Requirement::Required,
+                    Some(3)
+                ),
+            ]
+        );
+        assert_eq!(policy.check_fragment(&fragment, "5.10").len(), 2);
+
+        let config = Config::parse(
+            "CONFIG_EXT4_FS=m\nCONFIG_SELINUX_DEVELOP=y\n",
+            Path::new("config"),
+        )
+        .unwrap();
+        let violations = policy.check_config(
+            &config,
+            Path::new("config"),
+            "6.1",
+            "aarch64",
+            Some(&fragment),
+        );
+        assert_eq!(
+            problems(&violations),
+            vec![
+                ("CONFIG_EXT4_FS".to_string(), Requirement::Builtin, Some(1)),
+                ("CONFIG_NVME".to_string(), Requirement::Builtin, None),
+                (
+                    "CONFIG_SELINUX_DEVELOP".to_string(),
+                    Requirement::Forbidden,
+                    Some(2)
+                ),
+            ]
+        );
+        assert_eq!(violations[0].value, Some(Value::Module));
+    }
+}
@@ -0,0 +1,403 @@
+//! The `report` module renders config changes as Markdown, to paste into pull requests, or as JSON
+//! for other tools.
+
+use crate::config::Value;
+use crate::diff::{Change, ChangeKind};
+use crate::drift::{Column, Drift, DriftKind, Row};
+use crate::matrix::Matrix;
+use crate::merge::Override;
+use crate::policy::Violation;
+use crate::{error, Result};
+use serde::Serialize;
+use snafu::ResultExt;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Renders `value` as pretty-printed JSON.
+pub fn json<T>(value: &T) -> Result<String>
+where
+    T: Serialize,
+{
+    let mut json = serde_json::to_string_pretty(value).context(error::SerializeSnafu)?;
+    json.push('\n');
+    Ok(json)
+}
+
+/// Renders the changes between two configs as a Markdown table.
+pub fn changes_markdown(changes: &[Change]) -> String {
+    if changes.is_empty() {
+        return "No config changes.\n".to_string();
+    }
+
+    let mut output =
+        String::from("| Option | Before | After | Change |\n| --- | --- | --- | --- |\n");
+    for change in changes {
+        let _ = writeln!(
+            output,
+            "| `{}` | {} | {} | {} |",
+            change.option,
+            value_cell(change.before.as_ref()),
+            value_cell(change.after.as_ref()),
+            change.kind.describe()
+        );
+    }
+    output
+}
+
+/// Renders the changes of every architecture and kernel version as Markdown: a summary with the
+/// number of changes of each kind per config, then a table with a row per change and a column per
+/// config, which marks the configs that have the change.
+pub fn matrix_markdown(matrix: &Matrix) -> String {
+    let mut output = String::from("## Kernel config changes\n\n");
+    output.push_str(
+        "| Kernel | Arch | Release | Added | Removed | m → y | y → m | Value changed |\n\
+         | --- | --- | --- | ---: | ---: | ---: | ---: | ---: |\n",
+    );
+    for cell in &matrix.cells {
+        let count = |kind: ChangeKind| {
+            cell.changes
+                .iter()
+                .filter(|change| change.kind == kind)
+                .count()
+        };
+        let release = match (&cell.before_release, &cell.after_release) {
+            (Some(before), Some(after)) if before == after => before.clone(),
+            (Some(before), Some(after)) => format!("{} → {}", before, after),
+            (None, Some(release)) | (Some(release), None) => release.clone(),
+            (None, None) => String::new(),
+        };
+        let _ = writeln!(
+            output,
+            "| {} | {} | {} | {} | {} | {} | {} | {} |",
+            cell.version,
+            cell.arch,
+            release,
+            count(ChangeKind::Added),
+            count(ChangeKind::Removed),
+            count(ChangeKind::ModuleToBuiltin),
+            count(ChangeKind::BuiltinToModule),
+            count(ChangeKind::ValueChanged)
+        );
+    }
+
+    // The same change usually applies to several configs, so they share a row.
+    let mut rows: BTreeMap<&Change, Vec<bool>> = BTreeMap::new();
+    for (index, cell) in matrix.cells.iter().enumerate() {
+        for change in &cell.changes {
+            rows.entry(change)
+                .or_insert_with(|| vec![false; matrix.cells.len()])[index] = true;
+        }
+    }
+
+    output.push_str("\n### Changes\n\n");
+    if rows.is_empty() {
+        output.push_str("No config changes.\n");
+        return output;
+    }
+    output.push_str("| Option | Before | After |");
+    for cell in &matrix.cells {
+        let _ = write!(output, " {} {} |", cell.version, cell.arch);
+    }
+    output.push_str("\n| --- | --- | --- |");
+    output.push_str(&" :---: |".repeat(matrix.cells.len()));
+    output.push('\n');
+    for (change, cells) in rows {
+        let _ = write!(
+            output,
+            "| `{}` | {} | {} |",
+            change.option,
+            value_cell(change.before.as_ref()),
+            value_cell(change.after.as_ref())
+        );
+        for has_change in cells {
+            output.push_str(if has_change { " ✓ |" } else { " |" });
+        }
+        output.push('\n');
+    }
+    output
+}
+
+/// Renders the options that differ between kernel series as Markdown: a table with a row per
+/// option and a column per fragment and final config, then the options that only some fragments
+/// set, the differences that the allowlist allows, and the allowlist entries that no longer differ.
+pub fn drift_markdown(drift: &Drift) -> String {
+    let (allowed, listed): (Vec<_>, Vec<_>) =
+        drift.rows.iter().partition(|row| row.allowed.is_some());
+    let (drifted, inherited): (Vec<_>, Vec<_>) = listed
+        .into_iter()
+        .partition(|row| row.kind == DriftKind::Differs);
+
+    let mut output = String::from("## Kernel config drift\n\n");
+    if drifted.is_empty() {
+        output.push_str("No options differ between the kernel series.\n");
+    } else {
+        output.push_str(
+            "Options that differ between the kernel series, and aren't in the allowlist. A \
+             fragment that doesn't set the option, or a kernel that doesn't have it, shows –.\n\n",
+        );
+        output.push_str(&drift_table(&drift.columns, &drifted, false));
+    }
+
+    if !inherited.is_empty() {
+        output.push_str(
+            "\n## Options set by only some fragments\n\nThe other fragments inherit these from \
+             the config of their source package, which may be a change that wasn't ported. They \
+             don't fail the check.\n\n",
+        );
+        output.push_str(&drift_table(&drift.columns, &inherited, false));
+    }
+
+    if !allowed.is_empty() {
+        output.push_str("\n## Allowed differences\n\n");
+        output.push_str(&drift_table(&drift.columns, &allowed, true));
+    }
+
+    if !drift.stale.is_empty() {
+        output.push_str("\n## Stale allowlist entries\n\nThese options no longer differ:\n\n");
+        for option in &drift.stale {
+            let _ = writeln!(output, "* `{}`", option);
+        }
+    }
+    output
+}
+
+// Renders rows of the drift with a column per fragment and final config, and the description of
+// their allowlist entry if `with_reason` is set.
+fn drift_table(columns: &[Column], rows: &[&Row], with_reason: bool) -> String {
+    let mut output = String::from("| Option |");
+    for column in columns {
+        match &column.arch {
+            Some(arch) => {
+                let _ = write!(output, " {} {} |", column.version, arch);
+            }
+            None => {
+                let _ = write!(output, " {} fragment |", column.version);
+            }
+        }
+    }
+    output.push_str(if with_reason {
+        " Why |\n| --- |"
+    } else {
+        "\n| --- |"
+    });
+    output.push_str(&" --- |".repeat(columns.len()));
+    output.push_str(if with_reason { " --- |\n" } else { "\n" });
+
+    for row in rows {
+        let _ = write!(output, "| `{}` |", row.option);
+        for value in &row.values {
+            let cell = match value {
+                Some(value) => value_cell(Some(value).filter(|value| value.is_set())),
+                None => "–".to_string(),
+            };
+            let _ = write!(output, " {} |", cell);
+        }
+        if let Some(description) = row.allowed.as_ref().filter(|_| with_reason) {
+            let _ = write!(output, " {} |", description.replace('|', "\\|"));
+        }
+        output.push('\n');
+    }
+    output
+}
+
+/// Renders policy violations as a Markdown table, with the line of the fragment that set each
+/// option, if a fragment did.
+pub fn violations_markdown(violations: &[Violation]) -> String {
+    if violations.is_empty() {
+        return "No policy violations.\n".to_string();
+    }
+
+    let mut output = String::from(
+        "| File | Option | Problem | Fragment line | Rule |\n| --- | --- | --- | --- | --- |\n",
+    );
+    for violation in violations {
+        let fragment_line = match &violation.fragment_line {
+            Some(location) => format!("`{}`", location),
+            None => "not set by the fragment".to_string(),
+        };
+        let _ = writeln!(
+            output,
+            "| `{}` | `{}` | {}, is {} | {} | {} |",
+            violation.file.display(),
+            violation.option,
+            violation.requirement,
+            value_cell(violation.value.as_ref()),
+            fragment_line,
+            violation.rule.replace('|', "\\|")
+        );
+    }
+    output
+}
+
+/// Renders the fragment lines that the final config doesn't honor as a Markdown table, with why,
+/// if known.
+pub fn overrides_markdown(overrides: &[Override]) -> String {
+    if overrides.is_empty() {
+        return "The final config honors every fragment line.\n".to_string();
+    }
+
+    let mut output = String::from(
+        "| Fragment line | Option | Base | Requested | Final | Why |\n\
+         | --- | --- | --- | --- | --- | --- |\n",
+    );
+    for item in overrides {
+        let _ = writeln!(
+            output,
+            "| `{}` | `{}` | {} | {} | {} | {} |",
+            item.location,
+            item.option,
+            value_cell(item.base.as_ref()),
+            value_cell(Some(&item.requested).filter(|value| value.is_set())),
+            value_cell(item.final_value.as_ref()),
+            item.reasons.join("; ").replace('|', "\\|")
+        );
+    }
+    output
+}
+
+fn value_cell(value: Option<&Value>) -> String {
+    match value {
+        Some(value) => format!("`{}`", value.to_string().replace('|', "\\|")),
+        None => "not set".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::matrix::Cell;
+
+    fn change(
+        option: &str,
+        kind: ChangeKind,
+        before: Option<Value>,
+        after: Option<Value>,
+    ) -> Change {
+        Change {
+            option: option.to_string(),
+            kind,
+            before,
+            after,
+        }
+    }
+
+    #[test]
+    fn test_matrix_markdown() {
+        let to_builtin = change(
+            "CONFIG_A",
+            ChangeKind::ModuleToBuiltin,
+            Some(Value::Module),
+            Some(Value::Builtin),
+        );
+        let added = change("CONFIG_B", ChangeKind::Added, None, Some(Value::Builtin));
+        let matrix = Matrix {
+            cells: vec![
+                Cell {
+                    arch: "aarch64".to_string(),
+                    version: "6.1".to_string(),
+                    before_release: Some("6.1.127".to_string()),
+                    after_release: Some("6.1.128".to_string()),
+                    changes: vec![to_builtin.clone(), added],
+                },
+                Cell {
+                    arch: "x86_64".to_string(),
+                    version: "6.1".to_string(),
+                    before_release: None,
+                    after_release: None,
+                    changes: vec![to_builtin],
+                },
+            ],
+        };
+
+        let expected = "\
+## Kernel config changes
+
+| Kernel | Arch | Release | Added | Removed | m → y | y → m | Value changed |
+| --- | --- | --- | ---: | ---: | ---: | ---: | ---: |
+| 6.1 | aarch64 | 6.1.127 → 6.1.128 | 1 | 0 | 1 | 0 | 0 |
+| 6.1 | x86_64 |  | 0 | 0 | 1 | 0 | 0 |
+
+### Changes
+
+| Option | Before | After | 6.1 aarch64 | 6.1 x86_64 |
+| --- | --- | --- | :---: | :---: |
+| `CONFIG_A` | `m` | `y` | ✓ | ✓ |
+| `CONFIG_B` | not set | `y` | ✓ | |
+";
+        assert_eq!(matrix_markdown(&matrix), expected);
+
+        let json = json(&matrix).unwrap();
+        assert!(json.contains("\"kind\": \"module-to-builtin\""), "{}", json);
+        assert!(json.contains("\"before\": null"), "{}", json);
+    }
+
+    #[test]
+    fn test_drift_markdown() {
+        let drift = Drift {
+            columns: vec![
+                Column {
+                    version: "5.15".to_string(),
+                    arch: None,
+                },
+                Column {
+                    version: "6.1".to_string(),
+                    arch: Some("x86_64".to_string()),
+                },
+            ],
+            rows: vec![
+                Row {
+                    option: "CONFIG_A".to_string(),
+                    kind: DriftKind::Differs,
+                    values: vec![Some(Value::NotSet), Some(Value::Module)],
+                    allowed: None,
+                },
+                Row {
+                    option: "CONFIG_B".to_string(),
+                    kind: DriftKind::Differs,
+                    values: vec![None, Some(Value::Builtin)],
+                    allowed: Some("New in 6.1".to_string()),
+                },
+                Row {
+                    option: "CONFIG_D".to_string(),
+                    kind: DriftKind::Inherited,
+                    values: vec![Some(Value::Builtin), None],
+                    allowed: None,
+                },
+            ],
+            stale: vec!["CONFIG_C".to_string()],
+        };
+
+        let expected = "\
+## Kernel config drift
+
+Options that differ between the kernel series, and aren't in the allowlist. A fragment that \
+doesn't set the option, or a kernel that doesn't have it, shows –.
+
+| Option | 5.15 fragment | 6.1 x86_64 |
+| --- | --- | --- |
+| `CONFIG_A` | not set | `m` |
+
+## Options set by only some fragments
+
+The other fragments inherit these from the config of their source package, which may be a change \
+that wasn't ported. They don't fail the check.
+
+| Option | 5.15 fragment | 6.1 x86_64 |
+| --- | --- | --- |
+| `CONFIG_D` | `y` | – |
+
+## Allowed differences
+
+| Option | 5.15 fragment | 6.1 x86_64 | Why |
+| --- | --- | --- | --- |
+| `CONFIG_B` | – | `y` | New in 6.1 |
+
+## Stale allowlist entries
+
+These options no longer differ:
+
+* `CONFIG_C`
+";
+        assert_eq!(drift_markdown(&drift), expected);
+    }
+}
@@ -0,0 +1,9 @@
+# {{crate}}
+
+Current version: {{version}}
+
+{{readme}}
+
+## Colophon
+
+This text was generated from `README.tpl` by the `generate-readme` crate of this workspace, and includes the rustdoc from `src/lib.rs`.
@@ -0,0 +1,331 @@
+//! The `apply` module runs the actions of a [`Plan`].
+
+use crate::command::{command, command_output};
+use crate::layout::{get_layout, parse_created_gpu_instances, CreatedGpuInstance, Layout};
+use crate::paths::Paths;
+use crate::plan::{Action, Plan, Target};
+use crate::{error, Result};
+use log::{debug, error, info, warn};
+use snafu::{ensure, ResultExt};
+use std::fmt;
+
+/// Runs the actions of `plan` in order.
+pub fn apply(plan: &Plan, paths: &Paths) -> Result<()> {
+    for action in &plan.actions {
+        match action {
+            Action::SetMigMode { enabled, target } => set_mig_mode(paths, *enabled, target)?,
+            Action::RequestReboot(reason) => request_reboot(paths, reason)?,
+            Action::CreateInstances {
+                profile,
+                fallback,
+                target,
+            } => set_mig_profile(paths, profile, fallback.as_deref(), target)?,
+            Action::TryCreateInstances { candidates, target } => {
+                try_create_instances(paths, candidates, target)?
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs the nvidia-smi command to enable/disable MIG in the targeted GPUs
+pub fn set_mig_mode(paths: &Paths, mig_enabled: bool, target: &Target) -> Result<()> {
+    info!(
+        "{} MIG.",
+        if mig_enabled { "Enabling" } else { "Disabling" }
+    );
+
+    let mut args = vec!["-mig".to_string(), (mig_enabled as u8).to_string()];
+    args.extend(target.nvidia_smi_args());
+    command(&paths.nvidia_smi, args)?;
+
+    Ok(())
+}
+
+/// Runs the nvidia-smi commands to apply the correct MIG profile in the targeted GPUs.
+///
+/// The profiles in the comma-separated `profile_string` are created one at a time. If a step
+/// fails, the GPU instances created so far are destroyed, which restores the layout from before
+/// the apply. If the GPUs had no GPU instances before, the `fallback` profile string (usually the
+/// whole-GPU profile) is applied instead so that the GPUs stay usable. The returned error names
+/// the step that failed and the outcome of the rollback.
+pub fn set_mig_profile(
+    paths: &Paths,
+    profile_string: &str,
+    fallback: Option<&str>,
+    target: &Target,
+) -> Result<()> {
+    info!("Activating MIG profile ...");
+
+    let mut previous_layout = get_layout(paths)?;
+    previous_layout
+        .gpu_instances
+        .retain(|gpu_instance| target.includes(gpu_instance.gpu));
+    info!(
+        "Found {} existing GPU instances before applying the MIG profile.",
+        previous_layout.gpu_instances.len()
+    );
+
+    let profiles: Vec<&str> = profile_string.split(',').collect();
+    let mut created = Vec::new();
+    for (index, profile) in profiles.iter().enumerate() {
+        if let Err(e) = create_gpu_instances(paths, profile, target, &mut created) {
+            error!(
+                "Failed to create GPU instances for profile {} ({} of {}): {}",
+                profile,
+                index + 1,
+                profiles.len(),
+                e
+            );
+            let rollback = rollback(paths, &created, &previous_layout, fallback, target);
+            return Err(Box::new(e)).context(error::CreateInstancesSnafu {
+                profile: *profile,
+                step: index + 1,
+                total: profiles.len(),
+                rollback: rollback.to_string(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Destroys the GPU instances of `gpu` and creates the ones in `profiles`, to restore a layout
+/// that was changed outside of `nvidia-migmanager`. The MIG mode of the GPU isn't changed.
+pub fn restore_layout(paths: &Paths, gpu: u32, profiles: &[String]) -> Result<()> {
+    info!(
+        "Restoring the MIG layout of GPU {}: {}",
+        gpu,
+        profiles.join(",")
+    );
+
+    let layout = get_layout(paths)?;
+    for gpu_instance in layout.gpu_instances.iter().filter(|gi| gi.gpu == gpu) {
+        destroy_gpu_instance(
+            paths,
+            &CreatedGpuInstance {
+                gpu,
+                gi_id: gpu_instance.gi_id,
+            },
+        )?;
+    }
+    if profiles.is_empty() {
+        return Ok(());
+    }
+
+    set_mig_profile(paths, &profiles.join(","), None, &Target::Gpus(vec![gpu]))
+}
+
+// Runs `nvidia-smi mig -cgi` for a single profile and records the GPU instances it created, even
+// if the command failed partway through the GPUs.
+fn create_gpu_instances(
+    paths: &Paths,
+    profile: &str,
+    target: &Target,
+    created: &mut Vec<CreatedGpuInstance>,
+) -> Result<()> {
+    let mut args = vec!["mig", "-cgi", profile, "-C"]
+        .into_iter()
+        .map(String::from)
+        .collect::<Vec<_>>();
+    args.extend(target.nvidia_smi_args());
+    let output = command_output(&paths.nvidia_smi, args)?;
+    created.extend(parse_created_gpu_instances(&String::from_utf8_lossy(
+        &output.stdout,
+    )));
+
+    ensure!(
+        output.status.success(),
+        error::CommandFailureSnafu {
+            bin_path: &paths.nvidia_smi,
+            stderr: String::from_utf8_lossy(&output.stderr),
+        }
+    );
+
+    Ok(())
+}
+
+// Destroys the compute instances and then the GPU instance itself.
+fn destroy_gpu_instance(paths: &Paths, gpu_instance: &CreatedGpuInstance) -> Result<()> {
+    let gpu = gpu_instance.gpu.to_string();
+    let gi_id = gpu_instance.gi_id.to_string();
+
+    // The compute instances may not exist if `-C` failed, so only the GPU instance must go.
+    if let Err(e) = command(
+        &paths.nvidia_smi,
+        ["mig", "-dci", "-i", &gpu, "-gi", &gi_id],
+    ) {
+        debug!(
+            "Failed to destroy compute instances of GPU instance {}: {}",
+            gi_id, e
+        );
+    }
+    command(
+        &paths.nvidia_smi,
+        ["mig", "-dgi", "-i", &gpu, "-gi", &gi_id],
+    )?;
+
+    Ok(())
+}
+
+/// The outcome of undoing a partially applied MIG profile.
+#[derive(Debug, Clone, PartialEq)]
+enum Rollback {
+    Restored,
+    FellBack(String),
+    Failed(String),
+}
+
+impl fmt::Display for Rollback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rollback::Restored => write!(f, "restored the previous layout"),
+            Rollback::FellBack(profile) => write!(f, "fell back to profile {}", profile),
+            Rollback::Failed(reason) => write!(f, "rollback failed: {}", reason),
+        }
+    }
+}
+
+fn rollback(
+    paths: &Paths,
+    created: &[CreatedGpuInstance],
+    previous_layout: &Layout,
+    fallback: Option<&str>,
+    target: &Target,
+) -> Rollback {
+    warn!(
+        "Destroying {} GPU instances created by this apply.",
+        created.len()
+    );
+    for gpu_instance in created.iter().rev() {
+        if let Err(e) = destroy_gpu_instance(paths, gpu_instance) {
+            return Rollback::Failed(e.to_string());
+        }
+    }
+
+    // The GPU instances from before the apply were never touched, so they are intact. Only fall
+    // back when there were none, since a GPU in MIG mode without GPU instances is unusable.
+    let fallback = match fallback {
+        Some(fallback) if previous_layout.is_empty() => fallback,
+        _ => return Rollback::Restored,
+    };
+
+    warn!("Falling back to MIG profile {}.", fallback);
+    let mut created = Vec::new();
+    match create_gpu_instances(paths, fallback, target, &mut created) {
+        Ok(()) => Rollback::FellBack(fallback.to_string()),
+        Err(e) => Rollback::Failed(e.to_string()),
+    }
+}
+
+fn request_reboot(paths: &Paths, reason: &str) -> Result<()> {
+    info!("Rebooting to apply MIG Settings...");
+    let marker_path = paths.reboot_required_marker();
+    std::fs::write(&marker_path, reason).context(error::WriteMarkerSnafu { marker_path })
+}
+
+// Tries the candidate profile strings in order, and fails if none of them applies.
+fn try_create_instances(paths: &Paths, candidates: &[String], target: &Target) -> Result<()> {
+    for profile_string in candidates {
+        match set_mig_profile(paths, profile_string, None, target) {
+            Ok(()) => {
+                info!("Successfully applied MIG Profile: {}", profile_string);
+                return Ok(());
+            }
+            Err(e) => {
+                warn!(
+                    "The Profile {} is not a valid MIG Profile for the given GPU: {}",
+                    profile_string, e
+                );
+                continue;
+            }
+        }
+    }
+
+    error::NoValidProfileSnafu {
+        candidates: candidates.len(),
+    }
+    .fail()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+
+    // Writes a fake nvidia-smi that logs its arguments, creates GPU instance 1 on GPU 0 for
+    // profile 3g.20gb, and fails for any other profile.
+    fn fake_paths(dir: &Path) -> Paths {
+        let nvidia_smi = dir.join("nvidia-smi");
+        let script = format!(
+            r#"#!/bin/sh
+echo "$*" >> {log}
+case "$*" in
+  "mig -lgi") echo "No GPU instances found: Not Found"; exit 6 ;;
+  "mig -cgi 3g.20gb -C") echo "Successfully created GPU instance ID  1 on GPU  0 using profile MIG 3g.20gb (ID  9)" ;;
+  "mig -cgi "*) echo "Insufficient Resources" >&2; exit 2 ;;
+esac
+"#,
+            log = dir.join("calls").display()
+        );
+        fs::write(&nvidia_smi, script).unwrap();
+        fs::set_permissions(&nvidia_smi, fs::Permissions::from_mode(0o755)).unwrap();
+
+        Paths {
+            nvidia_smi,
+            state_dir: dir.to_path_buf(),
+            ..Default::default()
+        }
+    }
+
+    fn calls(dir: &Path) -> Vec<String> {
+        fs::read_to_string(dir.join("calls"))
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn test_set_mig_profile_rolls_back_partial_layout() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let paths = fake_paths(temp_dir.path());
+
+        let err = set_mig_profile(&paths, "3g.20gb,2g.10gb", Some("3g.20gb"), &Target::AllGpus)
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            error::Error::CreateInstances {
+                step: 2,
+                total: 2,
+                ..
+            }
+        ));
+        assert!(err.to_string().contains("fell back to profile 3g.20gb"));
+
+        let expected_calls = vec![
+            "mig -lgi",
+            "mig -cgi 3g.20gb -C",
+            "mig -cgi 2g.10gb -C",
+            "mig -dci -i 0 -gi 1",
+            "mig -dgi -i 0 -gi 1",
+            "mig -cgi 3g.20gb -C",
+        ];
+        assert_eq!(calls(temp_dir.path()), expected_calls)
+    }
+
+    #[test]
+    fn test_set_mig_profile_without_fallback() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let paths = fake_paths(temp_dir.path());
+
+        let err = set_mig_profile(&paths, "2g.10gb", None, &Target::Gpus(vec![0, 1])).unwrap_err();
+        assert!(err.to_string().contains("restored the previous layout"));
+
+        let expected_calls = vec!["mig -lgi", "mig -cgi 2g.10gb -C -i 0,1"];
+        assert_eq!(calls(temp_dir.path()), expected_calls)
+    }
+}
@@ -0,0 +1,52 @@
+use crate::{error, Result};
+use log::{debug, trace};
+use snafu::{ensure, ResultExt};
+use std::ffi::OsStr;
+use std::path::Path;
+use std::process::{Command, Output};
+
+/// Wrapper around process::Command that adds error checking.
+pub(crate) fn command<I, S>(bin_path: &Path, args: I) -> Result<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let output = command_output(bin_path, args)?;
+
+    ensure!(
+        output.status.success(),
+        error::CommandFailureSnafu {
+            bin_path,
+            stderr: String::from_utf8_lossy(&output.stderr),
+        }
+    );
+
+    let output_str = String::from_utf8_lossy(&output.stdout);
+
+    Ok(output_str.to_string())
+}
+
+/// Runs the command and returns its output, leaving the exit status to the caller.
+pub(crate) fn command_output<I, S>(bin_path: &Path, args: I) -> Result<Output>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let mut command = Command::new(bin_path);
+    command.args(args);
+    let output = command.output().context(error::ExecutionFailureSnafu {
+        command: format!("{:?}", command),
+    })?;
+
+    // The exit code is a structured field, so log pipelines can query failures by it.
+    debug!(
+        exit_code = output.status.code().unwrap_or(-1);
+        "'{}' exited with {}",
+        bin_path.display(),
+        output.status
+    );
+    trace!("stdout: {}", String::from_utf8_lossy(&output.stdout));
+    trace!("stderr: {}", String::from_utf8_lossy(&output.stderr));
+
+    Ok(output)
+}
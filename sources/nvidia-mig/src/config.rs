@@ -0,0 +1,265 @@
+//! The `config` module reads the MIG settings that `nvidia-migmanager` applies.
+
+use crate::gpu::MigGpu;
+use crate::paths::PathOverrides;
+use crate::{error, Result};
+use serde::Deserialize;
+use snafu::ResultExt;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Default location of the rendered MIG settings.
+pub const DEFAULT_CONFIG_PATH: &str = "/etc/nvidia-migmanager/nvidia-migmanager.toml";
+
+/// The MIG settings, as rendered from `settings.kubelet-device-plugins.nvidia`.
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct NvidiaMigConfig {
+    /// MIG is enabled when this is `mig`, and disabled for any other value.
+    #[serde(default)]
+    pub device_partitioning_strategy: String,
+    /// Maps a GPU key such as `a100.40gb` to a number of slices or an exact MIG profile.
+    #[serde(default)]
+    pub profile: HashMap<String, String>,
+    /// Overrides for the locations of tools and state files.
+    #[serde(default)]
+    pub paths: PathOverrides,
+    /// A `mig-parted` config to apply instead of the `profile` table.
+    #[serde(default)]
+    pub mig_parted: Option<MigPartedSettings>,
+    /// GPUs that are left alone, such as a GPU reserved for a host-level service.
+    #[serde(default)]
+    pub exclude: Vec<GpuSelector>,
+}
+
+/// Selects GPUs by index (`0`), UUID (`GPU-5d5ba0d6-...`), PCI bus ID (`00000000:10:1C.0`) or
+/// model. A model is either a GPU key of the `profile` table, such as `a100.40gb`, or the product
+/// name reported by `nvidia-smi`, such as `NVIDIA A100-SXM4-40GB`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(from = "RawGpuSelector")]
+pub enum GpuSelector {
+    Index(u32),
+    Uuid(String),
+    PciBusId(String),
+    Model(String),
+}
+
+// Indices may be given as TOML integers or as strings.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawGpuSelector {
+    Index(u32),
+    Text(String),
+}
+
+impl From<RawGpuSelector> for GpuSelector {
+    fn from(selector: RawGpuSelector) -> Self {
+        let selector = match selector {
+            RawGpuSelector::Index(index) => return GpuSelector::Index(index),
+            RawGpuSelector::Text(selector) => selector,
+        };
+        if let Ok(index) = selector.parse() {
+            GpuSelector::Index(index)
+        } else if selector.starts_with("GPU-") {
+            GpuSelector::Uuid(selector)
+        } else if selector.contains(':') {
+            GpuSelector::PciBusId(selector)
+        } else {
+            GpuSelector::Model(selector)
+        }
+    }
+}
+
+impl GpuSelector {
+    /// Returns whether `gpu` is selected.
+    pub fn matches(&self, gpu: &MigGpu) -> bool {
+        match self {
+            GpuSelector::Index(index) => gpu.index == *index,
+            GpuSelector::Uuid(uuid) => gpu.uuid.eq_ignore_ascii_case(uuid),
+            GpuSelector::PciBusId(pci_bus_id) => {
+                pci_bus_id_key(&gpu.pci_bus_id) == pci_bus_id_key(pci_bus_id)
+            }
+            GpuSelector::Model(model) => {
+                gpu.model.config_key() == Some(model.as_str())
+                    || gpu.name.eq_ignore_ascii_case(model)
+            }
+        }
+    }
+}
+
+impl fmt::Display for GpuSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GpuSelector::Index(index) => write!(f, "index {}", index),
+            GpuSelector::Uuid(uuid) => write!(f, "UUID {}", uuid),
+            GpuSelector::PciBusId(pci_bus_id) => write!(f, "PCI bus ID {}", pci_bus_id),
+            GpuSelector::Model(model) => write!(f, "model {}", model),
+        }
+    }
+}
+
+// `nvidia-smi` prints PCI bus IDs with an 8-digit domain, such as `00000000:10:1C.0`, while
+// `lspci` and sysfs use 4 digits or leave the domain out. Compare them without the domain's
+// leading zeros and regardless of case.
+fn pci_bus_id_key(pci_bus_id: &str) -> String {
+    let pci_bus_id = pci_bus_id.to_ascii_lowercase();
+    let (domain, rest) = match pci_bus_id.matches(':').count() {
+        2 => pci_bus_id.split_once(':').unwrap_or_default(),
+        _ => ("0", pci_bus_id.as_str()),
+    };
+    let domain = domain.trim_start_matches('0');
+    format!("{}:{}", if domain.is_empty() { "0" } else { domain }, rest)
+}
+
+/// Selects a named config from a `mig-parted` config file.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct MigPartedSettings {
+    /// Path to the `mig-parted` config file.
+    pub config_file: PathBuf,
+    /// Name of the config to apply, from the `mig-configs` of the file.
+    pub selected: String,
+}
+
+impl NvidiaMigConfig {
+    /// Read the config file to get MIG settings
+    pub fn from_file<P>(config_path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let config_str =
+            fs::read_to_string(config_path.as_ref()).context(error::ReadConfigSnafu {
+                config_path: config_path.as_ref(),
+            })?;
+
+        let config: NvidiaMigConfig =
+            toml::from_str(&config_str).context(error::TomlDeserializationSnafu {
+                config_path: config_path.as_ref(),
+            })?;
+
+        Ok(config)
+    }
+
+    /// Returns whether the settings ask for the GPUs to be partitioned with MIG.
+    pub fn mig_enabled(&self) -> bool {
+        self.device_partitioning_strategy == "mig"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_get_mig_settings() {
+        let config_toml = r#"
+            device-partitioning-strategy = "mig"
+            profile = { "a100.40gb" = "1g.5gb" }
+        "#;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let temp_config = Path::join(temp_dir.path(), "nvidia-migmanager.toml");
+        std::fs::write(&temp_config, config_toml).unwrap();
+
+        let mig_settings = NvidiaMigConfig::from_file(&temp_config).unwrap();
+        let mut mig_profile = HashMap::new();
+        mig_profile.insert("a100.40gb".to_string(), "1g.5gb".to_string());
+
+        let expected_mig_settings = NvidiaMigConfig {
+            device_partitioning_strategy: "mig".to_string(),
+            profile: mig_profile,
+            ..Default::default()
+        };
+
+        assert_eq!(mig_settings, expected_mig_settings)
+    }
+
+    #[test]
+    fn test_get_mig_settings_path_overrides() {
+        let config_toml = r#"
+            device-partitioning-strategy = "mig"
+
+            [paths]
+            nvidia-smi = "/host/usr/libexec/nvidia/tesla/bin/nvidia-smi"
+            state-dir = "/host/run/nvidia-migmanager"
+        "#;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let temp_config = Path::join(temp_dir.path(), "nvidia-migmanager.toml");
+        std::fs::write(&temp_config, config_toml).unwrap();
+
+        let mig_settings = NvidiaMigConfig::from_file(&temp_config).unwrap();
+
+        let expected_paths = PathOverrides {
+            nvidia_smi: Some("/host/usr/libexec/nvidia/tesla/bin/nvidia-smi".into()),
+            systemctl: None,
+            state_dir: Some("/host/run/nvidia-migmanager".into()),
+            history_dir: None,
+            metrics_file: None,
+        };
+
+        assert_eq!(mig_settings.paths, expected_paths)
+    }
+
+    #[test]
+    fn test_get_mig_settings_exclude() {
+        let config_toml = r#"
+            device-partitioning-strategy = "mig"
+            exclude = [3, "GPU-5d5ba0d6", "0000:10:1c.0", "a100.40gb"]
+        "#;
+        let mig_settings: NvidiaMigConfig = toml::from_str(config_toml).unwrap();
+
+        let expected_exclude = vec![
+            GpuSelector::Index(3),
+            GpuSelector::Uuid("GPU-5d5ba0d6".to_string()),
+            GpuSelector::PciBusId("0000:10:1c.0".to_string()),
+            GpuSelector::Model("a100.40gb".to_string()),
+        ];
+        assert_eq!(mig_settings.exclude, expected_exclude);
+
+        let gpu = MigGpu {
+            index: 0,
+            uuid: "GPU-5d5ba0d6".to_string(),
+            pci_bus_id: "00000000:10:1C.0".to_string(),
+            name: "NVIDIA A100-SXM4-40GB".to_string(),
+            pci_device_id: "0x20B010DE".to_string(),
+            model: crate::gpu::NvidiaGpu::A100_40GB,
+            state: crate::gpu::MigState::Enabled,
+            health: Default::default(),
+        };
+        let matching: Vec<_> = mig_settings
+            .exclude
+            .iter()
+            .map(|selector| selector.matches(&gpu))
+            .collect();
+        assert_eq!(matching, vec![false, true, true, true]);
+        assert!(
+            GpuSelector::from(RawGpuSelector::Text("NVIDIA A100-SXM4-40GB".to_string()))
+                .matches(&gpu)
+        );
+    }
+
+    #[test]
+    fn test_get_mig_settings_default_profiles() {
+        let config_toml = r#"
+            device-partitioning-strategy = "mig"
+        "#;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let temp_config = Path::join(temp_dir.path(), "nvidia-migmanager.toml");
+        std::fs::write(&temp_config, config_toml).unwrap();
+
+        let mig_settings = NvidiaMigConfig::from_file(&temp_config).unwrap();
+        let mig_profile = HashMap::new();
+
+        let expected_mig_settings = NvidiaMigConfig {
+            device_partitioning_strategy: "mig".to_string(),
+            profile: mig_profile,
+            ..Default::default()
+        };
+
+        assert_eq!(mig_settings, expected_mig_settings)
+    }
+}
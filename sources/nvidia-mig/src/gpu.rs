@@ -0,0 +1,278 @@
+//! The `gpu` module discovers the NVIDIA GPUs in the instance and their MIG state.
+
+use crate::command::command;
+use crate::health::{get_gpu_health, GpuHealth};
+use crate::paths::Paths;
+use crate::{error, Result};
+use log::{info, warn};
+use snafu::{ensure, OptionExt};
+
+const NVIDIA_VENDOR_ID: &str = "10DE";
+
+/// The GPU models with known MIG profiles.
+#[derive(Debug, PartialEq, Clone)]
+pub enum NvidiaGpu {
+    A100_40GB,
+    A100_80GB,
+    H100_80GB,
+    H200_141GB,
+    Other,
+}
+
+impl NvidiaGpu {
+    /// GPU keys used in the `profile` table of the MIG settings, for every known model.
+    pub const KNOWN_GPU_KEYS: [&'static str; 4] =
+        ["a100.40gb", "a100.80gb", "h100.80gb", "h200.141gb"];
+
+    /// Ampere GPUs need a GPU reset, and therefore a reboot, to change their MIG mode.
+    pub fn is_ampere(&self) -> bool {
+        use NvidiaGpu::*;
+        matches!(self, A100_40GB | A100_80GB)
+    }
+
+    /// Returns the key used for this model in the `profile` table of the MIG settings.
+    pub fn config_key(&self) -> Option<&'static str> {
+        match self {
+            NvidiaGpu::A100_40GB => Some("a100.40gb"),
+            NvidiaGpu::A100_80GB => Some("a100.80gb"),
+            NvidiaGpu::H100_80GB => Some("h100.80gb"),
+            NvidiaGpu::H200_141GB => Some("h200.141gb"),
+            NvidiaGpu::Other => None,
+        }
+    }
+}
+
+/// The MIG mode of a GPU, as reported by `nvidia-smi`.
+#[derive(Hash, Debug, Clone, PartialEq, Eq)]
+pub enum MigState {
+    Unsupported,
+    Enabled,
+    Disabled,
+    /// The current and pending MIG modes differ; the GPU needs a reset.
+    Transition,
+    Unknown,
+}
+
+impl MigState {
+    pub fn is_disabled(&self) -> bool {
+        matches!(self, MigState::Disabled)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        matches!(self, MigState::Enabled)
+    }
+
+    pub fn is_unsupported(&self) -> bool {
+        matches!(self, MigState::Unsupported)
+    }
+}
+
+/// A GPU found in the instance.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigGpu {
+    /// Index of the GPU, as used by `nvidia-smi -i`.
+    pub index: u32,
+    /// UUID of the GPU, such as `GPU-5d5ba0d6-d33d-2b2c-524d-9e3d8d2b8a77`.
+    pub uuid: String,
+    /// PCI bus ID, such as `00000000:10:1C.0`.
+    pub pci_bus_id: String,
+    /// Product name, such as `NVIDIA A100-SXM4-40GB`.
+    pub name: String,
+    /// PCI device ID, such as `0x20B010DE`.
+    pub pci_device_id: String,
+    pub model: NvidiaGpu,
+    pub state: MigState,
+    pub health: GpuHealth,
+}
+
+/// Uses pci-device id to find out the GPU model of the instance
+pub fn get_gpu_model(pci_device_id: &str) -> Result<NvidiaGpu> {
+    ensure!(
+        pci_device_id.ends_with(NVIDIA_VENDOR_ID),
+        error::GpuModelSnafu
+    );
+
+    if pci_device_id.starts_with("0x20B0") {
+        info!("Found NVIDIA A100-40GB GPU.");
+        Ok(NvidiaGpu::A100_40GB)
+    } else if pci_device_id.starts_with("0x20B2") || pci_device_id.starts_with("0x20B5") {
+        info!("Found NVIDIA A100-80GB GPU.");
+        Ok(NvidiaGpu::A100_80GB)
+    } else if pci_device_id.starts_with("0x2330")
+        || pci_device_id.starts_with("0x2321")
+        || pci_device_id.starts_with("0x2331")
+        || pci_device_id.starts_with("0x2339")
+    {
+        info!("Found NVIDIA H100-80GB GPU.");
+        Ok(NvidiaGpu::H100_80GB)
+    } else if pci_device_id.starts_with("0x2335")
+        || pci_device_id.starts_with("0x233B")
+        || pci_device_id.starts_with("0x2348")
+    {
+        info!("Found NVIDIA H200-141GB GPU.");
+        Ok(NvidiaGpu::H200_141GB)
+    } else {
+        warn!("Found NVIDIA Device but couldn't confirm variant.");
+        Ok(NvidiaGpu::Other)
+    }
+}
+
+/// Maps the current and pending MIG modes reported by `nvidia-smi` to a [`MigState`].
+pub fn get_gpu_state(current_state: &str, next_state: &str) -> MigState {
+    let mut gpu_state = MigState::Unknown;
+
+    if current_state != next_state {
+        gpu_state = MigState::Transition;
+    } else if current_state == "Enabled" {
+        gpu_state = MigState::Enabled;
+    } else if current_state == "Disabled" {
+        gpu_state = MigState::Disabled;
+    } else if current_state == "[N/A]" {
+        gpu_state = MigState::Unsupported;
+    }
+
+    gpu_state
+}
+
+/// Runs nvidia-smi command to find out the current state of the Nvidia GPU.
+pub fn get_gpu_info(paths: &Paths) -> Result<Vec<MigGpu>> {
+    info!("Fetching GPU devices data ...");
+
+    let output = command(
+        &paths.nvidia_smi,
+        [
+            "--query-gpu=index,pci.device_id,mig.mode.current,mig.mode.pending,uuid,pci.bus_id,name",
+            "--format=csv,noheader",
+        ],
+    )?;
+    let mut gpu_info = parse_gpu_info(&output)?;
+
+    // Older drivers don't report every health field; don't let that block MIG.
+    match get_gpu_health(paths) {
+        Ok(mut gpu_health) => {
+            for gpu in &mut gpu_info {
+                gpu.health = gpu_health.remove(&gpu.index).unwrap_or_default();
+            }
+        }
+        Err(e) => warn!(
+            "Failed to query GPU health, assuming the GPUs are healthy: {}",
+            e
+        ),
+    }
+
+    Ok(gpu_info)
+}
+
+/// Runs nvidia-smi to find out the version of the NVIDIA driver.
+pub fn get_driver_version(paths: &Paths) -> Result<String> {
+    let output = command(
+        &paths.nvidia_smi,
+        ["--query-gpu=driver_version", "--format=csv,noheader"],
+    )?;
+    // Every GPU reports the same driver, so the first one is enough.
+    let version = output.lines().next().unwrap_or_default().trim();
+    ensure!(!version.is_empty(), error::NvidiaSmiSnafu);
+
+    Ok(version.to_string())
+}
+
+/// Parses the output of `nvidia-smi --query-gpu=index,pci.device_id,mig.mode.current,
+/// mig.mode.pending,uuid,pci.bus_id,name --format=csv,noheader`.
+pub fn parse_gpu_info(output: &str) -> Result<Vec<MigGpu>> {
+    let mut gpu_info = Vec::new();
+
+    for row in output.lines() {
+        let parts: Vec<_> = row.split(", ").collect();
+
+        ensure!(parts.len() == 7, error::NvidiaSmiSnafu);
+
+        let index = parts[0].parse().ok().context(error::NvidiaSmiSnafu)?;
+        let gpu_model = get_gpu_model(parts[1])?;
+        let gpu_state = get_gpu_state(parts[2], parts[3]);
+
+        let gpu = MigGpu {
+            index,
+            uuid: parts[4].to_string(),
+            pci_bus_id: parts[5].to_string(),
+            name: parts[6].to_string(),
+            pci_device_id: parts[1].to_string(),
+            model: gpu_model,
+            state: gpu_state,
+            health: GpuHealth::default(),
+        };
+        gpu_info.push(gpu);
+    }
+
+    Ok(gpu_info)
+}
+
+/// Returns the GPU model of the instance, and fails if the instance has more than one model.
+pub fn get_instance_gpu(gpu_info: &[MigGpu]) -> Result<NvidiaGpu> {
+    let reference_gpu_model = gpu_info
+        .first()
+        .context(error::GpuModelSnafu)?
+        .model
+        .clone();
+    ensure!(
+        gpu_info.iter().all(|gpu| gpu.model == reference_gpu_model),
+        error::MigGpuSnafu
+    );
+
+    Ok(reference_gpu_model)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_gpu_info() {
+        let output = "0, 0x20B010DE, Disabled, Disabled, GPU-5d5ba0d6, 00000000:10:1C.0, \
+                      NVIDIA A100-SXM4-40GB\n\
+                      1, 0x233010DE, Enabled, Enabled, GPU-9e3d8d2b, 00000000:10:1D.0, \
+                      NVIDIA H100 80GB HBM3\n";
+        let gpu_info = parse_gpu_info(output).unwrap();
+
+        let expected_gpu_info = vec![
+            MigGpu {
+                index: 0,
+                uuid: "GPU-5d5ba0d6".to_string(),
+                pci_bus_id: "00000000:10:1C.0".to_string(),
+                name: "NVIDIA A100-SXM4-40GB".to_string(),
+                pci_device_id: "0x20B010DE".to_string(),
+                model: NvidiaGpu::A100_40GB,
+                state: MigState::Disabled,
+                health: GpuHealth::default(),
+            },
+            MigGpu {
+                index: 1,
+                uuid: "GPU-9e3d8d2b".to_string(),
+                pci_bus_id: "00000000:10:1D.0".to_string(),
+                name: "NVIDIA H100 80GB HBM3".to_string(),
+                pci_device_id: "0x233010DE".to_string(),
+                model: NvidiaGpu::H100_80GB,
+                state: MigState::Enabled,
+                health: GpuHealth::default(),
+            },
+        ];
+
+        assert_eq!(gpu_info, expected_gpu_info)
+    }
+
+    #[test]
+    fn test_parse_gpu_info_malformed() {
+        assert!(parse_gpu_info("0, 0x20B010DE, Disabled\n").is_err());
+        assert!(parse_gpu_info("0, 0x20B01234, Disabled, Disabled, GPU-1, 0:1:0.0, A\n").is_err());
+        assert!(
+            parse_gpu_info("first, 0x20B010DE, Disabled, Disabled, GPU-1, 0:1:0.0, A\n").is_err()
+        );
+    }
+
+    #[test]
+    fn test_get_gpu_state() {
+        assert_eq!(get_gpu_state("Enabled", "Enabled"), MigState::Enabled);
+        assert_eq!(get_gpu_state("Disabled", "Disabled"), MigState::Disabled);
+        assert_eq!(get_gpu_state("Disabled", "Enabled"), MigState::Transition);
+        assert_eq!(get_gpu_state("[N/A]", "[N/A]"), MigState::Unsupported);
+    }
+}
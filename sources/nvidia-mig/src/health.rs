@@ -0,0 +1,134 @@
+//! The `health` module checks the memory health of the GPUs, so that GPUs with uncorrectable ECC
+//! errors, pending page retirements or failed row remappings aren't partitioned.
+
+use crate::command::command;
+use crate::paths::Paths;
+use crate::{error, Result};
+use serde::{Deserialize, Serialize};
+use snafu::{ensure, OptionExt};
+use std::collections::HashMap;
+use std::fmt;
+
+/// The memory health of a GPU. Fields are `None` when the GPU doesn't report them, such as page
+/// retirement on GPUs that remap rows instead.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GpuHealth {
+    /// Uncorrectable ECC errors since the driver was loaded.
+    pub uncorrected_ecc_errors: Option<u64>,
+    /// Pages waiting to be retired on the next GPU reset.
+    pub retired_pages_pending: Option<bool>,
+    /// Rows waiting to be remapped on the next GPU reset.
+    pub remapped_rows_pending: Option<bool>,
+    /// A row remapping failed, so the GPU has bad memory that can't be worked around.
+    pub remapped_rows_failure: Option<bool>,
+}
+
+impl GpuHealth {
+    /// Returns the reasons why the GPU is unhealthy, or nothing if it is healthy.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if let Some(errors) = self.uncorrected_ecc_errors.filter(|errors| *errors > 0) {
+            problems.push(format!("{} uncorrectable ECC errors", errors));
+        }
+        if self.retired_pages_pending == Some(true) {
+            problems.push("page retirement pending".to_string());
+        }
+        if self.remapped_rows_pending == Some(true) {
+            problems.push("row remapping pending".to_string());
+        }
+        if self.remapped_rows_failure == Some(true) {
+            problems.push("row remapping failed".to_string());
+        }
+        problems
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.problems().is_empty()
+    }
+}
+
+impl fmt::Display for GpuHealth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let problems = self.problems();
+        if problems.is_empty() {
+            write!(f, "healthy")
+        } else {
+            write!(f, "unhealthy: {}", problems.join(", "))
+        }
+    }
+}
+
+/// Runs `nvidia-smi` to find out the memory health of every GPU, by GPU index.
+pub fn get_gpu_health(paths: &Paths) -> Result<HashMap<u32, GpuHealth>> {
+    let output = command(
+        &paths.nvidia_smi,
+        [
+            "--query-gpu=index,ecc.errors.uncorrected.volatile.total,retired_pages.pending,\
+             remapped_rows.pending,remapped_rows.failure",
+            "--format=csv,noheader",
+        ],
+    )?;
+
+    parse_gpu_health(&output)
+}
+
+/// Parses the output of `nvidia-smi --query-gpu=index,ecc.errors.uncorrected.volatile.total,
+/// retired_pages.pending,remapped_rows.pending,remapped_rows.failure --format=csv,noheader`.
+pub fn parse_gpu_health(output: &str) -> Result<HashMap<u32, GpuHealth>> {
+    let mut gpu_health = HashMap::new();
+
+    for row in output.lines() {
+        let parts: Vec<_> = row.split(", ").map(str::trim).collect();
+        ensure!(parts.len() == 5, error::NvidiaSmiSnafu);
+
+        let index: u32 = parts[0].parse().ok().context(error::NvidiaSmiSnafu)?;
+        gpu_health.insert(
+            index,
+            GpuHealth {
+                uncorrected_ecc_errors: parts[1].parse().ok(),
+                retired_pages_pending: parse_flag(parts[2]),
+                remapped_rows_pending: parse_flag(parts[3]),
+                remapped_rows_failure: parse_flag(parts[4]),
+            },
+        );
+    }
+
+    Ok(gpu_health)
+}
+
+// Flags are reported as `Yes`/`No` or as counts, and as `[N/A]` when not supported.
+fn parse_flag(value: &str) -> Option<bool> {
+    match value {
+        "Yes" => Some(true),
+        "No" => Some(false),
+        _ => value.parse::<u64>().ok().map(|count| count > 0),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_gpu_health() {
+        let output = "0, 0, [N/A], No, No\n1, 2, [N/A], 0, 1\n2, [N/A], Yes, [N/A], [N/A]\n";
+        let gpu_health = parse_gpu_health(output).unwrap();
+
+        assert!(gpu_health[&0].is_healthy());
+        assert_eq!(
+            gpu_health[&1].to_string(),
+            "unhealthy: 2 uncorrectable ECC errors, row remapping failed"
+        );
+        assert_eq!(
+            gpu_health[&2],
+            GpuHealth {
+                uncorrected_ecc_errors: None,
+                retired_pages_pending: Some(true),
+                remapped_rows_pending: None,
+                remapped_rows_failure: None,
+            }
+        );
+
+        assert!(parse_gpu_health("0, 0, No\n").is_err());
+    }
+}
@@ -0,0 +1,463 @@
+//! The `history` module keeps a log of the applies in the history directory, which persists
+//! across reboots. Every entry records the layout the apply asked for, the layout the GPUs ended
+//! up with, the driver version and the result.
+//!
+//! The last successful apply is the reference for drift detection: a GPU whose MIG mode or GPU
+//! instances no longer match it has been changed outside of `nvidia-migmanager`, for example by
+//! an admin running `nvidia-smi mig -dgi`.
+
+use crate::apply::restore_layout;
+use crate::gpu::MigGpu;
+use crate::inventory::{GpuDevice, Inventory};
+use crate::outcome::{ApplyResult, Outcome};
+use crate::paths::Paths;
+use crate::plan::{Action, Plan};
+use crate::state::write_atomic;
+use crate::{error, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+
+/// Name of the history file in the history directory.
+pub const HISTORY_FILE: &str = "history.jsonl";
+/// Bumped when the format of the history entries changes incompatibly.
+pub const HISTORY_VERSION: u32 = 1;
+/// Number of applies kept in the history file.
+pub const MAX_HISTORY_ENTRIES: usize = 100;
+
+// Changes on every boot. MIG GPU instances don't survive a reboot, so they can only drift within
+// the boot they were created in.
+const BOOT_ID_PATH: &str = "/proc/sys/kernel/random/boot_id";
+
+/// An apply, as recorded in the history file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub version: u32,
+    /// The boot the apply ran in.
+    pub boot_id: Option<String>,
+    pub driver_version: Option<String>,
+    /// The layout the apply asked for, for the GPUs it manages.
+    pub desired: Vec<DesiredLayout>,
+    /// The layout of every GPU after the apply.
+    pub actual: Vec<GpuLayout>,
+    pub result: ApplyResult,
+}
+
+/// The layout an apply asked for on a GPU.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DesiredLayout {
+    pub index: u32,
+    pub mig_enabled: bool,
+    /// The profile string to create, if any. Alternatives tried for unknown GPUs are separated
+    /// by ` or `.
+    pub profile: Option<String>,
+}
+
+/// The MIG mode and GPU instances of a GPU.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GpuLayout {
+    pub index: u32,
+    pub uuid: String,
+    pub mig_mode: String,
+    /// The profile of every GPU instance, largest first.
+    pub gpu_instances: Vec<String>,
+}
+
+impl GpuLayout {
+    /// Returns the layout of a GPU from the inventory.
+    pub fn from_device(gpu: &GpuDevice) -> Self {
+        // A GPU instance with several compute instances has a MIG device for each.
+        let gpu_instances: BTreeSet<_> = gpu
+            .mig_devices
+            .iter()
+            .map(|device| (device.gpu_instance_id, device.profile.as_str()))
+            .collect();
+        let mut gpu_instances: Vec<_> = gpu_instances
+            .into_iter()
+            .map(|(_, profile)| profile.to_string())
+            .collect();
+        gpu_instances.sort_by(|a, b| compute_slices(b).cmp(&compute_slices(a)).then(a.cmp(b)));
+
+        GpuLayout {
+            index: gpu.index,
+            uuid: gpu.uuid.clone(),
+            mig_mode: gpu.mig_mode.clone(),
+            gpu_instances,
+        }
+    }
+}
+
+impl fmt::Display for GpuLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MIG {}", self.mig_mode)?;
+        if self.gpu_instances.is_empty() {
+            write!(f, " without GPU instances")
+        } else {
+            write!(f, " with {}", self.gpu_instances.join(","))
+        }
+    }
+}
+
+// Returns the compute slices of a profile such as `3g.20gb`.
+fn compute_slices(profile: &str) -> u32 {
+    profile
+        .split_once('g')
+        .and_then(|(slices, _)| slices.parse().ok())
+        .unwrap_or_default()
+}
+
+/// A GPU whose layout no longer matches the last successful apply.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Drift {
+    pub expected: GpuLayout,
+    /// The current layout, or `None` if the GPU is gone.
+    pub actual: Option<GpuLayout>,
+}
+
+impl Drift {
+    /// Returns whether the MIG mode changed, which takes a GPU reset to undo.
+    pub fn mig_mode_changed(&self) -> bool {
+        self.actual
+            .as_ref()
+            .is_none_or(|actual| actual.mig_mode != self.expected.mig_mode)
+    }
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "GPU {} {}: expected {}, ",
+            self.expected.index, self.expected.uuid, self.expected
+        )?;
+        match &self.actual {
+            Some(actual) => write!(f, "found {}", actual),
+            None => write!(f, "the GPU is gone"),
+        }
+    }
+}
+
+impl HistoryEntry {
+    /// Builds the entry of an apply of `plan` to the GPUs in `gpu_info`, given the inventory
+    /// after the apply.
+    pub fn new(
+        gpu_info: &[MigGpu],
+        plan: &Plan,
+        inventory: Option<&Inventory>,
+        driver_version: Option<String>,
+        result: ApplyResult,
+    ) -> Self {
+        let desired = gpu_info
+            .iter()
+            .filter(|gpu| !plan.skipped.contains_key(&gpu.index))
+            .map(|gpu| desired_layout(gpu, plan))
+            .collect();
+        let actual = inventory
+            .map(|inventory| inventory.gpus.iter().map(GpuLayout::from_device).collect())
+            .unwrap_or_default();
+
+        HistoryEntry {
+            version: HISTORY_VERSION,
+            boot_id: boot_id(),
+            driver_version,
+            desired,
+            actual,
+            result,
+        }
+    }
+
+    /// Returns whether the apply succeeded, so that its layout is the one the GPUs should have.
+    pub fn is_successful(&self) -> bool {
+        self.result.error.is_none()
+            && matches!(
+                self.result.outcome,
+                Outcome::Unchanged | Outcome::Skipped | Outcome::Applied
+            )
+    }
+
+    /// Compares the layout of the GPUs in `inventory` with the layout after this apply. Only GPUs
+    /// the apply changed or found unchanged are compared; excluded and unhealthy GPUs may change
+    /// freely. Across reboots, only the MIG mode is compared, since GPU instances don't persist.
+    pub fn drift(&self, inventory: &Inventory) -> Vec<Drift> {
+        let same_boot = self.boot_id.is_some() && self.boot_id == boot_id();
+        self.drift_since(inventory, same_boot)
+    }
+
+    fn drift_since(&self, inventory: &Inventory, same_boot: bool) -> Vec<Drift> {
+        self.actual
+            .iter()
+            .filter(|expected| {
+                self.result.gpus.iter().any(|gpu| {
+                    gpu.uuid == expected.uuid
+                        && matches!(gpu.outcome, Outcome::Applied | Outcome::Unchanged)
+                })
+            })
+            .filter_map(|expected| {
+                let actual = inventory
+                    .gpus
+                    .iter()
+                    .find(|gpu| gpu.uuid == expected.uuid)
+                    .map(GpuLayout::from_device);
+                let drifted = match &actual {
+                    None => true,
+                    Some(actual) if same_boot => actual != expected,
+                    Some(actual) => actual.mig_mode != expected.mig_mode,
+                };
+                drifted.then(|| Drift {
+                    expected: expected.clone(),
+                    actual,
+                })
+            })
+            .collect()
+    }
+}
+
+fn desired_layout(gpu: &MigGpu, plan: &Plan) -> DesiredLayout {
+    let mut layout = DesiredLayout {
+        index: gpu.index,
+        mig_enabled: gpu.state.is_enabled(),
+        profile: None,
+    };
+    for action in &plan.actions {
+        match action {
+            Action::SetMigMode { enabled, target } if target.includes(gpu.index) => {
+                layout.mig_enabled = *enabled
+            }
+            Action::CreateInstances {
+                profile, target, ..
+            } if target.includes(gpu.index) => layout.profile = Some(profile.clone()),
+            Action::TryCreateInstances { candidates, target } if target.includes(gpu.index) => {
+                layout.profile = Some(candidates.join(" or "))
+            }
+            _ => {}
+        }
+    }
+
+    layout
+}
+
+fn boot_id() -> Option<String> {
+    fs::read_to_string(BOOT_ID_PATH)
+        .ok()
+        .map(|boot_id| boot_id.trim().to_string())
+}
+
+/// Reads the entries of the history file, oldest first. Entries that can't be parsed, such as
+/// ones from a newer version, are skipped.
+pub fn read_history(paths: &Paths) -> Result<Vec<HistoryEntry>> {
+    let path = paths.history_dir.join(HISTORY_FILE);
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).context(error::ReadStateSnafu { path }),
+    };
+
+    Ok(content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                warn!("Skipping an entry of {}: {}", path.display(), e);
+                None
+            }
+        })
+        .collect())
+}
+
+/// Returns the last successful apply in the history file, if any.
+pub fn last_successful(paths: &Paths) -> Result<Option<HistoryEntry>> {
+    Ok(read_history(paths)?
+        .into_iter()
+        .rev()
+        .find(HistoryEntry::is_successful))
+}
+
+/// Appends `entry` to the history file, dropping the oldest entries beyond
+/// [`MAX_HISTORY_ENTRIES`]. The file is replaced atomically.
+pub fn append(paths: &Paths, entry: &HistoryEntry) -> Result<()> {
+    let path = paths.history_dir.join(HISTORY_FILE);
+    fs::create_dir_all(&paths.history_dir).context(error::WriteStateSnafu {
+        path: &paths.history_dir,
+    })?;
+
+    let mut entries = read_history(paths)?;
+    entries.push(entry.clone());
+    let skip = entries.len().saturating_sub(MAX_HISTORY_ENTRIES);
+    let mut content = String::new();
+    for entry in &entries[skip..] {
+        content += &serde_json::to_string(entry).context(error::SerializeSnafu { path: &path })?;
+        content.push('\n');
+    }
+
+    write_atomic(&path, &content)
+}
+
+/// Restores the GPU instances of the GPUs in `drift`. GPUs whose MIG mode changed need a GPU
+/// reset, so they are left for `apply-mig` to fix on the next boot. Returns the number of GPUs
+/// that were restored.
+pub fn correct_drift(paths: &Paths, drift: &[Drift]) -> Result<usize> {
+    let mut restored = 0;
+    for drifted in drift {
+        if drifted.mig_mode_changed() {
+            warn!(
+                gpu_uuid = drifted.expected.uuid.as_str();
+                "Not correcting GPU {}, its MIG mode can only be restored by a reboot.",
+                drifted.expected.index
+            );
+            continue;
+        }
+        restore_layout(
+            paths,
+            drifted.expected.index,
+            &drifted.expected.gpu_instances,
+        )?;
+        restored += 1;
+    }
+
+    Ok(restored)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::inventory::{MigDevice, INVENTORY_VERSION};
+    use crate::outcome::{GpuResult, RESULT_VERSION};
+
+    fn device(index: u32, mig_mode: &str, gpu_instances: &[(u32, &str)]) -> GpuDevice {
+        GpuDevice {
+            index,
+            uuid: format!("GPU-{}", index),
+            model: "NVIDIA A100-SXM4-40GB".to_string(),
+            pci_device_id: "0x20B010DE".to_string(),
+            mig_mode: mig_mode.to_string(),
+            mig_mode_pending: mig_mode.to_string(),
+            mig_devices: gpu_instances
+                .iter()
+                .map(|(gpu_instance_id, profile)| MigDevice {
+                    gpu_instance_id: *gpu_instance_id,
+                    compute_instance_id: 0,
+                    profile: profile.to_string(),
+                    uuid: String::new(),
+                    memory_mib: 0,
+                })
+                .collect(),
+            health: Default::default(),
+        }
+    }
+
+    fn inventory(gpus: Vec<GpuDevice>) -> Inventory {
+        Inventory {
+            version: INVENTORY_VERSION,
+            gpus,
+        }
+    }
+
+    fn entry(actual: &Inventory, outcomes: &[Outcome]) -> HistoryEntry {
+        HistoryEntry {
+            version: HISTORY_VERSION,
+            boot_id: None,
+            driver_version: Some("535.161.08".to_string()),
+            desired: Vec::new(),
+            actual: actual.gpus.iter().map(GpuLayout::from_device).collect(),
+            result: ApplyResult {
+                version: RESULT_VERSION,
+                outcome: Outcome::Applied,
+                exit_code: 0,
+                error: None,
+                timestamp: 0,
+                gpus: actual
+                    .gpus
+                    .iter()
+                    .zip(outcomes)
+                    .map(|(gpu, outcome)| GpuResult {
+                        index: gpu.index,
+                        uuid: gpu.uuid.clone(),
+                        outcome: *outcome,
+                        detail: None,
+                    })
+                    .collect(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_gpu_layout_from_device() {
+        let mut gpu = device(0, "Enabled", &[(2, "2g.10gb"), (1, "3g.20gb")]);
+        gpu.mig_devices.push(MigDevice {
+            compute_instance_id: 1,
+            ..gpu.mig_devices[0].clone()
+        });
+
+        let layout = GpuLayout::from_device(&gpu);
+
+        assert_eq!(layout.gpu_instances, vec!["3g.20gb", "2g.10gb"]);
+        assert_eq!(layout.to_string(), "MIG Enabled with 3g.20gb,2g.10gb");
+    }
+
+    #[test]
+    fn test_drift() {
+        let applied = inventory(vec![
+            device(0, "Enabled", &[(1, "3g.20gb"), (2, "3g.20gb")]),
+            device(1, "Enabled", &[(1, "7g.40gb")]),
+            device(2, "Disabled", &[]),
+        ]);
+        let entry = entry(
+            &applied,
+            &[Outcome::Applied, Outcome::Skipped, Outcome::Unchanged],
+        );
+        // GPU instance 2 of GPU 0 was destroyed, and the excluded GPU 1 was repartitioned.
+        let current = inventory(vec![
+            device(0, "Enabled", &[(1, "3g.20gb")]),
+            device(1, "Enabled", &[(1, "4g.20gb")]),
+            device(2, "Disabled", &[]),
+        ]);
+
+        let drift = entry.drift_since(&current, true);
+        assert_eq!(drift.len(), 1);
+        assert!(!drift[0].mig_mode_changed());
+        assert_eq!(
+            drift[0].to_string(),
+            "GPU 0 GPU-0: expected MIG Enabled with 3g.20gb,3g.20gb, found MIG Enabled with 3g.20gb"
+        );
+
+        // After a reboot, the GPU instances are gone, but only a MIG mode change is drift.
+        let rebooted = inventory(vec![
+            device(0, "Disabled", &[]),
+            device(1, "Enabled", &[]),
+            device(2, "Disabled", &[]),
+        ]);
+        let drift = entry.drift_since(&rebooted, false);
+        assert_eq!(drift.len(), 1);
+        assert!(drift[0].mig_mode_changed());
+    }
+
+    #[test]
+    fn test_append_keeps_the_newest_entries() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let paths = Paths {
+            history_dir: temp_dir.path().join("history"),
+            ..Default::default()
+        };
+        let applied = inventory(vec![device(0, "Enabled", &[(1, "7g.40gb")])]);
+
+        for timestamp in 0..MAX_HISTORY_ENTRIES as u64 + 2 {
+            let mut entry = entry(&applied, &[Outcome::Applied]);
+            entry.result.timestamp = timestamp;
+            append(&paths, &entry).unwrap();
+        }
+        let mut failed = entry(&applied, &[Outcome::Failed]);
+        failed.result.outcome = Outcome::Failed;
+        append(&paths, &failed).unwrap();
+
+        let history = read_history(&paths).unwrap();
+        assert_eq!(history.len(), MAX_HISTORY_ENTRIES);
+        assert_eq!(history[0].result.timestamp, 3);
+        let last = last_successful(&paths).unwrap().unwrap();
+        assert_eq!(last.result.timestamp, MAX_HISTORY_ENTRIES as u64 + 1);
+    }
+}
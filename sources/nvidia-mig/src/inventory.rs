@@ -0,0 +1,258 @@
+//! The `inventory` module describes the GPUs and MIG devices of the instance in a stable,
+//! machine-readable form, so that other agents can read it instead of running `nvidia-smi`.
+
+use crate::command::command;
+use crate::health::{get_gpu_health, GpuHealth};
+use crate::paths::Paths;
+use crate::state::write_json;
+use crate::{error, Result};
+use log::warn;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use snafu::{ensure, OptionExt};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Name of the inventory file in the state directory.
+pub const INVENTORY_FILE: &str = "devices.json";
+/// Bumped when the format of the inventory changes incompatibly.
+pub const INVENTORY_VERSION: u32 = 1;
+
+// Matches a GPU or a MIG device in the output of `nvidia-smi -L`.
+const LIST_GPU_REGEX: &str = r"^GPU (\d+): .* \(UUID: (GPU-[^)]+)\)";
+const LIST_MIG_DEVICE_REGEX: &str = r"^\s+MIG (\S+)\s+Device\s+(\d+): \(UUID: (MIG-[^)]+)\)";
+// Matches a row of the "MIG devices" table printed by `nvidia-smi`, such as:
+// |  0    1   0   0  |     11MiB / 20224MiB | 42      0 |  3   0    2    0    0 |
+const MIG_DEVICE_ROW_REGEX: &str =
+    r"^\|\s*(\d+)\s+(\d+)\s+(\d+)\s+(\d+)\s*\|\s*\d+MiB\s*/\s*(\d+)MiB";
+
+/// The GPUs and MIG devices of the instance.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Inventory {
+    pub version: u32,
+    pub gpus: Vec<GpuDevice>,
+}
+
+/// A GPU, with the MIG devices it is partitioned into.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GpuDevice {
+    pub index: u32,
+    pub uuid: String,
+    pub model: String,
+    pub pci_device_id: String,
+    pub mig_mode: String,
+    /// The MIG mode after the next GPU reset.
+    #[serde(default)]
+    pub mig_mode_pending: String,
+    pub mig_devices: Vec<MigDevice>,
+    #[serde(default)]
+    pub health: GpuHealth,
+}
+
+impl fmt::Display for GpuDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "GPU {} {} ({}): MIG {}, {} MIG devices, {}",
+            self.index,
+            self.uuid,
+            self.model,
+            self.mig_mode,
+            self.mig_devices.len(),
+            self.health
+        )
+    }
+}
+
+/// A MIG device: a compute instance in a GPU instance.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MigDevice {
+    pub gpu_instance_id: u32,
+    pub compute_instance_id: u32,
+    pub profile: String,
+    pub uuid: String,
+    pub memory_mib: u64,
+}
+
+/// Runs `nvidia-smi` to build the inventory of the GPUs and their MIG devices.
+pub fn get_inventory(paths: &Paths) -> Result<Inventory> {
+    let gpus = command(
+        &paths.nvidia_smi,
+        [
+            "--query-gpu=index,uuid,name,pci.device_id,mig.mode.current,mig.mode.pending",
+            "--format=csv,noheader",
+        ],
+    )?;
+    let list = command(&paths.nvidia_smi, ["-L"])?;
+    let summary = command(&paths.nvidia_smi, [] as [&str; 0])?;
+    let mut inventory = parse_inventory(&gpus, &list, &summary)?;
+
+    match get_gpu_health(paths) {
+        Ok(mut gpu_health) => {
+            for gpu in &mut inventory.gpus {
+                gpu.health = gpu_health.remove(&gpu.index).unwrap_or_default();
+            }
+        }
+        Err(e) => warn!("Failed to query GPU health: {}", e),
+    }
+
+    Ok(inventory)
+}
+
+/// Builds the inventory from the output of `nvidia-smi --query-gpu`, `nvidia-smi -L` and the
+/// "MIG devices" table of `nvidia-smi`.
+pub fn parse_inventory(gpus: &str, list: &str, summary: &str) -> Result<Inventory> {
+    // MIG device UUIDs and profiles, by GPU index and MIG device index.
+    let mut listed = HashMap::new();
+    let gpu_regex = Regex::new(LIST_GPU_REGEX).unwrap();
+    let mig_device_regex = Regex::new(LIST_MIG_DEVICE_REGEX).unwrap();
+    let mut current_gpu = None;
+    for line in list.lines() {
+        if let Some(captures) = gpu_regex.captures(line) {
+            current_gpu = captures[1].parse::<u32>().ok();
+        } else if let (Some(captures), Some(gpu)) = (mig_device_regex.captures(line), current_gpu) {
+            let device: u32 = captures[2].parse().unwrap_or_default();
+            listed.insert(
+                (gpu, device),
+                (captures[1].to_string(), captures[3].to_string()),
+            );
+        }
+    }
+
+    // GI and CI IDs and memory, by GPU index.
+    let mut mig_devices: HashMap<u32, Vec<MigDevice>> = HashMap::new();
+    let row_regex = Regex::new(MIG_DEVICE_ROW_REGEX).unwrap();
+    for captures in summary.lines().filter_map(|line| row_regex.captures(line)) {
+        let gpu: u32 = captures[1].parse().unwrap_or_default();
+        let device: u32 = captures[4].parse().unwrap_or_default();
+        let (profile, uuid) = listed.remove(&(gpu, device)).unwrap_or_default();
+        mig_devices.entry(gpu).or_default().push(MigDevice {
+            gpu_instance_id: captures[2].parse().unwrap_or_default(),
+            compute_instance_id: captures[3].parse().unwrap_or_default(),
+            profile,
+            uuid,
+            memory_mib: captures[5].parse().unwrap_or_default(),
+        });
+    }
+
+    let mut inventory = Inventory {
+        version: INVENTORY_VERSION,
+        gpus: Vec::new(),
+    };
+    for row in gpus.lines() {
+        let parts: Vec<_> = row.split(", ").collect();
+        ensure!(parts.len() == 6, error::NvidiaSmiSnafu);
+
+        let index: u32 = parts[0].parse().ok().context(error::NvidiaSmiSnafu)?;
+        inventory.gpus.push(GpuDevice {
+            index,
+            uuid: parts[1].to_string(),
+            model: parts[2].to_string(),
+            pci_device_id: parts[3].to_string(),
+            mig_mode: parts[4].to_string(),
+            mig_mode_pending: parts[5].to_string(),
+            mig_devices: mig_devices.remove(&index).unwrap_or_default(),
+            health: GpuHealth::default(),
+        });
+    }
+
+    Ok(inventory)
+}
+
+/// Writes the inventory to `devices.json` in the state directory. The file is replaced
+/// atomically, so readers never see a partial inventory.
+pub fn write_inventory(paths: &Paths, inventory: &Inventory) -> Result<()> {
+    write_json(&paths.state_file(INVENTORY_FILE), inventory)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const GPUS: &str = "0, GPU-5d5ba0d6, NVIDIA A100-SXM4-40GB, 0x20B010DE, Enabled, Enabled\n\
+                        1, GPU-9e3d8d2b, NVIDIA A100-SXM4-40GB, 0x20B010DE, Disabled, Enabled\n";
+    const LIST: &str = r#"GPU 0: NVIDIA A100-SXM4-40GB (UUID: GPU-5d5ba0d6)
+  MIG 3g.20gb     Device  0: (UUID: MIG-c6d4f1ef)
+  MIG 3g.20gb     Device  1: (UUID: MIG-45d71c87)
+GPU 1: NVIDIA A100-SXM4-40GB (UUID: GPU-9e3d8d2b)
+"#;
+    const SUMMARY: &str = r#"
++-----------------------------------------------------------------------------+
+| MIG devices:                                                                |
++------------------+----------------------+-----------+-----------------------+
+| GPU  GI  CI  MIG |         Memory-Usage |        Vol|         Shared        |
+|      ID  ID  Dev |           BAR1-Usage | SM     Unc| CE  ENC  DEC  OFA  JPG|
+|                  |                      |        ECC|                       |
+|==================+======================+===========+=======================|
+|  0    1   0   0  |     11MiB / 20224MiB | 42      0 |  3   0    2    0    0 |
+|                  |      0MiB / 32767MiB |           |                       |
++------------------+----------------------+-----------+-----------------------+
+|  0    2   0   1  |      6MiB / 20096MiB | 42      0 |  3   0    2    0    0 |
+|                  |      0MiB / 32767MiB |           |                       |
++------------------+----------------------+-----------+-----------------------+
+"#;
+
+    #[test]
+    fn test_parse_inventory() {
+        let inventory = parse_inventory(GPUS, LIST, SUMMARY).unwrap();
+
+        let expected_inventory = Inventory {
+            version: INVENTORY_VERSION,
+            gpus: vec![
+                GpuDevice {
+                    index: 0,
+                    uuid: "GPU-5d5ba0d6".to_string(),
+                    model: "NVIDIA A100-SXM4-40GB".to_string(),
+                    pci_device_id: "0x20B010DE".to_string(),
+                    mig_mode: "Enabled".to_string(),
+                    mig_mode_pending: "Enabled".to_string(),
+                    mig_devices: vec![
+                        MigDevice {
+                            gpu_instance_id: 1,
+                            compute_instance_id: 0,
+                            profile: "3g.20gb".to_string(),
+                            uuid: "MIG-c6d4f1ef".to_string(),
+                            memory_mib: 20224,
+                        },
+                        MigDevice {
+                            gpu_instance_id: 2,
+                            compute_instance_id: 0,
+                            profile: "3g.20gb".to_string(),
+                            uuid: "MIG-45d71c87".to_string(),
+                            memory_mib: 20096,
+                        },
+                    ],
+                    health: GpuHealth::default(),
+                },
+                GpuDevice {
+                    index: 1,
+                    uuid: "GPU-9e3d8d2b".to_string(),
+                    model: "NVIDIA A100-SXM4-40GB".to_string(),
+                    pci_device_id: "0x20B010DE".to_string(),
+                    mig_mode: "Disabled".to_string(),
+                    mig_mode_pending: "Enabled".to_string(),
+                    mig_devices: vec![],
+                    health: GpuHealth::default(),
+                },
+            ],
+        };
+
+        assert_eq!(inventory, expected_inventory)
+    }
+
+    #[test]
+    fn test_write_inventory() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let paths = Paths {
+            state_dir: temp_dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        let inventory = parse_inventory(GPUS, LIST, SUMMARY).unwrap();
+
+        write_inventory(&paths, &inventory).unwrap();
+
+        let content = std::fs::read_to_string(temp_dir.path().join(INVENTORY_FILE)).unwrap();
+        let written: Inventory = serde_json::from_str(&content).unwrap();
+        assert_eq!(written, inventory)
+    }
+}
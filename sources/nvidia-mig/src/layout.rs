@@ -0,0 +1,162 @@
+//! The `layout` module reads the GPU instances that currently exist in the GPUs.
+
+use crate::command::command_output;
+use crate::paths::Paths;
+use crate::{error, Result};
+use regex::Regex;
+use snafu::ensure;
+
+// Matches a row of `nvidia-smi mig -lgi`, such as:
+// |   0  MIG 1g.5gb          19        7          4:1     |
+const GPU_INSTANCE_ROW_REGEX: &str =
+    r"^\|\s*(\d+)\s+MIG\s+(\S+)\s+(\d+)\s+(\d+)\s+(\d+):(\d+)\s*\|";
+// Matches the message printed by `nvidia-smi mig -cgi` for every GPU instance it creates.
+const CREATED_GPU_INSTANCE_REGEX: &str =
+    r"Successfully created GPU instance ID\s+(\d+) on GPU\s+(\d+)";
+const NO_GPU_INSTANCES: &str = "No GPU instances found";
+
+/// A GPU instance, as listed by `nvidia-smi mig -lgi`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GpuInstance {
+    /// Index of the GPU that holds the instance.
+    pub gpu: u32,
+    /// Name of the GPU instance profile, such as `1g.5gb`.
+    pub profile: String,
+    pub profile_id: u32,
+    pub gi_id: u32,
+    pub placement_start: u32,
+    pub placement_size: u32,
+}
+
+/// Identifies a GPU instance created by `nvidia-smi mig -cgi`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CreatedGpuInstance {
+    pub gpu: u32,
+    pub gi_id: u32,
+}
+
+/// The GPU instances of every GPU in the instance.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Layout {
+    pub gpu_instances: Vec<GpuInstance>,
+}
+
+impl Layout {
+    pub fn is_empty(&self) -> bool {
+        self.gpu_instances.is_empty()
+    }
+}
+
+/// Runs `nvidia-smi mig -lgi` to read the GPU instances of every GPU.
+pub fn get_layout(paths: &Paths) -> Result<Layout> {
+    let output = command_output(&paths.nvidia_smi, ["mig", "-lgi"])?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    // nvidia-smi fails when no GPU instances exist, which is an empty layout for us.
+    if stdout.contains(NO_GPU_INSTANCES) || stderr.contains(NO_GPU_INSTANCES) {
+        return Ok(Layout::default());
+    }
+    ensure!(
+        output.status.success(),
+        error::CommandFailureSnafu {
+            bin_path: &paths.nvidia_smi,
+            stderr,
+        }
+    );
+
+    Ok(parse_layout(&stdout))
+}
+
+/// Parses the table printed by `nvidia-smi mig -lgi`.
+pub fn parse_layout(output: &str) -> Layout {
+    let row_regex = Regex::new(GPU_INSTANCE_ROW_REGEX).unwrap();
+
+    let gpu_instances = output
+        .lines()
+        .filter_map(|line| row_regex.captures(line.trim()))
+        .map(|captures| GpuInstance {
+            gpu: captures[1].parse().unwrap_or_default(),
+            profile: captures[2].to_string(),
+            profile_id: captures[3].parse().unwrap_or_default(),
+            gi_id: captures[4].parse().unwrap_or_default(),
+            placement_start: captures[5].parse().unwrap_or_default(),
+            placement_size: captures[6].parse().unwrap_or_default(),
+        })
+        .collect();
+
+    Layout { gpu_instances }
+}
+
+/// Parses the GPU instances reported as created in the output of `nvidia-smi mig -cgi`. This is
+/// also meaningful when the command failed, since GPU instances created before the failure remain.
+pub fn parse_created_gpu_instances(output: &str) -> Vec<CreatedGpuInstance> {
+    let created_regex = Regex::new(CREATED_GPU_INSTANCE_REGEX).unwrap();
+
+    created_regex
+        .captures_iter(output)
+        .map(|captures| CreatedGpuInstance {
+            gi_id: captures[1].parse().unwrap_or_default(),
+            gpu: captures[2].parse().unwrap_or_default(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_layout() {
+        let output = r#"
++-------------------------------------------------------+
+| GPU instances:                                        |
+| GPU   Name             Profile  Instance   Placement  |
+|                          ID       ID       Start:Size |
+|=======================================================|
+|   0  MIG 3g.20gb          9        1          4:4     |
++-------------------------------------------------------+
+|   1  MIG 1g.5gb          19        7          0:1     |
++-------------------------------------------------------+
+"#;
+
+        let expected_layout = Layout {
+            gpu_instances: vec![
+                GpuInstance {
+                    gpu: 0,
+                    profile: "3g.20gb".to_string(),
+                    profile_id: 9,
+                    gi_id: 1,
+                    placement_start: 4,
+                    placement_size: 4,
+                },
+                GpuInstance {
+                    gpu: 1,
+                    profile: "1g.5gb".to_string(),
+                    profile_id: 19,
+                    gi_id: 7,
+                    placement_start: 0,
+                    placement_size: 1,
+                },
+            ],
+        };
+
+        assert_eq!(parse_layout(output), expected_layout)
+    }
+
+    #[test]
+    fn test_parse_created_gpu_instances() {
+        let output = r#"
+Successfully created GPU instance ID  9 on GPU  0 using profile MIG 1g.5gb (ID 19)
+Successfully created compute instance ID  0 on GPU  0 GPU instance ID  9 using profile MIG 1g.5gb (ID  0)
+Successfully created GPU instance ID 13 on GPU  1 using profile MIG 1g.5gb (ID 19)
+"#;
+
+        let expected_created = vec![
+            CreatedGpuInstance { gpu: 0, gi_id: 9 },
+            CreatedGpuInstance { gpu: 1, gi_id: 13 },
+        ];
+
+        assert_eq!(parse_created_gpu_instances(output), expected_created)
+    }
+}
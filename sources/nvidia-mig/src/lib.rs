@@ -0,0 +1,236 @@
+/*!
+# NVIDIA MIG
+`nvidia-mig` contains the logic used by `nvidia-migmanager` to discover NVIDIA GPUs, decide which
+Multi-Instance GPU (MIG) changes they need, and apply those changes through `nvidia-smi`. It is a
+library so that other Bottlerocket agents can reuse the same discovery and planning logic.
+
+The API is split into three steps:
+
+* **Discovery**: [`gpu::get_gpu_info`] queries `nvidia-smi` and returns a [`gpu::MigGpu`] for
+  every GPU in the instance, with its model, current MIG state and [`health::GpuHealth`].
+* **Planning**: [`plan::plan`] takes the MIG settings from [`config::NvidiaMigConfig`] and the
+  discovered GPUs and returns a [`plan::Plan`], with the reasons for its decisions. Planning
+  doesn't run any commands, so it is safe to call from validators and reporting tools.
+  Profiles given as [`requirements::Requirements`] are solved against the profile tables of the
+  GPU model.
+  [`mig_parted::plan`] does the same for a named config of an NVIDIA `mig-parted` YAML file.
+* **Applying**: [`apply::apply`] runs the actions in a [`plan::Plan`], and
+  [`reboot::reboot_if_required`] reboots the host if a previous apply requested it. Rebooting
+  only needs [`paths::Paths`], not the MIG settings or the GPUs.
+
+[`outcome::ApplyResult`] records what an apply did to every GPU, and [`metrics::render`] exports
+it with the [`inventory::Inventory`] in the Prometheus text format. [`history::append`] keeps a
+log of the applies that persists across reboots, and [`history::HistoryEntry::drift`] finds GPUs
+that were changed outside of `nvidia-migmanager` since, which [`metrics::render`] exports too.
+
+The locations of `nvidia-smi`, `systemctl` and the state directory are passed to every step as
+[`paths::Paths`], so the library can be pointed at host paths mounted elsewhere.
+
+## Example
+```no_run
+use nvidia_mig::{apply, config::NvidiaMigConfig, gpu, paths::Paths, plan};
+
+let config = NvidiaMigConfig::from_file(nvidia_mig::config::DEFAULT_CONFIG_PATH)?;
+let paths = Paths::default().with_overrides(&config.paths);
+let gpus = gpu::get_gpu_info(&paths)?;
+let plan = plan::plan(&config, &gpus)?;
+apply::apply(&plan, &paths)?;
+# Ok::<(), nvidia_mig::error::Error>(())
+```
+*/
+
+pub mod apply;
+mod command;
+pub mod config;
+pub mod gpu;
+pub mod health;
+pub mod history;
+pub mod inventory;
+pub mod layout;
+pub mod lock;
+pub mod metrics;
+pub mod mig_parted;
+pub mod outcome;
+pub mod paths;
+pub mod plan;
+pub mod profile;
+pub mod reboot;
+pub mod requirements;
+mod state;
+
+pub type Result<T> = std::result::Result<T, error::Error>;
+
+pub mod error {
+    use snafu::Snafu;
+    use std::path::PathBuf;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(crate)))]
+    pub enum Error {
+        #[snafu(display("Failed to read settings from config at {}: {}", config_path.display(), source))]
+        ReadConfig {
+            config_path: PathBuf,
+            source: std::io::Error,
+        },
+
+        #[snafu(display("Failed to write marker file at {}. Error: {}", marker_path.display(), source))]
+        WriteMarker {
+            marker_path: PathBuf,
+            source: std::io::Error,
+        },
+
+        #[snafu(display("Failed to deserialize settings from config at {}: {}", config_path.display(), source))]
+        TomlDeserialization {
+            config_path: PathBuf,
+            source: toml::de::Error,
+        },
+
+        #[snafu(display("Failed to deserialize mig-parted config at {}: {}", config_path.display(), source))]
+        YamlDeserialization {
+            config_path: PathBuf,
+            source: serde_norway::Error,
+        },
+
+        #[snafu(display("Unsupported mig-parted config version '{}'", version))]
+        MigPartedVersion { version: String },
+
+        #[snafu(display("The mig-parted config has no named config '{}'", selected))]
+        MigPartedSelected { selected: String },
+
+        #[snafu(display(
+            "GPU {} is selected by more than one entry of mig-parted config '{}'",
+            gpu,
+            selected
+        ))]
+        MigPartedOverlap { selected: String, gpu: u32 },
+
+        #[snafu(display("Unsupported MIG device '{}' in the mig-parted config", profile))]
+        MigPartedProfile { profile: String },
+
+        #[snafu(display("Invalid MIG requirements '{}'", value))]
+        Requirements { value: String },
+
+        #[snafu(display(
+            "No MIG layout of {} GPUs meets the requirements: {}",
+            gpu,
+            requirements
+        ))]
+        Unsatisfiable { gpu: String, requirements: String },
+
+        #[snafu(display(
+            "None of the {} MIG profiles for the unknown GPU could be applied",
+            candidates
+        ))]
+        NoValidProfile { candidates: usize },
+
+        #[snafu(display("Failed to deserialize MIG profile: {}", source))]
+        Deserialization { source: serde_plain::Error },
+
+        #[snafu(display("'{}' failed - stderr: {}", bin_path.display(), stderr))]
+        CommandFailure { bin_path: PathBuf, stderr: String },
+
+        #[snafu(display("Failed to execute '{}': {}", command, source))]
+        ExecutionFailure {
+            command: String,
+            source: std::io::Error,
+        },
+
+        #[snafu(display(
+            "Failed to create GPU instances for profile '{}' at step {} of {}, {}: {}",
+            profile,
+            step,
+            total,
+            rollback,
+            source
+        ))]
+        CreateInstances {
+            profile: String,
+            step: usize,
+            total: usize,
+            rollback: String,
+            source: Box<Error>,
+        },
+
+        #[snafu(display("Failed to open lock file {}: {}", path.display(), source))]
+        LockOpen {
+            path: PathBuf,
+            source: std::io::Error,
+        },
+
+        #[snafu(display("Failed to lock {}: {}", path.display(), source))]
+        Lock {
+            path: PathBuf,
+            source: nix::errno::Errno,
+        },
+
+        #[snafu(display(
+            "Another nvidia-migmanager (pid {}) is running and holds {}; gave up after {}s",
+            holder,
+            path.display(),
+            timeout_secs
+        ))]
+        LockTimeout {
+            path: PathBuf,
+            timeout_secs: u64,
+            holder: String,
+        },
+
+        #[snafu(display("Failed to serialize {}: {}", path.display(), source))]
+        Serialize {
+            path: PathBuf,
+            source: serde_json::Error,
+        },
+
+        #[snafu(display("Failed to write {}: {}", path.display(), source))]
+        WriteState {
+            path: PathBuf,
+            source: std::io::Error,
+        },
+
+        #[snafu(display("Failed to read {}: {}", path.display(), source))]
+        ReadState {
+            path: PathBuf,
+            source: std::io::Error,
+        },
+
+        #[snafu(display("Failed to deserialize {}: {}", path.display(), source))]
+        DeserializeState {
+            path: PathBuf,
+            source: serde_json::Error,
+        },
+
+        #[snafu(display("Nvidia GPU not available."))]
+        GpuModel {},
+
+        #[snafu(display("Invalid MIG Profile provided in the Settings."))]
+        MigProfile {},
+
+        #[snafu(display("MIG is unsupported because multiple variants of Nvidia GPU present."))]
+        MigGpu {},
+
+        #[snafu(display("NvidiaSmi command failed or has incorrect output format."))]
+        NvidiaSmi {},
+    }
+
+    impl Error {
+        /// Returns whether the error comes from invalid MIG settings, rather than from the GPUs or
+        /// the host.
+        pub fn is_invalid_config(&self) -> bool {
+            matches!(
+                self,
+                Error::ReadConfig { .. }
+                    | Error::TomlDeserialization { .. }
+                    | Error::YamlDeserialization { .. }
+                    | Error::MigPartedVersion { .. }
+                    | Error::MigPartedSelected { .. }
+                    | Error::MigPartedOverlap { .. }
+                    | Error::MigPartedProfile { .. }
+                    | Error::Requirements { .. }
+                    | Error::Unsatisfiable { .. }
+                    | Error::NoValidProfile { .. }
+                    | Error::Deserialization { .. }
+                    | Error::MigProfile { .. }
+            )
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! The `lock` module keeps concurrent `nvidia-migmanager` invocations from issuing interleaved
+//! `nvidia-smi` MIG commands.
+
+use crate::paths::Paths;
+use crate::{error, Result};
+use log::info;
+use nix::errno::Errno;
+use nix::fcntl::{Flock, FlockArg};
+use snafu::ResultExt;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const LOCK_FILE: &str = "nvidia-migmanager.lock";
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(250);
+
+/// An exclusive `flock` on the lock file in the state directory. The lock is released when this is
+/// dropped, or by the kernel when the process exits for any reason, including a crash.
+#[derive(Debug)]
+pub struct RunLock {
+    _lock: Flock<File>,
+}
+
+impl RunLock {
+    /// Takes the lock, waiting up to `timeout` for another invocation to release it. A zero
+    /// `timeout` fails immediately if the lock is held.
+    pub fn acquire(paths: &Paths, timeout: Duration) -> Result<RunLock> {
+        let lock_path = paths.state_file(LOCK_FILE);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&lock_path)
+            .context(error::LockOpenSnafu { path: &lock_path })?;
+
+        let start = Instant::now();
+        let mut waiting = false;
+        loop {
+            match Flock::lock(file, FlockArg::LockExclusiveNonblock) {
+                Ok(mut lock) => {
+                    record_holder(&mut lock).context(error::LockOpenSnafu { path: &lock_path })?;
+                    return Ok(RunLock { _lock: lock });
+                }
+                Err((mut contended, Errno::EWOULDBLOCK)) => {
+                    let holder = read_holder(&mut contended);
+                    let elapsed = start.elapsed();
+                    if elapsed >= timeout {
+                        return error::LockTimeoutSnafu {
+                            path: lock_path,
+                            timeout_secs: timeout.as_secs(),
+                            holder,
+                        }
+                        .fail();
+                    }
+                    if !waiting {
+                        info!(
+                            "Waiting for nvidia-migmanager (pid {}) to release {}",
+                            holder,
+                            lock_path.display()
+                        );
+                        waiting = true;
+                    }
+                    thread::sleep(LOCK_RETRY_INTERVAL.min(timeout - elapsed));
+                    file = contended;
+                }
+                Err((_, errno)) => {
+                    return Err(errno).context(error::LockSnafu { path: lock_path });
+                }
+            }
+        }
+    }
+}
+
+// Writes our pid to the lock file, so that a waiting invocation can say who holds the lock.
+fn record_holder(file: &mut File) -> std::io::Result<()> {
+    file.set_len(0)?;
+    file.rewind()?;
+    writeln!(file, "{}", std::process::id())?;
+    file.flush()
+}
+
+fn read_holder(file: &mut File) -> String {
+    let mut holder = String::new();
+    if file
+        .rewind()
+        .and_then(|_| file.read_to_string(&mut holder))
+        .is_err()
+    {
+        return "unknown".to_string();
+    }
+    match holder.trim() {
+        "" => "unknown".to_string(),
+        pid => pid.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_run_lock_is_exclusive() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let paths = Paths {
+            state_dir: temp_dir.path().to_path_buf(),
+            ..Default::default()
+        };
+
+        let lock = RunLock::acquire(&paths, Duration::ZERO).unwrap();
+
+        let err = RunLock::acquire(&paths, Duration::from_millis(300)).unwrap_err();
+        let expected_holder = std::process::id().to_string();
+        assert!(matches!(
+            err,
+            error::Error::LockTimeout { ref holder, .. } if *holder == expected_holder
+        ));
+
+        drop(lock);
+        RunLock::acquire(&paths, Duration::ZERO).unwrap();
+    }
+}
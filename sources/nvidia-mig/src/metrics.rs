@@ -0,0 +1,406 @@
+//! The `metrics` module renders the MIG state of the instance in the Prometheus text format, so
+//! that the node exporter's textfile collector can publish it without running `nvidia-smi`.
+
+use crate::history::Drift;
+use crate::inventory::Inventory;
+use crate::outcome::{ApplyResult, Outcome};
+use crate::paths::Paths;
+use crate::state::write_atomic;
+use crate::Result;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+// Every outcome, so that the last apply result is exported as a complete one-hot set.
+const OUTCOMES: [Outcome; 7] = [
+    Outcome::Unchanged,
+    Outcome::Skipped,
+    Outcome::Applied,
+    Outcome::Unsupported,
+    Outcome::RebootPending,
+    Outcome::InvalidConfig,
+    Outcome::Failed,
+];
+
+/// Renders the metrics for `inventory`, the result of the last apply, if there was one since boot,
+/// how the GPUs drifted since the last successful apply, if one is recorded, and whether a reboot
+/// is pending.
+pub fn render(
+    inventory: &Inventory,
+    last_result: Option<&ApplyResult>,
+    drift: Option<&[Drift]>,
+    reboot_pending: bool,
+) -> String {
+    let mut metrics = Metrics::default();
+
+    metrics.header(
+        "nvidia_migmanager_mig_mode_current",
+        "Whether MIG mode is currently enabled on the GPU.",
+    );
+    for gpu in &inventory.gpus {
+        metrics.sample(
+            "nvidia_migmanager_mig_mode_current",
+            &gpu_labels(gpu.index, &gpu.uuid),
+            is_enabled(&gpu.mig_mode),
+        );
+    }
+
+    metrics.header(
+        "nvidia_migmanager_mig_mode_pending",
+        "Whether MIG mode is enabled on the GPU after the next GPU reset.",
+    );
+    for gpu in &inventory.gpus {
+        metrics.sample(
+            "nvidia_migmanager_mig_mode_pending",
+            &gpu_labels(gpu.index, &gpu.uuid),
+            is_enabled(&gpu.mig_mode_pending),
+        );
+    }
+
+    metrics.header(
+        "nvidia_migmanager_gpu_healthy",
+        "Whether the GPU has no uncorrected ECC errors, pending page retirements or row remappings.",
+    );
+    for gpu in &inventory.gpus {
+        metrics.sample(
+            "nvidia_migmanager_gpu_healthy",
+            &gpu_labels(gpu.index, &gpu.uuid),
+            gpu.health.is_healthy() as u64,
+        );
+    }
+
+    metrics.header(
+        "nvidia_migmanager_gpu_instances",
+        "Number of GPU instances on the GPU, by MIG profile.",
+    );
+    for gpu in &inventory.gpus {
+        let mut gpu_instances: BTreeMap<&str, BTreeSet<u32>> = BTreeMap::new();
+        for device in &gpu.mig_devices {
+            gpu_instances
+                .entry(&device.profile)
+                .or_default()
+                .insert(device.gpu_instance_id);
+        }
+        for (profile, ids) in gpu_instances {
+            metrics.sample(
+                "nvidia_migmanager_gpu_instances",
+                &profile_labels(gpu.index, &gpu.uuid, profile),
+                ids.len() as u64,
+            );
+        }
+    }
+
+    metrics.header(
+        "nvidia_migmanager_compute_instances",
+        "Number of compute instances on the GPU, by MIG profile.",
+    );
+    for gpu in &inventory.gpus {
+        let mut compute_instances: BTreeMap<&str, u64> = BTreeMap::new();
+        for device in &gpu.mig_devices {
+            *compute_instances.entry(&device.profile).or_default() += 1;
+        }
+        for (profile, count) in compute_instances {
+            metrics.sample(
+                "nvidia_migmanager_compute_instances",
+                &profile_labels(gpu.index, &gpu.uuid, profile),
+                count,
+            );
+        }
+    }
+
+    metrics.header(
+        "nvidia_migmanager_layout_drift_unknown",
+        "Whether drift is unknown, since no successful apply is recorded.",
+    );
+    metrics.sample(
+        "nvidia_migmanager_layout_drift_unknown",
+        "",
+        drift.is_none() as u64,
+    );
+
+    if let Some(drift) = drift {
+        metrics.header(
+            "nvidia_migmanager_layout_drifted",
+            "Whether the MIG layout of the GPU changed since the last successful apply.",
+        );
+        for gpu in &inventory.gpus {
+            metrics.sample(
+                "nvidia_migmanager_layout_drifted",
+                &gpu_labels(gpu.index, &gpu.uuid),
+                drift
+                    .iter()
+                    .any(|drifted| drifted.expected.uuid == gpu.uuid) as u64,
+            );
+        }
+        // GPUs that are gone drifted too, but aren't in the inventory.
+        for drifted in drift.iter().filter(|drifted| drifted.actual.is_none()) {
+            metrics.sample(
+                "nvidia_migmanager_layout_drifted",
+                &gpu_labels(drifted.expected.index, &drifted.expected.uuid),
+                1,
+            );
+        }
+    }
+
+    if let Some(result) = last_result {
+        metrics.header(
+            "nvidia_migmanager_last_apply_result",
+            "Outcome of the last apply; 1 for the outcome it had and 0 for the others.",
+        );
+        for outcome in OUTCOMES {
+            metrics.sample(
+                "nvidia_migmanager_last_apply_result",
+                &format!("outcome=\"{}\"", outcome),
+                (outcome == result.outcome) as u64,
+            );
+        }
+
+        metrics.header(
+            "nvidia_migmanager_last_apply_exit_code",
+            "Exit code of the last apply.",
+        );
+        metrics.sample(
+            "nvidia_migmanager_last_apply_exit_code",
+            "",
+            result.exit_code as i64,
+        );
+
+        metrics.header(
+            "nvidia_migmanager_last_apply_timestamp_seconds",
+            "When the last apply finished, in seconds since the Unix epoch.",
+        );
+        metrics.sample(
+            "nvidia_migmanager_last_apply_timestamp_seconds",
+            "",
+            result.timestamp,
+        );
+    }
+
+    metrics.header(
+        "nvidia_migmanager_reboot_pending",
+        "Whether a reboot is pending to reset the GPUs.",
+    );
+    metrics.sample(
+        "nvidia_migmanager_reboot_pending",
+        "",
+        reboot_pending as u64,
+    );
+
+    metrics.0
+}
+
+/// Writes the metrics to the metrics file, if one is configured. The file is replaced atomically,
+/// so the textfile collector never reads a partial file.
+pub fn write_metrics(
+    paths: &Paths,
+    inventory: &Inventory,
+    last_result: Option<&ApplyResult>,
+    drift: Option<&[Drift]>,
+) -> Result<()> {
+    let metrics_file = match &paths.metrics_file {
+        Some(metrics_file) => metrics_file,
+        None => return Ok(()),
+    };
+    let reboot_pending = paths.reboot_required_marker().exists();
+    write_atomic(
+        metrics_file,
+        &render(inventory, last_result, drift, reboot_pending),
+    )
+}
+
+// Accumulates metrics in the Prometheus text format.
+#[derive(Default)]
+struct Metrics(String);
+
+impl Metrics {
+    fn header(&mut self, name: &str, help: &str) {
+        let _ = writeln!(self.0, "# HELP {} {}", name, help);
+        let _ = writeln!(self.0, "# TYPE {} gauge", name);
+    }
+
+    fn sample<V>(&mut self, name: &str, labels: &str, value: V)
+    where
+        V: std::fmt::Display,
+    {
+        let _ = match labels {
+            "" => writeln!(self.0, "{} {}", name, value),
+            labels => writeln!(self.0, "{}{{{}}} {}", name, labels, value),
+        };
+    }
+}
+
+fn gpu_labels(index: u32, uuid: &str) -> String {
+    format!("gpu=\"{}\",uuid=\"{}\"", index, escape(uuid))
+}
+
+fn profile_labels(index: u32, uuid: &str, profile: &str) -> String {
+    format!(
+        "{},profile=\"{}\"",
+        gpu_labels(index, uuid),
+        escape(profile)
+    )
+}
+
+fn is_enabled(mig_mode: &str) -> u64 {
+    mig_mode.eq_ignore_ascii_case("enabled") as u64
+}
+
+// Escapes a label value as required by the Prometheus text format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::history::GpuLayout;
+    use crate::inventory::{GpuDevice, MigDevice, INVENTORY_VERSION};
+    use crate::outcome::RESULT_VERSION;
+
+    fn mig_device(gpu_instance_id: u32, compute_instance_id: u32, profile: &str) -> MigDevice {
+        MigDevice {
+            gpu_instance_id,
+            compute_instance_id,
+            profile: profile.to_string(),
+            uuid: String::new(),
+            memory_mib: 0,
+        }
+    }
+
+    fn gpu_device(index: u32, uuid: &str) -> GpuDevice {
+        GpuDevice {
+            index,
+            uuid: uuid.to_string(),
+            model: "NVIDIA A100-SXM4-40GB".to_string(),
+            pci_device_id: "0x20B010DE".to_string(),
+            mig_mode: "Enabled".to_string(),
+            mig_mode_pending: "Enabled".to_string(),
+            mig_devices: Vec::new(),
+            health: Default::default(),
+        }
+    }
+
+    fn gpu_layout(index: u32, uuid: &str) -> GpuLayout {
+        GpuLayout {
+            index,
+            uuid: uuid.to_string(),
+            mig_mode: "Enabled".to_string(),
+            gpu_instances: vec!["3g.20gb".to_string()],
+        }
+    }
+
+    // The samples of `name` in `metrics`.
+    fn samples<'a>(metrics: &'a str, name: &str) -> Vec<&'a str> {
+        metrics
+            .lines()
+            .filter(|line| line.starts_with(name))
+            .collect()
+    }
+
+    #[test]
+    fn test_render() {
+        let inventory = Inventory {
+            version: INVENTORY_VERSION,
+            gpus: vec![GpuDevice {
+                index: 0,
+                uuid: "GPU-5d5ba0d6".to_string(),
+                model: "NVIDIA A100-SXM4-40GB".to_string(),
+                pci_device_id: "0x20B010DE".to_string(),
+                mig_mode: "Enabled".to_string(),
+                mig_mode_pending: "Disabled".to_string(),
+                mig_devices: vec![
+                    mig_device(1, 0, "3g.20gb"),
+                    mig_device(2, 0, "2g.10gb"),
+                    mig_device(2, 1, "2g.10gb"),
+                ],
+                health: Default::default(),
+            }],
+        };
+        let result = ApplyResult {
+            version: RESULT_VERSION,
+            outcome: Outcome::RebootPending,
+            exit_code: 3,
+            error: None,
+            timestamp: 1700000000,
+            gpus: Vec::new(),
+        };
+
+        let metrics = render(&inventory, Some(&result), Some(&[]), true);
+
+        let samples: Vec<_> = metrics
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .collect();
+        let expected_samples = vec![
+            r#"nvidia_migmanager_mig_mode_current{gpu="0",uuid="GPU-5d5ba0d6"} 1"#,
+            r#"nvidia_migmanager_mig_mode_pending{gpu="0",uuid="GPU-5d5ba0d6"} 0"#,
+            r#"nvidia_migmanager_gpu_healthy{gpu="0",uuid="GPU-5d5ba0d6"} 1"#,
+            r#"nvidia_migmanager_gpu_instances{gpu="0",uuid="GPU-5d5ba0d6",profile="2g.10gb"} 1"#,
+            r#"nvidia_migmanager_gpu_instances{gpu="0",uuid="GPU-5d5ba0d6",profile="3g.20gb"} 1"#,
+            r#"nvidia_migmanager_compute_instances{gpu="0",uuid="GPU-5d5ba0d6",profile="2g.10gb"} 2"#,
+            r#"nvidia_migmanager_compute_instances{gpu="0",uuid="GPU-5d5ba0d6",profile="3g.20gb"} 1"#,
+            "nvidia_migmanager_layout_drift_unknown 0",
+            r#"nvidia_migmanager_layout_drifted{gpu="0",uuid="GPU-5d5ba0d6"} 0"#,
+            r#"nvidia_migmanager_last_apply_result{outcome="unchanged"} 0"#,
+            r#"nvidia_migmanager_last_apply_result{outcome="skipped"} 0"#,
+            r#"nvidia_migmanager_last_apply_result{outcome="applied"} 0"#,
+            r#"nvidia_migmanager_last_apply_result{outcome="unsupported"} 0"#,
+            r#"nvidia_migmanager_last_apply_result{outcome="reboot-pending"} 1"#,
+            r#"nvidia_migmanager_last_apply_result{outcome="invalid-config"} 0"#,
+            r#"nvidia_migmanager_last_apply_result{outcome="failed"} 0"#,
+            "nvidia_migmanager_last_apply_exit_code 3",
+            "nvidia_migmanager_last_apply_timestamp_seconds 1700000000",
+            "nvidia_migmanager_reboot_pending 1",
+        ];
+        assert_eq!(samples, expected_samples);
+        assert!(metrics.contains("# TYPE nvidia_migmanager_reboot_pending gauge\n"));
+    }
+
+    #[test]
+    fn test_render_drift() {
+        let inventory = Inventory {
+            version: INVENTORY_VERSION,
+            gpus: vec![gpu_device(0, "GPU-0"), gpu_device(1, "GPU-1")],
+        };
+        let drift = vec![
+            Drift {
+                expected: gpu_layout(1, "GPU-1"),
+                actual: Some(GpuLayout::from_device(&inventory.gpus[1])),
+            },
+            // The GPU is gone.
+            Drift {
+                expected: gpu_layout(2, "GPU-2"),
+                actual: None,
+            },
+        ];
+
+        let metrics = render(&inventory, None, Some(&drift), false);
+        assert_eq!(
+            samples(&metrics, "nvidia_migmanager_layout_drift_unknown"),
+            vec!["nvidia_migmanager_layout_drift_unknown 0"]
+        );
+        assert_eq!(
+            samples(&metrics, "nvidia_migmanager_layout_drifted"),
+            vec![
+                r#"nvidia_migmanager_layout_drifted{gpu="0",uuid="GPU-0"} 0"#,
+                r#"nvidia_migmanager_layout_drifted{gpu="1",uuid="GPU-1"} 1"#,
+                r#"nvidia_migmanager_layout_drifted{gpu="2",uuid="GPU-2"} 1"#,
+            ]
+        );
+
+        // Without a successful apply, drift is unknown rather than absent.
+        let metrics = render(&inventory, None, None, false);
+        assert_eq!(
+            samples(&metrics, "nvidia_migmanager_layout_drift_unknown"),
+            vec!["nvidia_migmanager_layout_drift_unknown 1"]
+        );
+        assert!(samples(&metrics, "nvidia_migmanager_layout_drifted").is_empty());
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape(r#"a"b\c"#), r#"a\"b\\c"#);
+    }
+}
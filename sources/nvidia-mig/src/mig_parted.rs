@@ -0,0 +1,418 @@
+//! The `mig_parted` module reads NVIDIA `mig-parted` style `mig-configs` YAML and plans one of its
+//! named configs. Each entry of a named config selects GPUs with `devices` (`all` or a list of
+//! indices) and an optional `device-filter` of PCI device IDs, and enables MIG with the given
+//! `mig-devices` counts, or disables MIG.
+
+use crate::config::GpuSelector;
+use crate::gpu::MigGpu;
+use crate::plan::{is_excluded, is_unhealthy, Action, Plan, Skip, Target};
+use crate::profile::{known_gpu_profile, DEFAULT_PROFILE};
+use crate::{error, Result};
+use log::{info, warn};
+use regex::Regex;
+use serde::Deserialize;
+use snafu::{ensure, OptionExt, ResultExt};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+/// The only `mig-parted` config version that is understood.
+pub const MIG_PARTED_VERSION: &str = "v1";
+
+// GPU instance profiles, optionally with the media extensions, such as `1g.10gb+me`.
+const GPU_INSTANCE_PROFILE_REGEX: &str = r"^(\d+)g\.\d+gb(\+me)?$";
+
+/// A `mig-parted` config file.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct MigPartedConfig {
+    pub version: String,
+    pub mig_configs: BTreeMap<String, Vec<MigPartedDeviceConfig>>,
+}
+
+/// One entry of a named `mig-parted` config.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct MigPartedDeviceConfig {
+    #[serde(default)]
+    pub device_filter: Option<DeviceFilter>,
+    pub devices: Devices,
+    pub mig_enabled: bool,
+    #[serde(default)]
+    pub mig_devices: BTreeMap<String, u32>,
+}
+
+/// PCI device IDs, such as `0x20B010DE`, given as a single string or a list.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum DeviceFilter {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl DeviceFilter {
+    fn matches(&self, pci_device_id: &str) -> bool {
+        match self {
+            DeviceFilter::One(id) => id.eq_ignore_ascii_case(pci_device_id),
+            DeviceFilter::Many(ids) => ids.iter().any(|id| id.eq_ignore_ascii_case(pci_device_id)),
+        }
+    }
+}
+
+/// The GPUs selected by an entry: `all`, or a list of GPU indices.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Devices {
+    All(AllDevices),
+    Indices(Vec<u32>),
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub enum AllDevices {
+    #[serde(rename = "all")]
+    All,
+}
+
+impl MigPartedDeviceConfig {
+    fn matches(&self, gpu: &MigGpu) -> bool {
+        let device_selected = match &self.devices {
+            Devices::All(_) => true,
+            Devices::Indices(indices) => indices.contains(&gpu.index),
+        };
+        let device_filtered = self
+            .device_filter
+            .as_ref()
+            .map(|filter| filter.matches(&gpu.pci_device_id))
+            .unwrap_or(true);
+
+        device_selected && device_filtered
+    }
+
+    // Builds the comma-separated profile string for `nvidia-smi mig -cgi`, creating the largest
+    // GPU instances first so that they get a placement.
+    fn profile_string(&self) -> Result<String> {
+        let profile_regex = Regex::new(GPU_INSTANCE_PROFILE_REGEX).unwrap();
+
+        let mut profiles = Vec::new();
+        for (profile, count) in &self.mig_devices {
+            let compute_slices: u32 = profile_regex
+                .captures(profile)
+                .and_then(|captures| captures[1].parse().ok())
+                .context(error::MigPartedProfileSnafu { profile })?;
+            profiles.push((compute_slices, profile.as_str(), *count as usize));
+        }
+        profiles.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(b.1)));
+
+        Ok(profiles
+            .into_iter()
+            .flat_map(|(_, profile, count)| std::iter::repeat_n(profile, count))
+            .collect::<Vec<_>>()
+            .join(","))
+    }
+}
+
+impl MigPartedConfig {
+    /// Reads a `mig-parted` config file.
+    pub fn from_file<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let config_str = fs::read_to_string(path.as_ref()).context(error::ReadConfigSnafu {
+            config_path: path.as_ref(),
+        })?;
+
+        let config: MigPartedConfig =
+            serde_norway::from_str(&config_str).context(error::YamlDeserializationSnafu {
+                config_path: path.as_ref(),
+            })?;
+        ensure!(
+            config.version == MIG_PARTED_VERSION,
+            error::MigPartedVersionSnafu {
+                version: config.version
+            }
+        );
+
+        Ok(config)
+    }
+}
+
+/// Builds the plan that applies the named config `selected` to the GPUs in `gpu_info`. Every GPU
+/// may be selected by at most one entry of the named config; GPUs selected by none, or matching
+/// one of the `exclude` selectors, are left alone.
+pub fn plan(
+    config: &MigPartedConfig,
+    selected: &str,
+    gpu_info: &[MigGpu],
+    exclude: &[GpuSelector],
+) -> Result<Plan> {
+    let entries = config
+        .mig_configs
+        .get(selected)
+        .context(error::MigPartedSelectedSnafu { selected })?;
+    info!("Applying mig-parted config {}", selected);
+
+    let mut assigned: HashMap<u32, usize> = HashMap::new();
+    let mut plan = Plan::default();
+    plan.reasons
+        .push(format!("mig-parted config '{}' is selected", selected));
+    let mut reboot_reason = None;
+    let mut create_actions = Vec::new();
+
+    for (entry_index, entry) in entries.iter().enumerate() {
+        let mut gpus = Vec::new();
+        for gpu in gpu_info.iter().filter(|gpu| entry.matches(gpu)) {
+            ensure!(
+                assigned.insert(gpu.index, entry_index).is_none(),
+                error::MigPartedOverlapSnafu {
+                    selected,
+                    gpu: gpu.index
+                }
+            );
+            if is_excluded(gpu, exclude, &mut plan) {
+                continue;
+            }
+            if gpu.state.is_unsupported() {
+                warn!("MIG is not supported by GPU {}, skipping it.", gpu.index);
+                plan.reasons
+                    .push(format!("MIG is not supported by GPU {}", gpu.index));
+                plan.skipped.insert(gpu.index, Skip::Unsupported);
+                continue;
+            }
+            // Unhealthy GPUs are left whole rather than partitioned and handed to workloads.
+            if entry.mig_enabled && is_unhealthy(gpu, &mut plan) {
+                continue;
+            }
+            gpus.push(gpu);
+        }
+        if gpus.is_empty() {
+            continue;
+        }
+
+        let indices = |predicate: fn(&MigGpu) -> bool| -> Vec<u32> {
+            gpus.iter()
+                .filter(|gpu| predicate(gpu))
+                .map(|gpu| gpu.index)
+                .collect()
+        };
+        let to_change = if entry.mig_enabled {
+            indices(|gpu| gpu.state.is_disabled())
+        } else {
+            indices(|gpu| gpu.state.is_enabled())
+        };
+
+        if !to_change.is_empty() {
+            // Ampere GPUs need a reset, and so a reboot, before the new MIG mode applies.
+            if gpus
+                .iter()
+                .any(|gpu| to_change.contains(&gpu.index) && gpu.model.is_ampere())
+            {
+                reboot_reason.get_or_insert(if entry.mig_enabled {
+                    "Enabling MIG"
+                } else {
+                    "Disabling MIG"
+                });
+            }
+            plan.actions.push(Action::SetMigMode {
+                enabled: entry.mig_enabled,
+                target: Target::Gpus(to_change),
+            });
+        }
+
+        if entry.mig_enabled && !entry.mig_devices.is_empty() {
+            let profile = entry.profile_string()?;
+            // Fall back to the whole-GPU profile when all the GPUs are the same known model.
+            let fallback = match gpus.first() {
+                Some(first) if gpus.iter().all(|gpu| gpu.model == first.model) => {
+                    known_gpu_profile(&first.model, DEFAULT_PROFILE)?
+                        .filter(|whole_gpu_profile| *whole_gpu_profile != profile)
+                }
+                _ => None,
+            };
+            create_actions.push(Action::CreateInstances {
+                profile,
+                fallback,
+                target: Target::Gpus(gpus.iter().map(|gpu| gpu.index).collect()),
+            });
+        }
+    }
+
+    // GPU instances can only be created once MIG mode applies, which is after the reboot.
+    match reboot_reason {
+        Some(reason) => {
+            plan.actions.push(Action::RequestReboot(reason.to_string()));
+            plan.reasons.push(
+                "Ampere GPUs need a reboot before MIG mode applies, so GPU instances are created \
+                 on the next run"
+                    .to_string(),
+            );
+        }
+        None => plan.actions.extend(create_actions),
+    }
+
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gpu::{MigState, NvidiaGpu};
+    use crate::health::GpuHealth;
+
+    const MIG_PARTED_CONFIG: &str = r#"
+version: v1
+mig-configs:
+  all-disabled:
+    - devices: all
+      mig-enabled: false
+
+  all-balanced:
+    - device-filter: ["0x233010DE", "0x233110DE"]
+      devices: all
+      mig-enabled: true
+      mig-devices:
+        "1g.10gb": 2
+        "2g.20gb": 1
+        "3g.40gb": 1
+
+  custom:
+    - devices: [0, 1]
+      mig-enabled: true
+      mig-devices:
+        "3g.40gb": 2
+    - devices: [2]
+      mig-enabled: false
+
+  overlapping:
+    - devices: all
+      mig-enabled: true
+    - devices: [0]
+      mig-enabled: false
+"#;
+
+    fn mig_parted_config() -> MigPartedConfig {
+        serde_norway::from_str(MIG_PARTED_CONFIG).unwrap()
+    }
+
+    fn h100(index: u32, state: MigState) -> MigGpu {
+        MigGpu {
+            index,
+            uuid: format!("GPU-{}", index),
+            pci_bus_id: format!("00000000:{:02X}:00.0", index + 1),
+            name: "NVIDIA H100 80GB HBM3".to_string(),
+            pci_device_id: "0x233010DE".to_string(),
+            model: NvidiaGpu::H100_80GB,
+            state,
+            health: GpuHealth::default(),
+        }
+    }
+
+    #[test]
+    fn test_plan_all_balanced() {
+        let gpu_info = vec![h100(0, MigState::Disabled), h100(1, MigState::Enabled)];
+        let plan = plan(&mig_parted_config(), "all-balanced", &gpu_info, &[]).unwrap();
+
+        let expected_actions = vec![
+            Action::SetMigMode {
+                enabled: true,
+                target: Target::Gpus(vec![0]),
+            },
+            Action::CreateInstances {
+                profile: "3g.40gb,2g.20gb,1g.10gb,1g.10gb".to_string(),
+                fallback: Some("7g.80gb".to_string()),
+                target: Target::Gpus(vec![0, 1]),
+            },
+        ];
+
+        assert_eq!(plan.actions, expected_actions)
+    }
+
+    #[test]
+    fn test_plan_device_filter_skips_other_models() {
+        let mut a100 = h100(0, MigState::Disabled);
+        a100.pci_device_id = "0x20B010DE".to_string();
+        a100.model = NvidiaGpu::A100_40GB;
+
+        let plan = plan(&mig_parted_config(), "all-balanced", &[a100], &[]).unwrap();
+
+        assert!(plan.is_empty())
+    }
+
+    #[test]
+    fn test_plan_custom_devices() {
+        let gpu_info = vec![
+            h100(0, MigState::Enabled),
+            h100(1, MigState::Enabled),
+            h100(2, MigState::Enabled),
+            h100(3, MigState::Enabled),
+        ];
+        let plan = plan(&mig_parted_config(), "custom", &gpu_info, &[]).unwrap();
+
+        let expected_actions = vec![
+            Action::SetMigMode {
+                enabled: false,
+                target: Target::Gpus(vec![2]),
+            },
+            Action::CreateInstances {
+                profile: "3g.40gb,3g.40gb".to_string(),
+                fallback: Some("7g.80gb".to_string()),
+                target: Target::Gpus(vec![0, 1]),
+            },
+        ];
+
+        assert_eq!(plan.actions, expected_actions)
+    }
+
+    #[test]
+    fn test_plan_ampere_requires_reboot() {
+        let mut a100 = h100(0, MigState::Enabled);
+        a100.model = NvidiaGpu::A100_80GB;
+
+        let plan = plan(&mig_parted_config(), "all-disabled", &[a100], &[]).unwrap();
+
+        let expected_actions = vec![
+            Action::SetMigMode {
+                enabled: false,
+                target: Target::Gpus(vec![0]),
+            },
+            Action::RequestReboot("Disabling MIG".to_string()),
+        ];
+
+        assert_eq!(plan.actions, expected_actions)
+    }
+
+    #[test]
+    fn test_plan_excluded_devices() {
+        let gpu_info = vec![h100(0, MigState::Enabled), h100(1, MigState::Enabled)];
+        let exclude = vec![GpuSelector::Uuid("GPU-1".to_string())];
+        let plan = plan(&mig_parted_config(), "all-disabled", &gpu_info, &exclude).unwrap();
+
+        let expected_actions = vec![Action::SetMigMode {
+            enabled: false,
+            target: Target::Gpus(vec![0]),
+        }];
+
+        assert_eq!(plan.actions, expected_actions)
+    }
+
+    #[test]
+    fn test_plan_invalid() {
+        let gpu_info = vec![h100(0, MigState::Enabled)];
+
+        assert!(plan(&mig_parted_config(), "overlapping", &gpu_info, &[]).is_err());
+        assert!(plan(&mig_parted_config(), "missing", &gpu_info, &[]).is_err());
+    }
+
+    #[test]
+    fn test_mig_parted_config_from_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let temp_config = temp_dir.path().join("mig-parted.yaml");
+        std::fs::write(&temp_config, MIG_PARTED_CONFIG).unwrap();
+        assert_eq!(
+            MigPartedConfig::from_file(&temp_config).unwrap(),
+            mig_parted_config()
+        );
+
+        std::fs::write(&temp_config, "version: v2\nmig-configs: {}\n").unwrap();
+        assert!(MigPartedConfig::from_file(&temp_config).is_err());
+    }
+}
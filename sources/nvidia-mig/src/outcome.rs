@@ -0,0 +1,264 @@
+//! The `outcome` module describes what an apply did to every GPU, so that systemd units and health
+//! checks can react to it without parsing logs.
+
+use crate::error::Error;
+use crate::gpu::MigGpu;
+use crate::paths::Paths;
+use crate::plan::{Plan, Skip};
+use crate::state::{read_json, write_json};
+use crate::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Name of the result file in the state directory.
+pub const RESULT_FILE: &str = "result.json";
+/// Bumped when the format of the result file changes incompatibly.
+pub const RESULT_VERSION: u32 = 1;
+
+/// What an apply did to a GPU. The variants are ordered by severity; the outcome of the whole apply
+/// is the most severe outcome of its GPUs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    /// The GPU already matched the MIG settings.
+    Unchanged,
+    /// The GPU is excluded or unhealthy, and was left alone.
+    Skipped,
+    /// The MIG settings were applied to the GPU.
+    Applied,
+    /// MIG isn't supported by the GPU.
+    Unsupported,
+    /// The MIG mode of the GPU changes on the next reboot.
+    RebootPending,
+    /// The MIG settings are invalid for the GPU.
+    InvalidConfig,
+    /// Applying the MIG settings failed.
+    Failed,
+}
+
+impl Outcome {
+    /// Returns the exit code of `nvidia-migmanager apply-mig` for this outcome.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Outcome::Unchanged | Outcome::Skipped | Outcome::Applied => 0,
+            Outcome::Failed => 1,
+            Outcome::InvalidConfig => 2,
+            Outcome::RebootPending => 3,
+            Outcome::Unsupported => 4,
+        }
+    }
+
+    /// Returns the outcome of an apply that failed with `error`.
+    pub fn of_error(error: &Error) -> Outcome {
+        if error.is_invalid_config() {
+            Outcome::InvalidConfig
+        } else {
+            Outcome::Failed
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Outcome::Unchanged => "unchanged",
+            Outcome::Skipped => "skipped",
+            Outcome::Applied => "applied",
+            Outcome::Unsupported => "unsupported",
+            Outcome::RebootPending => "reboot-pending",
+            Outcome::InvalidConfig => "invalid-config",
+            Outcome::Failed => "failed",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The outcome of an apply for a single GPU.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GpuResult {
+    pub index: u32,
+    pub uuid: String,
+    pub outcome: Outcome,
+    /// Why the GPU was skipped or failed, if it was.
+    pub detail: Option<String>,
+}
+
+/// The outcome of an apply, as written to `result.json` in the state directory.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApplyResult {
+    pub version: u32,
+    pub outcome: Outcome,
+    pub exit_code: i32,
+    /// The error that stopped the apply, if any.
+    pub error: Option<String>,
+    /// When the apply finished, in seconds since the Unix epoch.
+    #[serde(default)]
+    pub timestamp: u64,
+    pub gpus: Vec<GpuResult>,
+}
+
+impl ApplyResult {
+    /// Builds the result of applying `plan` to the GPUs in `gpu_info`, given what `apply`
+    /// returned.
+    pub fn new(gpu_info: &[MigGpu], plan: &Plan, applied: &Result<()>) -> Self {
+        let gpus = gpu_info
+            .iter()
+            .map(|gpu| {
+                let (outcome, detail) = match (plan.skipped.get(&gpu.index), applied) {
+                    (Some(skip), _) => (skip_outcome(skip), Some(skip.to_string())),
+                    (None, Err(e)) => (Outcome::of_error(e), Some(e.to_string())),
+                    (None, Ok(())) if !plan.targets(gpu.index) => (Outcome::Unchanged, None),
+                    (None, Ok(())) if plan.requests_reboot() => (Outcome::RebootPending, None),
+                    (None, Ok(())) => (Outcome::Applied, None),
+                };
+                GpuResult {
+                    index: gpu.index,
+                    uuid: gpu.uuid.clone(),
+                    outcome,
+                    detail,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let outcome = match applied {
+            Err(e) => Outcome::of_error(e),
+            Ok(()) => gpus
+                .iter()
+                .map(|gpu| gpu.outcome)
+                .max()
+                .unwrap_or(Outcome::Unchanged),
+        };
+
+        ApplyResult {
+            version: RESULT_VERSION,
+            outcome,
+            exit_code: outcome.exit_code(),
+            error: applied.as_ref().err().map(ToString::to_string),
+            timestamp: now(),
+            gpus,
+        }
+    }
+
+    /// Builds the result of an apply that failed before it got to the GPUs, such as when the
+    /// config file can't be read.
+    pub fn from_error(error: &Error) -> Self {
+        let outcome = Outcome::of_error(error);
+        ApplyResult {
+            version: RESULT_VERSION,
+            outcome,
+            exit_code: outcome.exit_code(),
+            error: Some(error.to_string()),
+            timestamp: now(),
+            gpus: Vec::new(),
+        }
+    }
+}
+
+fn skip_outcome(skip: &Skip) -> Outcome {
+    match skip {
+        Skip::Excluded(_) | Skip::Unhealthy(_) => Outcome::Skipped,
+        Skip::Unsupported => Outcome::Unsupported,
+        Skip::NoProfile => Outcome::InvalidConfig,
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Writes the result to `result.json` in the state directory. The file is replaced atomically.
+pub fn write_result(paths: &Paths, result: &ApplyResult) -> Result<()> {
+    write_json(&paths.state_file(RESULT_FILE), result)
+}
+
+/// Reads the result of the last apply from `result.json` in the state directory, or returns
+/// `None` if there hasn't been an apply since boot.
+pub fn read_result(paths: &Paths) -> Result<Option<ApplyResult>> {
+    read_json(&paths.state_file(RESULT_FILE))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gpu::{MigState, NvidiaGpu};
+    use crate::plan::{Action, Target};
+
+    fn gpus(count: u32) -> Vec<MigGpu> {
+        (0..count)
+            .map(|index| MigGpu {
+                index,
+                uuid: format!("GPU-{}", index),
+                pci_bus_id: String::new(),
+                name: String::new(),
+                pci_device_id: String::new(),
+                model: NvidiaGpu::A100_40GB,
+                state: MigState::Disabled,
+                health: Default::default(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_apply_result_per_gpu() {
+        let mut plan = Plan::default();
+        plan.actions.push(Action::SetMigMode {
+            enabled: true,
+            target: Target::Gpus(vec![1]),
+        });
+        plan.actions
+            .push(Action::RequestReboot("Enabling MIG".to_string()));
+        plan.skipped
+            .insert(0, Skip::Excluded("index 0".to_string()));
+
+        let result = ApplyResult::new(&gpus(3), &plan, &Ok(()));
+
+        let outcomes: Vec<_> = result.gpus.iter().map(|gpu| gpu.outcome).collect();
+        assert_eq!(
+            outcomes,
+            vec![Outcome::Skipped, Outcome::RebootPending, Outcome::Unchanged]
+        );
+        assert_eq!(result.outcome, Outcome::RebootPending);
+        assert_eq!(result.outcome.to_string(), "reboot-pending");
+        assert_eq!(result.exit_code, 3);
+    }
+
+    #[test]
+    fn test_apply_result_error() {
+        let mut plan = Plan::default();
+        plan.skipped.insert(1, Skip::Unsupported);
+        let applied = Err(Error::NoValidProfile { candidates: 2 });
+
+        let result = ApplyResult::new(&gpus(2), &plan, &applied);
+
+        assert_eq!(result.gpus[0].outcome, Outcome::InvalidConfig);
+        assert_eq!(result.gpus[1].outcome, Outcome::Unsupported);
+        assert_eq!(result.outcome, Outcome::InvalidConfig);
+        assert_eq!(result.exit_code, 2);
+
+        let result = ApplyResult::from_error(&Error::NvidiaSmi {});
+        assert_eq!(result.outcome, Outcome::Failed);
+        assert!(result.gpus.is_empty());
+    }
+
+    #[test]
+    fn test_write_result() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let paths = Paths {
+            state_dir: temp_dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        let result = ApplyResult::new(&gpus(1), &Plan::default(), &Ok(()));
+
+        write_result(&paths, &result).unwrap();
+
+        let content = std::fs::read_to_string(temp_dir.path().join(RESULT_FILE)).unwrap();
+        assert!(content.contains(r#""outcome": "unchanged""#));
+        assert_eq!(read_result(&paths).unwrap(), Some(result));
+        std::fs::remove_file(temp_dir.path().join(RESULT_FILE)).unwrap();
+        assert_eq!(read_result(&paths).unwrap(), None)
+    }
+}
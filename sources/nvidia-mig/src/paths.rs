@@ -0,0 +1,146 @@
+//! The `paths` module holds the locations of the tools and state files used by `nvidia-migmanager`.
+//! They default to the host locations, and can be overridden so that `nvidia-migmanager` runs in a
+//! container with the host paths mounted elsewhere, or against fake tools in tests.
+
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// Path to the `nvidia-smi` binary used to query and configure the GPUs.
+pub const NVIDIA_SMI_PATH: &str = "/usr/libexec/nvidia/tesla/bin/nvidia-smi";
+/// Path to the `systemctl` binary used to reboot the host.
+pub const SYSTEMCTL_PATH: &str = "/usr/bin/systemctl";
+/// Directory for the runtime state of `nvidia-migmanager`.
+pub const STATE_DIR: &str = "/run/nvidia-migmanager";
+/// Directory for the apply history, which persists across reboots.
+pub const HISTORY_DIR: &str = "/var/lib/nvidia-migmanager";
+
+/// Marker file written when a GPU reset is needed for the MIG settings to take effect.
+const REBOOT_REQUIRED_MARKER_FILE: &str = "reboot-required";
+
+/// The resolved locations used while discovering, applying and rebooting.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Paths {
+    pub nvidia_smi: PathBuf,
+    pub systemctl: PathBuf,
+    pub state_dir: PathBuf,
+    pub history_dir: PathBuf,
+    /// Prometheus textfile written after `apply-mig` and `status`, if any.
+    pub metrics_file: Option<PathBuf>,
+}
+
+impl Default for Paths {
+    fn default() -> Self {
+        Self {
+            nvidia_smi: PathBuf::from(NVIDIA_SMI_PATH),
+            systemctl: PathBuf::from(SYSTEMCTL_PATH),
+            state_dir: PathBuf::from(STATE_DIR),
+            history_dir: PathBuf::from(HISTORY_DIR),
+            metrics_file: None,
+        }
+    }
+}
+
+impl Paths {
+    /// Returns a copy of these paths with every location set in `overrides` replaced.
+    pub fn with_overrides(mut self, overrides: &PathOverrides) -> Self {
+        if let Some(nvidia_smi) = &overrides.nvidia_smi {
+            self.nvidia_smi = nvidia_smi.clone();
+        }
+        if let Some(systemctl) = &overrides.systemctl {
+            self.systemctl = systemctl.clone();
+        }
+        if let Some(state_dir) = &overrides.state_dir {
+            self.state_dir = state_dir.clone();
+        }
+        if let Some(history_dir) = &overrides.history_dir {
+            self.history_dir = history_dir.clone();
+        }
+        if let Some(metrics_file) = &overrides.metrics_file {
+            self.metrics_file = Some(metrics_file.clone());
+        }
+        self
+    }
+
+    /// Path to the marker file that requests a reboot for a GPU reset.
+    pub fn reboot_required_marker(&self) -> PathBuf {
+        self.state_file(REBOOT_REQUIRED_MARKER_FILE)
+    }
+
+    /// Path to a file in the state directory.
+    pub fn state_file<P>(&self, name: P) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        self.state_dir.join(name)
+    }
+}
+
+/// Optional overrides for [`Paths`], read from the `paths` table of the config file or set from
+/// the command line.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PathOverrides {
+    /// Path to the `nvidia-smi` binary.
+    pub nvidia_smi: Option<PathBuf>,
+    /// Path to the `systemctl` binary.
+    pub systemctl: Option<PathBuf>,
+    /// Directory for runtime state, such as the `reboot-required` marker file.
+    pub state_dir: Option<PathBuf>,
+    /// Directory for the apply history, which persists across reboots.
+    pub history_dir: Option<PathBuf>,
+    /// Prometheus textfile to write after `apply-mig` and `status`.
+    pub metrics_file: Option<PathBuf>,
+}
+
+impl PathOverrides {
+    /// Returns the overrides in `other`, falling back to the ones in `self`.
+    pub fn merge(&self, other: &PathOverrides) -> PathOverrides {
+        PathOverrides {
+            nvidia_smi: other.nvidia_smi.clone().or_else(|| self.nvidia_smi.clone()),
+            systemctl: other.systemctl.clone().or_else(|| self.systemctl.clone()),
+            state_dir: other.state_dir.clone().or_else(|| self.state_dir.clone()),
+            history_dir: other
+                .history_dir
+                .clone()
+                .or_else(|| self.history_dir.clone()),
+            metrics_file: other
+                .metrics_file
+                .clone()
+                .or_else(|| self.metrics_file.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_paths_with_overrides() {
+        let config_overrides = PathOverrides {
+            nvidia_smi: Some(PathBuf::from("/host/nvidia-smi")),
+            state_dir: Some(PathBuf::from("/config/state")),
+            ..Default::default()
+        };
+        let cli_overrides = PathOverrides {
+            state_dir: Some(PathBuf::from("/tmp/state")),
+            ..Default::default()
+        };
+
+        let paths = Paths::default().with_overrides(&config_overrides.merge(&cli_overrides));
+
+        let expected_paths = Paths {
+            nvidia_smi: PathBuf::from("/host/nvidia-smi"),
+            systemctl: PathBuf::from(SYSTEMCTL_PATH),
+            state_dir: PathBuf::from("/tmp/state"),
+            history_dir: PathBuf::from(HISTORY_DIR),
+            metrics_file: None,
+        };
+
+        assert_eq!(paths, expected_paths);
+        assert_eq!(
+            paths.reboot_required_marker(),
+            PathBuf::from("/tmp/state/reboot-required")
+        );
+    }
+}
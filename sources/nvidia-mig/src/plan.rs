@@ -0,0 +1,666 @@
+//! The `plan` module decides which MIG changes the GPUs in the instance need, without running any
+//! commands.
+
+use crate::config::{GpuSelector, NvidiaMigConfig};
+use crate::gpu::{get_instance_gpu, MigGpu, NvidiaGpu};
+use crate::profile::{known_gpu_profile, unknown_gpu_profile, DEFAULT_PROFILE};
+use crate::requirements::{solve, Requirements};
+use crate::{error, Result};
+use log::{info, warn};
+use snafu::ensure;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// The GPUs that an [`Action`] applies to.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Target {
+    /// Every GPU in the instance.
+    #[default]
+    AllGpus,
+    /// The GPUs with these indices.
+    Gpus(Vec<u32>),
+}
+
+impl Target {
+    /// Returns whether the GPU with index `gpu` is targeted.
+    pub fn includes(&self, gpu: u32) -> bool {
+        match self {
+            Target::AllGpus => true,
+            Target::Gpus(gpus) => gpus.contains(&gpu),
+        }
+    }
+
+    /// Targets the `managed` GPUs, which is every GPU when none of `gpu_info` is left alone.
+    pub(crate) fn managed(managed: &[MigGpu], gpu_info: &[MigGpu]) -> Target {
+        if managed.len() == gpu_info.len() {
+            Target::AllGpus
+        } else {
+            Target::Gpus(managed.iter().map(|gpu| gpu.index).collect())
+        }
+    }
+
+    /// Returns the `nvidia-smi` arguments that select the targeted GPUs.
+    pub(crate) fn nvidia_smi_args(&self) -> Vec<String> {
+        match self {
+            Target::AllGpus => Vec::new(),
+            Target::Gpus(gpus) => vec![
+                "-i".to_string(),
+                gpus.iter()
+                    .map(u32::to_string)
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ],
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::AllGpus => write!(f, "all GPUs"),
+            Target::Gpus(gpus) => write!(
+                f,
+                "GPUs {}",
+                gpus.iter()
+                    .map(u32::to_string)
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+        }
+    }
+}
+
+/// A single step of a [`Plan`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Enable or disable MIG mode in the targeted GPUs.
+    SetMigMode { enabled: bool, target: Target },
+    /// Write the reboot-required marker file, with the reason as its content.
+    RequestReboot(String),
+    /// Create GPU and compute instances in the targeted GPUs from a comma-separated profile
+    /// string. If that fails on GPUs without GPU instances, the fallback profile string is applied.
+    CreateInstances {
+        profile: String,
+        fallback: Option<String>,
+        target: Target,
+    },
+    /// Like `CreateInstances`, for GPUs without a profile table. The candidate profile strings are
+    /// tried in order until one of them succeeds; the action fails only if none of them does.
+    TryCreateInstances {
+        candidates: Vec<String>,
+        target: Target,
+    },
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::SetMigMode { enabled, target } => write!(
+                f,
+                "{} MIG mode on {}",
+                if *enabled { "enable" } else { "disable" },
+                target
+            ),
+            Action::RequestReboot(reason) => write!(f, "request a reboot: {}", reason),
+            Action::CreateInstances {
+                profile,
+                fallback,
+                target,
+            } => {
+                write!(f, "create GPU instances {} on {}", profile, target)?;
+                if let Some(fallback) = fallback {
+                    write!(f, ", falling back to {}", fallback)?;
+                }
+                Ok(())
+            }
+            Action::TryCreateInstances { candidates, target } => write!(
+                f,
+                "try to create GPU instances {} on {}",
+                candidates.join(" or "),
+                target
+            ),
+        }
+    }
+}
+
+/// Why a GPU is left out of a [`Plan`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Skip {
+    /// The GPU matches a selector of the `exclude` list.
+    Excluded(String),
+    /// The GPU failed the health check; holds the health summary.
+    Unhealthy(String),
+    /// MIG isn't supported by the GPU.
+    Unsupported,
+    /// The MIG settings have no valid profile for the GPU.
+    NoProfile,
+}
+
+impl fmt::Display for Skip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Skip::Excluded(selector) => write!(f, "excluded by {}", selector),
+            Skip::Unhealthy(health) => write!(f, "{}", health),
+            Skip::Unsupported => write!(f, "MIG is not supported"),
+            Skip::NoProfile => write!(f, "no valid MIG profile in the settings"),
+        }
+    }
+}
+
+/// The ordered list of actions needed to apply the MIG settings, and the reasons for the
+/// decisions behind them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Plan {
+    pub actions: Vec<Action>,
+    pub reasons: Vec<String>,
+    /// The GPUs left out of the plan, by GPU index.
+    pub skipped: BTreeMap<u32, Skip>,
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            writeln!(f, "No changes needed.")?;
+        } else {
+            writeln!(f, "Actions:")?;
+            for (step, action) in self.actions.iter().enumerate() {
+                writeln!(f, "  {}. {}", step + 1, action)?;
+            }
+        }
+        if !self.reasons.is_empty() {
+            writeln!(f, "Reasons:")?;
+            for reason in &self.reasons {
+                writeln!(f, "  - {}", reason)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Plan {
+    /// Returns whether the GPUs already match the MIG settings.
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    /// Returns whether applying the plan leaves a reboot pending.
+    pub fn requests_reboot(&self) -> bool {
+        self.actions
+            .iter()
+            .any(|action| matches!(action, Action::RequestReboot(_)))
+    }
+
+    /// Returns whether an action of the plan targets the GPU with index `gpu`.
+    pub fn targets(&self, gpu: u32) -> bool {
+        self.actions.iter().any(|action| match action {
+            Action::SetMigMode { target, .. }
+            | Action::CreateInstances { target, .. }
+            | Action::TryCreateInstances { target, .. } => target.includes(gpu),
+            Action::RequestReboot(_) => false,
+        })
+    }
+
+    fn skip(&mut self, gpus: &[MigGpu], skip: Skip) {
+        for gpu in gpus {
+            self.skipped.insert(gpu.index, skip.clone());
+        }
+    }
+}
+
+/// Builds the plan that moves the GPUs in `gpu_info` to the state requested in `mig_settings`.
+pub fn plan(mig_settings: &NvidiaMigConfig, gpu_info: &[MigGpu]) -> Result<Plan> {
+    if mig_settings.mig_enabled() {
+        plan_enable_mig(mig_settings, gpu_info)
+    } else {
+        Ok(plan_disable_mig(mig_settings, gpu_info))
+    }
+}
+
+fn plan_enable_mig(mig_settings: &NvidiaMigConfig, gpu_info: &[MigGpu]) -> Result<Plan> {
+    ensure!(!gpu_info.is_empty(), error::GpuModelSnafu);
+
+    let mut plan = Plan::default();
+    let managed: Vec<_> = gpu_info
+        .iter()
+        .filter(|gpu| {
+            !is_excluded(gpu, &mig_settings.exclude, &mut plan) && !is_unhealthy(gpu, &mut plan)
+        })
+        .cloned()
+        .collect();
+    if managed.is_empty() {
+        return Ok(plan);
+    }
+    let target = Target::managed(&managed, gpu_info);
+    let gpu_info = &managed[..];
+
+    let (is_ampere_gpu_present, has_disabled_mig, is_mig_unsupported) = gpu_info.iter().fold(
+        (false, false, false),
+        |(ampere, disabled, unsupported), gpu| {
+            (
+                ampere || gpu.model.is_ampere(),
+                disabled || gpu.state.is_disabled(),
+                unsupported || gpu.state.is_unsupported(),
+            )
+        },
+    );
+
+    if is_mig_unsupported {
+        warn!("MIG is not supported by the available NVIDIA GPU.");
+        plan.reasons
+            .push("MIG is not supported by the available NVIDIA GPU".to_string());
+        plan.skip(gpu_info, Skip::Unsupported);
+        return Ok(plan);
+    }
+
+    if has_disabled_mig {
+        // Enable MIG for all the GPU
+        plan.actions.push(Action::SetMigMode {
+            enabled: true,
+            target: target.clone(),
+        });
+
+        // If any GPU is A100 create marker file for reboot to reconcile
+        // for the gpu reset and move from transitional state to enabled
+        if is_ampere_gpu_present {
+            plan.actions
+                .push(Action::RequestReboot("Enabling MIG".to_string()));
+            plan.reasons.push(
+                "Ampere GPUs need a reboot before MIG mode applies, so GPU instances are created \
+                 on the next run"
+                    .to_string(),
+            );
+
+            return Ok(plan);
+        }
+    }
+
+    match get_instance_gpu(gpu_info) {
+        Ok(gpu) if gpu != NvidiaGpu::Other => {
+            let mig_profile = gpu
+                .config_key()
+                .and_then(|key| mig_settings.profile.get(key))
+                .map(String::as_str)
+                .unwrap_or(DEFAULT_PROFILE);
+
+            info!("MIG Profile or the number of GPU slices: {:?}", mig_profile);
+            let profile = if Requirements::is_requirements(mig_profile) {
+                let requirements: Requirements = mig_profile.parse()?;
+                let solution = solve(&gpu, &requirements)?;
+                plan.reasons.push(format!(
+                    "{} is the best layout of {:?} for {}",
+                    solution, gpu, requirements
+                ));
+                Some(solution.profile_string())
+            } else {
+                known_gpu_profile(&gpu, mig_profile)?
+            };
+            if let Some(profile) = profile {
+                // Fall back to the whole-GPU profile, unless that is what failed.
+                let fallback = known_gpu_profile(&gpu, DEFAULT_PROFILE)?
+                    .filter(|whole_gpu_profile| *whole_gpu_profile != profile);
+                plan.actions.push(Action::CreateInstances {
+                    profile,
+                    fallback,
+                    target,
+                });
+            }
+        }
+        _ => {
+            let candidates = unknown_gpu_candidates(mig_settings);
+            if candidates.is_empty() {
+                warn!("The MIG settings have no valid MIG Profile for the given GPU.");
+                plan.reasons
+                    .push("The MIG settings have no valid MIG profile for the GPU".to_string());
+                plan.skip(gpu_info, Skip::NoProfile);
+            } else {
+                plan.actions
+                    .push(Action::TryCreateInstances { candidates, target });
+            }
+        }
+    }
+
+    Ok(plan)
+}
+
+/// Returns whether `gpu` matches one of the `exclude` selectors, in which case its MIG mode and
+/// layout are left alone. The decision is logged and recorded in `plan`.
+pub(crate) fn is_excluded(gpu: &MigGpu, exclude: &[GpuSelector], plan: &mut Plan) -> bool {
+    let Some(selector) = exclude.iter().find(|selector| selector.matches(gpu)) else {
+        return false;
+    };
+    info!(
+        gpu_uuid = gpu.uuid.as_str();
+        "GPU {} is excluded by {}, leaving it alone.",
+        gpu.index, selector
+    );
+    plan.reasons.push(format!(
+        "GPU {} is excluded by {}, so it is left alone",
+        gpu.index, selector
+    ));
+    plan.skipped
+        .insert(gpu.index, Skip::Excluded(selector.to_string()));
+
+    true
+}
+
+/// Returns whether `gpu` is unhealthy, in which case it must be left whole rather than
+/// partitioned and handed to workloads. The decision is logged and recorded in `plan`.
+pub(crate) fn is_unhealthy(gpu: &MigGpu, plan: &mut Plan) -> bool {
+    if gpu.health.is_healthy() {
+        return false;
+    }
+    warn!(
+        gpu_uuid = gpu.uuid.as_str();
+        "GPU {} is {}, leaving it whole.",
+        gpu.index, gpu.health
+    );
+    plan.reasons.push(format!(
+        "GPU {} is {}, so it is left whole",
+        gpu.index, gpu.health
+    ));
+    plan.skipped
+        .insert(gpu.index, Skip::Unhealthy(gpu.health.to_string()));
+
+    true
+}
+
+// The GPU in the current instance is not one of the known GPUs. We attempt using the profiles
+// that don't belong to one of the known GPUs.
+fn unknown_gpu_candidates(mig_settings: &NvidiaMigConfig) -> Vec<String> {
+    let mut entries: Vec<_> = mig_settings
+        .profile
+        .iter()
+        .filter(|(gpu, _)| !NvidiaGpu::KNOWN_GPU_KEYS.contains(&gpu.as_str()))
+        .collect();
+    entries.sort_by(|gpu, mig_profile| gpu.0.cmp(mig_profile.0));
+
+    entries
+        .into_iter()
+        .filter_map(
+            |(gpu, mig_profile)| match unknown_gpu_profile(gpu, mig_profile) {
+                Ok(profile_string) => Some(profile_string),
+                Err(_) => {
+                    warn!(
+                        "The Profile {} is not a valid MIG Profile for the given GPU.",
+                        mig_profile
+                    );
+                    None
+                }
+            },
+        )
+        .collect()
+}
+
+fn plan_disable_mig(mig_settings: &NvidiaMigConfig, all_gpus: &[MigGpu]) -> Plan {
+    let mut plan = Plan::default();
+    let managed: Vec<_> = all_gpus
+        .iter()
+        .filter(|gpu| !is_excluded(gpu, &mig_settings.exclude, &mut plan))
+        .cloned()
+        .collect();
+    let gpu_info = &managed[..];
+    let (is_ampere_gpu_present, has_enabled_mig) =
+        gpu_info
+            .iter()
+            .fold((false, false), |(ampere, enabled), gpu| {
+                (
+                    ampere || gpu.model.is_ampere(),
+                    enabled || gpu.state.is_enabled(),
+                )
+            });
+
+    if has_enabled_mig {
+        // Disable MIG for the GPU
+        plan.actions.push(Action::SetMigMode {
+            enabled: false,
+            target: Target::managed(gpu_info, all_gpus),
+        });
+
+        // If GPU is A100 create marker file for reboot to reconcile
+        // for the gpu reset and move from transitional state to disabled
+        if is_ampere_gpu_present {
+            plan.actions
+                .push(Action::RequestReboot("Disabling MIG".to_string()));
+            plan.reasons
+                .push("Ampere GPUs need a reboot before MIG mode applies".to_string());
+        }
+    }
+
+    plan
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gpu::MigState;
+    use crate::health::GpuHealth;
+    use std::collections::HashMap;
+
+    fn mig_config(profiles: &[(&str, &str)]) -> NvidiaMigConfig {
+        NvidiaMigConfig {
+            device_partitioning_strategy: "mig".to_string(),
+            profile: profiles
+                .iter()
+                .map(|(gpu, profile)| (gpu.to_string(), profile.to_string()))
+                .collect::<HashMap<_, _>>(),
+            ..Default::default()
+        }
+    }
+
+    fn gpus(model: NvidiaGpu, state: MigState, count: u32) -> Vec<MigGpu> {
+        (0..count)
+            .map(|index| MigGpu {
+                index,
+                uuid: format!("GPU-{}", index),
+                pci_bus_id: format!("00000000:{:02X}:00.0", index + 1),
+                name: String::new(),
+                pci_device_id: String::new(),
+                model: model.clone(),
+                state: state.clone(),
+                health: GpuHealth::default(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_plan_enable_ampere_requires_reboot() {
+        let plan = plan(
+            &mig_config(&[("a100.40gb", "2")]),
+            &gpus(NvidiaGpu::A100_40GB, MigState::Disabled, 8),
+        )
+        .unwrap();
+
+        let expected_actions = vec![
+            Action::SetMigMode {
+                enabled: true,
+                target: Target::AllGpus,
+            },
+            Action::RequestReboot("Enabling MIG".to_string()),
+        ];
+
+        assert_eq!(plan.actions, expected_actions)
+    }
+
+    #[test]
+    fn test_plan_enable_hopper_without_reboot() {
+        let plan = plan(
+            &mig_config(&[("h100.80gb", "4")]),
+            &gpus(NvidiaGpu::H100_80GB, MigState::Disabled, 8),
+        )
+        .unwrap();
+
+        let expected_actions = vec![
+            Action::SetMigMode {
+                enabled: true,
+                target: Target::AllGpus,
+            },
+            Action::CreateInstances {
+                profile: "1g.20gb,1g.20gb,1g.20gb,1g.20gb".to_string(),
+                fallback: Some("7g.80gb".to_string()),
+                target: Target::AllGpus,
+            },
+        ];
+
+        assert_eq!(plan.actions, expected_actions)
+    }
+
+    #[test]
+    fn test_plan_requirements() {
+        let plan_solved = plan(
+            &mig_config(&[("a100.80gb", "min-memory=20gb")]),
+            &gpus(NvidiaGpu::A100_80GB, MigState::Enabled, 2),
+        )
+        .unwrap();
+
+        let expected_actions = vec![Action::CreateInstances {
+            profile: "2g.20gb,2g.20gb,2g.20gb".to_string(),
+            fallback: Some("7g.80gb".to_string()),
+            target: Target::AllGpus,
+        }];
+        assert_eq!(plan_solved.actions, expected_actions);
+        assert_eq!(
+            plan_solved.reasons,
+            vec![
+                "3 x 2g.20gb (20 GB and 2 compute slices each) is the best layout of A100_80GB \
+                 for at least 20 GB per partition, as many partitions as possible"
+                    .to_string()
+            ]
+        );
+
+        assert!(plan(
+            &mig_config(&[("a100.80gb", "min-memory=100gb")]),
+            &gpus(NvidiaGpu::A100_80GB, MigState::Enabled, 1),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_plan_skips_unhealthy_gpus() {
+        let mut gpu_info = gpus(NvidiaGpu::H100_80GB, MigState::Disabled, 3);
+        gpu_info[1].health.uncorrected_ecc_errors = Some(1);
+
+        let plan_healthy = plan(&mig_config(&[("h100.80gb", "7g.80gb")]), &gpu_info).unwrap();
+
+        let expected_actions = vec![
+            Action::SetMigMode {
+                enabled: true,
+                target: Target::Gpus(vec![0, 2]),
+            },
+            Action::CreateInstances {
+                profile: "7g.80gb".to_string(),
+                fallback: None,
+                target: Target::Gpus(vec![0, 2]),
+            },
+        ];
+        assert_eq!(plan_healthy.actions, expected_actions);
+        assert_eq!(
+            plan_healthy.reasons,
+            vec!["GPU 1 is unhealthy: 1 uncorrectable ECC errors, so it is left whole".to_string()]
+        );
+
+        gpu_info[0].health.remapped_rows_failure = Some(true);
+        let plan_unhealthy = plan(&mig_config(&[]), &gpu_info[..1]).unwrap();
+        assert!(plan_unhealthy.is_empty());
+    }
+
+    #[test]
+    fn test_plan_skips_excluded_gpus() {
+        let mut mig_settings = mig_config(&[("a100.40gb", "2")]);
+        mig_settings.exclude = vec![
+            GpuSelector::Index(0),
+            GpuSelector::PciBusId("0000:03:00.0".to_string()),
+        ];
+
+        let plan_enable = plan(
+            &mig_settings,
+            &gpus(NvidiaGpu::A100_40GB, MigState::Enabled, 4),
+        )
+        .unwrap();
+        let expected_actions = vec![Action::CreateInstances {
+            profile: "3g.20gb,3g.20gb".to_string(),
+            fallback: Some("7g.40gb".to_string()),
+            target: Target::Gpus(vec![1, 3]),
+        }];
+        assert_eq!(plan_enable.actions, expected_actions);
+
+        // Excluded Ampere GPUs don't need a reboot either.
+        mig_settings.device_partitioning_strategy = String::new();
+        let mut gpu_info = gpus(NvidiaGpu::H100_80GB, MigState::Enabled, 2);
+        gpu_info[0].model = NvidiaGpu::A100_40GB;
+        let plan_disable = plan(&mig_settings, &gpu_info).unwrap();
+        let expected_actions = vec![Action::SetMigMode {
+            enabled: false,
+            target: Target::Gpus(vec![1]),
+        }];
+        assert_eq!(plan_disable.actions, expected_actions);
+    }
+
+    #[test]
+    fn test_plan_unknown_gpu_candidates() {
+        let plan = plan(
+            &mig_config(&[
+                ("a100.40gb", "2"),
+                ("b200.180gb", "1g.2gb"),
+                ("b100.96gb", "3g.48gb"),
+            ]),
+            &gpus(NvidiaGpu::Other, MigState::Enabled, 1),
+        )
+        .unwrap();
+
+        let expected_actions = vec![Action::TryCreateInstances {
+            candidates: vec![
+                "3g.48gb,3g.48gb".to_string(),
+                "1g.2gb,1g.2gb,1g.2gb,1g.2gb,1g.2gb,1g.2gb,1g.2gb".to_string(),
+            ],
+            target: Target::AllGpus,
+        }];
+
+        assert_eq!(plan.actions, expected_actions)
+    }
+
+    #[test]
+    fn test_plan_unsupported() {
+        let plan = plan(
+            &mig_config(&[]),
+            &gpus(NvidiaGpu::Other, MigState::Unsupported, 1),
+        )
+        .unwrap();
+
+        assert!(plan.is_empty());
+        assert_eq!(plan.skipped.get(&0), Some(&Skip::Unsupported));
+
+        let plan_no_profile = super::plan(
+            &mig_config(&[("a100.40gb", "2")]),
+            &gpus(NvidiaGpu::Other, MigState::Enabled, 1),
+        )
+        .unwrap();
+        assert!(plan_no_profile.is_empty());
+        assert_eq!(plan_no_profile.skipped.get(&0), Some(&Skip::NoProfile));
+    }
+
+    #[test]
+    fn test_plan_disable() {
+        let mig_settings = NvidiaMigConfig::default();
+
+        let plan_ampere = plan(
+            &mig_settings,
+            &gpus(NvidiaGpu::A100_80GB, MigState::Enabled, 1),
+        )
+        .unwrap();
+        let expected_actions = vec![
+            Action::SetMigMode {
+                enabled: false,
+                target: Target::AllGpus,
+            },
+            Action::RequestReboot("Disabling MIG".to_string()),
+        ];
+        assert_eq!(plan_ampere.actions, expected_actions);
+
+        let plan_disabled = plan(
+            &mig_settings,
+            &gpus(NvidiaGpu::H100_80GB, MigState::Disabled, 1),
+        )
+        .unwrap();
+        assert!(plan_disabled.is_empty())
+    }
+}
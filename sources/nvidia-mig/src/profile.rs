@@ -0,0 +1,352 @@
+//! The `profile` module maps the MIG settings to the `nvidia-smi` MIG profile strings for each GPU
+//! model.
+
+use crate::gpu::NvidiaGpu;
+use crate::{error, Result};
+use regex::Regex;
+use serde::Deserialize;
+use snafu::{ensure, OptionExt, ResultExt};
+use std::cmp::min;
+
+const GPU_MODEL_REGEX: &str = r"[A-Za-z]\d+\.(\d+)gb";
+const MIG_PROFILE_REGEX: &str = r"(\d+)g\.(\d+)gb";
+
+/// Value used when the MIG settings have no profile for the GPU in the instance.
+pub const DEFAULT_PROFILE: &str = "1";
+
+#[derive(Deserialize)]
+pub enum NvidiaA100_40gbMigProfile {
+    #[serde(alias = "1g.5gb")]
+    #[serde(alias = "7")]
+    Mig1g5gb,
+
+    #[serde(alias = "2g.10gb")]
+    #[serde(alias = "3")]
+    Mig2g10gb,
+
+    #[serde(alias = "3g.20gb")]
+    #[serde(alias = "2")]
+    Mig3g20gb,
+
+    #[serde(alias = "7g.40gb")]
+    #[serde(alias = "1")]
+    #[serde(other)]
+    Mig7g40gb,
+}
+
+impl MigGpuProfile for NvidiaA100_40gbMigProfile {
+    fn get_mig_profile(&self) -> &str {
+        match self {
+            NvidiaA100_40gbMigProfile::Mig7g40gb => "7g.40gb",
+            NvidiaA100_40gbMigProfile::Mig3g20gb => "3g.20gb,3g.20gb",
+            NvidiaA100_40gbMigProfile::Mig2g10gb => "2g.10gb,2g.10gb,2g.10gb",
+            NvidiaA100_40gbMigProfile::Mig1g5gb => {
+                "1g.5gb,1g.5gb,1g.5gb,1g.5gb,1g.5gb,1g.5gb,1g.5gb"
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub enum NvidiaA100_80gbMigProfile {
+    #[serde(alias = "1g.10gb")]
+    #[serde(alias = "7")]
+    Mig1g10gb,
+
+    #[serde(alias = "2g.20gb")]
+    #[serde(alias = "3")]
+    Mig2g20gb,
+
+    #[serde(alias = "3g.40gb")]
+    #[serde(alias = "2")]
+    Mig3g40gb,
+
+    #[serde(alias = "7g.80gb")]
+    #[serde(alias = "1")]
+    #[serde(other)]
+    Mig7g80gb,
+}
+
+impl MigGpuProfile for NvidiaA100_80gbMigProfile {
+    fn get_mig_profile(&self) -> &str {
+        match self {
+            NvidiaA100_80gbMigProfile::Mig7g80gb => "7g.80gb",
+            NvidiaA100_80gbMigProfile::Mig3g40gb => "3g.40gb,3g.40gb",
+            NvidiaA100_80gbMigProfile::Mig2g20gb => "2g.20gb,2g.20gb,2g.20gb",
+            NvidiaA100_80gbMigProfile::Mig1g10gb => {
+                "1g.10gb,1g.10gb,1g.10gb,1g.10gb,1g.10gb,1g.10gb,1g.10gb"
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub enum NvidiaH100_80gbMigProfile {
+    #[serde(alias = "1g.10gb")]
+    #[serde(alias = "7")]
+    Mig1g10gb,
+
+    #[serde(alias = "1g.20gb")]
+    #[serde(alias = "4")]
+    Mig1g20gb,
+
+    #[serde(alias = "2g.20gb")]
+    #[serde(alias = "3")]
+    Mig2g20gb,
+
+    #[serde(alias = "3g.40gb")]
+    #[serde(alias = "2")]
+    Mig3g40gb,
+
+    #[serde(alias = "7g.80gb")]
+    #[serde(alias = "1")]
+    #[serde(other)]
+    Mig7g80gb,
+}
+
+impl MigGpuProfile for NvidiaH100_80gbMigProfile {
+    fn get_mig_profile(&self) -> &str {
+        match self {
+            NvidiaH100_80gbMigProfile::Mig7g80gb => "7g.80gb",
+            NvidiaH100_80gbMigProfile::Mig3g40gb => "3g.40gb,3g.40gb",
+            NvidiaH100_80gbMigProfile::Mig2g20gb => "2g.20gb,2g.20gb,2g.20gb",
+            NvidiaH100_80gbMigProfile::Mig1g20gb => "1g.20gb,1g.20gb,1g.20gb,1g.20gb",
+            NvidiaH100_80gbMigProfile::Mig1g10gb => {
+                "1g.10gb,1g.10gb,1g.10gb,1g.10gb,1g.10gb,1g.10gb,1g.10gb"
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub enum NvidiaH200_141gbMigProfile {
+    #[serde(alias = "1g.18gb")]
+    #[serde(alias = "7")]
+    Mig1g18gb,
+
+    #[serde(alias = "1g.35gb")]
+    #[serde(alias = "4")]
+    Mig1g35gb,
+
+    #[serde(alias = "2g.35gb")]
+    #[serde(alias = "3")]
+    Mig2g35gb,
+
+    #[serde(alias = "3g.71gb")]
+    #[serde(alias = "2")]
+    Mig3g71gb,
+
+    #[serde(alias = "7g.141gb")]
+    #[serde(alias = "1")]
+    #[serde(other)]
+    Mig7g141gb,
+}
+
+impl MigGpuProfile for NvidiaH200_141gbMigProfile {
+    fn get_mig_profile(&self) -> &str {
+        match self {
+            NvidiaH200_141gbMigProfile::Mig7g141gb => "7g.141gb",
+            NvidiaH200_141gbMigProfile::Mig3g71gb => "3g.71gb,3g.71gb",
+            NvidiaH200_141gbMigProfile::Mig2g35gb => "2g.35gb,2g.35gb,2g.35gb",
+            NvidiaH200_141gbMigProfile::Mig1g35gb => "1g.35gb,1g.35gb,1g.35gb,1g.35gb",
+            NvidiaH200_141gbMigProfile::Mig1g18gb => {
+                "1g.18gb,1g.18gb,1g.18gb,1g.18gb,1g.18gb,1g.18gb,1g.18gb"
+            }
+        }
+    }
+}
+
+/// A GPU instance profile supported by a GPU model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GpuInstanceProfile {
+    /// Name of the profile, such as `3g.20gb`.
+    pub name: &'static str,
+    pub compute_slices: u32,
+    pub memory_gb: u32,
+    /// How many GPU instances of this profile fit in a single GPU.
+    pub max_count: u32,
+}
+
+const fn gi(
+    name: &'static str,
+    compute_slices: u32,
+    memory_gb: u32,
+    max_count: u32,
+) -> GpuInstanceProfile {
+    GpuInstanceProfile {
+        name,
+        compute_slices,
+        memory_gb,
+        max_count,
+    }
+}
+
+const A100_40GB_PROFILES: &[GpuInstanceProfile] = &[
+    gi("1g.5gb", 1, 5, 7),
+    gi("2g.10gb", 2, 10, 3),
+    gi("3g.20gb", 3, 20, 2),
+    gi("4g.20gb", 4, 20, 1),
+    gi("7g.40gb", 7, 40, 1),
+];
+
+const A100_80GB_PROFILES: &[GpuInstanceProfile] = &[
+    gi("1g.10gb", 1, 10, 7),
+    gi("2g.20gb", 2, 20, 3),
+    gi("3g.40gb", 3, 40, 2),
+    gi("4g.40gb", 4, 40, 1),
+    gi("7g.80gb", 7, 80, 1),
+];
+
+const H100_80GB_PROFILES: &[GpuInstanceProfile] = &[
+    gi("1g.10gb", 1, 10, 7),
+    gi("1g.20gb", 1, 20, 4),
+    gi("2g.20gb", 2, 20, 3),
+    gi("3g.40gb", 3, 40, 2),
+    gi("4g.40gb", 4, 40, 1),
+    gi("7g.80gb", 7, 80, 1),
+];
+
+const H200_141GB_PROFILES: &[GpuInstanceProfile] = &[
+    gi("1g.18gb", 1, 18, 7),
+    gi("1g.35gb", 1, 35, 4),
+    gi("2g.35gb", 2, 35, 3),
+    gi("3g.71gb", 3, 71, 2),
+    gi("4g.71gb", 4, 71, 1),
+    gi("7g.141gb", 7, 141, 1),
+];
+
+/// Returns the GPU instance profiles of a known GPU model, or an empty list for
+/// [`NvidiaGpu::Other`].
+pub fn gpu_instance_profiles(gpu: &NvidiaGpu) -> &'static [GpuInstanceProfile] {
+    match gpu {
+        NvidiaGpu::A100_40GB => A100_40GB_PROFILES,
+        NvidiaGpu::A100_80GB => A100_80GB_PROFILES,
+        NvidiaGpu::H100_80GB => H100_80GB_PROFILES,
+        NvidiaGpu::H200_141GB => H200_141GB_PROFILES,
+        NvidiaGpu::Other => &[],
+    }
+}
+
+/// Implemented by the MIG profile tables of every known GPU model. The tables deserialize from
+/// either a number of slices, such as `"2"`, or an exact MIG profile, such as `"3g.20gb"`.
+pub trait MigGpuProfile: for<'de> Deserialize<'de> {
+    /// Returns the comma-separated list of GPU instance profiles passed to `nvidia-smi mig -cgi`.
+    fn get_mig_profile(&self) -> &str;
+}
+
+fn profile_string<T>(mig_profile: &str) -> Result<String>
+where
+    T: MigGpuProfile,
+{
+    let profile = serde_plain::from_str::<T>(mig_profile).context(error::DeserializationSnafu)?;
+
+    Ok(profile.get_mig_profile().to_string())
+}
+
+/// Returns the profile string for one of the known GPU models, or `None` for
+/// [`NvidiaGpu::Other`]. Unrecognized values fall back to the whole-GPU profile.
+pub fn known_gpu_profile(gpu: &NvidiaGpu, mig_profile: &str) -> Result<Option<String>> {
+    let profile = match gpu {
+        NvidiaGpu::A100_40GB => profile_string::<NvidiaA100_40gbMigProfile>(mig_profile)?,
+        NvidiaGpu::A100_80GB => profile_string::<NvidiaA100_80gbMigProfile>(mig_profile)?,
+        NvidiaGpu::H100_80GB => profile_string::<NvidiaH100_80gbMigProfile>(mig_profile)?,
+        NvidiaGpu::H200_141GB => profile_string::<NvidiaH200_141gbMigProfile>(mig_profile)?,
+        NvidiaGpu::Other => return Ok(None),
+    };
+
+    Ok(Some(profile))
+}
+
+/// Builds the profile string for a GPU without a profile table, from a GPU key such as
+/// `a100.40gb` and an exact MIG profile such as `1g.5gb`.
+pub fn unknown_gpu_profile(gpu: &str, mig_profile: &str) -> Result<String> {
+    // If the GPU is unknown, we want the exact MIG Profile and not the number of slices.
+    ensure!(mig_profile.len() > 1, error::MigProfileSnafu {});
+
+    // The GPU and MIG Profile here are expected in a deterministic format and enforced in
+    // settings API. We parse this to form the MIG profile string using known GPU hardware constraints.
+    let gpu_regex = Regex::new(GPU_MODEL_REGEX).unwrap();
+    let profile_regex = Regex::new(MIG_PROFILE_REGEX).unwrap();
+
+    let gpu_ram: usize = gpu_regex
+        .captures(gpu)
+        .map(|captures| captures[1].parse().unwrap_or(0))
+        .context(error::MigProfileSnafu)?;
+    let (compute_slices, slice_ram): (usize, usize) = profile_regex
+        .captures(mig_profile)
+        .map(|captures| {
+            (
+                captures[1].parse().unwrap_or(0),
+                captures[2].parse().unwrap_or(0),
+            )
+        })
+        .context(error::MigProfileSnafu)?;
+
+    // Prevents unsafe division below and enforces logical limits to RAM and compute slices
+    ensure!(
+        gpu_ram > 0 && slice_ram > 0 && compute_slices > 0,
+        error::MigProfileSnafu {}
+    );
+
+    // There are total 7 compute slices in a MIG supported GPU. So, total
+    // number of partitions of the GPU will be minimum of
+    // 7/(compute slices in each partition) and (total VRAM / VRAM of each partition)
+    let num_slices: usize = min(gpu_ram / slice_ram, 7 / compute_slices);
+    ensure!(num_slices > 0, error::MigProfileSnafu {});
+
+    Ok(std::iter::repeat_n(mig_profile, num_slices)
+        .collect::<Vec<_>>()
+        .join(","))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_get_mig_profile_deserialization() {
+        let mig_profile = "1g.5gb".to_string();
+        let profile =
+            serde_plain::from_str::<NvidiaA100_40gbMigProfile>(mig_profile.as_str()).unwrap();
+
+        let profile_string = profile.get_mig_profile();
+        let expected_profile_string = "1g.5gb,1g.5gb,1g.5gb,1g.5gb,1g.5gb,1g.5gb,1g.5gb";
+
+        assert_eq!(profile_string, expected_profile_string)
+    }
+
+    #[test]
+    fn test_get_mig_profile_deserialization_default() {
+        let mig_profile = "1g.8gb".to_string();
+        let profile =
+            serde_plain::from_str::<NvidiaA100_40gbMigProfile>(mig_profile.as_str()).unwrap();
+        let profile_string = profile.get_mig_profile();
+        let expected_profile_string = "7g.40gb";
+
+        assert_eq!(profile_string, expected_profile_string)
+    }
+
+    #[test]
+    fn test_known_gpu_profile() {
+        let profile = known_gpu_profile(&NvidiaGpu::H100_80GB, "4").unwrap();
+        assert_eq!(profile.as_deref(), Some("1g.20gb,1g.20gb,1g.20gb,1g.20gb"));
+
+        let profile = known_gpu_profile(&NvidiaGpu::Other, "4").unwrap();
+        assert_eq!(profile, None);
+    }
+
+    #[test]
+    fn test_get_mig_profile_unknown_gpu() {
+        let profile_string = unknown_gpu_profile("a100.40gb", "1g.5gb").unwrap();
+        let expected_profile_string = "1g.5gb,1g.5gb,1g.5gb,1g.5gb,1g.5gb,1g.5gb,1g.5gb";
+
+        assert_eq!(profile_string, expected_profile_string)
+    }
+
+    #[test]
+    fn test_get_mig_profile_unknown_gpu_invalid() {
+        assert!(unknown_gpu_profile("a100.40gb", "2").is_err());
+        assert!(unknown_gpu_profile("a100", "1g.5gb").is_err());
+        assert!(unknown_gpu_profile("a100.40gb", "1g.80gb").is_err());
+    }
+}
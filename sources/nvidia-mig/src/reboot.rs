@@ -0,0 +1,259 @@
+//! The `reboot` module reboots the host when a previous apply requested a GPU reset. It only needs
+//! the reboot-required marker file and `systemctl`, so it works even when the config file or the
+//! GPUs can't be read.
+
+use crate::command::command;
+use crate::error::Error;
+use crate::paths::Paths;
+use crate::Result;
+use log::{info, warn};
+use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Reason given to systemd when the marker file doesn't hold one.
+const DEFAULT_REBOOT_REASON: &str = "GPU reset required to apply MIG settings";
+const INHIBITOR_RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How [`reboot_if_required`] coordinates the reboot with the rest of the host.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RebootOptions {
+    /// Time to wait before rebooting.
+    pub delay: Duration,
+    /// Reason logged by systemd with the shutdown message. Defaults to the reason written to the
+    /// marker file by the apply.
+    pub reason: Option<String>,
+    /// Whether to honor systemd inhibitor locks that block shutdown, such as the ones taken with
+    /// `systemd-inhibit --what=shutdown`.
+    pub check_inhibitors: bool,
+    /// How long to wait for inhibitor locks to be released before rebooting anyway. Without a
+    /// timeout, the reboot waits for them for as long as it takes.
+    pub inhibitor_timeout: Option<Duration>,
+}
+
+impl Default for RebootOptions {
+    fn default() -> Self {
+        Self {
+            delay: Duration::ZERO,
+            reason: None,
+            check_inhibitors: true,
+            inhibitor_timeout: None,
+        }
+    }
+}
+
+/// Reboots the host if a previous apply wrote the reboot-required marker file. This doesn't return
+/// once the reboot has been requested.
+///
+/// If the reboot is blocked by an inhibitor lock, the request is retried until the lock is
+/// released, which keeps the boot from proceeding with GPUs that still need a reset. If
+/// `options.inhibitor_timeout` is set and passes first, the reboot is made without checking
+/// inhibitors. The marker file is left in place on failure, so that a later invocation can retry.
+pub fn reboot_if_required(paths: &Paths, options: &RebootOptions) -> Result<()> {
+    let marker_path = paths.reboot_required_marker();
+    if !marker_path.exists() {
+        info!("GPU reset not required.");
+        return Ok(());
+    }
+
+    let reason = options.reason.clone().unwrap_or_else(|| {
+        let marker_reason = fs::read_to_string(&marker_path).unwrap_or_default();
+        match marker_reason.trim() {
+            "" => DEFAULT_REBOOT_REASON.to_string(),
+            marker_reason => format!("{} requires a GPU reset", marker_reason),
+        }
+    });
+
+    if !options.delay.is_zero() {
+        info!(
+            "GPU reset is required to apply MIG Settings. Rebooting in {}s...",
+            options.delay.as_secs()
+        );
+        thread::sleep(options.delay);
+    }
+
+    info!("GPU reset is required to apply MIG Settings. Initiating reboot...");
+    request_reboot(paths, &reason, options, INHIBITOR_RETRY_INTERVAL)?;
+
+    // The "systemctl reboot" process will not block until the host does
+    // reboot, but return as soon as the request either failed or the job
+    // to start the systemd reboot.target and its dependencies have been
+    // enqueued. As the shutdown.target that is being pulled in conflicts
+    // with most anything else, the other jobs needed to boot the host
+    // will be cancelled and the boot will not proceed.
+    //
+    // The above is subtle, so slowly spin here until systemd kills this
+    // nvidia-migmanager process as part of the host shutting down by sending it
+    // SIGTERM. This serves as a more obvious line of defense against the
+    // boot proceeding past a required reboot.
+    loop {
+        thread::sleep(Duration::from_secs(5));
+        info!("Still waiting for the host to be rebooted...");
+    }
+}
+
+// Runs `systemctl reboot`, retrying every `retry_interval` while an inhibitor lock blocks it. Once
+// the timeout passes, if there is one, the reboot is requested without checking inhibitors.
+fn request_reboot(
+    paths: &Paths,
+    reason: &str,
+    options: &RebootOptions,
+    retry_interval: Duration,
+) -> Result<()> {
+    let reboot_args = |check_inhibitors: bool| {
+        vec![
+            "reboot".to_string(),
+            format!("--message=nvidia-migmanager: {}", reason),
+            format!(
+                "--check-inhibitors={}",
+                if check_inhibitors { "yes" } else { "no" }
+            ),
+        ]
+    };
+    if !options.check_inhibitors {
+        return command(&paths.systemctl, reboot_args(false)).map(|_| ());
+    }
+
+    let args = reboot_args(true);
+    let start = Instant::now();
+    loop {
+        match command(&paths.systemctl, &args) {
+            Ok(_) => return Ok(()),
+            Err(Error::CommandFailure { stderr, .. }) if stderr.contains("inhibit") => {
+                let mut retry_interval = retry_interval;
+                if let Some(timeout) = options.inhibitor_timeout {
+                    let elapsed = start.elapsed();
+                    if elapsed >= timeout {
+                        warn!(
+                            "The reboot is still inhibited after {}s, rebooting anyway: {}",
+                            timeout.as_secs(),
+                            stderr.trim()
+                        );
+                        return command(&paths.systemctl, reboot_args(false)).map(|_| ());
+                    }
+                    retry_interval = retry_interval.min(timeout - elapsed);
+                }
+                info!("The reboot is inhibited, retrying: {}", stderr.trim());
+                thread::sleep(retry_interval);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+
+    // Writes a fake systemctl that logs its arguments and, unless inhibitors aren't checked, refuses
+    // to reboot because of an inhibitor lock, which is released after the second attempt.
+    fn fake_paths(dir: &Path) -> Paths {
+        let systemctl = dir.join("systemctl");
+        let script = format!(
+            r#"#!/bin/sh
+echo "$*" >> {log}
+case "$*" in *--check-inhibitors=no*) exit 0 ;; esac
+[ "$(wc -l < {log})" -gt 2 ] && exit 0
+echo "Operation inhibited by \"backup\" (PID 42 \"backupd\", user root), reason is \"Backing up\"." >&2
+exit 1
+"#,
+            log = dir.join("calls").display()
+        );
+        fs::write(&systemctl, script).unwrap();
+        fs::set_permissions(&systemctl, fs::Permissions::from_mode(0o755)).unwrap();
+
+        Paths {
+            systemctl,
+            state_dir: dir.to_path_buf(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_reboot_not_required() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let paths = fake_paths(temp_dir.path());
+
+        reboot_if_required(&paths, &RebootOptions::default()).unwrap();
+
+        assert!(!temp_dir.path().join("calls").exists());
+    }
+
+    #[test]
+    fn test_reboot_inhibitor_timeout() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let paths = fake_paths(temp_dir.path());
+        let options = RebootOptions {
+            inhibitor_timeout: Some(Duration::ZERO),
+            ..Default::default()
+        };
+
+        // Once the timeout passes, the reboot is requested again without checking inhibitors.
+        request_reboot(
+            &paths,
+            "Enabling MIG requires a GPU reset",
+            &options,
+            INHIBITOR_RETRY_INTERVAL,
+        )
+        .unwrap();
+
+        let calls = fs::read_to_string(temp_dir.path().join("calls")).unwrap();
+        assert_eq!(
+            calls,
+            "reboot --message=nvidia-migmanager: Enabling MIG requires a GPU reset \
+             --check-inhibitors=yes\n\
+             reboot --message=nvidia-migmanager: Enabling MIG requires a GPU reset \
+             --check-inhibitors=no\n"
+        );
+    }
+
+    #[test]
+    fn test_reboot_waits_for_inhibitors() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let paths = fake_paths(temp_dir.path());
+
+        // Without a timeout, inhibitors are never overridden.
+        request_reboot(
+            &paths,
+            "Enabling MIG requires a GPU reset",
+            &RebootOptions::default(),
+            Duration::from_millis(10),
+        )
+        .unwrap();
+
+        let calls = fs::read_to_string(temp_dir.path().join("calls")).unwrap();
+        assert_eq!(
+            calls,
+            "reboot --message=nvidia-migmanager: Enabling MIG requires a GPU reset \
+             --check-inhibitors=yes\n"
+                .repeat(3)
+        );
+    }
+
+    #[test]
+    fn test_reboot_ignore_inhibitors() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let paths = fake_paths(temp_dir.path());
+        let options = RebootOptions {
+            check_inhibitors: false,
+            ..Default::default()
+        };
+
+        request_reboot(
+            &paths,
+            "Enabling MIG requires a GPU reset",
+            &options,
+            INHIBITOR_RETRY_INTERVAL,
+        )
+        .unwrap();
+
+        let calls = fs::read_to_string(temp_dir.path().join("calls")).unwrap();
+        assert_eq!(
+            calls,
+            "reboot --message=nvidia-migmanager: Enabling MIG requires a GPU reset \
+             --check-inhibitors=no\n"
+        );
+    }
+}
@@ -0,0 +1,188 @@
+//! The `requirements` module picks a MIG layout from workload requirements instead of an exact
+//! profile. Requirements are comma-separated `key=value` pairs:
+//!
+//! * `min-memory=<N>gb`: every partition has at least N GB of memory.
+//! * `min-compute=<N>`: every partition has at least N of the 7 compute slices.
+//! * `partitions=<N>`: exactly N partitions of equal size.
+//!
+//! Without `partitions`, the layout with the most partitions wins. With it, the largest partitions
+//! that fit N times in the GPU win. All partitions of a layout use the same profile.
+
+use crate::gpu::NvidiaGpu;
+use crate::profile::{gpu_instance_profiles, GpuInstanceProfile};
+use crate::{error, Result};
+use snafu::OptionExt;
+use std::fmt;
+use std::str::FromStr;
+
+/// The requirements for the partitions of a GPU.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Requirements {
+    pub min_memory_gb: Option<u32>,
+    pub min_compute_slices: Option<u32>,
+    pub partitions: Option<u32>,
+}
+
+impl Requirements {
+    /// Returns whether a value from the `profile` table holds requirements, rather than a number
+    /// of slices or an exact MIG profile.
+    pub fn is_requirements(value: &str) -> bool {
+        value.contains('=')
+    }
+}
+
+impl FromStr for Requirements {
+    type Err = error::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let mut requirements = Requirements::default();
+        for pair in value.split(',').map(str::trim) {
+            let (key, amount) = pair
+                .split_once('=')
+                .context(error::RequirementsSnafu { value })?;
+            let amount = amount.trim();
+            let parsed = match key.trim() {
+                "min-memory" => &mut requirements.min_memory_gb,
+                "min-compute" => &mut requirements.min_compute_slices,
+                "partitions" => &mut requirements.partitions,
+                _ => return error::RequirementsSnafu { value }.fail(),
+            };
+            let amount = amount
+                .strip_suffix("gb")
+                .filter(|_| key.trim() == "min-memory")
+                .unwrap_or(amount);
+            *parsed = Some(
+                amount
+                    .parse()
+                    .ok()
+                    .filter(|amount| *amount > 0)
+                    .context(error::RequirementsSnafu { value })?,
+            );
+        }
+
+        Ok(requirements)
+    }
+}
+
+impl fmt::Display for Requirements {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(memory) = self.min_memory_gb {
+            parts.push(format!("at least {} GB per partition", memory));
+        }
+        if let Some(compute) = self.min_compute_slices {
+            parts.push(format!("at least {} compute slices per partition", compute));
+        }
+        match self.partitions {
+            Some(partitions) => parts.push(format!("{} partitions of equal size", partitions)),
+            None => parts.push("as many partitions as possible".to_string()),
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// The layout chosen for a set of requirements.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Solution {
+    pub profile: GpuInstanceProfile,
+    pub count: u32,
+}
+
+impl Solution {
+    /// Returns the comma-separated list of GPU instance profiles passed to `nvidia-smi mig -cgi`.
+    pub fn profile_string(&self) -> String {
+        std::iter::repeat_n(self.profile.name, self.count as usize)
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+impl fmt::Display for Solution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} x {} ({} GB and {} compute slices each)",
+            self.count, self.profile.name, self.profile.memory_gb, self.profile.compute_slices
+        )
+    }
+}
+
+/// Picks the best layout of `gpu` that meets `requirements`.
+pub fn solve(gpu: &NvidiaGpu, requirements: &Requirements) -> Result<Solution> {
+    let wanted = requirements.partitions.unwrap_or(1);
+    let candidates = gpu_instance_profiles(gpu).iter().filter(|profile| {
+        profile.memory_gb >= requirements.min_memory_gb.unwrap_or(0)
+            && profile.compute_slices >= requirements.min_compute_slices.unwrap_or(0)
+            && profile.max_count >= wanted
+    });
+
+    let solution = match requirements.partitions {
+        // The largest partitions that still fit the requested number of times.
+        Some(partitions) => candidates
+            .max_by_key(|profile| (profile.memory_gb, profile.compute_slices))
+            .map(|profile| Solution {
+                profile: *profile,
+                count: partitions,
+            }),
+        // The most partitions, then the largest ones.
+        None => candidates
+            .max_by_key(|profile| (profile.max_count, profile.memory_gb, profile.compute_slices))
+            .map(|profile| Solution {
+                profile: *profile,
+                count: profile.max_count,
+            }),
+    };
+
+    solution.context(error::UnsatisfiableSnafu {
+        gpu: format!("{:?}", gpu),
+        requirements: requirements.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_requirements_from_str() {
+        let requirements: Requirements = "min-memory=20gb, partitions=2".parse().unwrap();
+        let expected_requirements = Requirements {
+            min_memory_gb: Some(20),
+            min_compute_slices: None,
+            partitions: Some(2),
+        };
+        assert_eq!(requirements, expected_requirements);
+
+        assert!("min-memory=twenty".parse::<Requirements>().is_err());
+        assert!("min-compute=2gb".parse::<Requirements>().is_err());
+        assert!("max-memory=20gb".parse::<Requirements>().is_err());
+        assert!("partitions=0".parse::<Requirements>().is_err());
+    }
+
+    #[test]
+    fn test_solve_maximizes_partitions() {
+        let requirements: Requirements = "min-memory=20gb".parse().unwrap();
+
+        let solution = solve(&NvidiaGpu::H100_80GB, &requirements).unwrap();
+        assert_eq!(solution.profile_string(), "1g.20gb,1g.20gb,1g.20gb,1g.20gb");
+
+        let solution = solve(&NvidiaGpu::A100_40GB, &requirements).unwrap();
+        assert_eq!(solution.profile_string(), "3g.20gb,3g.20gb");
+    }
+
+    #[test]
+    fn test_solve_equal_partitions() {
+        let requirements: Requirements = "partitions=3".parse().unwrap();
+        let solution = solve(&NvidiaGpu::H200_141GB, &requirements).unwrap();
+        assert_eq!(solution.profile_string(), "2g.35gb,2g.35gb,2g.35gb");
+
+        let requirements: Requirements = "partitions=4,min-compute=2".parse().unwrap();
+        assert!(solve(&NvidiaGpu::H200_141GB, &requirements).is_err());
+    }
+
+    #[test]
+    fn test_solve_unknown_gpu() {
+        let requirements: Requirements = "partitions=1".parse().unwrap();
+        assert!(solve(&NvidiaGpu::Other, &requirements).is_err());
+    }
+}
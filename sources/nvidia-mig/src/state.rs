@@ -0,0 +1,46 @@
+//! The `state` module reads and writes the files that `nvidia-migmanager` leaves for other agents.
+
+use crate::{error, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use snafu::ResultExt;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// Reads the JSON file at `path`, or returns `None` if it doesn't exist.
+pub(crate) fn read_json<T>(path: &Path) -> Result<Option<T>>
+where
+    T: DeserializeOwned,
+{
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).context(error::ReadStateSnafu { path }),
+    };
+    serde_json::from_str(&content)
+        .map(Some)
+        .context(error::DeserializeStateSnafu { path })
+}
+
+/// Writes `value` as pretty-printed JSON to `path`. The file is replaced atomically, so readers
+/// never see a partial file.
+pub(crate) fn write_json<T>(path: &Path, value: &T) -> Result<()>
+where
+    T: Serialize,
+{
+    let content = serde_json::to_string_pretty(value).context(error::SerializeSnafu { path })?;
+    write_atomic(path, &(content + "\n"))
+}
+
+/// Writes `content` to a temporary file next to `path`, and renames it to `path`.
+pub(crate) fn write_atomic(path: &Path, content: &str) -> Result<()> {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let temp_path = path.with_file_name(format!(".{}.tmp", file_name));
+
+    fs::write(&temp_path, content).context(error::WriteStateSnafu { path: &temp_path })?;
+    fs::rename(&temp_path, path).context(error::WriteStateSnafu { path })
+}
@@ -4,6 +4,17 @@ Current version: {{version}}
 
 {{readme}}
 
+## Configuration reference
+
+The keys of the config file. The same description is available as a JSON Schema in
+[`nvidia-migmanager.schema.json`](nvidia-migmanager.schema.json).
+
+{{schema}}
+
+## Command-line reference
+
+{{cli}}
+
 ## Colophon
 
-This text was generated using [cargo-readme](https://crates.io/crates/cargo-readme), and includes the rustdoc from `src/main.rs`.
+This text was generated from `README.tpl` by the `generate-readme` crate of this workspace, and includes the rustdoc from `src/main.rs`.
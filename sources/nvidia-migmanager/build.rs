@@ -1,3 +1,6 @@
 fn main() {
-    generate_readme::from_main().unwrap();
+    if let Err(e) = generate_readme::from_main() {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,281 @@
+//! Log backends for `nvidia-migmanager`. Besides plain text, records can be sent to journald with
+//! structured fields, or printed to stdout as JSON lines.
+//!
+//! Structured fields come from the key-values of a record, such as
+//! `info!(gpu_uuid = gpu.uuid.as_str(); "...")`, and from the current phase set with
+//! [`set_phase`]. In journald, they are upper-cased and prefixed with `MIG_`, so `gpu_uuid` becomes
+//! `MIG_GPU_UUID`.
+
+use crate::error;
+use crate::Result;
+use log::kv::{self, Key, Value, VisitSource};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde_json::{Map, Value as JsonValue};
+use simplelog::{Config as LogConfig, SimpleLogger};
+use snafu::{ensure, ResultExt};
+use std::fmt;
+use std::io::{self, Write};
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::RwLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+const SYSLOG_IDENTIFIER: &str = "nvidia-migmanager";
+
+// What nvidia-migmanager is doing, attached to every record as the `phase` field.
+static PHASE: RwLock<&str> = RwLock::new("startup");
+
+/// Where and how log records are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LogFormat {
+    /// Plain text, with errors on stderr and anything less on stdout.
+    Text,
+    /// One JSON object per record on stdout.
+    Json,
+    /// Structured entries sent to the journal.
+    Journald,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            "journald" => Ok(LogFormat::Journald),
+            _ => Err(format!(
+                "unknown log format '{}', expected text, json or journald",
+                s
+            )),
+        }
+    }
+}
+
+/// Sets up the global logger.
+pub(crate) fn init(format: LogFormat, level: LevelFilter) -> Result<()> {
+    match format {
+        LogFormat::Text => {
+            // SimpleLogger will send errors to stderr and anything less to stdout.
+            return SimpleLogger::init(level, LogConfig::default()).context(error::LoggerSnafu);
+        }
+        LogFormat::Json => log::set_boxed_logger(Box::new(JsonLogger { level })),
+        LogFormat::Journald => {
+            ensure!(
+                Path::new(JOURNALD_SOCKET).exists(),
+                error::JournaldSnafu {
+                    socket: JOURNALD_SOCKET
+                }
+            );
+            let socket = UnixDatagram::unbound().context(error::JournaldSocketSnafu)?;
+            log::set_boxed_logger(Box::new(JournaldLogger { level, socket }))
+        }
+    }
+    .context(error::LoggerSnafu)?;
+    log::set_max_level(level);
+
+    Ok(())
+}
+
+/// Sets the phase attached to the records logged from now on.
+pub(crate) fn set_phase(phase: &'static str) {
+    if let Ok(mut current) = PHASE.write() {
+        *current = phase;
+    }
+}
+
+fn phase() -> &'static str {
+    PHASE.read().map(|phase| *phase).unwrap_or_default()
+}
+
+// Collects the phase and the key-values of a record, in order. Key-values of the record take
+// precedence over the phase.
+fn fields(record: &Record) -> Vec<(String, String)> {
+    struct Collect(Vec<(String, String)>);
+
+    impl<'kvs> VisitSource<'kvs> for Collect {
+        fn visit_pair(
+            &mut self,
+            key: Key<'kvs>,
+            value: Value<'kvs>,
+        ) -> std::result::Result<(), kv::Error> {
+            self.0.retain(|(existing, _)| existing != key.as_str());
+            self.0.push((key.as_str().to_string(), value.to_string()));
+            Ok(())
+        }
+    }
+
+    let mut fields = Collect(vec![("phase".to_string(), phase().to_string())]);
+    let _ = record.key_values().visit(&mut fields);
+    fields.0
+}
+
+/// Prints every record as a JSON object on its own line of stdout.
+struct JsonLogger {
+    level: LevelFilter,
+}
+
+impl Log for JsonLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = json_line(record, SystemTime::now());
+        let mut stdout = io::stdout().lock();
+        let _ = writeln!(stdout, "{}", line);
+    }
+
+    fn flush(&self) {
+        let _ = io::stdout().flush();
+    }
+}
+
+fn json_line(record: &Record, now: SystemTime) -> JsonValue {
+    let timestamp = now
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs_f64())
+        .unwrap_or_default();
+
+    let mut object = Map::new();
+    object.insert("timestamp".to_string(), timestamp.into());
+    object.insert("level".to_string(), record.level().as_str().into());
+    object.insert("target".to_string(), record.target().into());
+    object.insert("message".to_string(), record.args().to_string().into());
+    for (key, value) in fields(record) {
+        object.insert(key, value.into());
+    }
+
+    JsonValue::Object(object)
+}
+
+/// Sends every record to the journal through its native protocol.
+struct JournaldLogger {
+    level: LevelFilter,
+    socket: UnixDatagram,
+}
+
+impl Log for JournaldLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let entry = journal_entry(record);
+        // Don't lose the record if the journal is unavailable.
+        if self.socket.send_to(&entry, JOURNALD_SOCKET).is_err() {
+            eprintln!("{}: {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+fn journal_entry(record: &Record) -> Vec<u8> {
+    let mut entry = Vec::new();
+    append_field(&mut entry, "MESSAGE", record.args());
+    append_field(&mut entry, "PRIORITY", priority(record.level()));
+    append_field(&mut entry, "SYSLOG_IDENTIFIER", SYSLOG_IDENTIFIER);
+    append_field(&mut entry, "CODE_MODULE", record.target());
+    for (key, value) in fields(record) {
+        append_field(&mut entry, &journal_field_name(&key), value);
+    }
+
+    entry
+}
+
+// Journal field names are upper-case letters, digits and underscores.
+fn journal_field_name(key: &str) -> String {
+    let name: String = key
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' => c.to_ascii_uppercase(),
+            _ => '_',
+        })
+        .collect();
+    format!("MIG_{}", name)
+}
+
+// Values with newlines use the binary form of the native protocol: the name, a newline, the
+// length as a little-endian u64 and the value.
+fn append_field<V>(entry: &mut Vec<u8>, name: &str, value: V)
+where
+    V: fmt::Display,
+{
+    let value = value.to_string();
+    entry.extend_from_slice(name.as_bytes());
+    if value.contains('\n') {
+        entry.push(b'\n');
+        entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        entry.push(b'=');
+    }
+    entry.extend_from_slice(value.as_bytes());
+    entry.push(b'\n');
+}
+
+// Maps log levels to syslog priorities.
+fn priority(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    fn with_record<F>(f: F)
+    where
+        F: FnOnce(&Record),
+    {
+        let kvs = [("gpu_uuid", "GPU-5d5ba0d6"), ("result", "applied")];
+        let record = Record::builder()
+            .args(format_args!("GPU 0 applied"))
+            .level(Level::Info)
+            .target("nvidia_migmanager")
+            .key_values(&kvs)
+            .build();
+        f(&record)
+    }
+
+    #[test]
+    fn test_journal_entry() {
+        with_record(|record| {
+            let entry = String::from_utf8(journal_entry(record)).unwrap();
+            assert!(entry.starts_with("MESSAGE=GPU 0 applied\nPRIORITY=6\n"));
+            assert!(entry.contains("\nMIG_GPU_UUID=GPU-5d5ba0d6\n"));
+            assert!(entry.contains("\nMIG_RESULT=applied\n"));
+            assert!(entry.contains("\nMIG_PHASE="));
+        });
+
+        let mut entry = Vec::new();
+        append_field(&mut entry, "MESSAGE", "a\nb");
+        assert_eq!(entry, b"MESSAGE\n\x03\0\0\0\0\0\0\0a\nb\n");
+    }
+
+    #[test]
+    fn test_json_line() {
+        with_record(|record| {
+            let now = UNIX_EPOCH + Duration::from_millis(1_700_000_000_500);
+            let line = json_line(record, now);
+            assert_eq!(line["timestamp"], 1_700_000_000.5);
+            assert_eq!(line["level"], "INFO");
+            assert_eq!(line["message"], "GPU 0 applied");
+            assert_eq!(line["gpu_uuid"], "GPU-5d5ba0d6");
+            assert_eq!(line["result"], "applied");
+        });
+    }
+}
@@ -20,45 +20,274 @@ device-partitioning-strategy="mig"
 ```
 This would partition the GPUs in an instance with A100 GPU into 2 parts, instance with H100
 into 4 parts and instance with H200 into 3 parts.
-*/
 
-mod mig_profile;
+A profile can also describe the workload instead of the layout, as comma-separated requirements:
+`min-memory=<N>gb` for the memory of every partition, `min-compute=<N>` for its compute slices
+out of 7, and `partitions=<N>` for the number of partitions of equal size. Without `partitions`,
+`nvidia-migmanager` picks the layout with the most partitions that meet the requirements, and
+with it, the largest partitions that fit that many times in the GPU model found.
+```toml
+[settings.kubelet-device-plugins.nvidia.mig.profile]
+"a100.80gb"="min-memory=20gb"
+"h100.80gb"="partitions=2"
+```
+`nvidia-migmanager plan` prints the actions that `apply-mig` would run, with the chosen layout
+and the reasons for it, without changing the GPUs.
+
+The GPU instances of a profile are created one step at a time. If a step fails, the GPU instances
+created so far are destroyed, and GPUs that had no GPU instances before fall back to the whole-GPU
+profile. The error names the step that failed and the outcome of the rollback.
+
+## mig-parted configs
+Instead of the `profile` table, the config file can point at an NVIDIA `mig-parted` style
+`mig-configs` YAML file and select one of its named configs. Entries select GPUs with
+`devices: all` or a list of GPU indices, optionally narrowed by `device-filter` PCI device IDs,
+and each GPU must be selected by at most one entry. GPUs that aren't selected are left alone.
+```toml
+device-partitioning-strategy="mig"
+
+[mig-parted]
+config-file="/etc/nvidia-migmanager/mig-parted.yaml"
+selected="all-balanced"
+```
+
+## Excluding GPUs
+GPUs listed in `exclude` are left alone: their MIG mode and GPU instances aren't changed, and
+they don't cause a reboot. This keeps the layout of a GPU reserved for a host-level service.
+GPUs are selected by index, UUID, PCI bus ID, or model, given as a GPU key of the `profile`
+table or as the product name reported by `nvidia-smi`.
+```toml
+exclude=[0, "GPU-5d5ba0d6-d33d-2b2c-524d-9e3d8d2b8a77", "00000000:10:1C.0", "a100.40gb"]
+```
+
+## Outcome
+`apply-mig` writes `result.json` to the state directory with the outcome of the apply for every
+GPU: `applied`, `unchanged`, `reboot-pending`, `unsupported`, `invalid-config`, `skipped` (for
+excluded and unhealthy GPUs) or `failed`, with the details of skipped and failed GPUs. The
+outcome of the whole apply is the most severe outcome of its GPUs, and sets the exit code:
+
+| Exit code | Outcome |
+|-----------|---------|
+| 0 | `applied`, `unchanged` or `skipped` |
+| 1 | `failed` |
+| 2 | `invalid-config` |
+| 3 | `reboot-pending` |
+| 4 | `unsupported` |
+
+```json
+{
+  "version": 1,
+  "outcome": "reboot-pending",
+  "exit_code": 3,
+  "error": null,
+  "timestamp": 1700000000,
+  "gpus": [
+    {
+      "index": 0,
+      "uuid": "GPU-5d5ba0d6-d33d-2b2c-524d-9e3d8d2b8a77",
+      "outcome": "reboot-pending",
+      "detail": null
+    }
+  ]
+}
+```
+
+## Rebooting
+Ampere GPUs need a reset, and so a reboot, before a new MIG mode applies. `apply-mig` then writes
+the `reboot-required` marker file, and `reboot-if-required` reboots the host. It only reads the
+marker file, so it still reboots when the config file is unreadable or `nvidia-smi` fails. The
+reason recorded by `apply-mig` is passed to systemd, which logs it with the shutdown message.
+
+`reboot-if-required` honors systemd inhibitor locks that block shutdown, such as the ones taken
+with `systemd-inhibit --what=shutdown`, and retries until they are released, so the boot doesn't
+proceed with GPUs that still need a reset. Deployments that would rather override a stuck
+inhibitor set `--inhibitor-timeout`: once that many seconds pass, the host reboots anyway.
+`--ignore-inhibitors` doesn't wait for them at all. `--delay` waits before rebooting, and
+`--reason` overrides the reason passed to systemd.
+```shell
+nvidia-migmanager reboot-if-required --delay 30 --reason "MIG layout change"
+```
+
+## Paths
+The locations of `nvidia-smi`, `systemctl`, the state directory (which holds the
+`reboot-required` marker file) and the history directory default to their host locations. They
+can be overridden in the `paths` table of the config file, or with the `--nvidia-smi-path`,
+`--systemctl-path`, `--state-dir` and `--history-dir` options, which take precedence over the
+config file. This allows running
+`nvidia-migmanager` from a privileged container with the host paths mounted elsewhere.
+```toml
+[paths]
+nvidia-smi="/.bottlerocket/rootfs/usr/libexec/nvidia/tesla/bin/nvidia-smi"
+state-dir="/.bottlerocket/rootfs/run/nvidia-migmanager"
+```
+
+## Device inventory
+After `apply-mig`, `nvidia-migmanager` writes `devices.json` to the state directory
+(`/run/nvidia-migmanager/devices.json` by default). It lists every GPU with its index, UUID,
+model, PCI device ID and current and pending MIG mode, and for each MIG device its GPU instance ID, compute instance
+ID, profile, UUID and memory size, so that other agents don't need to run `nvidia-smi`:
+```json
+{
+  "version": 1,
+  "gpus": [
+    {
+      "index": 0,
+      "uuid": "GPU-5d5ba0d6-d33d-2b2c-524d-9e3d8d2b8a77",
+      "model": "NVIDIA A100-SXM4-40GB",
+      "pci_device_id": "0x20B010DE",
+      "mig_mode": "Enabled",
+      "mig_mode_pending": "Enabled",
+      "mig_devices": [
+        {
+          "gpu_instance_id": 1,
+          "compute_instance_id": 0,
+          "profile": "3g.20gb",
+          "uuid": "MIG-c6d4f1ef-42e4-5de3-91c7-45d71c87eb3f",
+          "memory_mib": 20224
+        }
+      ],
+      "health": {
+        "uncorrected_ecc_errors": 0,
+        "retired_pages_pending": null,
+        "remapped_rows_pending": false,
+        "remapped_rows_failure": false
+      }
+    }
+  ]
+}
+```
+`nvidia-migmanager status` prints the same information as one line per GPU.
+
+## Apply history and drift
+Every `apply-mig` appends an entry to `history.jsonl` in the history directory
+(`/var/lib/nvidia-migmanager` by default), which persists across reboots. An entry records the
+layout the apply asked for on every managed GPU, the MIG mode and GPU instances of every GPU
+after the apply, the NVIDIA driver version and the result. The last 100 entries are kept.
+
+The layout after the last successful apply is the reference for drift detection. `apply-mig`
+compares the GPUs with it before applying and logs any drift, and `status` prints it:
+```text
+Drift: GPU 0 GPU-5d5ba0d6-d33d-2b2c-524d-9e3d8d2b8a77: expected MIG Enabled with 3g.20gb,3g.20gb, found MIG Enabled with 3g.20gb
+```
+Excluded and unhealthy GPUs aren't compared. GPU instances don't survive a reboot, so after a
+reboot only the MIG mode is compared. `status --correct-drift` destroys the GPU instances of the
+drifted GPUs and recreates the ones from the last apply. A changed MIG mode needs a GPU reset, so
+it is only reported, and `apply-mig` restores it on the next boot.
+
+## Metrics
+With `--metrics-file` or `metrics-file` in the `paths` table, `apply-mig` and `status` write the
+MIG state in the Prometheus text format, for the node exporter's textfile collector. The file is
+replaced atomically. It has the current and pending MIG mode and health of every GPU, the number
+of GPU and compute instances of every MIG profile, whether the layout of every GPU drifted since
+the last successful apply, the outcome, exit code and time of the last apply, and whether a reboot
+is pending:
+```text
+nvidia_migmanager_mig_mode_current{gpu="0",uuid="GPU-5d5ba0d6-d33d-2b2c-524d-9e3d8d2b8a77"} 1
+nvidia_migmanager_mig_mode_pending{gpu="0",uuid="GPU-5d5ba0d6-d33d-2b2c-524d-9e3d8d2b8a77"} 1
+nvidia_migmanager_gpu_instances{gpu="0",uuid="GPU-5d5ba0d6-d33d-2b2c-524d-9e3d8d2b8a77",profile="3g.20gb"} 2
+nvidia_migmanager_compute_instances{gpu="0",uuid="GPU-5d5ba0d6-d33d-2b2c-524d-9e3d8d2b8a77",profile="3g.20gb"} 2
+nvidia_migmanager_layout_drift_unknown 0
+nvidia_migmanager_layout_drifted{gpu="0",uuid="GPU-5d5ba0d6-d33d-2b2c-524d-9e3d8d2b8a77"} 0
+nvidia_migmanager_last_apply_result{outcome="applied"} 1
+nvidia_migmanager_last_apply_exit_code 0
+nvidia_migmanager_last_apply_timestamp_seconds 1700000000
+nvidia_migmanager_reboot_pending 0
+```
+`nvidia_migmanager_last_apply_result` has a sample for every outcome, set to 1 for the outcome of
+the last apply. The last apply metrics are left out until `apply-mig` has run since boot.
+`nvidia_migmanager_layout_drifted` is left out, and `nvidia_migmanager_layout_drift_unknown` is 1,
+until an apply succeeds. GPUs that drifted by going away keep a sample set to 1.
+
+## GPU health
+Before enabling MIG, `nvidia-migmanager` checks the uncorrectable ECC error count, pending page
+retirements and the row-remapping status of every GPU. GPUs with uncorrectable ECC errors, pending
+page retirements or row remappings, or a failed row remapping are not partitioned: they are left
+whole with a warning, and the other GPUs are partitioned as usual. Fields a GPU doesn't report
+are `null` in the inventory and don't count against its health.
+
+## Logging
+`--log-format` selects how records are written: `text` (the default) prints them as plain text,
+`json` prints one JSON object per record on stdout, and `journald` sends them to the journal with
+structured fields. Besides the message, level and target, records carry the phase of the run
+(`discover`, `plan`, `apply`, `report`, `status` or `reboot`), and when they apply, the UUID of
+the GPU, the outcome for it, and the exit code of the `nvidia-smi` command. In the journal, these
+are the `MIG_PHASE`, `MIG_GPU_UUID`, `MIG_RESULT` and `MIG_EXIT_CODE` fields:
+```shell
+journalctl -t nvidia-migmanager MIG_RESULT=failed -o verbose
+```
+```json
+{"gpu_uuid":"GPU-5d5ba0d6-d33d-2b2c-524d-9e3d8d2b8a77","level":"INFO","message":"GPU 0: applied","phase":"report","result":"applied","target":"nvidia_migmanager","timestamp":1700000000.25}
+```
+
+## Locking
+Only one `apply-mig` or `reboot-if-required` runs at a time. They take an exclusive lock on
+`nvidia-migmanager.lock` in the state directory, and wait up to `--lock-timeout` seconds for
+another invocation to release it. `--lock-timeout 0` fails immediately instead. The lock is
+released by the kernel when the process exits, even if it crashes. `plan` and `status` only read
+the state of the GPUs, and don't take the lock, except for `status --correct-drift`.
+
+State is only written under the lock. An `apply-mig` that can't take it exits with 1 without
+writing `result.json`, the history or the metrics, which belong to the invocation that holds it,
+and `status` only writes the metrics if the lock is free.
+*/
 
-use crate::mig_profile::*;
 use argh::FromArgs;
-use log::{info, trace, warn};
-use regex::Regex;
-use serde::Deserialize;
-use simplelog::{Config as LogConfig, LevelFilter, SimpleLogger};
-use snafu::{ensure, ResultExt};
-use std::cmp::min;
-use std::collections::HashMap;
-use std::ffi::OsStr;
-use std::fs;
-use std::path::Path;
-use std::process::{self, Command};
-use std::thread;
+use log::{error, info, warn};
+use nvidia_mig::config::{NvidiaMigConfig, DEFAULT_CONFIG_PATH};
+use nvidia_mig::history::{self, Drift, HistoryEntry};
+use nvidia_mig::inventory::Inventory;
+use nvidia_mig::lock::RunLock;
+use nvidia_mig::mig_parted::{self, MigPartedConfig};
+use nvidia_mig::outcome::{self, ApplyResult, Outcome};
+use nvidia_mig::paths::{PathOverrides, Paths};
+use nvidia_mig::reboot::{self, RebootOptions};
+use nvidia_mig::{apply, gpu, inventory, metrics, plan};
+use simplelog::LevelFilter;
+use std::path::{Path, PathBuf};
+use std::process;
 use std::time::Duration;
 
-const NVIDIA_VENDOR_ID: &str = "10DE";
+mod logging;
 
-const DEFAULT_CONFIG_PATH: &str = "/etc/nvidia-migmanager/nvidia-migmanager.toml";
-const NVIDIA_SMI_PATH: &str = "/usr/libexec/nvidia/tesla/bin/nvidia-smi";
-const SYSTEMCTL_PATH: &str = "/usr/bin/systemctl";
-const REBOOT_REQUIRED_MARKER_FILE: &str = "/run/nvidia-migmanager/reboot-required";
+use logging::{set_phase, LogFormat};
 
-const GPU_MODEL_REGEX: &str = r"[A-Za-z]\d+\.(\d+)gb";
-const MIG_PROFILE_REGEX: &str = r"(\d+)g\.(\d+)gb";
+const DEFAULT_LOCK_TIMEOUT_SECS: u64 = 60;
 
-/// Stores arguments
+/// Applies the MIG settings of the NVIDIA GPUs in the instance
 #[derive(FromArgs, PartialEq, Debug)]
 struct Args {
-    /// log-level trace|debug|info|warn|error
+    /// log-level trace|debug|info|warn|error, info if not given
     #[argh(option)]
     log_level: Option<LevelFilter>,
+    /// log-format text|json|journald
+    #[argh(option, default = "LogFormat::Text")]
+    log_format: LogFormat,
     /// configuration file with the desired MIG settings
     #[argh(option, default = "DEFAULT_CONFIG_PATH.to_string()", short = 'd')]
     config_path: String,
+    /// path to the nvidia-smi binary, overriding the config file;
+    /// /usr/libexec/nvidia/tesla/bin/nvidia-smi if neither sets it
+    #[argh(option)]
+    nvidia_smi_path: Option<PathBuf>,
+    /// path to the systemctl binary, overriding the config file;
+    /// /usr/bin/systemctl if neither sets it
+    #[argh(option)]
+    systemctl_path: Option<PathBuf>,
+    /// directory for runtime state such as the reboot-required marker file, overriding the config
+    /// file; /run/nvidia-migmanager if neither sets it
+    #[argh(option)]
+    state_dir: Option<PathBuf>,
+    /// directory for the apply history, which persists across reboots, overriding the config file;
+    /// /var/lib/nvidia-migmanager if neither sets it
+    #[argh(option)]
+    history_dir: Option<PathBuf>,
+    /// path to a Prometheus textfile to write after apply-mig and status, overriding the config
+    /// file; not written if neither sets it
+    #[argh(option)]
+    metrics_file: Option<PathBuf>,
+    /// seconds to wait for another nvidia-migmanager to finish, 0 to fail immediately
+    #[argh(option, default = "DEFAULT_LOCK_TIMEOUT_SECS")]
+    lock_timeout: u64,
     #[argh(subcommand)]
     subcommand: Subcommand,
 }
@@ -68,617 +297,411 @@ struct Args {
 #[argh(subcommand)]
 enum Subcommand {
     HandleMigManager(HandleMigManagerArgs),
+    Plan(PlanArgs),
     RebootIfRequired(RebootIfRequiredArgs),
+    Status(StatusArgs),
 }
 
 #[derive(FromArgs, PartialEq, Debug)]
 #[argh(subcommand, name = "reboot-if-required")]
 /// Reboot the host if reboot-to-reconcile is set and the boot settings changed
-struct RebootIfRequiredArgs {}
+struct RebootIfRequiredArgs {
+    /// seconds to wait before rebooting
+    #[argh(option, default = "0")]
+    delay: u64,
+    /// reason for the reboot, logged by systemd; defaults to the reason recorded by apply-mig
+    #[argh(option)]
+    reason: Option<String>,
+    /// reboot even if a systemd inhibitor lock blocks shutdown
+    #[argh(switch)]
+    ignore_inhibitors: bool,
+    /// seconds to wait for inhibitor locks before rebooting anyway; unset or 0 waits until they are
+    /// released
+    #[argh(option)]
+    inhibitor_timeout: Option<u64>,
+}
 
-/// Handles logic to apply MIG
+/// Applies the MIG mode and profiles in the configuration file to the GPUs
 #[derive(FromArgs, Debug, PartialEq)]
 #[argh(subcommand, name = "apply-mig")]
 struct HandleMigManagerArgs {}
 
-#[derive(Debug, PartialEq, Deserialize)]
-#[serde(rename_all = "kebab-case")]
-struct NvidiaMigConfig {
-    #[serde(default)]
-    device_partitioning_strategy: String,
-    #[serde(default)]
-    profile: HashMap<String, String>,
-}
+/// Prints the MIG changes that apply-mig would make, and why, without making them
+#[derive(FromArgs, Debug, PartialEq)]
+#[argh(subcommand, name = "plan")]
+struct PlanArgs {}
 
-#[derive(Debug, PartialEq, Clone)]
-enum NvidiaGpu {
-    A100_40GB,
-    A100_80GB,
-    H100_80GB,
-    H200_141GB,
-    Other,
+/// Prints the MIG mode, MIG devices and health of every GPU, and how they drifted since the last
+/// apply
+#[derive(FromArgs, Debug, PartialEq)]
+#[argh(subcommand, name = "status")]
+struct StatusArgs {
+    /// restore the GPU instances of GPUs that drifted since the last apply
+    #[argh(switch)]
+    correct_drift: bool,
 }
 
-impl NvidiaGpu {
-    fn is_ampere(&self) -> bool {
-        use NvidiaGpu::*;
-        matches!(self, A100_40GB | A100_80GB)
+fn build_plan(
+    mig_settings: &NvidiaMigConfig,
+    gpu_info: &[gpu::MigGpu],
+) -> nvidia_mig::Result<plan::Plan> {
+    match &mig_settings.mig_parted {
+        Some(mig_parted) if mig_settings.mig_enabled() => {
+            let mig_parted_config = MigPartedConfig::from_file(&mig_parted.config_file)?;
+            mig_parted::plan(
+                &mig_parted_config,
+                &mig_parted.selected,
+                gpu_info,
+                &mig_settings.exclude,
+            )
+        }
+        _ => plan::plan(mig_settings, gpu_info),
     }
 }
 
-#[derive(Hash, Debug, Clone, PartialEq, Eq)]
-enum MigState {
-    Unsupported,
-    Enabled,
-    Disabled,
-    Transition,
-    Unknown,
-}
-
-impl MigState {
-    fn is_disabled(&self) -> bool {
-        matches!(self, MigState::Disabled)
-    }
-
-    fn is_enabled(&self) -> bool {
-        matches!(self, MigState::Enabled)
-    }
-
-    fn is_unsupported(&self) -> bool {
-        matches!(self, MigState::Unsupported)
+fn handle_mig_manager(
+    mig_settings: &NvidiaMigConfig,
+    gpu_info: &[gpu::MigGpu],
+    paths: &Paths,
+) -> (ApplyResult, plan::Plan, Option<Inventory>) {
+    set_phase("plan");
+    let (plan, applied) = match build_plan(mig_settings, gpu_info) {
+        Ok(plan) => {
+            for reason in &plan.reasons {
+                info!("{}", reason);
+            }
+            set_phase("apply");
+            let applied = apply::apply(&plan, paths);
+            (plan, applied)
+        }
+        Err(e) => (plan::Plan::default(), Err(e)),
+    };
+
+    // The inventory is informational, so failing to write it doesn't fail the apply.
+    set_phase("report");
+    let devices = inventory::get_inventory(paths).and_then(|devices| {
+        inventory::write_inventory(paths, &devices)?;
+        Ok(devices)
+    });
+    if let Err(e) = &devices {
+        warn!("Failed to write the MIG device inventory: {}", e);
     }
-}
 
-struct MigGpu {
-    model: NvidiaGpu,
-    state: MigState,
+    let result = ApplyResult::new(gpu_info, &plan, &applied);
+    (result, plan, devices.ok())
 }
 
-/// Wrapper around process::Command that adds error checking.
-fn command<I, S>(bin_path: &str, args: I) -> Result<String>
-where
-    I: IntoIterator<Item = S>,
-    S: AsRef<OsStr>,
-{
-    let mut command = Command::new(bin_path);
-    command.args(args);
-    let output = command
-        .output()
-        .context(error::ExecutionFailureSnafu { command })?;
-
-    trace!("stdout: {}", String::from_utf8_lossy(&output.stdout));
-    trace!("stderr: {}", String::from_utf8_lossy(&output.stderr));
-
-    ensure!(
-        output.status.success(),
-        error::CommandFailureSnafu { bin_path, output }
-    );
-
-    let output_str = String::from_utf8_lossy(&output.stdout);
-
-    Ok(output_str.to_string())
-}
-
-// Runs the nvidia-smi command to enable/disable MIG in all GPUs
-fn set_mig_mode(mig_enabled: bool) -> Result<()> {
-    info!(
-        "{} MIG.",
-        if mig_enabled { "Enabling" } else { "Disabling" }
-    );
-
-    command(NVIDIA_SMI_PATH, ["-mig", &(mig_enabled as u8).to_string()])?;
-
-    Ok(())
-}
-
-// Runs the nvidia-smi command to apply the correct MIG profile in all the GPUs
-fn set_mig_profile(profile_string: &str) -> Result<()> {
-    info!("Activating MIG profile ...");
-
-    command(NVIDIA_SMI_PATH, ["mig", "-cgi", profile_string, "-C"])?;
-
-    Ok(())
+// Returns how the GPUs drifted since the last successful apply. Drift is informational, so
+// failing to detect it is only logged.
+fn detect_drift(paths: &Paths, devices: &Inventory) -> Option<(HistoryEntry, Vec<Drift>)> {
+    match history::last_successful(paths) {
+        Ok(Some(last)) => {
+            let drift = last.drift(devices);
+            Some((last, drift))
+        }
+        Ok(None) => None,
+        Err(e) => {
+            warn!("Failed to read the apply history: {}", e);
+            None
+        }
+    }
 }
 
-// Uses pci-device id to find out the GPU model of the instance
-fn get_gpu_model(pci_device_id: &str) -> Result<NvidiaGpu> {
-    ensure!(
-        pci_device_id.ends_with(NVIDIA_VENDOR_ID),
-        error::GpuModelSnafu
-    );
-
-    if pci_device_id.starts_with("0x20B0") {
-        info!("Found NVIDIA A100-40GB GPU.");
-        Ok(NvidiaGpu::A100_40GB)
-    } else if pci_device_id.starts_with("0x20B2") || pci_device_id.starts_with("0x20B5") {
-        info!("Found NVIDIA A100-80GB GPU.");
-        Ok(NvidiaGpu::A100_80GB)
-    } else if pci_device_id.starts_with("0x2330")
-        || pci_device_id.starts_with("0x2321")
-        || pci_device_id.starts_with("0x2331")
-        || pci_device_id.starts_with("0x2339")
-    {
-        info!("Found NVIDIA H100-80GB GPU.");
-        Ok(NvidiaGpu::H100_80GB)
-    } else if pci_device_id.starts_with("0x2335")
-        || pci_device_id.starts_with("0x233B")
-        || pci_device_id.starts_with("0x2348")
-    {
-        info!("Found NVIDIA H200-141GB GPU.");
-        Ok(NvidiaGpu::H200_141GB)
+// Appends the apply to the history. Like the inventory, the history is informational, so failing
+// to write it doesn't fail the apply.
+fn record_history(
+    paths: &Paths,
+    gpu_info: &[gpu::MigGpu],
+    plan: &plan::Plan,
+    devices: Option<&Inventory>,
+    result: &ApplyResult,
+) {
+    let driver_version = if gpu_info.is_empty() {
+        None
     } else {
-        warn!("Found NVIDIA Device but couldn't confirm variant.");
-        Ok(NvidiaGpu::Other)
+        gpu::get_driver_version(paths)
+            .map_err(|e| warn!("Failed to query the driver version: {}", e))
+            .ok()
+    };
+    let entry = HistoryEntry::new(gpu_info, plan, devices, driver_version, result.clone());
+    if let Err(e) = history::append(paths, &entry) {
+        warn!("Failed to write the apply history: {}", e);
     }
 }
 
-fn get_gpu_state(current_state: &str, next_state: &str) -> MigState {
-    let mut gpu_state = MigState::Unknown;
-
-    if current_state != next_state {
-        gpu_state = MigState::Transition;
-    } else if current_state == "Enabled" {
-        gpu_state = MigState::Enabled;
-    } else if current_state == "Disabled" {
-        gpu_state = MigState::Disabled;
-    } else if current_state == "[N/A]" {
-        gpu_state = MigState::Unsupported;
+// Writes the metrics file, if one is configured. Like the inventory, the metrics are
+// informational, so failing to write them is only logged.
+fn write_metrics(
+    paths: &Paths,
+    devices: &Inventory,
+    last_result: Option<&ApplyResult>,
+    drift: Option<&[Drift]>,
+) {
+    if let Err(e) = metrics::write_metrics(paths, devices, last_result, drift) {
+        warn!("Failed to write the metrics file: {}", e);
     }
-
-    gpu_state
 }
 
-// Runs nvidia-smi command to find out the current state of the Nvidia GPU.
-fn get_gpu_info() -> Result<Vec<MigGpu>> {
-    info!("Fetching GPU devices data ...");
-
-    let output = command(
-        NVIDIA_SMI_PATH,
-        [
-            "--query-gpu=pci.device_id,mig.mode.current,mig.mode.pending",
-            "--format=csv,noheader",
-        ],
-    )?;
-
-    let mut gpu_info = Vec::new();
-
-    for row in output.lines() {
-        let parts: Vec<_> = row.split(", ").collect();
-
-        ensure!(parts.len() == 3, error::NvidiaSmiSnafu);
-
-        let gpu_model = get_gpu_model(parts[0])?;
-        let gpu_state = get_gpu_state(parts[1], parts[2]);
+// Resolves the paths from the config file and the command line, where the command line takes
+// precedence.
+fn resolve_paths(args: &Args, config_paths: &PathOverrides) -> Paths {
+    let cli_paths = PathOverrides {
+        nvidia_smi: args.nvidia_smi_path.clone(),
+        systemctl: args.systemctl_path.clone(),
+        state_dir: args.state_dir.clone(),
+        history_dir: args.history_dir.clone(),
+        metrics_file: args.metrics_file.clone(),
+    };
+    Paths::default().with_overrides(&config_paths.merge(&cli_paths))
+}
 
-        let gpu = MigGpu {
-            model: gpu_model,
-            state: gpu_state,
-        };
-        gpu_info.push(gpu);
+// Resolves the paths for subcommands that don't need the MIG settings. The config file is only
+// read for its `paths` table, and failing to read it isn't an error.
+fn paths_only(args: &Args) -> Paths {
+    if !Path::new(&args.config_path).exists() {
+        return resolve_paths(args, &PathOverrides::default());
+    }
+    match NvidiaMigConfig::from_file(&args.config_path) {
+        Ok(mig_settings) => resolve_paths(args, &mig_settings.paths),
+        Err(e) => {
+            warn!(
+                "Using the default paths, failed to read the config file: {}",
+                e
+            );
+            resolve_paths(args, &PathOverrides::default())
+        }
     }
-
-    Ok(gpu_info)
 }
 
-/// Read the config file to get MIG settings
-fn get_mig_settings<P>(config_path: P) -> Result<NvidiaMigConfig>
-where
-    P: AsRef<Path>,
-{
-    let config_str = fs::read_to_string(config_path.as_ref()).context(error::ReadConfigSnafu {
-        config_path: config_path.as_ref(),
-    })?;
-
-    let config: NvidiaMigConfig =
-        toml::from_str(&config_str).context(error::TomlDeserializationSnafu {
-            config_path: config_path.as_ref(),
-        })?;
-
-    Ok(config)
+fn lock(args: &Args, paths: &Paths) -> nvidia_mig::Result<RunLock> {
+    RunLock::acquire(paths, Duration::from_secs(args.lock_timeout))
 }
 
-fn process_mig_config<T>(model_str: &str, mig_settings: &NvidiaMigConfig) -> Result<()>
-where
-    T: MigGpuProfile,
-{
-    let default_profile = "1".to_string();
-    let gpu_model = model_str.to_string();
-    let mig_profile = mig_settings
-        .profile
-        .get(&gpu_model)
-        .unwrap_or(&default_profile);
-
-    info!("MIG Profile or the number of GPU slices: {:?}", mig_profile);
-    let profile =
-        serde_plain::from_str::<T>(mig_profile.as_str()).context(error::DeserializationSnafu)?;
-
-    set_mig_profile(profile.get_mig_profile())
-}
+fn apply_mig(args: &Args) -> i32 {
+    set_phase("discover");
+    let mig_settings = NvidiaMigConfig::from_file(&args.config_path);
+    // If the config file can't be read, the failure is recorded in the state directory given on
+    // the command line.
+    let paths = match &mig_settings {
+        Ok(mig_settings) => resolve_paths(args, &mig_settings.paths),
+        Err(_) => resolve_paths(args, &PathOverrides::default()),
+    };
+    // Held until we return, so that no other invocation issues MIG commands or writes the result,
+    // history and metrics in the meantime. Without the lock, the running invocation owns the
+    // state, so the failure is only logged.
+    let _lock = match lock(args, &paths) {
+        Ok(lock) => lock,
+        Err(e) => {
+            error!("{}", e);
+            return Outcome::of_error(&e).exit_code();
+        }
+    };
 
-fn process_unknown_gpu_mig_config(gpu: &str, mig_profile: &str) -> Result<()> {
-    // If the GPU is unknown, we want the exact MIG Profile and not the number of slices.
-    ensure!(mig_profile.len() > 1, error::MigProfileSnafu {});
-
-    // The GPU and MIG Profile here are expected in a deterministic format and enforced in
-    // settings API. We parse this to form the MIG profile string using known GPU hardware constraints.
-    let gpu_regex = Regex::new(GPU_MODEL_REGEX).unwrap();
-    let profile_regex = Regex::new(MIG_PROFILE_REGEX).unwrap();
-
-    let gpu_ram: usize = gpu_regex
-        .captures(gpu)
-        .map(|captures| captures[1].parse().unwrap_or(0))
-        .unwrap();
-    let (compute_slices, slice_ram) = profile_regex
-        .captures(mig_profile)
-        .map(|captures| {
-            (
-                captures[1].parse().unwrap_or(0),
-                captures[2].parse().unwrap_or(0),
-            )
+    let mut gpu_info = Vec::new();
+    let mut applied_plan = plan::Plan::default();
+    let mut devices = None;
+    let result = mig_settings
+        .and_then(|mig_settings| {
+            gpu_info = gpu::get_gpu_info(&paths)?;
+            if let Ok(current) = inventory::get_inventory(&paths) {
+                for drifted in detect_drift(&paths, &current)
+                    .map(|(_, drift)| drift)
+                    .unwrap_or_default()
+                {
+                    warn!(
+                        gpu_uuid = drifted.expected.uuid.as_str();
+                        "The MIG layout changed since the last apply: {}",
+                        drifted
+                    );
+                }
+            }
+            let (result, plan, inventory) = handle_mig_manager(&mig_settings, &gpu_info, &paths);
+            applied_plan = plan;
+            devices = inventory;
+            Ok(result)
         })
-        .unwrap();
+        .unwrap_or_else(|e| ApplyResult::from_error(&e));
 
-    // Prevents unsafe division below and enforces logical limits to RAM and compute slices
-    ensure!(
-        gpu_ram > 0 && slice_ram > 0 && compute_slices > 0,
-        error::MigProfileSnafu {}
-    );
-
-    // There are total 7 compute slices in a MIG supported GPU. So, total
-    // number of partitions of the GPU will be minimum of
-    // 7/(compute slices in each partition) and (total VRAM / VRAM of each partition)
-    let num_slices: usize = min(gpu_ram / slice_ram, 7 / compute_slices);
-    let profile_string = std::iter::repeat(mig_profile)
-        .take(num_slices)
-        .collect::<Vec<_>>()
-        .join(",");
+    set_phase("report");
+    if let Err(e) = outcome::write_result(&paths, &result) {
+        warn!("Failed to write the apply result: {}", e);
+    }
+    record_history(&paths, &gpu_info, &applied_plan, devices.as_ref(), &result);
+    // Without an inventory, drift is unknown rather than every GPU being gone.
+    let drift = devices
+        .as_ref()
+        .and_then(|devices| detect_drift(&paths, devices))
+        .map(|(_, drift)| drift);
+    // Export the result even when the apply failed before it got to the GPUs.
+    let devices = devices.unwrap_or_else(|| Inventory {
+        version: inventory::INVENTORY_VERSION,
+        gpus: Vec::new(),
+    });
+    write_metrics(&paths, &devices, Some(&result), drift.as_deref());
+    if let Some(e) = &result.error {
+        error!("{}", e);
+    }
+    for gpu in &result.gpus {
+        info!(
+            gpu_uuid = gpu.uuid.as_str(), result:% = gpu.outcome;
+            "GPU {}: {}{}",
+            gpu.index,
+            gpu.outcome,
+            gpu.detail.as_deref().map(|detail| format!(", {}", detail)).unwrap_or_default()
+        );
+    }
+    info!(result:% = result.outcome; "MIG apply outcome: {}", result.outcome);
 
-    set_mig_profile(profile_string.as_str())
+    result.exit_code
 }
 
-fn get_instance_gpu(gpu_info: &[MigGpu]) -> Result<NvidiaGpu> {
-    let reference_gpu_model = gpu_info.first().unwrap().model.clone();
-    ensure!(
-        gpu_info.iter().all(|gpu| gpu.model == reference_gpu_model),
-        error::MigGpuSnafu
-    );
+fn print_plan(args: &Args) -> Result<()> {
+    set_phase("plan");
+    let mig_settings = NvidiaMigConfig::from_file(&args.config_path)?;
+    let paths = resolve_paths(args, &mig_settings.paths);
+    let gpu_info = gpu::get_gpu_info(&paths)?;
+    print!("{}", build_plan(&mig_settings, &gpu_info)?);
 
-    Ok(reference_gpu_model)
+    Ok(())
 }
 
-fn enable_mig(mig_settings: NvidiaMigConfig, gpu_info: &[MigGpu]) -> Result<()> {
-    ensure!(!gpu_info.is_empty(), error::GpuModelSnafu);
-
-    let (is_ampere_gpu_present, has_disabled_mig, is_mig_unsupported) = gpu_info.iter().fold(
-        (false, false, false),
-        |(ampere, disabled, unsupported), gpu| {
-            (
-                ampere || gpu.model.is_ampere(),
-                disabled || gpu.state.is_disabled(),
-                unsupported || gpu.state.is_unsupported(),
-            )
-        },
-    );
-
-    if is_mig_unsupported {
-        warn!("MIG is not supported by the available NVIDIA GPU.");
-        return Ok(());
-    }
-
-    if has_disabled_mig {
-        // Enable MIG for all the GPU
-        set_mig_mode(true)?;
-
-        // If any GPU is A100 create marker file for reboot to reconcile
-        // for the gpu reset and move from transitional state to enabled
-        if is_ampere_gpu_present {
-            info!("Rebooting to apply MIG Settings...");
-            std::fs::write(REBOOT_REQUIRED_MARKER_FILE, "Enabling MIG").context(
-                error::WriteMarkerSnafu {
-                    marker_path: REBOOT_REQUIRED_MARKER_FILE,
-                },
-            )?;
-
-            return Ok(());
+fn print_status(args: &Args, status_args: &StatusArgs) -> Result<()> {
+    set_phase("status");
+    let paths = paths_only(args);
+    let mut devices = inventory::get_inventory(&paths)?;
+    let mut drift = detect_drift(&paths, &devices);
+
+    if let Some((_, drifted)) = drift.as_ref().filter(|(_, drifted)| !drifted.is_empty()) {
+        if status_args.correct_drift {
+            // Don't change the GPUs while another invocation is changing them.
+            let _lock = lock(args, &paths)?;
+            let restored = history::correct_drift(&paths, drifted)?;
+            info!("Restored the MIG layout of {} GPUs.", restored);
+            devices = inventory::get_inventory(&paths)?;
+            drift = detect_drift(&paths, &devices);
         }
     }
 
-    match get_instance_gpu(gpu_info) {
-        Ok(NvidiaGpu::A100_40GB) => {
-            process_mig_config::<NvidiaA100_40gbMigProfile>("a100.40gb", &mig_settings)
-        }
-        Ok(NvidiaGpu::A100_80GB) => {
-            process_mig_config::<NvidiaA100_80gbMigProfile>("a100.80gb", &mig_settings)
-        }
-        Ok(NvidiaGpu::H100_80GB) => {
-            process_mig_config::<NvidiaH100_80gbMigProfile>("h100.80gb", &mig_settings)
-        }
-        Ok(NvidiaGpu::H200_141GB) => {
-            process_mig_config::<NvidiaH200_141gbMigProfile>("h200.141gb", &mig_settings)
-        }
-        _ => {
-            let known_gpus: Vec<&str> = vec!["a100.40gb", "a100.80gb", "h100.80gb", "h200.141gb"];
-            let mut filtered_map = mig_settings.profile;
-            filtered_map.retain(|key, _| !known_gpus.contains(&key.as_str()));
-
-            let mut entries: Vec<_> = filtered_map.into_iter().collect();
-            entries.sort_by(|gpu, mig_profile| gpu.0.cmp(&mig_profile.0));
-
-            // The GPU in the current instance is not one of the known GPUs. We attempt using the profiles that doesn't belong to one of the known GPUs.
-            for (gpu, mig_profile) in entries {
-                match process_unknown_gpu_mig_config(&gpu, &mig_profile) {
-                    Ok(()) => {
-                        info!("Successfully applied MIG Profile: {}", mig_profile);
-                        return Ok(());
-                    }
-                    Err(_) => {
-                        warn!(
-                            "The Profile {} is not a valid MIG Profile for the given GPU.",
-                            mig_profile
-                        );
-                        continue;
-                    }
-                }
+    for gpu in &devices.gpus {
+        println!("{}", gpu);
+    }
+    match &drift {
+        None => println!("No successful apply recorded, drift is unknown."),
+        Some((last, drifted)) if drifted.is_empty() => println!(
+            "No drift since the apply at {} (driver {}).",
+            last.result.timestamp,
+            last.driver_version.as_deref().unwrap_or("unknown")
+        ),
+        Some((_, drifted)) => {
+            for drifted in drifted {
+                println!("Drift: {}", drifted);
             }
-
-            Ok(())
         }
     }
-}
 
-fn disable_mig(gpu_info: &[MigGpu]) -> Result<()> {
-    let (is_ampere_gpu_present, has_enabled_mig) =
-        gpu_info
-            .iter()
-            .fold((false, false), |(ampere, enabled), gpu| {
-                (
-                    ampere || gpu.model.is_ampere(),
-                    enabled || gpu.state.is_enabled(),
-                )
-            });
-
-    if has_enabled_mig {
-        // Disable MIG for the GPU
-        set_mig_mode(false)?;
-
-        // If GPU is A100 create marker file for reboot to reconcile
-        // for the gpu reset and move from transitional state to disabled
-        if is_ampere_gpu_present {
-            info!("Rebooting to apply MIG Settings...");
-            std::fs::write(REBOOT_REQUIRED_MARKER_FILE, "Disabling MIG").context(
-                error::WriteMarkerSnafu {
-                    marker_path: REBOOT_REQUIRED_MARKER_FILE,
-                },
-            )?;
+    // The invocation that holds the lock writes the metrics once it is done.
+    let _lock = match RunLock::acquire(&paths, Duration::ZERO) {
+        Ok(lock) => lock,
+        Err(e) => {
+            info!("Not writing the metrics: {}", e);
+            return Ok(());
         }
-    }
+    };
+    let last_result = outcome::read_result(&paths).unwrap_or_else(|e| {
+        warn!("Failed to read the last apply result: {}", e);
+        None
+    });
+    write_metrics(
+        &paths,
+        &devices,
+        last_result.as_ref(),
+        drift.as_ref().map(|(_, drifted)| drifted.as_slice()),
+    );
 
     Ok(())
 }
 
-fn handle_mig_manager(mig_settings: NvidiaMigConfig, gpu_info: &[MigGpu]) -> Result<()> {
-    if mig_settings.device_partitioning_strategy == "mig" {
-        enable_mig(mig_settings, gpu_info)
-    } else {
-        disable_mig(gpu_info)
-    }
-}
-
-fn reboot_if_required() -> Result<()> {
-    let reboot_required = Path::new(REBOOT_REQUIRED_MARKER_FILE).exists();
-
-    if reboot_required {
-        info!("GPU reset is required to apply MIG Settings. Initiating reboot...");
-        command(SYSTEMCTL_PATH, ["reboot"])?;
-        // The "systemctl reboot" process will not block until the host does
-        // reboot, but return as soon as the request either failed or the job
-        // to start the systemd reboot.target and its dependencies have been
-        // enqueued. As the shutdown.target that is being pulled in conflicts
-        // with most anything else, the other jobs needed to boot the host
-        // will be cancelled and the boot will not proceed.
-        //
-        // The above is subtle, so slowly spin here until systemd kills this
-        // nvidia-migmanager process as part of the host shutting down by sending it
-        // SIGTERM. This serves as a more obvious line of defense against the
-        // boot proceeding past a required reboot.
-        loop {
-            thread::sleep(Duration::from_secs(5));
-            info!("Still waiting for the host to be rebooted...");
-        }
-    } else {
-        info!("GPU reset not required.");
-    }
+fn reboot_if_required(args: &Args, reboot_args: &RebootIfRequiredArgs) -> Result<()> {
+    set_phase("reboot");
+    let paths = paths_only(args);
+    // Don't reboot while another invocation is changing the GPUs.
+    let _lock = lock(args, &paths)?;
+    let options = RebootOptions {
+        delay: Duration::from_secs(reboot_args.delay),
+        reason: reboot_args.reason.clone(),
+        check_inhibitors: !reboot_args.ignore_inhibitors,
+        inhibitor_timeout: reboot_args
+            .inhibitor_timeout
+            .filter(|secs| *secs != 0)
+            .map(Duration::from_secs),
+    };
+    reboot::reboot_if_required(&paths, &options)?;
 
     Ok(())
 }
 
-fn run() -> Result<()> {
+/// Returns the exit code of the process.
+fn run() -> Result<i32> {
     let args: Args = argh::from_env();
 
-    // SimpleLogger will send errors to stderr and anything less to stdout.
     let log_level = args.log_level.unwrap_or(LevelFilter::Info);
-    SimpleLogger::init(log_level, LogConfig::default()).context(error::LoggerSnafu)?;
+    logging::init(args.log_format, log_level)?;
 
     info!("nvidia-migmanager started");
 
-    let mig_settings = get_mig_settings(args.config_path)?;
-    let gpu_info = get_gpu_info()?;
-
-    match args.subcommand {
-        Subcommand::HandleMigManager(_) => handle_mig_manager(mig_settings, &gpu_info),
-        Subcommand::RebootIfRequired(_) => reboot_if_required(),
+    // Each subcommand loads only what it needs, so that rebooting doesn't depend on the config
+    // file or the GPUs.
+    match &args.subcommand {
+        Subcommand::HandleMigManager(_) => Ok(apply_mig(&args)),
+        Subcommand::Plan(_) => print_plan(&args).map(|()| 0),
+        Subcommand::RebootIfRequired(reboot_args) => {
+            reboot_if_required(&args, reboot_args).map(|()| 0)
+        }
+        Subcommand::Status(status_args) => print_status(&args, status_args).map(|()| 0),
     }
 }
 
 fn main() {
-    if let Err(e) = run() {
-        eprintln!("{}", e);
-        process::exit(1);
+    match run() {
+        Ok(exit_code) => process::exit(exit_code),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(e.exit_code());
+        }
     }
 }
 
 // =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
 
 mod error {
+    use nvidia_mig::outcome::Outcome;
     use snafu::Snafu;
-    use std::path::PathBuf;
-    use std::process::{Command, Output};
 
     #[derive(Debug, Snafu)]
     #[snafu(visibility(pub(super)))]
     pub(super) enum Error {
-        #[snafu(display("Failed to read settings from config at {}: {}", config_path.display(), source))]
-        ReadConfig {
-            config_path: PathBuf,
-            source: std::io::Error,
-        },
-
-        #[snafu(display("Failed to write marker file at {}. Error: {}", marker_path.display(), source))]
-        WriteMarker {
-            marker_path: PathBuf,
-            source: std::io::Error,
-        },
-
-        #[snafu(display("Failed to deserialize settings from config at {}: {}", config_path.display(), source))]
-        TomlDeserialization {
-            config_path: PathBuf,
-            source: toml::de::Error,
-        },
-
-        #[snafu(display("Failed to deserialize MIG profile: {}", source))]
-        Deserialization { source: serde_plain::Error },
-
-        #[snafu(display("'{}' failed - stderr: {}",
-                        bin_path, String::from_utf8_lossy(&output.stderr)))]
-        CommandFailure { bin_path: String, output: Output },
-
-        #[snafu(display("Failed to execute '{:?}': {}", command, source))]
-        ExecutionFailure {
-            command: Command,
-            source: std::io::Error,
-        },
-
         #[snafu(display("Logger setup error: {}", source))]
         Logger { source: log::SetLoggerError },
 
-        #[snafu(display("Nvidia GPU not available."))]
-        GpuModel {},
+        #[snafu(display("Can't log to journald, {} doesn't exist", socket))]
+        Journald { socket: &'static str },
 
-        #[snafu(display("Invalid MIG Profile provided in the Settings."))]
-        MigProfile {},
+        #[snafu(display("Failed to create a socket for journald: {}", source))]
+        JournaldSocket { source: std::io::Error },
 
-        #[snafu(display("MIG is unsupported because multiple variants of Nvidia GPU present."))]
-        MigGpu {},
+        #[snafu(context(false), display("{}", source))]
+        Mig { source: nvidia_mig::error::Error },
+    }
 
-        #[snafu(display("NvidiaSmi command failed or has incorrect output format."))]
-        NvidiaSmi {},
+    impl Error {
+        /// Returns the exit code for the error, which tells invalid MIG settings apart.
+        pub(super) fn exit_code(&self) -> i32 {
+            match self {
+                Error::Logger { .. } | Error::Journald { .. } | Error::JournaldSocket { .. } => 1,
+                Error::Mig { source } => Outcome::of_error(source).exit_code(),
+            }
+        }
     }
 }
 
 type Result<T> = std::result::Result<T, error::Error>;
-
-#[cfg(test)]
-mod test {
-    use super::*;
-
-    #[test]
-    fn test_get_mig_settings() {
-        let config_toml = r#"
-            device-partitioning-strategy = "mig"
-            profile = { "a100.40gb" = "1g.5gb" }
-        "#;
-
-        let temp_dir = tempfile::TempDir::new().unwrap();
-        let temp_config = Path::join(temp_dir.path(), "nvidia-migmanager.toml");
-        std::fs::write(&temp_config, config_toml).unwrap();
-
-        let mig_settings = get_mig_settings(&temp_config).unwrap();
-        let mut mig_profile = HashMap::new();
-        mig_profile.insert("a100.40gb".to_string(), "1g.5gb".to_string());
-
-        let expected_mig_settings = NvidiaMigConfig {
-            device_partitioning_strategy: "mig".to_string(),
-            profile: mig_profile,
-        };
-
-        assert_eq!(mig_settings, expected_mig_settings)
-    }
-
-    #[test]
-    fn test_get_mig_settings_default_profiles() {
-        let config_toml = r#"
-            device-partitioning-strategy = "mig"
-        "#;
-
-        let temp_dir = tempfile::TempDir::new().unwrap();
-        let temp_config = Path::join(temp_dir.path(), "nvidia-migmanager.toml");
-        std::fs::write(&temp_config, config_toml).unwrap();
-
-        let mig_settings = get_mig_settings(&temp_config).unwrap();
-        let mig_profile = HashMap::new();
-
-        let expected_mig_settings = NvidiaMigConfig {
-            device_partitioning_strategy: "mig".to_string(),
-            profile: mig_profile,
-        };
-
-        assert_eq!(mig_settings, expected_mig_settings)
-    }
-
-    #[test]
-    fn test_get_mig_profile_deserialization() {
-        let mig_profile = "1g.5gb".to_string();
-        let profile =
-            serde_plain::from_str::<NvidiaA100_40gbMigProfile>(mig_profile.as_str()).unwrap();
-
-        let profile_string = profile.get_mig_profile();
-        let expected_profile_string = "1g.5gb,1g.5gb,1g.5gb,1g.5gb,1g.5gb,1g.5gb,1g.5gb";
-
-        assert_eq!(profile_string, expected_profile_string)
-    }
-
-    #[test]
-    fn test_get_mig_profile_deserialization_default() {
-        let mig_profile = "1g.8gb".to_string();
-        let profile =
-            serde_plain::from_str::<NvidiaA100_40gbMigProfile>(mig_profile.as_str()).unwrap();
-        let profile_string = profile.get_mig_profile();
-        let expected_profile_string = "7g.40gb";
-
-        assert_eq!(profile_string, expected_profile_string)
-    }
-
-    #[test]
-    fn test_get_mig_profile_unknown_gpu() {
-        let gpu = "a100.40gb";
-        let mig_profile = "1g.5gb";
-
-        let gpu_regex = Regex::new(GPU_MODEL_REGEX).unwrap();
-        let profile_regex = Regex::new(MIG_PROFILE_REGEX).unwrap();
-
-        let gpu_ram: usize = gpu_regex
-            .captures(gpu)
-            .map(|captures| captures[1].parse().unwrap_or(0))
-            .unwrap();
-        let (compute_slices, slice_ram) = profile_regex
-            .captures(mig_profile)
-            .map(|captures| {
-                (
-                    captures[1].parse().unwrap_or(0),
-                    captures[2].parse().unwrap_or(0),
-                )
-            })
-            .unwrap();
-
-        let num_slices: usize = min(gpu_ram / slice_ram, 7 / compute_slices);
-
-        let profile_string = std::iter::repeat(mig_profile)
-            .take(num_slices)
-            .collect::<Vec<_>>()
-            .join(",");
-
-        let expected_profile_string = "1g.5gb,1g.5gb,1g.5gb,1g.5gb,1g.5gb,1g.5gb,1g.5gb";
-
-        assert_eq!(profile_string, expected_profile_string)
-    }
-}
@@ -0,0 +1,378 @@
+//! Runs the `nvidia-migmanager` binary against a scripted fake `nvidia-smi` and `systemctl`, and
+//! checks the exact commands it runs and the files it leaves in the state directory.
+//!
+//! The fake `nvidia-smi` logs its arguments and replies with the response registered for them
+//! with [`Harness::respond`] or [`Harness::fail`]. Commands without a response succeed without
+//! output, which `nvidia-smi` does for `-mig` and `mig -lgi` on an empty layout.
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const GPU_QUERY: &str = "--query-gpu=index,pci.device_id,mig.mode.current,mig.mode.pending,uuid,\
+                         pci.bus_id,name --format=csv,noheader";
+const HEALTH_QUERY: &str = "--query-gpu=index,ecc.errors.uncorrected.volatile.total,\
+                            retired_pages.pending,remapped_rows.pending,remapped_rows.failure \
+                            --format=csv,noheader";
+const INVENTORY_QUERY: &str = "--query-gpu=index,uuid,name,pci.device_id,mig.mode.current,\
+                               mig.mode.pending --format=csv,noheader";
+const SIGTERM: i32 = 15;
+const DRIVER_QUERY: &str = "--query-gpu=driver_version --format=csv,noheader";
+// What the inventory runs: the GPU query, the MIG device list, the summary table and the health.
+const INVENTORY_CALLS: [&str; 4] = [INVENTORY_QUERY, "-L", "", HEALTH_QUERY];
+
+// The fake tools key their responses by their arguments, with anything but letters and digits
+// replaced by underscores.
+const FAKE_NVIDIA_SMI: &str = r#"#!/bin/sh
+dir=$(dirname "$0")
+echo "$*" >> "$dir/nvidia-smi.calls"
+key=$(printf '%s' "_$*" | tr -c 'A-Za-z0-9' '_')
+[ -f "$dir/responses/$key.out" ] && cat "$dir/responses/$key.out"
+[ -f "$dir/responses/$key.err" ] && cat "$dir/responses/$key.err" >&2
+[ -f "$dir/responses/$key.code" ] && exit "$(cat "$dir/responses/$key.code")"
+exit 0
+"#;
+// Like systemd does when the host shuts down, stop nvidia-migmanager once the reboot is queued.
+const FAKE_SYSTEMCTL: &str = r#"#!/bin/sh
+dir=$(dirname "$0")
+echo "$*" >> "$dir/systemctl.calls"
+kill -TERM "$PPID"
+"#;
+
+struct Harness {
+    dir: tempfile::TempDir,
+}
+
+impl Harness {
+    fn new(config: &str) -> Self {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::create_dir(dir.path().join("responses")).unwrap();
+        for (name, script) in [
+            ("nvidia-smi", FAKE_NVIDIA_SMI),
+            ("systemctl", FAKE_SYSTEMCTL),
+        ] {
+            let path = dir.path().join(name);
+            fs::write(&path, script).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        fs::write(dir.path().join("nvidia-migmanager.toml"), config).unwrap();
+
+        let harness = Harness { dir };
+        harness.respond(DRIVER_QUERY, "535.161.08\n");
+        harness
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.path().join(name)
+    }
+
+    fn response(&self, args: &str, extension: &str) -> PathBuf {
+        let key: String = format!("_{}", args)
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        self.path("responses")
+            .join(format!("{}.{}", key, extension))
+    }
+
+    /// Makes `nvidia-smi <args>` print `stdout`.
+    fn respond(&self, args: &str, stdout: &str) {
+        fs::write(self.response(args, "out"), stdout).unwrap();
+    }
+
+    /// Makes `nvidia-smi <args>` print `stderr` and exit with `code`.
+    fn fail(&self, args: &str, stderr: &str, code: i32) {
+        fs::write(self.response(args, "err"), stderr).unwrap();
+        fs::write(self.response(args, "code"), code.to_string()).unwrap();
+    }
+
+    /// Makes the GPU queries describe `gpus`, as rows of index, PCI device ID, MIG mode, UUID and
+    /// name.
+    fn gpus(&self, gpus: &[(u32, &str, &str, &str, &str)]) {
+        let mut gpu_query = String::new();
+        let mut inventory_query = String::new();
+        for (index, pci_device_id, mig_mode, uuid, name) in gpus {
+            gpu_query += &format!(
+                "{index}, {pci_device_id}, {mig_mode}, {mig_mode}, {uuid}, 00000000:{:02X}:00.0, \
+                 {name}\n",
+                0x10 + index
+            );
+            inventory_query +=
+                &format!("{index}, {uuid}, {name}, {pci_device_id}, {mig_mode}, {mig_mode}\n");
+        }
+        self.respond(GPU_QUERY, &gpu_query);
+        self.respond(INVENTORY_QUERY, &inventory_query);
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_nvidia-migmanager"));
+        command
+            .arg("--config-path")
+            .arg(self.path("nvidia-migmanager.toml"))
+            .arg("--nvidia-smi-path")
+            .arg(self.path("nvidia-smi"))
+            .arg("--systemctl-path")
+            .arg(self.path("systemctl"))
+            .arg("--state-dir")
+            .arg(self.dir.path())
+            .arg("--history-dir")
+            .arg(self.path("history"))
+            .args(["--lock-timeout", "0"])
+            .args(args);
+        command
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        self.command(args).output().unwrap()
+    }
+
+    /// Runs a subcommand that may not return on its own, and stops it after `timeout`.
+    fn run_with_timeout(&self, args: &[&str], timeout: Duration) -> ExitStatus {
+        let mut child = self
+            .command(args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let start = Instant::now();
+        loop {
+            if let Some(status) = child.try_wait().unwrap() {
+                return status;
+            }
+            if start.elapsed() > timeout {
+                child.kill().unwrap();
+                panic!("nvidia-migmanager {:?} didn't exit in {:?}", args, timeout);
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    fn calls(&self, tool: &str) -> Vec<String> {
+        read_lines(&self.path(&format!("{}.calls", tool)))
+    }
+
+    fn marker(&self) -> Option<String> {
+        fs::read_to_string(self.path("reboot-required")).ok()
+    }
+
+    fn result(&self) -> serde_json::Value {
+        let content = fs::read_to_string(self.path("result.json")).unwrap();
+        serde_json::from_str(&content).unwrap()
+    }
+}
+
+fn read_lines(path: &Path) -> Vec<String> {
+    fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+// The calls of an apply-mig that gets to the GPUs: discovery, the inventory for the drift check,
+// the MIG commands of the plan, and the inventory and driver version for the history.
+fn apply_calls(mig_commands: &[&str]) -> Vec<String> {
+    let mut calls = vec![GPU_QUERY, HEALTH_QUERY];
+    calls.extend(INVENTORY_CALLS);
+    calls.extend(mig_commands);
+    calls.extend(INVENTORY_CALLS);
+    calls.push(DRIVER_QUERY);
+    calls.into_iter().map(String::from).collect()
+}
+
+#[test]
+fn test_a100_enable_mig_with_reboot() {
+    let harness = Harness::new(
+        r#"
+        device-partitioning-strategy = "mig"
+        profile = { "a100.40gb" = "2" }
+        "#,
+    );
+    harness.gpus(&[(
+        0,
+        "0x20B010DE",
+        "Disabled",
+        "GPU-a100-0",
+        "NVIDIA A100-SXM4-40GB",
+    )]);
+
+    let output = harness.run(&["apply-mig"]);
+
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(harness.calls("nvidia-smi"), apply_calls(&["-mig 1"]));
+    assert_eq!(harness.marker().as_deref(), Some("Enabling MIG"));
+    assert_eq!(harness.result()["outcome"], "reboot-pending");
+    assert_eq!(harness.result()["gpus"][0]["outcome"], "reboot-pending");
+    assert!(harness.calls("systemctl").is_empty());
+
+    // The fake systemctl stops nvidia-migmanager like the shutdown would.
+    let status = harness.run_with_timeout(&["reboot-if-required"], Duration::from_secs(30));
+
+    assert_eq!(status.signal(), Some(SIGTERM));
+    assert_eq!(
+        harness.calls("systemctl"),
+        vec![
+            "reboot --message=nvidia-migmanager: Enabling MIG requires a GPU reset \
+             --check-inhibitors=yes"
+        ]
+    );
+    assert!(harness.marker().is_some());
+}
+
+#[test]
+fn test_h100_enable_mig_without_reboot() {
+    let harness = Harness::new(
+        r#"
+        device-partitioning-strategy = "mig"
+        profile = { "h100.80gb" = "7" }
+        "#,
+    );
+    harness.gpus(&[
+        (
+            0,
+            "0x233010DE",
+            "Disabled",
+            "GPU-h100-0",
+            "NVIDIA H100 80GB HBM3",
+        ),
+        (
+            1,
+            "0x233010DE",
+            "Disabled",
+            "GPU-h100-1",
+            "NVIDIA H100 80GB HBM3",
+        ),
+    ]);
+    // Older drivers can't report the health of the GPUs, which doesn't block MIG.
+    harness.fail(
+        HEALTH_QUERY,
+        "Field \"remapped_rows.pending\" is not a valid field to query.\n",
+        2,
+    );
+    harness.respond(
+        "mig -cgi 1g.10gb -C",
+        "Successfully created GPU instance ID  9 on GPU  0 using profile MIG 1g.10gb (ID 19)\n\
+         Successfully created GPU instance ID  9 on GPU  1 using profile MIG 1g.10gb (ID 19)\n",
+    );
+
+    let output = harness.run(&["apply-mig"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        harness.calls("nvidia-smi"),
+        apply_calls(&[
+            "-mig 1",
+            "mig -lgi",
+            // GPU instances are created one profile at a time, so that a failure can be rolled
+            // back.
+            "mig -cgi 1g.10gb -C",
+            "mig -cgi 1g.10gb -C",
+            "mig -cgi 1g.10gb -C",
+            "mig -cgi 1g.10gb -C",
+            "mig -cgi 1g.10gb -C",
+            "mig -cgi 1g.10gb -C",
+            "mig -cgi 1g.10gb -C",
+        ])
+    );
+    assert_eq!(harness.marker(), None);
+    assert_eq!(harness.result()["outcome"], "applied");
+    let history = read_lines(&harness.path("history/history.jsonl"));
+    assert_eq!(history.len(), 1);
+    assert!(history[0].contains(r#""driver_version":"535.161.08""#));
+}
+
+#[test]
+fn test_mixed_models() {
+    let harness = Harness::new(
+        r#"
+        device-partitioning-strategy = "mig"
+        profile = { "a100.40gb" = "2", "h100.80gb" = "2" }
+        "#,
+    );
+    harness.gpus(&[
+        (
+            0,
+            "0x20B010DE",
+            "Enabled",
+            "GPU-a100-0",
+            "NVIDIA A100-SXM4-40GB",
+        ),
+        (
+            1,
+            "0x233010DE",
+            "Enabled",
+            "GPU-h100-1",
+            "NVIDIA H100 80GB HBM3",
+        ),
+    ]);
+
+    let output = harness.run(&["apply-mig"]);
+
+    // Mixed models are treated as unknown GPUs, which only use profiles given for GPU keys other
+    // than the known ones. There are none, so no MIG commands run.
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(harness.calls("nvidia-smi"), apply_calls(&[]));
+    assert_eq!(harness.result()["outcome"], "invalid-config");
+    assert_eq!(
+        harness.result()["gpus"][1]["detail"],
+        "no valid MIG profile in the settings"
+    );
+    assert_eq!(harness.marker(), None);
+}
+
+#[test]
+fn test_malformed_output() {
+    let harness = Harness::new(r#"device-partitioning-strategy = "mig""#);
+    harness.respond(
+        GPU_QUERY,
+        "NVIDIA-SMI has failed because it couldn't communicate\n",
+    );
+
+    let output = harness.run(&["apply-mig"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(harness.calls("nvidia-smi"), vec![GPU_QUERY]);
+    assert_eq!(harness.result()["outcome"], "failed");
+    assert_eq!(harness.marker(), None);
+}
+
+#[test]
+fn test_a100_disable_mig_with_reboot() {
+    let harness = Harness::new(r#"device-partitioning-strategy = "none""#);
+    harness.gpus(&[(
+        0,
+        "0x20B010DE",
+        "Enabled",
+        "GPU-a100-0",
+        "NVIDIA A100-SXM4-40GB",
+    )]);
+
+    let output = harness.run(&["apply-mig"]);
+
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(harness.calls("nvidia-smi"), apply_calls(&["-mig 0"]));
+    assert_eq!(harness.marker().as_deref(), Some("Disabling MIG"));
+    assert_eq!(harness.result()["outcome"], "reboot-pending");
+}
+
+#[test]
+fn test_lock_held() {
+    let harness = Harness::new(r#"device-partitioning-strategy = "mig""#);
+    let paths = nvidia_mig::paths::Paths {
+        state_dir: harness.dir.path().to_path_buf(),
+        ..Default::default()
+    };
+    let _lock = nvidia_mig::lock::RunLock::acquire(&paths, Duration::ZERO).unwrap();
+
+    let output = harness.run(&["apply-mig"]);
+
+    // The invocation holding the lock owns the state, so nothing is written.
+    assert_eq!(output.status.code(), Some(1));
+    assert!(harness.calls("nvidia-smi").is_empty());
+    assert!(!harness.path("result.json").exists());
+    assert!(!harness.path("history").exists());
+}